@@ -97,7 +97,7 @@ fn run(args: Args) -> Result<(), String> {
 
     // Highlight based on output format
     if args.html {
-        let mut highlighter = Highlighter::new();
+        let highlighter = Highlighter::new();
         let html = highlighter
             .highlight(lang, &content)
             .map_err(|e| format!("Highlighting failed: {}", e))?;
@@ -123,7 +123,7 @@ fn run(args: Args) -> Result<(), String> {
             None => builtin::catppuccin_mocha(), // Default theme
         };
 
-        let mut highlighter = AnsiHighlighter::new(theme.clone());
+        let highlighter = AnsiHighlighter::new(theme.clone());
         let ansi = highlighter
             .highlight(lang, &content)
             .map_err(|e| format!("Highlighting failed: {}", e))?;
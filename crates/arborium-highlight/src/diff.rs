@@ -0,0 +1,565 @@
+//! Syntax-highlighted diffs.
+//!
+//! [`diff_to_html`] compares two versions of a file line by line and renders
+//! the result as a unified diff, with each side highlighted using its own
+//! spans. Lines that were modified rather than purely added or removed also
+//! get their changed characters marked. [`diff_to_side_by_side_html`] renders
+//! the same comparison as a two-column table.
+
+use std::ops::Range;
+
+use arborium_theme::tag_for_capture;
+
+use crate::render::{html_escape, make_html_tags};
+use crate::{HtmlFormat, Span, spans_to_tokens};
+
+/// Inputs larger than this many line pairs (after trimming the common prefix
+/// and suffix) are diffed as a whole-block replacement rather than with the
+/// quadratic LCS table.
+const MAX_LCS_CELLS: usize = 4_000_000;
+
+/// One line-level step of a diff, as line indices into the old and new text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DiffOp {
+    Equal { old: usize, new: usize },
+    Delete { old: usize },
+    Insert { new: usize },
+}
+
+/// Render a unified, syntax-highlighted diff of `old` and `new` as HTML.
+///
+/// `old_spans` and `new_spans` are the highlighting spans of each version.
+/// Every line becomes a `<span class="diff-line diff-{kind}">`, where kind is
+/// `context`, `added` or `removed`, followed by a newline. Each line starts
+/// with a gutter of two `<span class="diff-number">` cells (old and new line
+/// numbers, empty where a side has no line) and a
+/// `<span class="diff-marker">` holding ` `, `+` or `-`. As in `git diff`, a
+/// block of changes lists all its removed lines before its added ones. When
+/// a removed line is replaced by an added one, the characters that differ
+/// are wrapped in `<span class="diff-change">`.
+///
+/// ```rust
+/// use arborium_highlight::{HtmlFormat, diff_to_html};
+///
+/// let html = diff_to_html("a\nb\n", vec![], "a\nc\n", vec![], &HtmlFormat::default());
+/// assert!(html.contains("<span class=\"diff-line diff-removed\">"));
+/// assert!(html.contains("<span class=\"diff-change\">c</span>"));
+/// ```
+pub fn diff_to_html(
+    old: &str,
+    old_spans: Vec<Span>,
+    new: &str,
+    new_spans: Vec<Span>,
+    format: &HtmlFormat,
+) -> String {
+    let old = DiffSide::new(old, old_spans);
+    let new = DiffSide::new(new, new_spans);
+
+    let mut html = String::with_capacity((old.source.len() + new.source.len()) * 2);
+    let mut rows = diff_rows(&old, &new).into_iter().peekable();
+    while let Some(row) = rows.next() {
+        if let DiffRow::Context { old: o, new: n } = row {
+            push_line(&mut html, "context", Some(o), Some(n), ' ');
+            old.push_code(&mut html, o, None, format);
+            html.push_str("</span>\n");
+            continue;
+        }
+
+        // A run of changes shows all its removed lines, then all its added ones
+        let mut run = vec![row];
+        while let Some(row) = rows.next_if(|row| matches!(row, DiffRow::Changed { .. })) {
+            run.push(row);
+        }
+        for row in &run {
+            if let DiffRow::Changed {
+                old: Some(o),
+                old_change,
+                ..
+            } = row
+            {
+                push_line(&mut html, "removed", Some(*o), None, '-');
+                old.push_code(&mut html, *o, old_change.clone(), format);
+                html.push_str("</span>\n");
+            }
+        }
+        for row in &run {
+            if let DiffRow::Changed {
+                new: Some(n),
+                new_change,
+                ..
+            } = row
+            {
+                push_line(&mut html, "added", None, Some(*n), '+');
+                new.push_code(&mut html, *n, new_change.clone(), format);
+                html.push_str("</span>\n");
+            }
+        }
+    }
+    html
+}
+
+/// Render a side-by-side, syntax-highlighted diff of `old` and `new` as an
+/// HTML table.
+///
+/// The table has the class `diff-side-by-side` and one `<tr>` per row, with
+/// four cells: old line number, old code, new line number, new code. Number
+/// cells have the class `diff-number` and code cells `diff-code` plus
+/// `diff-context`, `diff-removed`, `diff-added` or, where a side has no line
+/// because the other side was purely added or removed, `diff-empty`. Removed
+/// and added lines are aligned on the same row when one replaces the other,
+/// with the differing characters wrapped in `<span class="diff-change">` as in
+/// [`diff_to_html`].
+///
+/// ```rust
+/// use arborium_highlight::{HtmlFormat, diff_to_side_by_side_html};
+///
+/// let html = diff_to_side_by_side_html("a\n", vec![], "a\nb\n", vec![], &HtmlFormat::default());
+/// assert!(html.starts_with("<table class=\"diff-side-by-side\">"));
+/// assert!(html.contains("<td class=\"diff-code diff-empty\"></td>"));
+/// ```
+pub fn diff_to_side_by_side_html(
+    old: &str,
+    old_spans: Vec<Span>,
+    new: &str,
+    new_spans: Vec<Span>,
+    format: &HtmlFormat,
+) -> String {
+    let old = DiffSide::new(old, old_spans);
+    let new = DiffSide::new(new, new_spans);
+
+    let mut html = String::with_capacity((old.source.len() + new.source.len()) * 3);
+    html.push_str("<table class=\"diff-side-by-side\">\n");
+    for row in diff_rows(&old, &new) {
+        html.push_str("<tr>");
+        match row {
+            DiffRow::Context { old: o, new: n } => {
+                push_cells(&mut html, &old, Some(o), None, "diff-context", format);
+                push_cells(&mut html, &new, Some(n), None, "diff-context", format);
+            }
+            DiffRow::Changed {
+                old: o,
+                new: n,
+                old_change,
+                new_change,
+            } => {
+                push_cells(&mut html, &old, o, old_change, "diff-removed", format);
+                push_cells(&mut html, &new, n, new_change, "diff-added", format);
+            }
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>");
+    html
+}
+
+/// Write the number and code cells of one side of a side-by-side row.
+fn push_cells(
+    html: &mut String,
+    side: &DiffSide<'_>,
+    line: Option<usize>,
+    change: Option<Range<usize>>,
+    class: &str,
+    format: &HtmlFormat,
+) {
+    html.push_str("<td class=\"diff-number\">");
+    match line {
+        Some(line) => {
+            html.push_str(&(line + 1).to_string());
+            html.push_str("</td><td class=\"diff-code ");
+            html.push_str(class);
+            html.push_str("\">");
+            side.push_code(html, line, change, format);
+        }
+        None => html.push_str("</td><td class=\"diff-code diff-empty\">"),
+    }
+    html.push_str("</td>");
+}
+
+/// Diff two sides line by line and group the result into rows.
+fn diff_rows(old: &DiffSide<'_>, new: &DiffSide<'_>) -> Vec<DiffRow> {
+    let ops = diff_lines(&old.line_texts(), &new.line_texts());
+    pair_changes(&ops, old, new)
+}
+
+/// Write the opening of a diff line and its gutter.
+fn push_line(html: &mut String, kind: &str, old: Option<usize>, new: Option<usize>, marker: char) {
+    html.push_str("<span class=\"diff-line diff-");
+    html.push_str(kind);
+    html.push_str("\">");
+    for number in [old, new] {
+        html.push_str("<span class=\"diff-number\">");
+        if let Some(line) = number {
+            html.push_str(&(line + 1).to_string());
+        }
+        html.push_str("</span>");
+    }
+    html.push_str("<span class=\"diff-marker\">");
+    html.push(marker);
+    html.push_str("</span>");
+}
+
+/// A row of diff output: an unchanged line, or a removed and/or added line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum DiffRow {
+    Context {
+        old: usize,
+        new: usize,
+    },
+    /// A removed line, an added line, or a removed line replaced by an added
+    /// one. The change ranges are the differing bytes, relative to the source
+    /// of each side; they are only set when both lines are present.
+    Changed {
+        old: Option<usize>,
+        new: Option<usize>,
+        old_change: Option<Range<usize>>,
+        new_change: Option<Range<usize>>,
+    },
+}
+
+/// Group diff operations into rows, pairing each run of deleted lines with
+/// the inserted lines that follow it.
+pub(crate) fn pair_changes(ops: &[DiffOp], old: &DiffSide<'_>, new: &DiffSide<'_>) -> Vec<DiffRow> {
+    let mut rows = Vec::with_capacity(ops.len());
+    let mut i = 0;
+    while i < ops.len() {
+        if let DiffOp::Equal { old, new } = ops[i] {
+            rows.push(DiffRow::Context { old, new });
+            i += 1;
+            continue;
+        }
+
+        let mut deleted = Vec::new();
+        let mut inserted = Vec::new();
+        while i < ops.len() {
+            match ops[i] {
+                DiffOp::Delete { old } => deleted.push(old),
+                DiffOp::Insert { new } => inserted.push(new),
+                DiffOp::Equal { .. } => break,
+            }
+            i += 1;
+        }
+
+        for k in 0..deleted.len().max(inserted.len()) {
+            let (o, n) = (deleted.get(k).copied(), inserted.get(k).copied());
+            let (old_change, new_change) = match (o, n) {
+                (Some(o), Some(n)) => {
+                    let (a, b) = changed_ranges(old.line_text(o), new.line_text(n));
+                    let old_start = old.lines[o].start;
+                    let new_start = new.lines[n].start;
+                    (
+                        Some(old_start + a.start..old_start + a.end),
+                        Some(new_start + b.start..new_start + b.end),
+                    )
+                }
+                _ => (None, None),
+            };
+            rows.push(DiffRow::Changed {
+                old: o,
+                new: n,
+                old_change,
+                new_change,
+            });
+        }
+    }
+    rows
+}
+
+/// The differing middle of two lines, after their common prefix and suffix.
+fn changed_ranges(old: &str, new: &str) -> (Range<usize>, Range<usize>) {
+    let prefix: usize = old
+        .chars()
+        .zip(new.chars())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum();
+    let suffix: usize = old[prefix..]
+        .chars()
+        .rev()
+        .zip(new[prefix..].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum();
+    (prefix..old.len() - suffix, prefix..new.len() - suffix)
+}
+
+/// Compute a line diff with a longest-common-subsequence table.
+pub(crate) fn diff_lines(old: &[&str], new: &[&str]) -> Vec<DiffOp> {
+    // Common prefix and suffix need no table
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let mut ops: Vec<DiffOp> = (0..prefix)
+        .map(|i| DiffOp::Equal { old: i, new: i })
+        .collect();
+
+    let (n, m) = (old_mid.len(), new_mid.len());
+    if n.saturating_mul(m) > MAX_LCS_CELLS {
+        ops.extend((0..n).map(|i| DiffOp::Delete { old: prefix + i }));
+        ops.extend((0..m).map(|j| DiffOp::Insert { new: prefix + j }));
+    } else {
+        // lcs[i][j]: length of the LCS of old_mid[i..] and new_mid[j..]
+        let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+        let at = |i: usize, j: usize| i * (m + 1) + j;
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[at(i, j)] = if old_mid[i] == new_mid[j] {
+                    lcs[at(i + 1, j + 1)] + 1
+                } else {
+                    lcs[at(i + 1, j)].max(lcs[at(i, j + 1)])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && old_mid[i] == new_mid[j] {
+                ops.push(DiffOp::Equal {
+                    old: prefix + i,
+                    new: prefix + j,
+                });
+                i += 1;
+                j += 1;
+            } else if i < n && (j == m || lcs[at(i + 1, j)] >= lcs[at(i, j + 1)]) {
+                ops.push(DiffOp::Delete { old: prefix + i });
+                i += 1;
+            } else {
+                ops.push(DiffOp::Insert { new: prefix + j });
+                j += 1;
+            }
+        }
+    }
+
+    let (old_end, new_end) = (old.len() - suffix, new.len() - suffix);
+    ops.extend((0..suffix).map(|k| DiffOp::Equal {
+        old: old_end + k,
+        new: new_end + k,
+    }));
+    ops
+}
+
+/// One side of a diff: its source split into lines of highlighted segments.
+pub(crate) struct DiffSide<'a> {
+    source: &'a str,
+    /// Byte range of each line, without its newline. A trailing newline does
+    /// not start another line.
+    lines: Vec<Range<usize>>,
+    /// Highlighted segments of each line: byte range and HTML tag, if any.
+    segments: Vec<Vec<(Range<usize>, Option<&'static str>)>>,
+}
+
+impl<'a> DiffSide<'a> {
+    pub(crate) fn new(source: &'a str, spans: Vec<Span>) -> Self {
+        let mut lines = Vec::new();
+        let mut start = 0;
+        for (i, c) in source.char_indices() {
+            if c == '\n' {
+                lines.push(start..i);
+                start = i + 1;
+            }
+        }
+        if start < source.len() {
+            lines.push(start..source.len());
+        }
+
+        let mut segments = vec![Vec::new(); lines.len()];
+        let mut line = 0;
+        for token in spans_to_tokens(source, spans) {
+            let tag = token.capture.as_deref().and_then(tag_for_capture);
+            let mut offset = token.range.start;
+            for (i, piece) in token.text.split('\n').enumerate() {
+                if i > 0 {
+                    line += 1;
+                }
+                if !piece.is_empty() {
+                    segments[line].push((offset..offset + piece.len(), tag));
+                }
+                offset += piece.len() + 1;
+            }
+        }
+
+        Self {
+            source,
+            lines,
+            segments,
+        }
+    }
+
+    pub(crate) fn line_texts(&self) -> Vec<&'a str> {
+        (0..self.lines.len()).map(|i| self.line_text(i)).collect()
+    }
+
+    pub(crate) fn line_text(&self, line: usize) -> &'a str {
+        &self.source[self.lines[line].clone()]
+    }
+
+    /// Write the highlighted code of a line, marking the `change` byte range.
+    pub(crate) fn push_code(
+        &self,
+        html: &mut String,
+        line: usize,
+        change: Option<Range<usize>>,
+        format: &HtmlFormat,
+    ) {
+        let change = change.filter(|change| !change.is_empty());
+        let mut in_change = false;
+        for (range, tag) in &self.segments[line] {
+            // Split the segment where the change starts and ends
+            let mut cuts = vec![range.start, range.end];
+            if let Some(change) = &change {
+                for cut in [change.start, change.end] {
+                    if range.start < cut && cut < range.end {
+                        cuts.insert(cuts.len() - 1, cut);
+                    }
+                }
+            }
+
+            for piece in cuts.windows(2) {
+                let changed = change
+                    .as_ref()
+                    .is_some_and(|change| change.start <= piece[0] && piece[1] <= change.end);
+                if changed != in_change {
+                    html.push_str(if changed {
+                        "<span class=\"diff-change\">"
+                    } else {
+                        "</span>"
+                    });
+                    in_change = changed;
+                }
+
+                let text = html_escape(&self.source[piece[0]..piece[1]]);
+                match tag {
+                    Some(tag) => {
+                        let (open_tag, close_tag) = make_html_tags(tag, format);
+                        html.push_str(&open_tag);
+                        html.push_str(&text);
+                        html.push_str(&close_tag);
+                    }
+                    None => html.push_str(&text),
+                }
+            }
+        }
+        if in_change {
+            html.push_str("</span>");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_lines() {
+        let ops = diff_lines(&["a", "b", "c", "d"], &["a", "c", "x", "d"]);
+        assert_eq!(
+            ops,
+            [
+                DiffOp::Equal { old: 0, new: 0 },
+                DiffOp::Delete { old: 1 },
+                DiffOp::Equal { old: 2, new: 1 },
+                DiffOp::Insert { new: 2 },
+                DiffOp::Equal { old: 3, new: 3 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_changed_ranges() {
+        assert_eq!(changed_ranges("let x = 1;", "let x = 22;"), (8..9, 8..10));
+        assert_eq!(changed_ranges("é", "è"), (0..2, 0..2));
+    }
+
+    #[test]
+    fn test_side_by_side_alignment() {
+        let html = diff_to_side_by_side_html(
+            "a\nb\nc\n",
+            vec![],
+            "a\nB\nc\nd\n",
+            vec![],
+            &HtmlFormat::default(),
+        );
+        let rows: Vec<&str> = html.lines().filter(|l| l.starts_with("<tr>")).collect();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(
+            rows[1],
+            "<tr><td class=\"diff-number\">2</td>\
+             <td class=\"diff-code diff-removed\"><span class=\"diff-change\">b</span></td>\
+             <td class=\"diff-number\">2</td>\
+             <td class=\"diff-code diff-added\"><span class=\"diff-change\">B</span></td></tr>"
+        );
+        assert_eq!(
+            rows[3],
+            "<tr><td class=\"diff-number\"></td><td class=\"diff-code diff-empty\"></td>\
+             <td class=\"diff-number\">4</td><td class=\"diff-code diff-added\">d</td></tr>"
+        );
+    }
+
+    #[test]
+    fn test_diff_to_html_groups_replaced_lines() {
+        let html = diff_to_html(
+            "a\nb\nc\nd\n",
+            vec![],
+            "a\nx\ny\nd\n",
+            vec![],
+            &HtmlFormat::default(),
+        );
+        let markers: Vec<&str> = html
+            .lines()
+            .map(|line| {
+                let marker = line.split("<span class=\"diff-marker\">").nth(1).unwrap();
+                &marker[..1]
+            })
+            .collect();
+        assert_eq!(markers, [" ", "-", "-", "+", "+", " "]);
+        // Replaced lines are still paired for their changed characters
+        assert!(
+            html.contains(
+                "<span class=\"diff-marker\">-</span><span class=\"diff-change\">b</span>"
+            )
+        );
+        assert!(
+            html.contains(
+                "<span class=\"diff-marker\">+</span><span class=\"diff-change\">y</span>"
+            )
+        );
+    }
+
+    #[test]
+    fn test_diff_to_html() {
+        let old = "fn a() {}\nlet x = 1;\n";
+        let new = "fn a() {}\nlet x = 2;\n";
+        let keyword = |start| Span {
+            start,
+            end: start + 3,
+            capture: "keyword".into(),
+        };
+        let html = diff_to_html(
+            old,
+            vec![keyword(10)],
+            new,
+            vec![keyword(10)],
+            &HtmlFormat::default(),
+        );
+        assert_eq!(
+            html,
+            "<span class=\"diff-line diff-context\">\
+             <span class=\"diff-number\">1</span><span class=\"diff-number\">1</span>\
+             <span class=\"diff-marker\"> </span>fn a() {}</span>\n\
+             <span class=\"diff-line diff-removed\">\
+             <span class=\"diff-number\">2</span><span class=\"diff-number\"></span>\
+             <span class=\"diff-marker\">-</span>\
+             <a-k>let</a-k> x = <span class=\"diff-change\">1</span>;</span>\n\
+             <span class=\"diff-line diff-added\">\
+             <span class=\"diff-number\"></span><span class=\"diff-number\">2</span>\
+             <span class=\"diff-marker\">+</span>\
+             <a-k>let</a-k> x = <span class=\"diff-change\">2</span>;</span>\n"
+        );
+    }
+}
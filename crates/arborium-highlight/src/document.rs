@@ -0,0 +1,199 @@
+//! Structured, line-oriented highlighting output.
+//!
+//! HTML and ANSI renderers produce a flat string. GUI and TUI consumers usually
+//! want the opposite: a list of lines, each a list of styled runs they can draw
+//! themselves. [`spans_to_document`] resolves overlapping spans the same way the
+//! HTML renderer does (innermost span wins) and splits the result on newlines.
+
+use crate::Span;
+use crate::render::spans_to_themed;
+
+/// A run of text within one line that has a single style.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StyledToken {
+    /// The text of this run. Never contains a newline.
+    pub text: String,
+    /// Byte offset of the run in the source.
+    pub start: u32,
+    /// Index into the theme's style array (use with `Theme::style()`), or
+    /// `None` for unhighlighted text.
+    pub theme_index: Option<usize>,
+}
+
+/// Highlighted source split into lines of styled tokens.
+///
+/// Produced by [`spans_to_document`]. Lines are split on `\n`, which is not
+/// included in any token; there is always at least one (possibly empty) line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HighlightedDocument {
+    /// The language the source was highlighted as.
+    pub language: String,
+    /// Styled tokens for each line. Adjacent tokens never share a style.
+    pub lines: Vec<Vec<StyledToken>>,
+}
+
+impl HighlightedDocument {
+    /// Iterate over the plain text of each line.
+    pub fn line_texts(&self) -> impl Iterator<Item = String> + '_ {
+        self.lines
+            .iter()
+            .map(|line| line.iter().map(|token| token.text.as_str()).collect())
+    }
+}
+
+/// Build a [`HighlightedDocument`] from raw spans.
+///
+/// ```rust
+/// use arborium_highlight::{Span, spans_to_document};
+///
+/// let source = "fn a() {}\nfn b() {}";
+/// let spans = vec![
+///     Span { start: 0, end: 2, capture: "keyword".into() },
+///     Span { start: 10, end: 12, capture: "keyword".into() },
+/// ];
+///
+/// let doc = spans_to_document("rust", source, spans);
+/// assert_eq!(doc.lines.len(), 2);
+/// assert_eq!(doc.lines[1][0].text, "fn");
+/// assert!(doc.lines[1][0].theme_index.is_some());
+/// assert_eq!(doc.lines[1][1].text, " b() {}");
+/// assert_eq!(doc.lines[1][1].theme_index, None);
+/// ```
+pub fn spans_to_document(language: &str, source: &str, spans: Vec<Span>) -> HighlightedDocument {
+    let themed = spans_to_themed(spans);
+
+    // Boundary events, as in the HTML renderer: ends before starts at the same
+    // position, and the most recently opened span is the one in effect.
+    let mut events: Vec<(u32, bool, usize)> = Vec::with_capacity(themed.len() * 2);
+    for (i, span) in themed.iter().enumerate() {
+        events.push((span.start, true, i));
+        events.push((span.end, false, i));
+    }
+    events.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+
+    let mut builder = DocumentBuilder {
+        source,
+        lines: vec![Vec::new()],
+    };
+    let mut last_pos = 0;
+    let mut stack: Vec<usize> = Vec::new();
+
+    for (pos, is_start, span_idx) in events {
+        let pos = (pos as usize).min(source.len());
+        if pos > last_pos {
+            let style = stack.last().map(|&i| themed[i].theme_index);
+            builder.push(last_pos, pos, style);
+            last_pos = pos;
+        }
+
+        if is_start {
+            stack.push(span_idx);
+        } else if let Some(idx) = stack.iter().rposition(|&x| x == span_idx) {
+            stack.remove(idx);
+        }
+    }
+    builder.push(last_pos, source.len(), None);
+
+    HighlightedDocument {
+        language: language.to_string(),
+        lines: builder.lines,
+    }
+}
+
+/// Accumulates styled runs into lines, merging adjacent runs of the same style.
+struct DocumentBuilder<'a> {
+    source: &'a str,
+    lines: Vec<Vec<StyledToken>>,
+}
+
+impl DocumentBuilder<'_> {
+    fn push(&mut self, start: usize, end: usize, theme_index: Option<usize>) {
+        if start >= end
+            || !self.source.is_char_boundary(start)
+            || !self.source.is_char_boundary(end)
+        {
+            return;
+        }
+
+        let mut offset = start;
+        for (i, piece) in self.source[start..end].split('\n').enumerate() {
+            if i > 0 {
+                self.lines.push(Vec::new());
+            }
+            self.push_piece(piece, offset, theme_index);
+            offset += piece.len() + 1;
+        }
+    }
+
+    fn push_piece(&mut self, text: &str, start: usize, theme_index: Option<usize>) {
+        if text.is_empty() {
+            return;
+        }
+        let line = self.lines.last_mut().expect("always at least one line");
+        if let Some(last) = line.last_mut() {
+            if last.theme_index == theme_index {
+                last.text.push_str(text);
+                return;
+            }
+        }
+        line.push(StyledToken {
+            text: text.to_string(),
+            start: start as u32,
+            theme_index,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(start: u32, end: u32, capture: &str) -> Span {
+        Span {
+            start,
+            end,
+            capture: capture.into(),
+        }
+    }
+
+    #[test]
+    fn test_multiline_span_is_split_per_line() {
+        let source = "/* a\nb */ x";
+        let doc = spans_to_document("c", source, vec![span(0, 9, "comment")]);
+
+        assert_eq!(doc.lines.len(), 2);
+        assert_eq!(doc.lines[0].len(), 1);
+        assert_eq!(doc.lines[0][0].text, "/* a");
+        assert_eq!(doc.lines[1][0].text, "b */");
+        assert_eq!(doc.lines[1][0].start, 5);
+        assert_eq!(doc.lines[0][0].theme_index, doc.lines[1][0].theme_index);
+        assert_eq!(doc.lines[1][1].text, " x");
+        assert_eq!(doc.lines[1][1].theme_index, None);
+    }
+
+    #[test]
+    fn test_nested_span_wins() {
+        let source = "\"a{x}b\"";
+        let doc = spans_to_document(
+            "python",
+            source,
+            vec![span(0, 7, "string"), span(3, 4, "variable")],
+        );
+
+        let line = &doc.lines[0];
+        assert_eq!(line.len(), 3);
+        assert_eq!(line[1].text, "x");
+        assert_ne!(line[0].theme_index, line[1].theme_index);
+        assert_eq!(line[0].theme_index, line[2].theme_index);
+    }
+
+    #[test]
+    fn test_empty_lines_and_text() {
+        let doc = spans_to_document("text", "a\n\nb\n", vec![]);
+        let texts: Vec<String> = doc.line_texts().collect();
+        assert_eq!(texts, ["a", "", "b", ""]);
+
+        let doc = spans_to_document("text", "", vec![]);
+        assert_eq!(doc.lines, vec![Vec::new()]);
+    }
+}
@@ -0,0 +1,191 @@
+//! Event-based output compatible with `tree-sitter-highlight`.
+//!
+//! The `tree-sitter-highlight` crate reports highlighting as a flat stream of
+//! [`HighlightEvent`]s: runs of source text, interleaved with the start and end
+//! of each highlight. [`spans_to_events`] produces the same shape from arborium
+//! spans, so renderers written against that crate can switch to arborium's
+//! grammars with little more than an import change.
+
+use crate::Span;
+use crate::render::{ThemedSpan, spans_to_themed};
+
+/// A highlight category, as an index into `HIGHLIGHT_NAMES`.
+///
+/// Mirrors `tree_sitter_highlight::Highlight`. The same index can be passed to
+/// `Theme::style()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Highlight(pub usize);
+
+/// One step of highlighted output, mirroring `tree_sitter_highlight::HighlightEvent`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HighlightEvent {
+    /// A run of source text, as a byte range, styled by every open highlight.
+    Source {
+        /// Byte offset where the run starts (inclusive).
+        start: usize,
+        /// Byte offset where the run ends (exclusive).
+        end: usize,
+    },
+    /// A highlight begins; it applies until the matching [`HighlightEnd`](Self::HighlightEnd).
+    HighlightStart(Highlight),
+    /// The most recently started highlight ends.
+    HighlightEnd,
+}
+
+/// Convert raw spans into a properly nested stream of [`HighlightEvent`]s.
+///
+/// `Source` events cover the whole of `source` in order. Starts and ends are
+/// always balanced; spans that overlap without nesting are split so that the
+/// innermost (most recently started) highlight stays on top.
+///
+/// ```rust
+/// use arborium_highlight::{HighlightEvent, Span, spans_to_events};
+///
+/// let spans = vec![Span { start: 0, end: 2, capture: "keyword".into() }];
+/// let events = spans_to_events("fn main", spans);
+///
+/// assert!(matches!(events[0], HighlightEvent::HighlightStart(_)));
+/// assert_eq!(events[1], HighlightEvent::Source { start: 0, end: 2 });
+/// assert_eq!(events[2], HighlightEvent::HighlightEnd);
+/// assert_eq!(events[3], HighlightEvent::Source { start: 2, end: 7 });
+/// ```
+pub fn spans_to_events(source: &str, spans: Vec<Span>) -> Vec<HighlightEvent> {
+    let themed = spans_to_themed(spans);
+
+    // Boundary events, as in the HTML renderer: ends before starts at the same
+    // position.
+    let mut boundaries: Vec<(usize, bool, usize)> = Vec::with_capacity(themed.len() * 2);
+    for (i, span) in themed.iter().enumerate() {
+        let start = (span.start as usize).min(source.len());
+        let end = (span.end as usize).min(source.len());
+        if start < end {
+            boundaries.push((start, true, i));
+            boundaries.push((end, false, i));
+        }
+    }
+    boundaries.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+
+    let mut events = Vec::with_capacity(boundaries.len() * 2 + 1);
+    // Spans currently in effect, and those for which a start has been emitted
+    let mut active: Vec<usize> = Vec::new();
+    let mut open: Vec<usize> = Vec::new();
+    let mut last_pos = 0;
+
+    for (pos, is_start, span_idx) in boundaries {
+        if pos > last_pos {
+            reconcile(&mut events, &mut open, &active, &themed);
+            events.push(HighlightEvent::Source {
+                start: last_pos,
+                end: pos,
+            });
+            last_pos = pos;
+        }
+
+        if is_start {
+            active.push(span_idx);
+        } else if let Some(idx) = active.iter().rposition(|&x| x == span_idx) {
+            active.remove(idx);
+        }
+    }
+
+    reconcile(&mut events, &mut open, &active, &themed);
+    if last_pos < source.len() {
+        events.push(HighlightEvent::Source {
+            start: last_pos,
+            end: source.len(),
+        });
+    }
+
+    events
+}
+
+/// Emit the ends and starts that turn the `open` stack into `active`.
+fn reconcile(
+    events: &mut Vec<HighlightEvent>,
+    open: &mut Vec<usize>,
+    active: &[usize],
+    themed: &[ThemedSpan],
+) {
+    let common = open.iter().zip(active).take_while(|(a, b)| a == b).count();
+
+    for _ in common..open.len() {
+        events.push(HighlightEvent::HighlightEnd);
+    }
+    open.truncate(common);
+
+    for &span_idx in &active[common..] {
+        events.push(HighlightEvent::HighlightStart(Highlight(
+            themed[span_idx].theme_index,
+        )));
+        open.push(span_idx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(start: u32, end: u32, capture: &str) -> Span {
+        Span {
+            start,
+            end,
+            capture: capture.into(),
+        }
+    }
+
+    /// Rebuild the source from `Source` events and check starts/ends balance.
+    fn check(source: &str, events: &[HighlightEvent]) {
+        let mut text = String::new();
+        let mut depth = 0i32;
+        for event in events {
+            match *event {
+                HighlightEvent::Source { start, end } => text.push_str(&source[start..end]),
+                HighlightEvent::HighlightStart(_) => depth += 1,
+                HighlightEvent::HighlightEnd => {
+                    depth -= 1;
+                    assert!(depth >= 0, "unbalanced end in {events:?}");
+                }
+            }
+        }
+        assert_eq!(depth, 0);
+        assert_eq!(text, source);
+    }
+
+    #[test]
+    fn test_nested_spans() {
+        let source = "\"a{x}b\"";
+        let events = spans_to_events(source, vec![span(0, 7, "string"), span(3, 4, "variable")]);
+        check(source, &events);
+
+        let themed = spans_to_themed(vec![span(0, 7, "string"), span(3, 4, "variable")]);
+        let (string, variable) = (themed[0].theme_index, themed[1].theme_index);
+        assert_eq!(
+            events,
+            vec![
+                HighlightEvent::HighlightStart(Highlight(string)),
+                HighlightEvent::Source { start: 0, end: 3 },
+                HighlightEvent::HighlightStart(Highlight(variable)),
+                HighlightEvent::Source { start: 3, end: 4 },
+                HighlightEvent::HighlightEnd,
+                HighlightEvent::Source { start: 4, end: 7 },
+                HighlightEvent::HighlightEnd,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_overlapping_spans_stay_balanced() {
+        let source = "abcdef";
+        let events = spans_to_events(source, vec![span(0, 4, "keyword"), span(2, 6, "string")]);
+        check(source, &events);
+    }
+
+    #[test]
+    fn test_empty_and_unstyled() {
+        assert!(spans_to_events("", vec![]).is_empty());
+        assert_eq!(
+            spans_to_events("abc", vec![]),
+            vec![HighlightEvent::Source { start: 0, end: 3 }]
+        );
+    }
+}
@@ -96,19 +96,45 @@
 //! - **`CustomElementsWithPrefix(prefix)`**: Custom elements with your prefix, e.g., `<code-k>`
 //! - **`ClassNames`**: Traditional `<span class="keyword">` for compatibility
 //! - **`ClassNamesWithPrefix(prefix)`**: Namespaced classes like `<span class="arb-keyword">`
+//! - **`HljsClasses`**: highlight.js classes like `<span class="hljs-keyword">`
+//! - **`PrismClasses`**: Prism.js classes like `<span class="token keyword">`
+//! - **`PygmentsClasses`**: Pygments classes like `<span class="k">`
 //!
 //! See [`HtmlFormat`] for examples and use cases.
 
+mod diff;
+mod document;
+mod events;
+mod pango;
+mod position;
 mod render;
+mod source_map;
+#[cfg(feature = "anstyle")]
+mod styled;
+mod tokens;
 mod types;
 
 #[cfg(feature = "tree-sitter")]
 pub mod tree_sitter;
 
+pub use diff::{diff_to_html, diff_to_side_by_side_html};
+pub use document::{HighlightedDocument, StyledToken, spans_to_document};
+pub use events::{Highlight, HighlightEvent, spans_to_events};
+pub use pango::spans_to_pango;
+pub use position::{
+    LineIndex, OffsetEncoding, OffsetMap, Position, PositionedSpan, with_positions,
+};
 pub use render::{
-    AnsiOptions, ThemedSpan, html_escape, spans_to_ansi, spans_to_ansi_with_options, spans_to_html,
-    spans_to_themed, write_spans_as_ansi, write_spans_as_html,
+    AnsiLine, AnsiOptions, ColorDepth, EscapeFn, HtmlEscape, HtmlOptions, Overflow, ThemedSpan,
+    html_escape, render_block, spans_to_ansi, spans_to_ansi_lines, spans_to_ansi_with_options,
+    spans_to_ansi_with_source_map, spans_to_html, spans_to_html_with_options,
+    spans_to_html_with_source_map, spans_to_themed, write_spans_as_ansi,
+    write_spans_as_ansi_with_options, write_spans_as_html, write_spans_as_html_with_options,
 };
+pub use source_map::{SourceMap, SourceMapping};
+#[cfg(feature = "anstyle")]
+pub use styled::{spans_to_anstyle, to_anstyle};
+pub use tokens::{Token, spans_to_tokens};
 pub use types::{HighlightError, Injection, ParseResult, Span};
 
 #[cfg(feature = "tree-sitter")]
@@ -185,7 +211,15 @@ pub trait GrammarProvider {
 }
 
 /// HTML output format for syntax highlighting.
+///
+/// With the `serde` feature, variants are written in kebab-case, e.g.
+/// `"class-names"` or `{ "class-names-with-prefix": "arb" }`.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
 pub enum HtmlFormat {
     /// Custom elements with default prefix: `<a-k>`, `<a-f>`, etc. (default)
     ///
@@ -220,7 +254,10 @@ pub enum HtmlFormat {
 
     /// Class-based spans with custom prefix: `<span class="prefix-keyword">`, etc.
     ///
-    /// Useful for namespacing CSS classes.
+    /// Useful for namespacing CSS classes or matching an existing naming
+    /// convention. A `-` is inserted after the prefix unless it already ends
+    /// in `-` or `_`, so `"hl-"` and `"hl"` both produce `hl-keyword`, while
+    /// `"tok_"` produces `tok_keyword`.
     ///
     /// # Example
     /// ```html
@@ -228,6 +265,42 @@ pub enum HtmlFormat {
     /// <span class="arb-keyword">fn</span> <span class="arb-function">main</span>()
     /// ```
     ClassNamesWithPrefix(String),
+
+    /// highlight.js class names: `<span class="hljs-keyword">`, etc.
+    ///
+    /// Lets arborium output reuse existing highlight.js CSS themes unchanged.
+    /// Slots without a highlight.js equivalent (e.g. strikethrough) are
+    /// emitted as plain text.
+    ///
+    /// # Example
+    /// ```html
+    /// <span class="hljs-keyword">fn</span> <span class="hljs-title function_">main</span>()
+    /// ```
+    HljsClasses,
+
+    /// Prism.js token classes: `<span class="token keyword">`, etc.
+    ///
+    /// Lets server-rendered arborium output be styled by existing Prism theme
+    /// CSS. Slots without a Prism equivalent (e.g. errors) are emitted as
+    /// plain text.
+    ///
+    /// # Example
+    /// ```html
+    /// <span class="token keyword">fn</span> <span class="token function">main</span>()
+    /// ```
+    PrismClasses,
+
+    /// Pygments short classes: `<span class="k">`, `<span class="nf">`, etc.
+    ///
+    /// Lets arborium output be styled by the Pygments stylesheets that Sphinx,
+    /// Jekyll and friends already ship. Slots without a Pygments token type
+    /// (e.g. links) are emitted as plain text.
+    ///
+    /// # Example
+    /// ```html
+    /// <span class="k">fn</span> <span class="nf">main</span>()
+    /// ```
+    PygmentsClasses,
 }
 
 impl Default for HtmlFormat {
@@ -587,6 +660,7 @@ mod tests {
                             capture: "keyword".into(),
                         }],
                         injections: vec![],
+                        errors: vec![],
                     },
                 },
             )]
@@ -613,6 +687,7 @@ mod tests {
                                 language: "inner".into(),
                                 include_children: false,
                             }],
+                            errors: vec![],
                         },
                     },
                 ),
@@ -626,6 +701,7 @@ mod tests {
                                 capture: "string".into(),
                             }],
                             injections: vec![],
+                            errors: vec![],
                         },
                     },
                 ),
@@ -668,6 +744,7 @@ mod tests {
                             capture: "keyword".into(),
                         }],
                         injections: vec![],
+                        errors: vec![],
                     },
                 },
             )]
@@ -0,0 +1,121 @@
+//! Pango markup output for GTK applications.
+//!
+//! [`spans_to_pango`] produces markup that can be passed straight to
+//! `gtk::Label::set_markup` or `gtk::TextBuffer::insert_markup`, with the
+//! theme's colors and modifiers as `<span>` attributes.
+
+use arborium_theme::Theme;
+
+use crate::Span;
+use crate::document::spans_to_document;
+use crate::render::html_escape;
+
+/// Render spans as Pango markup using colors from `theme`.
+///
+/// Overlapping spans resolve as in the HTML renderer (innermost wins), and
+/// spans never cross a newline. Only per-token styles are emitted; set the
+/// theme's background and foreground on the widget itself.
+///
+/// ```rust
+/// use arborium_highlight::{Span, spans_to_pango};
+/// use arborium_theme::{Color, Style, Theme, slot_to_highlight_index, ThemeSlot};
+///
+/// let mut theme = Theme::new("example");
+/// let keyword = slot_to_highlight_index(ThemeSlot::Keyword).unwrap();
+/// theme.set_style(keyword, Style::new().fg(Color::new(0xc6, 0x78, 0xdd)).bold());
+///
+/// let spans = vec![Span { start: 0, end: 2, capture: "keyword".into() }];
+/// assert_eq!(
+///     spans_to_pango("fn a<b>", spans, &theme),
+///     "<span foreground=\"#c678dd\" weight=\"bold\">fn</span> a&lt;b&gt;"
+/// );
+/// ```
+pub fn spans_to_pango(source: &str, spans: Vec<Span>, theme: &Theme) -> String {
+    let document = spans_to_document("", source, spans);
+
+    let mut markup = String::with_capacity(source.len() * 2);
+    for (i, line) in document.lines.iter().enumerate() {
+        if i > 0 {
+            markup.push('\n');
+        }
+        for token in line {
+            let attributes = token
+                .theme_index
+                .map(|index| pango_attributes(theme, index))
+                .unwrap_or_default();
+            if attributes.is_empty() {
+                markup.push_str(&html_escape(&token.text));
+            } else {
+                markup.push_str("<span");
+                markup.push_str(&attributes);
+                markup.push('>');
+                markup.push_str(&html_escape(&token.text));
+                markup.push_str("</span>");
+            }
+        }
+    }
+    markup
+}
+
+/// The Pango `<span>` attributes for a theme style, each with a leading space.
+fn pango_attributes(theme: &Theme, index: usize) -> String {
+    let Some(style) = theme.style(index) else {
+        return String::new();
+    };
+
+    let mut attributes = String::new();
+    if let Some(fg) = &style.fg {
+        attributes.push_str(&format!(" foreground=\"{}\"", fg.to_hex()));
+    }
+    if let Some(bg) = &style.bg {
+        attributes.push_str(&format!(" background=\"{}\"", bg.to_hex()));
+    }
+    if style.modifiers.bold {
+        attributes.push_str(" weight=\"bold\"");
+    }
+    if style.modifiers.italic {
+        attributes.push_str(" style=\"italic\"");
+    }
+    if style.modifiers.underline {
+        attributes.push_str(" underline=\"single\"");
+    }
+    if style.modifiers.strikethrough {
+        attributes.push_str(" strikethrough=\"true\"");
+    }
+    attributes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arborium_theme::{Color, Style, ThemeSlot, slot_to_highlight_index};
+
+    #[test]
+    fn test_multiline_span_and_unstyled_slot() {
+        let mut theme = Theme::new("test");
+        let comment = slot_to_highlight_index(ThemeSlot::Comment).unwrap();
+        theme.set_style(
+            comment,
+            Style::new().fg(Color::new(0x5c, 0x63, 0x70)).italic(),
+        );
+
+        let spans = vec![
+            Span {
+                start: 0,
+                end: 9,
+                capture: "comment".into(),
+            },
+            // Keyword has no style in this theme
+            Span {
+                start: 10,
+                end: 12,
+                capture: "keyword".into(),
+            },
+        ];
+        assert_eq!(
+            spans_to_pango("/* a\nb */ fn", spans, &theme),
+            "<span foreground=\"#5c6370\" style=\"italic\">/* a</span>\n\
+             <span foreground=\"#5c6370\" style=\"italic\">b */</span> fn"
+        );
+    }
+}
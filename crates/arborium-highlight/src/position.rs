@@ -0,0 +1,344 @@
+//! Conversion of span byte offsets to other position encodings.
+//!
+//! [`Span`] offsets are UTF-8 byte offsets into the source. Editors and protocols
+//! often count differently: LSP and JavaScript (CodeMirror, Monaco) use UTF-16
+//! code units, and some tools count Unicode scalar values (`char`s).
+//! [`OffsetMap`] converts between them after a single pass over the source, and
+//! [`LineIndex`] / [`with_positions`] add line and column numbers.
+
+use std::ops::Range;
+
+use crate::types::Span;
+
+/// Unit in which a text offset is counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum OffsetEncoding {
+    /// UTF-8 bytes, as stored in [`Span`] (default).
+    #[default]
+    Utf8,
+
+    /// UTF-16 code units, as used by LSP and JavaScript strings.
+    Utf16,
+
+    /// Unicode scalar values, as yielded by [`str::chars`].
+    Char,
+}
+
+/// Converts byte offsets in one source string to UTF-16 or `char` offsets.
+///
+/// Building the map is `O(n)` in the source length; each conversion is a
+/// binary search over the non-ASCII characters, so pure-ASCII sources cost
+/// almost nothing.
+///
+/// # Example
+///
+/// ```rust
+/// use arborium_highlight::{OffsetEncoding, OffsetMap};
+///
+/// let source = "é = \"😀\"";
+/// let map = OffsetMap::new(source);
+///
+/// // '😀' starts at byte 6, UTF-16 unit 5, char 5
+/// assert_eq!(map.convert(6, OffsetEncoding::Utf16), 5);
+/// assert_eq!(map.convert(6, OffsetEncoding::Char), 5);
+///
+/// // The closing quote follows a 4-byte, 2-unit, 1-char emoji
+/// assert_eq!(map.convert(10, OffsetEncoding::Utf16), 7);
+/// assert_eq!(map.convert(10, OffsetEncoding::Char), 6);
+/// ```
+#[derive(Debug, Clone)]
+pub struct OffsetMap {
+    /// Offsets just past each non-ASCII character, sorted by byte offset.
+    checkpoints: Vec<Checkpoint>,
+    /// Source length in bytes.
+    len: u32,
+}
+
+/// Equivalent offsets in each encoding just past a non-ASCII character.
+#[derive(Debug, Clone, Copy, Default)]
+struct Checkpoint {
+    /// Byte offset where the character starts.
+    start: u32,
+    byte: u32,
+    utf16: u32,
+    char: u32,
+}
+
+impl OffsetMap {
+    /// Build an offset map for `source`.
+    pub fn new(source: &str) -> Self {
+        let mut checkpoints = Vec::new();
+        let mut utf16 = 0;
+
+        for (i, (byte, c)) in source.char_indices().enumerate() {
+            utf16 += c.len_utf16() as u32;
+            if !c.is_ascii() {
+                checkpoints.push(Checkpoint {
+                    start: byte as u32,
+                    byte: (byte + c.len_utf8()) as u32,
+                    utf16,
+                    char: i as u32 + 1,
+                });
+            }
+        }
+
+        Self {
+            checkpoints,
+            len: source.len() as u32,
+        }
+    }
+
+    /// Convert a byte offset to the given encoding.
+    ///
+    /// Offsets past the end of the source are clamped to its length. For
+    /// UTF-16 and `char` encodings, an offset inside a multi-byte character
+    /// maps to the start of that character.
+    pub fn convert(&self, byte_offset: u32, encoding: OffsetEncoding) -> u32 {
+        let byte = byte_offset.min(self.len);
+
+        // Last checkpoint at or before `byte`; everything after it is ASCII
+        // up to the next non-ASCII character.
+        let idx = self.checkpoints.partition_point(|cp| cp.byte <= byte);
+        let base = match idx.checked_sub(1) {
+            Some(i) => self.checkpoints[i],
+            None => Checkpoint::default(),
+        };
+
+        // An offset inside the next non-ASCII character maps to its start
+        let next_start = self.checkpoints.get(idx).map_or(self.len, |cp| cp.start);
+        let ascii_run = byte.min(next_start) - base.byte;
+
+        match encoding {
+            OffsetEncoding::Utf8 => byte,
+            OffsetEncoding::Utf16 => base.utf16 + ascii_run,
+            OffsetEncoding::Char => base.char + ascii_run,
+        }
+    }
+
+    /// Convert a byte offset to UTF-16 code units.
+    pub fn to_utf16(&self, byte_offset: u32) -> u32 {
+        self.convert(byte_offset, OffsetEncoding::Utf16)
+    }
+
+    /// Convert a byte offset to a `char` index.
+    pub fn to_char(&self, byte_offset: u32) -> u32 {
+        self.convert(byte_offset, OffsetEncoding::Char)
+    }
+
+    /// Convert a span's byte range to the given encoding.
+    pub fn span_range(&self, span: &Span, encoding: OffsetEncoding) -> Range<u32> {
+        self.convert(span.start, encoding)..self.convert(span.end, encoding)
+    }
+}
+
+/// A zero-based line and column in a source string.
+///
+/// The column unit depends on the [`OffsetEncoding`] it was computed with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    /// Zero-based line number.
+    pub line: u32,
+    /// Zero-based column within the line.
+    pub column: u32,
+}
+
+/// A [`Span`] with line/column positions for both ends.
+///
+/// Produced by [`with_positions`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PositionedSpan {
+    /// The original span, with byte offsets.
+    pub span: Span,
+    /// Position of `span.start`.
+    pub start: Position,
+    /// Position of `span.end`.
+    pub end: Position,
+}
+
+/// Maps byte offsets in one source string to line/column [`Position`]s.
+///
+/// Lines are separated by `\n`; a preceding `\r` counts as part of the line.
+///
+/// # Example
+///
+/// ```rust
+/// use arborium_highlight::{LineIndex, OffsetEncoding, Position};
+///
+/// let index = LineIndex::new("fn main() {\n    42\n}");
+/// assert_eq!(
+///     index.position(16, OffsetEncoding::Utf8),
+///     Position { line: 1, column: 4 }
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct LineIndex {
+    /// Byte offset of the start of each line.
+    line_starts: Vec<u32>,
+    offsets: OffsetMap,
+}
+
+impl LineIndex {
+    /// Build a line index for `source`.
+    pub fn new(source: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i as u32 + 1))
+            .collect();
+
+        Self {
+            line_starts,
+            offsets: OffsetMap::new(source),
+        }
+    }
+
+    /// Number of lines in the source (at least 1).
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Convert a byte offset to a line/column position.
+    ///
+    /// The column is counted in `encoding` units from the start of the line.
+    /// Offsets past the end of the source are clamped to its length.
+    pub fn position(&self, byte_offset: u32, encoding: OffsetEncoding) -> Position {
+        let byte = byte_offset.min(self.offsets.len);
+        let line = self.line_starts.partition_point(|&start| start <= byte) - 1;
+        let line_start = self.line_starts[line];
+        let column =
+            self.offsets.convert(byte, encoding) - self.offsets.convert(line_start, encoding);
+
+        Position {
+            line: line as u32,
+            column,
+        }
+    }
+
+    /// The offset converter this index uses for columns.
+    pub fn offsets(&self) -> &OffsetMap {
+        &self.offsets
+    }
+}
+
+/// Attach line/column positions to spans.
+///
+/// Columns are counted in `encoding` units. This is a post-processing step for
+/// consumers that need positions, such as editors and diagnostics; the index is
+/// built once and shared across all spans.
+///
+/// ```rust
+/// use arborium_highlight::{OffsetEncoding, Position, Span, with_positions};
+///
+/// let source = "let a;\nlet b;";
+/// let spans = vec![Span { start: 7, end: 10, capture: "keyword".into() }];
+///
+/// let positioned = with_positions(source, spans, OffsetEncoding::Utf16);
+/// assert_eq!(positioned[0].start, Position { line: 1, column: 0 });
+/// assert_eq!(positioned[0].end, Position { line: 1, column: 3 });
+/// ```
+pub fn with_positions(
+    source: &str,
+    spans: Vec<Span>,
+    encoding: OffsetEncoding,
+) -> Vec<PositionedSpan> {
+    let index = LineIndex::new(source);
+    spans
+        .into_iter()
+        .map(|span| PositionedSpan {
+            start: index.position(span.start, encoding),
+            end: index.position(span.end, encoding),
+            span,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reference implementation: count by iterating the prefix.
+    fn naive(source: &str, byte: usize, encoding: OffsetEncoding) -> u32 {
+        let mut byte = byte.min(source.len());
+        if encoding == OffsetEncoding::Utf8 {
+            return byte as u32;
+        }
+        while !source.is_char_boundary(byte) {
+            byte -= 1;
+        }
+        let prefix = &source[..byte];
+        match encoding {
+            OffsetEncoding::Utf8 => unreachable!(),
+            OffsetEncoding::Utf16 => prefix.encode_utf16().count() as u32,
+            OffsetEncoding::Char => prefix.chars().count() as u32,
+        }
+    }
+
+    #[test]
+    fn test_matches_naive_conversion() {
+        for source in ["", "fn main() {}", "é", "aé😀b\n日本語 x", "😀😀"] {
+            let map = OffsetMap::new(source);
+            for byte in 0..=source.len() + 2 {
+                for encoding in [
+                    OffsetEncoding::Utf8,
+                    OffsetEncoding::Utf16,
+                    OffsetEncoding::Char,
+                ] {
+                    assert_eq!(
+                        map.convert(byte as u32, encoding),
+                        naive(source, byte, encoding),
+                        "{source:?} @ {byte} as {encoding:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_span_range() {
+        let source = "// ü\nlet";
+        let map = OffsetMap::new(source);
+        let span = Span {
+            start: 6,
+            end: 9,
+            capture: "keyword".into(),
+        };
+        assert_eq!(map.span_range(&span, OffsetEncoding::Utf16), 5..8);
+    }
+
+    #[test]
+    fn test_line_index_positions() {
+        let source = "a\r\nü😀b\n\nc";
+        let index = LineIndex::new(source);
+        assert_eq!(index.line_count(), 4);
+
+        let pos = |byte, encoding| index.position(byte, encoding);
+        assert_eq!(
+            pos(0, OffsetEncoding::Utf8),
+            Position { line: 0, column: 0 }
+        );
+        // 'b' after a 2-byte and a 4-byte character
+        assert_eq!(
+            pos(9, OffsetEncoding::Utf8),
+            Position { line: 1, column: 6 }
+        );
+        assert_eq!(
+            pos(9, OffsetEncoding::Utf16),
+            Position { line: 1, column: 3 }
+        );
+        assert_eq!(
+            pos(9, OffsetEncoding::Char),
+            Position { line: 1, column: 2 }
+        );
+        // Empty line, then last line
+        assert_eq!(
+            pos(11, OffsetEncoding::Char),
+            Position { line: 2, column: 0 }
+        );
+        assert_eq!(
+            pos(13, OffsetEncoding::Char),
+            Position { line: 3, column: 1 }
+        );
+        assert_eq!(
+            pos(99, OffsetEncoding::Char),
+            Position { line: 3, column: 1 }
+        );
+    }
+}
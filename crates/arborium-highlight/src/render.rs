@@ -11,12 +11,20 @@
 //! - `keyword` at bytes 5-8
 //!
 //! Both map to the "keyword" slot (`k` tag), so they become a single `<a-k>` element.
+//!
+//! # Determinism
+//!
+//! Rendering is deterministic: the same source and spans always produce
+//! byte-identical HTML, ANSI, and themed output, regardless of process or
+//! platform. Spans are deduplicated through an ordered map rather than a hash
+//! map, so golden-file tests and content-addressed caches can rely on it.
 
-use crate::{HtmlFormat, Span};
+use crate::{HtmlFormat, LineIndex, OffsetEncoding, SourceMap, Span};
 use arborium_theme::{
-    Theme, capture_to_slot, slot_to_highlight_index, tag_for_capture, tag_to_name,
+    Color, Theme, capture_to_slot, slot_to_highlight_index, tag_for_capture, tag_to_name,
 };
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 use std::io::{self, Write};
 
 /// A span with a theme style index for rendering.
@@ -47,7 +55,7 @@ pub fn spans_to_themed(spans: Vec<Span>) -> Vec<ThemedSpan> {
     spans.sort_by(|a, b| a.start.cmp(&b.start).then_with(|| b.end.cmp(&a.end)));
 
     // Deduplicate ranges - prefer spans that map to a theme slot
-    let mut deduped: HashMap<(u32, u32), Span> = HashMap::new();
+    let mut deduped: BTreeMap<(u32, u32), Span> = BTreeMap::new();
     for span in spans {
         let key = (span.start, span.end);
         let new_has_slot = slot_to_highlight_index(capture_to_slot(&span.capture)).is_some();
@@ -89,7 +97,7 @@ use unicode_width::UnicodeWidthChar;
 /// Generate opening and closing HTML tags based on the configured format.
 ///
 /// Returns (opening_tag, closing_tag) for the given short tag and format.
-fn make_html_tags(short_tag: &str, format: &HtmlFormat) -> (String, String) {
+pub(crate) fn make_html_tags(short_tag: &str, format: &HtmlFormat) -> (String, String) {
     match format {
         HtmlFormat::CustomElements => {
             let open = format!("<a-{short_tag}>");
@@ -113,7 +121,13 @@ fn make_html_tags(short_tag: &str, format: &HtmlFormat) -> (String, String) {
         }
         HtmlFormat::ClassNamesWithPrefix(prefix) => {
             if let Some(name) = tag_to_name(short_tag) {
-                let open = format!("<span class=\"{prefix}-{name}\">");
+                // A prefix that already ends in a separator ("hl-", "tok_") is used as-is
+                let sep = if prefix.ends_with(['-', '_']) {
+                    ""
+                } else {
+                    "-"
+                };
+                let open = format!("<span class=\"{prefix}{sep}{name}\">");
                 let close = "</span>".to_string();
                 (open, close)
             } else {
@@ -121,19 +135,144 @@ fn make_html_tags(short_tag: &str, format: &HtmlFormat) -> (String, String) {
                 ("<span>".to_string(), "</span>".to_string())
             }
         }
+        HtmlFormat::HljsClasses => match hljs_class(short_tag) {
+            Some(class) => (format!("<span class=\"{class}\">"), "</span>".to_string()),
+            None => (String::new(), String::new()),
+        },
+        HtmlFormat::PygmentsClasses => match pygments_class(short_tag) {
+            Some(class) => (format!("<span class=\"{class}\">"), "</span>".to_string()),
+            None => (String::new(), String::new()),
+        },
+        HtmlFormat::PrismClasses => match prism_class(short_tag) {
+            Some(class) => (
+                format!("<span class=\"token {class}\">"),
+                "</span>".to_string(),
+            ),
+            None => (String::new(), String::new()),
+        },
     }
 }
 
+/// The highlight.js class for a theme slot tag, if it has one.
+///
+/// Follows the highlight.js 11 class reference, including its compound
+/// `title function_` / `title class_` scopes.
+fn hljs_class(short_tag: &str) -> Option<&'static str> {
+    Some(match short_tag {
+        "k" => "hljs-keyword",
+        "f" => "hljs-title function_",
+        "s" => "hljs-string",
+        "c" => "hljs-comment",
+        "t" => "hljs-type",
+        "v" => "hljs-variable",
+        "co" => "hljs-variable constant_",
+        "n" => "hljs-number",
+        "o" => "hljs-operator",
+        "p" => "hljs-punctuation",
+        "pr" => "hljs-property",
+        "at" => "hljs-attr",
+        "tg" => "hljs-name",
+        "m" => "hljs-meta",
+        "l" => "hljs-symbol",
+        "ns" => "hljs-title class_",
+        "cr" => "hljs-title class_",
+        "tt" => "hljs-section",
+        "st" => "hljs-strong",
+        "em" => "hljs-emphasis",
+        "tu" => "hljs-link",
+        "tl" => "hljs-code",
+        "da" => "hljs-addition",
+        "dd" => "hljs-deletion",
+        "eb" => "hljs-subst",
+        _ => return None,
+    })
+}
+
+/// The Prism.js token type for a theme slot tag, if it has one.
+///
+/// Uses the token names shared by Prism's bundled themes; the `token` class is
+/// added by the caller.
+fn prism_class(short_tag: &str) -> Option<&'static str> {
+    Some(match short_tag {
+        "k" => "keyword",
+        "f" => "function",
+        "s" => "string",
+        "c" => "comment",
+        "t" => "class-name",
+        "v" => "variable",
+        "co" => "constant",
+        "n" => "number",
+        "o" => "operator",
+        "p" => "punctuation",
+        "pr" => "property",
+        "at" => "attr-name",
+        "tg" => "tag",
+        "m" => "macro",
+        "l" => "symbol",
+        "ns" => "namespace",
+        "cr" => "class-name",
+        "tt" => "title",
+        "st" => "bold",
+        "em" => "italic",
+        "tu" => "url",
+        "tl" => "code",
+        "tx" => "strike",
+        "da" => "inserted",
+        "dd" => "deleted",
+        "eb" => "interpolation",
+        _ => return None,
+    })
+}
+
+/// The Pygments short class for a theme slot tag, if it has one.
+///
+/// These are the abbreviated token types used in Pygments' generated CSS
+/// (`Name.Function` is `nf`, `Generic.Inserted` is `gi`, ...).
+fn pygments_class(short_tag: &str) -> Option<&'static str> {
+    Some(match short_tag {
+        "k" => "k",
+        "f" => "nf",
+        "s" => "s",
+        "c" => "c",
+        "t" => "kt",
+        "v" => "nv",
+        "co" => "no",
+        "n" => "m",
+        "o" => "o",
+        "p" => "p",
+        "pr" => "py",
+        "at" => "na",
+        "tg" => "nt",
+        "m" => "cp",
+        "l" => "nl",
+        "ns" => "nn",
+        "cr" => "nc",
+        "tt" => "gh",
+        "st" => "gs",
+        "em" => "ge",
+        "tl" => "sb",
+        "da" => "gi",
+        "dd" => "gd",
+        "eb" => "si",
+        "er" => "err",
+        _ => return None,
+    })
+}
+
 /// A normalized span with theme slot tag.
 #[derive(Debug, Clone)]
 struct NormalizedSpan {
     start: u32,
     end: u32,
     tag: &'static str,
+    /// The original capture name (of the first span, if coalesced).
+    capture: String,
 }
 
 /// Normalize spans: map captures to theme slots and merge adjacent spans with same tag.
-fn normalize_and_coalesce(spans: Vec<Span>) -> Vec<NormalizedSpan> {
+///
+/// With `keep_captures`, spans are only merged if their captures match too.
+fn normalize_and_coalesce(spans: Vec<Span>, keep_captures: bool) -> Vec<NormalizedSpan> {
     if spans.is_empty() {
         return vec![];
     }
@@ -146,6 +285,7 @@ fn normalize_and_coalesce(spans: Vec<Span>) -> Vec<NormalizedSpan> {
                 start: span.start,
                 end: span.end,
                 tag,
+                capture: span.capture,
             })
         })
         .collect();
@@ -163,7 +303,10 @@ fn normalize_and_coalesce(spans: Vec<Span>) -> Vec<NormalizedSpan> {
     for span in normalized {
         if let Some(last) = coalesced.last_mut() {
             // If this span is adjacent (or overlapping) and has the same tag, merge
-            if span.tag == last.tag && span.start <= last.end {
+            if span.tag == last.tag
+                && span.start <= last.end
+                && (!keep_captures || span.capture == last.capture)
+            {
                 // Extend the last span to cover this one
                 last.end = last.end.max(span.end);
                 continue;
@@ -175,6 +318,184 @@ fn normalize_and_coalesce(spans: Vec<Span>) -> Vec<NormalizedSpan> {
     coalesced
 }
 
+/// Options controlling HTML rendering beyond the [`HtmlFormat`].
+///
+/// The defaults produce bare highlighted markup, exactly like [`spans_to_html`].
+///
+/// With the `serde` feature, missing fields take their [`Default`] values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct HtmlOptions {
+    /// If true, start each line with a `<span class="line-number">N</span>`
+    /// gutter cell.
+    ///
+    /// A trailing newline does not get a number of its own.
+    pub line_numbers: bool,
+    /// The number of the first line, for excerpts of a larger file.
+    pub line_number_start: usize,
+    /// If set, wrap each line in `<{element} class="line">…</{element}>`,
+    /// e.g. `Some("span".into())`, so CSS can style whole lines.
+    ///
+    /// The newline between lines stays outside the wrapper; the line-number
+    /// gutter is inside it.
+    pub line_wrapper: Option<String>,
+    /// Line numbers to emphasize, e.g. to call out changed lines.
+    ///
+    /// Numbers count from [`line_number_start`](Self::line_number_start).
+    /// Each listed line gets the classes `line highlighted`; if no
+    /// [`line_wrapper`](Self::line_wrapper) is set, lines are wrapped in
+    /// `<span>`s so there is an element to carry the class.
+    pub highlighted_lines: Vec<usize>,
+    /// If set, give each line an `id` of this prefix followed by its number,
+    /// e.g. `Some("L".into())` for `#L42`-style deep links.
+    ///
+    /// Like [`highlighted_lines`](Self::highlighted_lines), this wraps lines
+    /// in `<span>`s if no [`line_wrapper`](Self::line_wrapper) is set. Use
+    /// distinct prefixes for several code blocks on one page.
+    pub line_anchor_prefix: Option<String>,
+    /// If true, add the full capture name to every highlighted element, e.g.
+    /// `<a-k data-capture="keyword.function">`.
+    ///
+    /// Keeps the specificity that mapping captures to theme slots collapses,
+    /// for inspectors and debugging. Adjacent spans are then only merged if
+    /// their captures match.
+    pub capture_attributes: bool,
+    /// If true, emit properly nested elements for overlapping spans, e.g. a
+    /// variable inside a string, instead of only the innermost one.
+    ///
+    /// Spans that overlap without nesting are split at the boundary, so the
+    /// output is always well-formed. Lets CSS style both an injected region
+    /// and the constructs inside it.
+    pub nested: bool,
+    /// How source text is escaped.
+    ///
+    /// Attribute values such as line anchors are always fully escaped.
+    pub escape: HtmlEscape,
+    /// If true, lay the code out as a `<table class="code">` with one
+    /// `<tr class="line">` per line, like GitLab and Gitea do.
+    ///
+    /// Each row has a gutter cell and a code cell:
+    /// `<td class="line-number" data-line-number="N"></td><td class="line-code">…</td>`.
+    /// The gutter cell is empty so that copying a selection never includes
+    /// line numbers; show them with CSS such as
+    /// `td.line-number::before { content: attr(data-line-number) }`. Rows
+    /// carry the [`highlighted_lines`](Self::highlighted_lines) class and
+    /// [`line_anchor_prefix`](Self::line_anchor_prefix) `id`, and
+    /// [`line_numbers`](Self::line_numbers) and
+    /// [`line_wrapper`](Self::line_wrapper) are ignored.
+    pub table: bool,
+}
+
+impl Default for HtmlOptions {
+    fn default() -> Self {
+        Self {
+            line_numbers: false,
+            line_number_start: 1,
+            line_wrapper: None,
+            highlighted_lines: Vec::new(),
+            line_anchor_prefix: None,
+            capture_attributes: false,
+            nested: false,
+            escape: HtmlEscape::default(),
+            table: false,
+        }
+    }
+}
+
+/// How [`HtmlOptions`] escapes source text.
+///
+/// `<`, `>` and `&` are always escaped, unless a [`custom`](Self::custom)
+/// function says otherwise. The defaults match [`html_escape`].
+///
+/// ```rust
+/// use arborium_highlight::HtmlEscape;
+///
+/// let escape = HtmlEscape {
+///     quotes: false,
+///     non_ascii: true,
+///     ..HtmlEscape::default()
+/// };
+/// assert_eq!(escape.escape("\"é\" < 1"), "\"&#xE9;\" &lt; 1");
+/// ```
+#[derive(Debug, Clone, Copy, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct HtmlEscape {
+    /// If true (the default), escape `"` and `'`.
+    ///
+    /// Quotes only need escaping inside attribute values, so turning this off
+    /// is safe for highlighted text and makes quote-heavy sources smaller.
+    pub quotes: bool,
+    /// If true, write every non-ASCII character as a numeric character
+    /// reference such as `&#xE9;`, for output that must stay ASCII.
+    pub non_ascii: bool,
+    /// A function consulted before the rules above, returning the
+    /// replacement for a character or `None` to apply the rules.
+    ///
+    /// Skipped by serde.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub custom: Option<EscapeFn>,
+}
+
+/// A caller-provided escape function; see [`HtmlEscape::custom`].
+pub type EscapeFn = fn(char) -> Option<Cow<'static, str>>;
+
+impl PartialEq for HtmlEscape {
+    fn eq(&self, other: &Self) -> bool {
+        self.quotes == other.quotes
+            && self.non_ascii == other.non_ascii
+            && match (self.custom, other.custom) {
+                (Some(a), Some(b)) => std::ptr::fn_addr_eq(a, b),
+                (a, b) => a.is_none() && b.is_none(),
+            }
+    }
+}
+
+impl Default for HtmlEscape {
+    fn default() -> Self {
+        Self {
+            quotes: true,
+            non_ascii: false,
+            custom: None,
+        }
+    }
+}
+
+impl HtmlEscape {
+    /// Escape `text` according to this policy.
+    pub fn escape(&self, text: &str) -> String {
+        let mut result = String::with_capacity(text.len());
+        for c in text.chars() {
+            match self.escape_char(c) {
+                Some(escaped) => result.push_str(&escaped),
+                None => result.push(c),
+            }
+        }
+        result
+    }
+
+    /// The replacement for `c`, or `None` if it is written as is.
+    pub fn escape_char(&self, c: char) -> Option<Cow<'static, str>> {
+        if let Some(custom) = self.custom {
+            if let Some(escaped) = custom(c) {
+                return Some(escaped);
+            }
+        }
+        match c {
+            '"' | '\'' if !self.quotes => None,
+            c if !c.is_ascii() && self.non_ascii => Some(Cow::Owned(format!("&#x{:X};", c as u32))),
+            c => html_entity(c).map(Cow::Borrowed),
+        }
+    }
+}
+
 /// Deduplicate spans and convert to HTML.
 ///
 /// This handles:
@@ -184,8 +505,124 @@ fn normalize_and_coalesce(spans: Vec<Span>) -> Vec<NormalizedSpan> {
 ///
 /// The `format` parameter controls the HTML output style.
 pub fn spans_to_html(source: &str, spans: Vec<Span>, format: &HtmlFormat) -> String {
+    spans_to_html_with_options(source, spans, format, &HtmlOptions::default())
+}
+
+/// Convert spans to HTML, with line-level [`HtmlOptions`].
+///
+/// ```rust
+/// use arborium_highlight::{HtmlFormat, HtmlOptions, Span, spans_to_html_with_options};
+///
+/// let spans = vec![Span { start: 0, end: 2, capture: "keyword".into() }];
+/// let options = HtmlOptions {
+///     line_numbers: true,
+///     line_number_start: 10,
+///     ..Default::default()
+/// };
+/// let html = spans_to_html_with_options("fn a\nb", spans, &HtmlFormat::default(), &options);
+///
+/// assert_eq!(
+///     html,
+///     "<span class=\"line-number\">10</span><a-k>fn</a-k> a\n\
+///      <span class=\"line-number\">11</span>b"
+/// );
+/// ```
+pub fn spans_to_html_with_options(
+    source: &str,
+    spans: Vec<Span>,
+    format: &HtmlFormat,
+    options: &HtmlOptions,
+) -> String {
+    let html = String::with_capacity(source.len() * 2);
+    render_html(html, source, spans, format, options, None)
+}
+
+/// Convert spans to HTML, also returning a [`SourceMap`] from the HTML back
+/// to the source.
+///
+/// The HTML is identical to [`spans_to_html_with_options`]'s. Each run of
+/// source text is mapped, including newlines; each escaped character gets a
+/// mapping of its own, so offsets inside an entity resolve to the character.
+pub fn spans_to_html_with_source_map(
+    source: &str,
+    spans: Vec<Span>,
+    format: &HtmlFormat,
+    options: &HtmlOptions,
+) -> (String, SourceMap) {
+    let mut map = SourceMap::default();
+    let html = String::with_capacity(source.len() * 2);
+    let html = render_html(html, source, spans, format, options, Some(&mut map));
+    (html, map)
+}
+
+/// Render spans as HTML into `output`, returning it.
+///
+/// If `map` is given, source text written to the output is recorded in it.
+fn render_html<O: RenderOutput>(
+    output: O,
+    source: &str,
+    spans: Vec<Span>,
+    format: &HtmlFormat,
+    options: &HtmlOptions,
+    map: Option<&mut SourceMap>,
+) -> O {
+    let spans = prepare_html_spans(spans, options.capture_attributes);
+    let mut out = HtmlWriter::new(output, &spans, format, options, map);
+    if spans.is_empty() {
+        out.push_text(source, &[]);
+        return out.finish();
+    }
+
+    // Build events from spans
+    let mut events: Vec<(u32, bool, usize)> = Vec::new(); // (pos, is_start, span_index)
+    for (i, span) in spans.iter().enumerate() {
+        events.push((span.start, true, i));
+        events.push((span.end, false, i));
+    }
+
+    // Sort events: by position, then ends before starts at same position
+    events.sort_by(|a, b| {
+        a.0.cmp(&b.0).then_with(|| a.1.cmp(&b.1)) // false (end) < true (start)
+    });
+
+    // Process events with a stack
+    let mut last_pos: usize = 0;
+    let mut stack: Vec<usize> = Vec::new(); // indices into spans
+
+    for (pos, is_start, span_idx) in events {
+        let pos = pos as usize;
+
+        // Emit any source text before this position
+        if pos > last_pos && pos <= source.len() {
+            out.push_text(&source[last_pos..pos], &stack);
+            last_pos = pos;
+        }
+
+        // Update the stack
+        if is_start {
+            stack.push(span_idx);
+        } else {
+            // Remove this span from stack
+            if let Some(idx) = stack.iter().rposition(|&x| x == span_idx) {
+                stack.remove(idx);
+            }
+        }
+    }
+
+    // Emit remaining text
+    if last_pos < source.len() {
+        out.push_text(&source[last_pos..], &stack);
+    }
+
+    out.finish()
+}
+
+/// Deduplicate, normalize and coalesce spans for HTML rendering.
+///
+/// The result is sorted by `(start, -end)`, so longer spans come first.
+fn prepare_html_spans(spans: Vec<Span>, keep_captures: bool) -> Vec<NormalizedSpan> {
     if spans.is_empty() {
-        return html_escape(source);
+        return Vec::new();
     }
 
     // Sort spans by (start, -end) so longer spans come first at same start
@@ -195,7 +632,7 @@ pub fn spans_to_html(source: &str, spans: Vec<Span>, format: &HtmlFormat) -> Str
     // Deduplicate: for spans with the exact same (start, end), prefer spans with styling.
     // This handles the case where @comment @spell produces two spans - we want @comment,
     // not @spell (which maps to ThemeSlot::None and produces no HTML).
-    let mut deduped: HashMap<(u32, u32), Span> = HashMap::new();
+    let mut deduped: BTreeMap<(u32, u32), Span> = BTreeMap::new();
     for span in spans {
         let key = (span.start, span.end);
         let new_has_styling = tag_for_capture(&span.capture).is_some();
@@ -215,120 +652,437 @@ pub fn spans_to_html(source: &str, spans: Vec<Span>, format: &HtmlFormat) -> Str
     let spans: Vec<Span> = deduped.into_values().collect();
 
     // Normalize to theme slots and coalesce adjacent same-tag spans
-    let spans = normalize_and_coalesce(spans);
-
-    if spans.is_empty() {
-        return html_escape(source);
-    }
+    let mut spans = normalize_and_coalesce(spans, keep_captures);
 
     // Re-sort after coalescing
-    let mut spans = spans;
     spans.sort_by(|a, b| a.start.cmp(&b.start).then_with(|| b.end.cmp(&a.end)));
+    spans
+}
 
-    // Build events from spans
-    let mut events: Vec<(u32, bool, usize)> = Vec::new(); // (pos, is_start, span_index)
-    for (i, span) in spans.iter().enumerate() {
-        events.push((span.start, true, i));
-        events.push((span.end, false, i));
+/// Destination for rendered HTML or ANSI.
+trait RenderOutput {
+    fn push_str(&mut self, s: &str);
+
+    /// Number of bytes written so far.
+    fn position(&self) -> usize;
+}
+
+impl RenderOutput for String {
+    fn push_str(&mut self, s: &str) {
+        String::push_str(self, s);
     }
 
-    // Sort events: by position, then ends before starts at same position
-    events.sort_by(|a, b| {
-        a.0.cmp(&b.0).then_with(|| a.1.cmp(&b.1)) // false (end) < true (start)
-    });
+    fn position(&self) -> usize {
+        self.len()
+    }
+}
 
-    // Process events with a stack
-    let mut html = String::with_capacity(source.len() * 2);
-    let mut last_pos: usize = 0;
-    let mut stack: Vec<usize> = Vec::new(); // indices into spans
+/// Size at which [`ChunkedWriter`] passes buffered output on to its writer.
+const CHUNK_SIZE: usize = 8 * 1024;
 
-    for (pos, is_start, span_idx) in events {
-        let pos = pos as usize;
+/// Writes output to an [`io::Write`] in chunks of about [`CHUNK_SIZE`].
+///
+/// The first I/O error is kept and returned by [`finish`](Self::finish);
+/// nothing more is written after it.
+struct ChunkedWriter<'w, W: Write> {
+    w: &'w mut W,
+    buf: String,
+    /// Bytes passed on to `w` so far.
+    flushed: usize,
+    error: Option<io::Error>,
+}
 
-        // Emit any source text before this position
-        if pos > last_pos && pos <= source.len() {
-            let text = &source[last_pos..pos];
-            if let Some(&top_idx) = stack.last() {
-                let tag = spans[top_idx].tag;
-                let (open_tag, close_tag) = make_html_tags(tag, format);
-                html.push_str(&open_tag);
-                html.push_str(&html_escape(text));
-                html.push_str(&close_tag);
-            } else {
-                html.push_str(&html_escape(text));
+impl<'w, W: Write> ChunkedWriter<'w, W> {
+    fn new(w: &'w mut W) -> Self {
+        Self {
+            w,
+            buf: String::with_capacity(CHUNK_SIZE * 2),
+            flushed: 0,
+            error: None,
+        }
+    }
+
+    fn flush_buf(&mut self) {
+        if self.error.is_none() && !self.buf.is_empty() {
+            if let Err(e) = self.w.write_all(self.buf.as_bytes()) {
+                self.error = Some(e);
             }
-            last_pos = pos;
         }
+        self.flushed += self.buf.len();
+        self.buf.clear();
+    }
 
-        // Update the stack
-        if is_start {
-            stack.push(span_idx);
+    fn finish(mut self) -> io::Result<()> {
+        self.flush_buf();
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> RenderOutput for ChunkedWriter<'_, W> {
+    fn push_str(&mut self, s: &str) {
+        self.buf.push_str(s);
+        if self.buf.len() >= CHUNK_SIZE {
+            self.flush_buf();
+        }
+    }
+
+    fn position(&self) -> usize {
+        self.flushed + self.buf.len()
+    }
+}
+
+/// Accumulates HTML output, applying [`HtmlOptions`] at line boundaries.
+///
+/// With line-level options (line numbers, wrappers, highlighted lines,
+/// anchors or a table), highlight elements are closed before a newline and
+/// reopened after it, so no element is left open across a line. Otherwise an
+/// element covers its whole span, newlines included.
+struct HtmlWriter<'a, O> {
+    html: O,
+    spans: &'a [NormalizedSpan],
+    format: &'a HtmlFormat,
+    options: &'a HtmlOptions,
+    /// Element wrapping each line, if lines are wrapped.
+    wrapper: Option<&'a str>,
+    /// Number of the current line.
+    line: usize,
+    /// Whether the current line's prefix has been written yet.
+    line_started: bool,
+    /// Spans whose elements are currently open, outermost first (nested mode).
+    open: Vec<usize>,
+    /// Source offset of the next text to be written.
+    source_pos: usize,
+    /// Where to record the output position of source text, if anywhere.
+    map: Option<&'a mut SourceMap>,
+}
+
+impl<'a, O: RenderOutput> HtmlWriter<'a, O> {
+    fn new(
+        html: O,
+        spans: &'a [NormalizedSpan],
+        format: &'a HtmlFormat,
+        options: &'a HtmlOptions,
+        map: Option<&'a mut SourceMap>,
+    ) -> Self {
+        let mut html = html;
+        let wrapper = if options.table {
+            html.push_str("<table class=\"code\"><tbody>\n");
+            Some("tr")
         } else {
-            // Remove this span from stack
-            if let Some(idx) = stack.iter().rposition(|&x| x == span_idx) {
-                stack.remove(idx);
+            options.line_wrapper.as_deref().or(
+                // Line attributes need an element to live on
+                (!options.highlighted_lines.is_empty() || options.line_anchor_prefix.is_some())
+                    .then_some("span"),
+            )
+        };
+        Self {
+            html,
+            spans,
+            format,
+            options,
+            wrapper,
+            line: options.line_number_start,
+            line_started: false,
+            open: Vec::new(),
+            source_pos: 0,
+            map,
+        }
+    }
+
+    /// Write a run of source text covered by the spans in `stack` (indices
+    /// into `spans`, innermost last).
+    ///
+    /// Only the innermost span is rendered, unless [`HtmlOptions::nested`] is set.
+    fn push_text(&mut self, text: &str, stack: &[usize]) {
+        if !self.splits_lines() {
+            self.push_piece(text, stack);
+            return;
+        }
+        for (i, piece) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.close_all();
+                self.start_line();
+                self.end_line();
+                let out_start = self.html.position();
+                self.html.push_str("\n");
+                if let Some(map) = self.map.as_deref_mut() {
+                    map.push(
+                        out_start..out_start + 1,
+                        self.source_pos..self.source_pos + 1,
+                    );
+                }
+                self.source_pos += 1;
+                self.line += 1;
+                self.line_started = false;
             }
+            self.push_piece(piece, stack);
         }
     }
 
-    // Emit remaining text
-    if last_pos < source.len() {
-        let text = &source[last_pos..];
-        if let Some(&top_idx) = stack.last() {
-            let tag = spans[top_idx].tag;
-            let (open_tag, close_tag) = make_html_tags(tag, format);
-            html.push_str(&open_tag);
-            html.push_str(&html_escape(text));
-            html.push_str(&close_tag);
+    /// Whether line-level options need elements closed at each newline.
+    fn splits_lines(&self) -> bool {
+        self.options.line_numbers || self.wrapper.is_some()
+    }
+
+    /// Write text inside the elements for `stack`, on the current line unless
+    /// lines aren't split.
+    fn push_piece(&mut self, text: &str, stack: &[usize]) {
+        if text.is_empty() {
+            return;
+        }
+        self.start_line();
+        if self.options.nested {
+            self.reconcile(stack);
+            self.push_source(text);
+        } else if let Some(&top_idx) = stack.last() {
+            let (open_tag, close_tag) = self.tags(top_idx);
+            self.html.push_str(&open_tag);
+            self.push_source(text);
+            self.html.push_str(&close_tag);
         } else {
-            html.push_str(&html_escape(text));
+            self.push_source(text);
+        }
+    }
+
+    /// Write escaped source text, which contains no newline if lines are split.
+    fn push_source(&mut self, text: &str) {
+        let start = self.source_pos;
+        self.source_pos += text.len();
+        let escape = &self.options.escape;
+        let Some(map) = self.map.as_deref_mut() else {
+            self.html.push_str(&escape.escape(text));
+            return;
+        };
+
+        // Map unescaped runs as a whole and each escaped character on its own
+        let mut run_start = 0;
+        for (i, c) in text.char_indices() {
+            let Some(escaped) = escape.escape_char(c) else {
+                continue;
+            };
+            let out = self.html.position();
+            self.html.push_str(&text[run_start..i]);
+            map.push(out..self.html.position(), start + run_start..start + i);
+
+            let end = i + c.len_utf8();
+            let out = self.html.position();
+            self.html.push_str(&escaped);
+            map.push(out..self.html.position(), start + i..start + end);
+            run_start = end;
+        }
+        let out = self.html.position();
+        self.html.push_str(&text[run_start..]);
+        map.push(
+            out..self.html.position(),
+            start + run_start..start + text.len(),
+        );
+    }
+
+    /// Close and open elements so that exactly those of `stack` are open.
+    fn reconcile(&mut self, stack: &[usize]) {
+        let common = self
+            .open
+            .iter()
+            .zip(stack)
+            .take_while(|(a, b)| a == b)
+            .count();
+        while self.open.len() > common {
+            let span_idx = self.open.pop().expect("open is longer than common");
+            self.html.push_str(&self.tags(span_idx).1);
+        }
+        for &span_idx in &stack[common..] {
+            self.html.push_str(&self.tags(span_idx).0);
+            self.open.push(span_idx);
+        }
+    }
+
+    /// Close every open element (nested mode).
+    fn close_all(&mut self) {
+        self.reconcile(&[]);
+    }
+
+    /// The opening and closing tags for a span.
+    fn tags(&self, span_idx: usize) -> (String, String) {
+        let span = &self.spans[span_idx];
+        let (mut open_tag, close_tag) = make_html_tags(span.tag, self.format);
+        if self.options.capture_attributes && !open_tag.is_empty() {
+            open_tag.pop(); // the closing '>'
+            open_tag.push_str(" data-capture=\"");
+            open_tag.push_str(&html_escape(&span.capture));
+            open_tag.push_str("\">");
+        }
+        (open_tag, close_tag)
+    }
+
+    /// Write the current line's prefix, if not done already.
+    fn start_line(&mut self) {
+        if self.line_started {
+            return;
+        }
+        self.line_started = true;
+        if let Some(element) = self.wrapper {
+            let class = if self.options.highlighted_lines.contains(&self.line) {
+                "line highlighted"
+            } else {
+                "line"
+            };
+            match &self.options.line_anchor_prefix {
+                Some(prefix) => self.html.push_str(&format!(
+                    "<{element} id=\"{}{}\" class=\"{class}\">",
+                    html_escape(prefix),
+                    self.line
+                )),
+                None => self
+                    .html
+                    .push_str(&format!("<{element} class=\"{class}\">")),
+            }
+        }
+        if self.options.table {
+            self.html
+                .push_str("<td class=\"line-number\" data-line-number=\"");
+            self.html.push_str(&self.line.to_string());
+            self.html.push_str("\"></td><td class=\"line-code\">");
+        } else if self.options.line_numbers {
+            self.html.push_str("<span class=\"line-number\">");
+            self.html.push_str(&self.line.to_string());
+            self.html.push_str("</span>");
+        }
+    }
+
+    /// Close the current line's wrapper, if any. Only call after `start_line`.
+    fn end_line(&mut self) {
+        if self.options.table {
+            self.html.push_str("</td>");
+        }
+        if let Some(element) = self.wrapper {
+            self.html.push_str(&format!("</{element}>"));
         }
     }
 
-    html
+    fn finish(mut self) -> O {
+        self.close_all();
+        if self.line_started {
+            self.end_line();
+        }
+        if self.options.table {
+            if self.line_started {
+                self.html.push_str("\n");
+            }
+            self.html.push_str("</tbody></table>");
+        }
+        self.html
+    }
 }
 
 /// Write spans as HTML to a writer.
 ///
-/// This is more efficient than `spans_to_html` for streaming output.
+/// Output is streamed in chunks of a few kilobytes as it is rendered, so
+/// large files never need a complete in-memory copy of the HTML. The writer
+/// is not buffered further; wrap unbuffered files or sockets in a
+/// [`BufWriter`](std::io::BufWriter) if small chunks matter.
 pub fn write_spans_as_html<W: Write>(
     w: &mut W,
     source: &str,
     spans: Vec<Span>,
     format: &HtmlFormat,
 ) -> io::Result<()> {
-    let html = spans_to_html(source, spans, format);
-    w.write_all(html.as_bytes())
+    write_spans_as_html_with_options(w, source, spans, format, &HtmlOptions::default())
+}
+
+/// Write spans as HTML to a writer, with line-level [`HtmlOptions`].
+///
+/// Streams like [`write_spans_as_html`].
+pub fn write_spans_as_html_with_options<W: Write>(
+    w: &mut W,
+    source: &str,
+    spans: Vec<Span>,
+    format: &HtmlFormat,
+    options: &HtmlOptions,
+) -> io::Result<()> {
+    render_html(ChunkedWriter::new(w), source, spans, format, options, None).finish()
+}
+
+/// Wrap highlighted HTML in a `<pre><code class="language-…">` block.
+///
+/// `html` is inserted as-is, so it should come from [`spans_to_html`] or
+/// similar. The language and attribute values are escaped; attribute names
+/// are written verbatim and must be valid HTML attribute names.
+///
+/// ```rust
+/// use arborium_highlight::render_block;
+///
+/// let block = render_block("rust", "<a-k>fn</a-k>", &[("data-file", "main.rs")]);
+/// assert_eq!(
+///     block,
+///     "<pre><code class=\"language-rust\" data-file=\"main.rs\"><a-k>fn</a-k></code></pre>"
+/// );
+/// ```
+pub fn render_block(language: &str, html: &str, attributes: &[(&str, &str)]) -> String {
+    let mut block = String::with_capacity(html.len() + 64);
+    block.push_str("<pre><code class=\"language-");
+    block.push_str(&html_escape(language));
+    block.push('"');
+    for (name, value) in attributes {
+        block.push(' ');
+        block.push_str(name);
+        block.push_str("=\"");
+        block.push_str(&html_escape(value));
+        block.push('"');
+    }
+    block.push('>');
+    block.push_str(html);
+    block.push_str("</code></pre>");
+    block
 }
 
 /// Escape HTML special characters.
 pub fn html_escape(text: &str) -> String {
     let mut result = String::with_capacity(text.len());
     for c in text.chars() {
-        match c {
-            '<' => result.push_str("&lt;"),
-            '>' => result.push_str("&gt;"),
-            '&' => result.push_str("&amp;"),
-            '"' => result.push_str("&quot;"),
-            '\'' => result.push_str("&#39;"),
-            _ => result.push(c),
+        match html_entity(c) {
+            Some(entity) => result.push_str(entity),
+            None => result.push(c),
         }
     }
     result
 }
 
+/// The entity [`html_escape`] replaces `c` with, if any.
+fn html_entity(c: char) -> Option<&'static str> {
+    match c {
+        '<' => Some("&lt;"),
+        '>' => Some("&gt;"),
+        '&' => Some("&amp;"),
+        '"' => Some("&quot;"),
+        '\'' => Some("&#39;"),
+        _ => None,
+    }
+}
+
 /// Options controlling ANSI rendering behavior.
+///
+/// With the `serde` feature, missing fields take their [`Default`] values.
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct AnsiOptions {
     /// If true, apply the theme's foreground/background as a base style
     /// for all text (including un-highlighted regions).
     pub use_theme_base_style: bool,
-    /// Optional hard wrap width (in columns). When None, no wrapping is
-    /// performed and the original line structure is preserved.
+    /// Optional width (in columns) to wrap or truncate lines at, as chosen by
+    /// `overflow`. When None, the original line structure is preserved.
     pub width: Option<usize>,
     /// If true and `width` is set, pad each visual line with spaces up
     /// to exactly `width` columns.
     pub pad_to_width: bool,
+    /// What to do with lines longer than `width`.
+    pub overflow: Overflow,
     /// Tab width (in columns) used when computing display width.
     pub tab_width: usize,
     /// Horizontal margin (in columns) outside the border/background.
@@ -345,6 +1099,54 @@ pub struct AnsiOptions {
     pub padding_y: usize,
     /// If true, draw a border around the code block using half-block characters.
     pub border: bool,
+    /// Color palette to emit. Theme colors are quantized to the nearest
+    /// palette entry when the terminal doesn't support truecolor.
+    pub color_depth: ColorDepth,
+    /// If true, start each line with its number and a `│` separator, like
+    /// `bat` does.
+    ///
+    /// The gutter is dimmed and counts towards `width`; continuation lines
+    /// produced by wrapping get an empty gutter.
+    pub line_numbers: bool,
+    /// The number of the first line, for excerpts of a larger file.
+    pub line_number_start: usize,
+    /// If set, print a `File: {name}` header above the code. When `width` is
+    /// set, horizontal rules frame the header and close the output.
+    pub file_name: Option<String>,
+}
+
+/// How ANSI output handles lines longer than [`AnsiOptions::width`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum Overflow {
+    /// Continue the line on the next row (default).
+    #[default]
+    Wrap,
+    /// Cut the line off at the width, dropping the rest of it.
+    Truncate,
+}
+
+/// How many colors ANSI output may use.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum ColorDepth {
+    /// No colors; only text modifiers such as bold and italic are emitted.
+    NoColor,
+    /// The 16 standard ANSI colors (`ESC[31m`, `ESC[91m`, ...).
+    Ansi16,
+    /// The xterm 256-color palette (`ESC[38;5;Nm`).
+    Ansi256,
+    /// 24-bit RGB (`ESC[38;2;R;G;Bm`) (default).
+    #[default]
+    TrueColor,
 }
 
 /// Unicode block drawing characters used to create visual borders around ANSI output.
@@ -388,12 +1190,17 @@ impl Default for AnsiOptions {
             use_theme_base_style: false,
             width,
             pad_to_width: width.is_some(),
+            overflow: Overflow::default(),
             tab_width: 4,
             margin_x: 0,
             margin_y: 0,
             padding_x: 0,
             padding_y: 0,
             border: false,
+            color_depth: ColorDepth::default(),
+            line_numbers: false,
+            line_number_start: 1,
+            file_name: None,
         }
     }
 }
@@ -418,27 +1225,62 @@ fn char_display_width(c: char, col: usize, tab_width: usize) -> usize {
     }
 }
 
+/// Position on the visual line being written.
+#[derive(Default)]
+struct LineCursor {
+    /// Display column, including left padding.
+    col: usize,
+    /// Whether the rest of the source line is being dropped
+    /// ([`Overflow::Truncate`]).
+    clipped: bool,
+}
+
+/// Settings that stay the same for every [`write_wrapped_text`] call in one
+/// render.
+#[derive(Clone, Copy)]
+struct WrapSettings<'a> {
+    options: &'a AnsiOptions,
+    theme: &'a Theme,
+    /// The theme's base style, re-emitted after each line break.
+    base_ansi: &'a str,
+    /// Whether styles are rendered over the theme's background.
+    use_base_bg: bool,
+    /// The border color escape, empty without a border.
+    border_style: &'a str,
+}
+
+/// Write source text, wrapping and decorating it as `settings.options` ask.
+///
+/// `text_offset` is the source offset of `text`; if `map` is given, the output
+/// position of each source character is recorded in it.
 fn write_wrapped_text(
     out: &mut String,
     text: &str,
-    options: &AnsiOptions,
-    current_col: &mut usize,
-    base_ansi: &str,
+    text_offset: usize,
+    mut map: Option<&mut SourceMap>,
+    line: &mut LineCursor,
     active_style: Option<usize>,
-    theme: &Theme,
-    use_base_bg: bool,
-    border_style: &str,
+    settings: WrapSettings<'_>,
 ) {
+    let WrapSettings {
+        options,
+        theme,
+        base_ansi,
+        use_base_bg,
+        border_style,
+    } = settings;
+
     // No wrapping requested: just track column and append text.
     let Some(inner_width) = options.width else {
-        for ch in text.chars() {
+        for (i, ch) in text.char_indices() {
+            let out_start = out.len();
             match ch {
                 '\n' | '\r' => {
-                    *current_col = 0;
+                    line.col = 0;
                     out.push(ch);
                 }
                 other => {
-                    let w = char_display_width(other, *current_col, options.tab_width);
+                    let w = char_display_width(other, line.col, options.tab_width);
                     if other == '\t' {
                         for _ in 0..w {
                             out.push(' ');
@@ -446,9 +1288,13 @@ fn write_wrapped_text(
                     } else {
                         out.push(other);
                     }
-                    *current_col += w;
+                    line.col += w;
                 }
             }
+            if let Some(map) = map.as_deref_mut() {
+                let source = text_offset + i;
+                map.push(out_start..out.len(), source..source + ch.len_utf8());
+            }
         }
         return;
     };
@@ -466,9 +1312,10 @@ fn write_wrapped_text(
     let content_end = width.saturating_sub(padding_x); // where content should stop (before right padding)
     let pad_to_width = options.pad_to_width;
 
-    for ch in text.chars() {
+    for (i, ch) in text.char_indices() {
+        let source = text_offset + i..text_offset + i + ch.len_utf8();
         // At the start of a visual line, emit margin + left border + left padding
-        if *current_col == 0 {
+        if line.col == 0 {
             // Left margin
             for _ in 0..margin_x {
                 out.push(' ');
@@ -487,14 +1334,14 @@ fn write_wrapped_text(
                 for _ in 0..padding_x {
                     out.push(' ');
                 }
-                *current_col += padding_x;
+                line.col += padding_x;
             }
         }
 
         if ch == '\n' || ch == '\r' {
             // Pad to full width (including right padding)
-            if pad_to_width && *current_col < width {
-                let pad = width - *current_col;
+            if pad_to_width && line.col < width {
+                let pad = width - line.col;
                 for _ in 0..pad {
                     out.push(' ');
                 }
@@ -507,8 +1354,12 @@ fn write_wrapped_text(
             }
             // Reset before newline so background doesn't extend to terminal edge
             out.push_str(Theme::ANSI_RESET);
+            if let Some(map) = map.as_deref_mut() {
+                map.push(out.len()..out.len() + 1, source);
+            }
             out.push('\n');
-            *current_col = 0;
+            line.col = 0;
+            line.clipped = false;
 
             if !base_ansi.is_empty() {
                 out.push_str(base_ansi);
@@ -524,12 +1375,20 @@ fn write_wrapped_text(
             continue;
         }
 
-        let w = char_display_width(ch, *current_col, options.tab_width);
-        // Wrap when we would exceed the content area (before right padding)
-        if w > 0 && *current_col + w > content_end {
+        if line.clipped {
+            continue;
+        }
+        let w = char_display_width(ch, line.col, options.tab_width);
+        // Wrap when we would exceed the content area (before right padding)
+        if w > 0 && line.col + w > content_end {
+            if options.overflow == Overflow::Truncate {
+                // Zero-width characters that follow (combining marks) go too
+                line.clipped = true;
+                continue;
+            }
             // Pad to full width (including right padding)
-            if pad_to_width && *current_col < width {
-                let pad = width - *current_col;
+            if pad_to_width && line.col < width {
+                let pad = width - line.col;
                 for _ in 0..pad {
                     out.push(' ');
                 }
@@ -543,7 +1402,7 @@ fn write_wrapped_text(
             // Reset before newline so background doesn't extend to terminal edge
             out.push_str(Theme::ANSI_RESET);
             out.push('\n');
-            *current_col = 0;
+            line.col = 0;
 
             if !base_ansi.is_empty() {
                 out.push_str(base_ansi);
@@ -576,19 +1435,23 @@ fn write_wrapped_text(
                 for _ in 0..padding_x {
                     out.push(' ');
                 }
-                *current_col += padding_x;
+                line.col += padding_x;
             }
         }
 
+        let out_start = out.len();
         if ch == '\t' {
-            let w = char_display_width('\t', *current_col, options.tab_width);
+            let w = char_display_width('\t', line.col, options.tab_width);
             for _ in 0..w {
                 out.push(' ');
             }
-            *current_col += w;
+            line.col += w;
         } else {
             out.push(ch);
-            *current_col += w;
+            line.col += w;
+        }
+        if let Some(map) = map.as_deref_mut() {
+            map.push(out_start..out.len(), source);
         }
     }
 }
@@ -608,20 +1471,151 @@ pub fn spans_to_ansi_with_options(
     theme: &Theme,
     options: &AnsiOptions,
 ) -> String {
+    render_ansi(source, spans, theme, options, None, None)
+}
+
+/// ANSI rendering that also returns a [`SourceMap`] from the output back to
+/// the source.
+///
+/// The output is identical to [`spans_to_ansi_with_options`]'s. Escape
+/// sequences, borders, padding and line breaks inserted by wrapping are not
+/// mapped; an expanded tab maps to the tab.
+pub fn spans_to_ansi_with_source_map(
+    source: &str,
+    spans: Vec<Span>,
+    theme: &Theme,
+    options: &AnsiOptions,
+) -> (String, SourceMap) {
+    let mut map = SourceMap::default();
+    let ansi = render_ansi(source, spans, theme, options, Some(&mut map), None);
+    (ansi, map)
+}
+
+/// One row of ANSI output, produced by [`spans_to_ansi_lines`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnsiLine {
+    /// The row's text, without a trailing newline. It starts by restoring the
+    /// style in effect at the start of the row and ends with a reset, so any
+    /// row can be printed on its own.
+    pub text: String,
+    /// Zero-based index of the source line shown on this row, or `None` for
+    /// rows that only hold decoration (file header, borders, padding).
+    ///
+    /// When `width` is set, the rows a long line wraps onto all carry that
+    /// line's index.
+    pub line: Option<usize>,
+}
+
+/// ANSI rendering split into self-contained rows.
+///
+/// The rows are those of [`spans_to_ansi_with_options`]'s output, so a pager
+/// or TUI can highlight once and then print only the rows in view.
+///
+/// ```rust
+/// use arborium_highlight::{AnsiOptions, Span, spans_to_ansi_lines};
+/// use arborium_theme::theme::builtin;
+///
+/// let spans = vec![Span { start: 0, end: 9, capture: "comment".into() }];
+/// let lines = spans_to_ansi_lines(
+///     "/* a\nb */ c",
+///     spans,
+///     &builtin::catppuccin_mocha(),
+///     &AnsiOptions::default(),
+/// );
+///
+/// assert_eq!(lines.len(), 2);
+/// assert_eq!(lines[1].line, Some(1));
+/// // The comment's style is re-applied at the start of the second row
+/// assert!(lines[1].text.starts_with("\x1b["));
+/// ```
+pub fn spans_to_ansi_lines(
+    source: &str,
+    spans: Vec<Span>,
+    theme: &Theme,
+    options: &AnsiOptions,
+) -> Vec<AnsiLine> {
+    let (ansi, map) = spans_to_ansi_with_source_map(source, spans, theme, options);
+    let index = LineIndex::new(source);
+
+    let mut lines = Vec::new();
+    // SGR sequences in effect at the start of the current row
+    let mut active = String::new();
+    let mut row_start = 0;
+    for row in ansi.split('\n') {
+        // The first source character on the row, including the newline that ends it
+        let row_end = (row_start + row.len() + 1).min(ansi.len());
+        let line = (row_start..row_end)
+            .find_map(|out| map.source_offset(out))
+            .map(|offset| index.position(offset as u32, OffsetEncoding::Utf8).line as usize);
+
+        let mut text = String::with_capacity(active.len() + row.len() + Theme::ANSI_RESET.len());
+        text.push_str(&active);
+        text.push_str(row);
+        track_sgr(&mut active, row);
+        if !active.is_empty() {
+            text.push_str(Theme::ANSI_RESET);
+        }
+        lines.push(AnsiLine { text, line });
+        row_start += row.len() + 1;
+    }
+    lines
+}
+
+/// Update `active`, the SGR sequences in effect, with those in `text`.
+fn track_sgr(active: &mut String, text: &str) {
+    let mut rest = text;
+    while let Some(start) = rest.find("\x1b[") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find('m') else {
+            break;
+        };
+        let params = &after[..end];
+        if params.is_empty() || params == "0" || params.starts_with("0;") {
+            active.clear();
+        }
+        if !params.is_empty() && params != "0" {
+            active.push_str(&rest[start..start + end + 3]);
+        }
+        rest = &after[end + 1..];
+    }
+}
+
+/// Render spans as ANSI, recording source positions in `map` if given.
+///
+/// If `stream` is given, finished output is passed to it in chunks of about
+/// [`CHUNK_SIZE`] as rendering goes, and only the remainder is returned.
+/// Streaming and `map` are not meant to be combined.
+fn render_ansi(
+    source: &str,
+    spans: Vec<Span>,
+    theme: &Theme,
+    options: &AnsiOptions,
+    mut map: Option<&mut SourceMap>,
+    mut stream: Option<&mut dyn RenderOutput>,
+) -> String {
+    if options.line_numbers || options.file_name.is_some() {
+        return render_ansi_with_gutter(source, spans, theme, options, map);
+    }
+
     // Trim trailing newlines from source
     let source = source.trim_end_matches('\n');
 
     if spans.is_empty() {
-        return source.to_string();
+        return unstyled_ansi(source, map);
     }
 
     // Sort spans by (start, -end) so longer spans come first at same start
     let mut spans = spans;
     spans.sort_by(|a, b| a.start.cmp(&b.start).then_with(|| b.end.cmp(&a.end)));
 
+    let palette = match options.color_depth {
+        ColorDepth::Ansi256 => ansi256_palette(theme),
+        _ => HashMap::new(),
+    };
+
     // Deduplicate ranges the same way as HTML, but based on whether the
     // capture maps to a themed slot.
-    let mut deduped: HashMap<(u32, u32), Span> = HashMap::new();
+    let mut deduped: BTreeMap<(u32, u32), Span> = BTreeMap::new();
     for span in spans {
         let key = (span.start, span.end);
         let new_has_slot = slot_to_highlight_index(capture_to_slot(&span.capture)).is_some();
@@ -650,6 +1644,10 @@ pub fn spans_to_ansi_with_options(
     let mut normalized: Vec<StyledSpan> = spans
         .into_iter()
         .filter_map(|span| {
+            // Zero-length spans style nothing
+            if span.start >= span.end {
+                return None;
+            }
             let slot = capture_to_slot(&span.capture);
             let index = slot_to_highlight_index(slot)?;
             // Filter out empty styles when using base style - they'll just use the base
@@ -669,7 +1667,7 @@ pub fn spans_to_ansi_with_options(
         .collect();
 
     if normalized.is_empty() {
-        return source.to_string();
+        return unstyled_ansi(source, map);
     }
 
     // Sort by start
@@ -688,7 +1686,7 @@ pub fn spans_to_ansi_with_options(
     }
 
     if coalesced.is_empty() {
-        return source.to_string();
+        return unstyled_ansi(source, map);
     }
 
     // Build events from spans
@@ -704,7 +1702,7 @@ pub fn spans_to_ansi_with_options(
     let mut last_pos: usize = 0;
     let mut stack: Vec<usize> = Vec::new();
     let mut active_style: Option<usize> = None;
-    let mut current_col: usize = 0;
+    let mut line = LineCursor::default();
 
     let base_ansi = if options.use_theme_base_style {
         theme.ansi_base_style()
@@ -713,6 +1711,24 @@ pub fn spans_to_ansi_with_options(
     };
     let use_base_bg = options.use_theme_base_style;
 
+    // The style to render text in, given the active one and the one the
+    // spans ask for: styles that emit nothing count as no style, and a style
+    // identical to the active one keeps it, so no escapes are written for it.
+    let style_string = |index: usize| {
+        if use_base_bg {
+            theme.ansi_style_with_base_bg(index)
+        } else {
+            theme.ansi_style(index)
+        }
+    };
+    let resolve_style = |active: Option<usize>, desired: Option<usize>| {
+        let desired = desired.filter(|&d| !style_string(d).is_empty())?;
+        match active {
+            Some(a) if a != desired && style_string(a) == style_string(desired) => Some(a),
+            _ => Some(desired),
+        }
+    };
+
     // Track if we've output anything yet to avoid duplicate base style at start
     let mut output_started = false;
 
@@ -726,6 +1742,14 @@ pub fn spans_to_ansi_with_options(
         String::new()
     };
 
+    let wrap = WrapSettings {
+        options,
+        theme,
+        base_ansi: &base_ansi,
+        use_base_bg,
+        border_style: &border_style,
+    };
+
     // Minimum width to ensure usable output on narrow terminals
     const MIN_WIDTH: usize = 10;
 
@@ -805,7 +1829,7 @@ pub fn spans_to_ansi_with_options(
         let pos = pos as usize;
         if pos > last_pos && pos <= source.len() {
             let text = &source[last_pos..pos];
-            let desired = stack.last().copied().map(|idx| coalesced[idx].index);
+            let desired = resolve_style(active_style, stack.last().map(|&i| coalesced[i].index));
 
             match (active_style, desired) {
                 (Some(a), Some(d)) if a == d => {
@@ -813,13 +1837,11 @@ pub fn spans_to_ansi_with_options(
                     write_wrapped_text(
                         &mut out,
                         text,
-                        options,
-                        &mut current_col,
-                        &base_ansi,
+                        last_pos,
+                        map.as_deref_mut(),
+                        &mut line,
                         Some(a),
-                        theme,
-                        use_base_bg,
-                        &border_style,
+                        wrap,
                     );
                 }
                 (Some(_), Some(d)) => {
@@ -843,13 +1865,11 @@ pub fn spans_to_ansi_with_options(
                     write_wrapped_text(
                         &mut out,
                         text,
-                        options,
-                        &mut current_col,
-                        &base_ansi,
+                        last_pos,
+                        map.as_deref_mut(),
+                        &mut line,
                         Some(d),
-                        theme,
-                        use_base_bg,
-                        &border_style,
+                        wrap,
                     );
                     active_style = Some(d);
                 }
@@ -875,13 +1895,11 @@ pub fn spans_to_ansi_with_options(
                     write_wrapped_text(
                         &mut out,
                         text,
-                        options,
-                        &mut current_col,
-                        &base_ansi,
+                        last_pos,
+                        map.as_deref_mut(),
+                        &mut line,
                         Some(d),
-                        theme,
-                        use_base_bg,
-                        &border_style,
+                        wrap,
                     );
                     active_style = Some(d);
                 }
@@ -894,13 +1912,11 @@ pub fn spans_to_ansi_with_options(
                     write_wrapped_text(
                         &mut out,
                         text,
-                        options,
-                        &mut current_col,
-                        &base_ansi,
+                        last_pos,
+                        map.as_deref_mut(),
+                        &mut line,
                         None,
-                        theme,
-                        use_base_bg,
-                        &border_style,
+                        wrap,
                     );
                     active_style = None;
                 }
@@ -913,13 +1929,11 @@ pub fn spans_to_ansi_with_options(
                     write_wrapped_text(
                         &mut out,
                         text,
-                        options,
-                        &mut current_col,
-                        &base_ansi,
+                        last_pos,
+                        map.as_deref_mut(),
+                        &mut line,
                         None,
-                        theme,
-                        use_base_bg,
-                        &border_style,
+                        wrap,
                     );
                 }
             }
@@ -927,6 +1941,16 @@ pub fn spans_to_ansi_with_options(
             last_pos = pos;
         }
 
+        if let Some(stream) = stream.as_deref_mut() {
+            if out.len() >= CHUNK_SIZE {
+                match options.color_depth {
+                    ColorDepth::TrueColor => stream.push_str(&out),
+                    depth => stream.push_str(&downgrade_colors(&out, depth, None, &palette)),
+                }
+                out.clear();
+            }
+        }
+
         if is_start {
             stack.push(span_idx);
         } else if let Some(idx) = stack.iter().rposition(|&x| x == span_idx) {
@@ -936,19 +1960,17 @@ pub fn spans_to_ansi_with_options(
 
     if last_pos < source.len() {
         let text = &source[last_pos..];
-        let desired = stack.last().copied().map(|idx| coalesced[idx].index);
+        let desired = resolve_style(active_style, stack.last().map(|&i| coalesced[i].index));
         match (active_style, desired) {
             (Some(a), Some(d)) if a == d => {
                 write_wrapped_text(
                     &mut out,
                     text,
-                    options,
-                    &mut current_col,
-                    &base_ansi,
+                    last_pos,
+                    map.as_deref_mut(),
+                    &mut line,
                     Some(a),
-                    theme,
-                    use_base_bg,
-                    &border_style,
+                    wrap,
                 );
             }
             (Some(_), Some(d)) => {
@@ -970,13 +1992,11 @@ pub fn spans_to_ansi_with_options(
                 write_wrapped_text(
                     &mut out,
                     text,
-                    options,
-                    &mut current_col,
-                    &base_ansi,
+                    last_pos,
+                    map.as_deref_mut(),
+                    &mut line,
                     Some(d),
-                    theme,
-                    use_base_bg,
-                    &border_style,
+                    wrap,
                 );
                 active_style = Some(d);
             }
@@ -997,13 +2017,11 @@ pub fn spans_to_ansi_with_options(
                 write_wrapped_text(
                     &mut out,
                     text,
-                    options,
-                    &mut current_col,
-                    &base_ansi,
+                    last_pos,
+                    map.as_deref_mut(),
+                    &mut line,
                     Some(d),
-                    theme,
-                    use_base_bg,
-                    &border_style,
+                    wrap,
                 );
                 active_style = Some(d);
             }
@@ -1015,13 +2033,11 @@ pub fn spans_to_ansi_with_options(
                 write_wrapped_text(
                     &mut out,
                     text,
-                    options,
-                    &mut current_col,
-                    &base_ansi,
+                    last_pos,
+                    map.as_deref_mut(),
+                    &mut line,
                     None,
-                    theme,
-                    use_base_bg,
-                    &border_style,
+                    wrap,
                 );
                 active_style = None;
             }
@@ -1032,13 +2048,11 @@ pub fn spans_to_ansi_with_options(
                 write_wrapped_text(
                     &mut out,
                     text,
-                    options,
-                    &mut current_col,
-                    &base_ansi,
+                    last_pos,
+                    map.as_deref_mut(),
+                    &mut line,
                     None,
-                    theme,
-                    use_base_bg,
-                    &border_style,
+                    wrap,
                 );
             }
         }
@@ -1055,8 +2069,8 @@ pub fn spans_to_ansi_with_options(
         };
 
         // Pad the final content line out to the full width.
-        if pad_to_width && current_col < inner_width {
-            let pad = inner_width - current_col;
+        if pad_to_width && line.col < inner_width {
+            let pad = inner_width - line.col;
             for _ in 0..pad {
                 out.push(' ');
             }
@@ -1129,18 +2143,332 @@ pub fn spans_to_ansi_with_options(
         out.push_str(Theme::ANSI_RESET);
     }
 
+    match options.color_depth {
+        ColorDepth::TrueColor => out,
+        depth => downgrade_colors(&out, depth, map, &palette),
+    }
+}
+
+/// SGR sequence for the line-number gutter and file header rules of themes
+/// without a [`gutter`](Theme::gutter) color.
+const ANSI_DIM: &str = "\x1b[2m";
+
+/// SGR sequence for the file name in the header.
+const ANSI_BOLD: &str = "\x1b[1m";
+
+/// Render with the line-number gutter and file header of [`AnsiOptions`].
+///
+/// The code is rendered without them, narrowed by the gutter width, and the
+/// gutter is then inserted at the start of every output line. Source line
+/// starts are found through a [`SourceMap`], so only the first visual line of
+/// a wrapped line is numbered.
+fn render_ansi_with_gutter(
+    source: &str,
+    spans: Vec<Span>,
+    theme: &Theme,
+    options: &AnsiOptions,
+    map: Option<&mut SourceMap>,
+) -> String {
+    let source = source.trim_end_matches('\n');
+    let last_line = options.line_number_start + source.matches('\n').count();
+    let number_width = last_line.to_string().len();
+    // " {number} │ "
+    let gutter_width = if options.line_numbers {
+        number_width + 4
+    } else {
+        0
+    };
+
+    // The theme's gutter color if it has one, dimmed text otherwise
+    let gutter_style = match theme.gutter {
+        Some(color) if !matches!(options.color_depth, ColorDepth::NoColor) => {
+            let color = theme.opaque(color);
+            let sgr = format!("\x1b[38;2;{};{};{}m", color.r, color.g, color.b);
+            match options.color_depth {
+                ColorDepth::TrueColor => sgr,
+                depth => downgrade_colors(&sgr, depth, None, &HashMap::new()),
+            }
+        }
+        _ => ANSI_DIM.to_string(),
+    };
+
+    let body_options = AnsiOptions {
+        line_numbers: false,
+        file_name: None,
+        width: options.width.map(|w| w.saturating_sub(gutter_width)),
+        ..options.clone()
+    };
+    let mut body_map = SourceMap::default();
+    let body = render_ansi(
+        source,
+        spans,
+        theme,
+        &body_options,
+        Some(&mut body_map),
+        None,
+    );
+
+    // The source line starting on each output line, if any
+    let mut line_numbers: Vec<Option<usize>> = vec![None; body.matches('\n').count() + 1];
+    let line_starts = std::iter::once(0).chain(source.match_indices('\n').map(|(i, _)| i + 1));
+    for (number, start) in (options.line_number_start..).zip(line_starts) {
+        if let Some(out) = body_map.output_offset(start) {
+            line_numbers[body[..out].matches('\n').count()] = Some(number);
+        }
+    }
+
+    // Horizontal rule, with `joint` where it crosses the separator
+    let rule = |joint: char| -> String {
+        let width = options.width.unwrap_or(0);
+        let mut rule = gutter_style.clone();
+        if options.line_numbers {
+            rule.extend(std::iter::repeat_n('─', number_width + 2));
+            rule.push(joint);
+            rule.extend(std::iter::repeat_n(
+                '─',
+                width.saturating_sub(number_width + 3),
+            ));
+        } else {
+            rule.extend(std::iter::repeat_n('─', width));
+        }
+        rule.push_str(Theme::ANSI_RESET);
+        rule.push('\n');
+        rule
+    };
+    let gutter = |number: Option<usize>| -> String {
+        match number {
+            Some(n) => format!("{gutter_style} {n:>number_width$} │ {}", Theme::ANSI_RESET),
+            None => format!(
+                "{gutter_style} {:number_width$} │ {}",
+                "",
+                Theme::ANSI_RESET
+            ),
+        }
+    };
+
+    let mut out = String::with_capacity(body.len() + line_numbers.len() * (gutter_width + 12));
+    if let Some(name) = &options.file_name {
+        if options.width.is_some() {
+            out.push_str(&rule('┬'));
+        }
+        if options.line_numbers {
+            out.push_str(&gutter(None));
+        }
+        out.push_str(&format!("File: {ANSI_BOLD}{}{}\n", name, Theme::ANSI_RESET));
+        if options.width.is_some() {
+            out.push_str(&rule('┼'));
+        }
+    }
+
+    // Where the gutter is inserted, as (offset in `body`, bytes inserted)
+    let mut shifts = vec![(0, out.len() as isize)];
+    // SGR sequences in effect since the last reset, re-applied after the gutter
+    let mut active = String::new();
+    let mut offset = 0;
+    for (i, line) in body.split('\n').enumerate() {
+        if i > 0 {
+            out.push('\n');
+            offset += 1;
+        }
+        if options.line_numbers {
+            let before = out.len();
+            if !active.is_empty() {
+                out.push_str(Theme::ANSI_RESET);
+            }
+            out.push_str(&gutter(line_numbers[i]));
+            out.push_str(&active);
+            shifts.push((offset, (out.len() - before) as isize));
+        }
+        out.push_str(line);
+        offset += line.len();
+
+        let mut rest = line;
+        while let Some(start) = rest.find("\x1b[") {
+            let Some(end) = rest[start..].find('m') else {
+                break;
+            };
+            let sequence = &rest[start..start + end + 1];
+            if matches!(sequence, "\x1b[0m" | "\x1b[m") {
+                active.clear();
+            } else {
+                active.push_str(sequence);
+            }
+            rest = &rest[start + end + 1..];
+        }
+    }
+    if !active.is_empty() {
+        out.push_str(Theme::ANSI_RESET);
+    }
+    if options.file_name.is_some() && options.width.is_some() {
+        out.push('\n');
+        out.push_str(rule('┴').trim_end_matches('\n'));
+    }
+
+    if let Some(map) = map {
+        body_map.shift_output(&shifts);
+        *map = body_map;
+    }
+    out
+}
+
+/// Output for source with no styled spans: the source itself.
+fn unstyled_ansi(source: &str, map: Option<&mut SourceMap>) -> String {
+    if let Some(map) = map {
+        *map = SourceMap::identity(source.len());
+    }
+    source.to_string()
+}
+
+/// The xterm-256 index of each color `theme`'s styles draw with, as
+/// precomputed by [`Theme::ansi256`].
+fn ansi256_palette(theme: &Theme) -> HashMap<Color, u8> {
+    let mut palette = HashMap::new();
+    for (index, ansi) in theme.ansi256().into_iter().enumerate() {
+        let Some(style) = theme.style(index) else {
+            continue;
+        };
+        for (color, ansi) in [(style.fg, ansi.fg), (style.bg, ansi.bg)] {
+            if let (Some(color), Some(ansi)) = (color, ansi) {
+                palette.insert(theme.opaque(color), ansi);
+            }
+        }
+    }
+    palette
+}
+
+/// Rewrite the truecolor SGR sequences in `ansi` for a smaller palette.
+///
+/// The renderer only emits SGR (`ESC[...m`) sequences, so this rewrites the
+/// `38;2;R;G;B` and `48;2;R;G;B` parameters of each one and drops sequences
+/// left with no parameters. Output offsets in `map` are moved to match.
+/// Colors found in `palette` take its 256-color index rather than being
+/// quantized again.
+fn downgrade_colors(
+    ansi: &str,
+    depth: ColorDepth,
+    map: Option<&mut SourceMap>,
+    palette: &HashMap<Color, u8>,
+) -> String {
+    let mut out = String::with_capacity(ansi.len());
+    let mut rest = ansi;
+    // Where the output got shorter or longer, as offsets into `ansi`
+    let mut shifts: Vec<(usize, isize)> = Vec::new();
+
+    while let Some(start) = rest.find("\x1b[") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find('m') else {
+            out.push_str(&rest[start..]);
+            rest = "";
+            break;
+        };
+
+        let before = out.len();
+        let params = downgrade_sgr_params(&after[..end], depth, palette);
+        if !params.is_empty() {
+            out.push_str("\x1b[");
+            out.push_str(&params.join(";"));
+            out.push('m');
+        }
+        let old_len = end + 3;
+        let new_len = out.len() - before;
+        if new_len != old_len {
+            let offset = ansi.len() - rest.len() + start;
+            shifts.push((offset, new_len as isize - old_len as isize));
+        }
+        rest = &after[end + 1..];
+    }
+
+    out.push_str(rest);
+    if let Some(map) = map {
+        map.shift_output(&shifts);
+    }
+    out
+}
+
+fn downgrade_sgr_params(
+    params: &str,
+    depth: ColorDepth,
+    palette: &HashMap<Color, u8>,
+) -> Vec<String> {
+    let parts: Vec<&str> = params.split(';').collect();
+    let mut out = Vec::with_capacity(parts.len());
+    let mut i = 0;
+
+    while i < parts.len() {
+        let is_rgb = matches!(parts[i], "38" | "48") && parts.get(i + 1) == Some(&"2");
+        let rgb = parts.get(i + 2..i + 5).filter(|_| is_rgb).and_then(|c| {
+            Some(Color::new(
+                c[0].parse().ok()?,
+                c[1].parse().ok()?,
+                c[2].parse().ok()?,
+            ))
+        });
+        let Some(color) = rgb else {
+            out.push(parts[i].to_string());
+            i += 1;
+            continue;
+        };
+
+        let background = parts[i] == "48";
+        match depth {
+            ColorDepth::NoColor => {}
+            ColorDepth::Ansi16 => {
+                let index = color.to_ansi16();
+                let base = match (background, index < 8) {
+                    (false, true) => 30,
+                    (false, false) => 90 - 8,
+                    (true, true) => 40,
+                    (true, false) => 100 - 8,
+                };
+                out.push((base + index as u32).to_string());
+            }
+            ColorDepth::Ansi256 => {
+                out.push(parts[i].to_string());
+                out.push("5".to_string());
+                let index = palette
+                    .get(&color)
+                    .copied()
+                    .unwrap_or_else(|| color.to_ansi256());
+                out.push(index.to_string());
+            }
+            ColorDepth::TrueColor => out.extend(parts[i..i + 5].iter().map(|p| p.to_string())),
+        }
+        i += 5;
+    }
+
     out
 }
 
 /// Write spans as ANSI-colored text to a writer.
+///
+/// Streams like [`write_spans_as_ansi_with_options`].
 pub fn write_spans_as_ansi<W: Write>(
     w: &mut W,
     source: &str,
     spans: Vec<Span>,
     theme: &Theme,
 ) -> io::Result<()> {
-    let ansi = spans_to_ansi(source, spans, theme);
-    w.write_all(ansi.as_bytes())
+    write_spans_as_ansi_with_options(w, source, spans, theme, &AnsiOptions::default())
+}
+
+/// Write spans as ANSI-colored text to a writer, with [`AnsiOptions`].
+///
+/// Output is streamed in chunks of a few kilobytes as it is rendered, so
+/// large files never need a complete in-memory copy of the colored text. The
+/// line-number gutter and file header are the exception: with either
+/// enabled, the output is assembled before it is written.
+pub fn write_spans_as_ansi_with_options<W: Write>(
+    w: &mut W,
+    source: &str,
+    spans: Vec<Span>,
+    theme: &Theme,
+    options: &AnsiOptions,
+) -> io::Result<()> {
+    let mut writer = ChunkedWriter::new(w);
+    let rest = render_ansi(source, spans, theme, options, None, Some(&mut writer));
+    writer.push_str(&rest);
+    writer.finish()
 }
 
 #[cfg(test)]
@@ -1286,7 +2614,7 @@ mod tests {
         let kw_idx = slot_to_highlight_index(capture_to_slot("keyword")).unwrap();
         let fn_idx = slot_to_highlight_index(capture_to_slot("function")).unwrap();
 
-        let ansi = spans_to_ansi(source, spans, theme);
+        let ansi = spans_to_ansi(source, spans, &theme);
 
         let expected = format!(
             "{}fn{} {}main{}",
@@ -1311,7 +2639,7 @@ mod tests {
         let mut options = AnsiOptions::default();
         options.use_theme_base_style = true;
 
-        let ansi = spans_to_ansi_with_options(source, spans, theme, &options);
+        let ansi = spans_to_ansi_with_options(source, spans, &theme, &options);
         let base = theme.ansi_base_style();
 
         assert!(ansi.starts_with(&base));
@@ -1333,12 +2661,462 @@ mod tests {
         options.width = Some(4);
         options.pad_to_width = false;
 
-        let ansi = spans_to_ansi_with_options(source, spans, theme, &options);
+        let ansi = spans_to_ansi_with_options(source, spans, &theme, &options);
 
         assert!(ansi.contains('\n'));
         assert!(ansi.ends_with(Theme::ANSI_RESET));
     }
 
+    #[test]
+    fn test_ansi_color_depth_downgrade() {
+        let sgr = "\x1b[1;38;2;255;0;0;48;2;0;0;0mx\x1b[0m";
+        assert_eq!(
+            downgrade_colors(sgr, ColorDepth::Ansi256, None, &HashMap::new()),
+            "\x1b[1;38;5;196;48;5;16mx\x1b[0m"
+        );
+        assert_eq!(
+            downgrade_colors(sgr, ColorDepth::Ansi16, None, &HashMap::new()),
+            "\x1b[1;91;40mx\x1b[0m"
+        );
+        assert_eq!(
+            downgrade_colors(sgr, ColorDepth::NoColor, None, &HashMap::new()),
+            "\x1b[1mx\x1b[0m"
+        );
+        assert_eq!(
+            downgrade_colors(
+                "\x1b[38;2;1;2;3mx",
+                ColorDepth::NoColor,
+                None,
+                &HashMap::new()
+            ),
+            "x"
+        );
+        // Precomputed indices are used as they are
+        let palette = HashMap::from([(Color::new(255, 0, 0), 9)]);
+        assert_eq!(
+            downgrade_colors(sgr, ColorDepth::Ansi256, None, &palette),
+            "\x1b[1;38;5;9;48;5;16mx\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_ansi_with_color_depth_option() {
+        let theme = arborium_theme::theme::builtin::catppuccin_mocha();
+        let spans = vec![Span {
+            start: 0,
+            end: 2,
+            capture: "keyword".into(),
+        }];
+        let mut options = AnsiOptions {
+            width: None,
+            ..AnsiOptions::default()
+        };
+
+        options.color_depth = ColorDepth::Ansi256;
+        let ansi = spans_to_ansi_with_options("fn", spans.clone(), &theme, &options);
+        assert!(ansi.contains("38;5;"));
+        assert!(!ansi.contains("38;2;"));
+
+        options.color_depth = ColorDepth::NoColor;
+        let ansi = spans_to_ansi_with_options("fn", spans, &theme, &options);
+        assert!(!ansi.contains("38;"));
+    }
+
+    #[test]
+    fn test_html_table_layout() {
+        let spans = vec![Span {
+            start: 0,
+            end: 2,
+            capture: "keyword".into(),
+        }];
+        let options = HtmlOptions {
+            table: true,
+            highlighted_lines: vec![2],
+            ..HtmlOptions::default()
+        };
+        let html = spans_to_html_with_options("fn a\n\nb", spans, &HtmlFormat::default(), &options);
+        assert_eq!(
+            html,
+            "<table class=\"code\"><tbody>\n\
+             <tr class=\"line\"><td class=\"line-number\" data-line-number=\"1\"></td>\
+             <td class=\"line-code\"><a-k>fn</a-k> a</td></tr>\n\
+             <tr class=\"line highlighted\"><td class=\"line-number\" data-line-number=\"2\"></td>\
+             <td class=\"line-code\"></td></tr>\n\
+             <tr class=\"line\"><td class=\"line-number\" data-line-number=\"3\"></td>\
+             <td class=\"line-code\">b</td></tr>\n\
+             </tbody></table>"
+        );
+
+        // A trailing newline does not add an empty row
+        let html = spans_to_html_with_options("a\n", vec![], &HtmlFormat::default(), &options);
+        assert_eq!(html.matches("<tr").count(), 1);
+        assert!(html.ends_with("</tr>\n</tbody></table>"));
+    }
+
+    #[test]
+    fn test_html_escape_policy() {
+        let source = "'引用' < \"x\"";
+        let spans = vec![Span {
+            start: 0,
+            end: 8,
+            capture: "string".into(),
+        }];
+        let format = HtmlFormat::default();
+        let render = |escape| {
+            let options = HtmlOptions {
+                escape,
+                ..HtmlOptions::default()
+            };
+            spans_to_html_with_options(source, spans.clone(), &format, &options)
+        };
+
+        assert_eq!(
+            render(HtmlEscape::default()),
+            spans_to_html(source, spans.clone(), &format)
+        );
+        assert_eq!(
+            render(HtmlEscape {
+                quotes: false,
+                ..HtmlEscape::default()
+            }),
+            "<a-s>'引用'</a-s> &lt; \"x\""
+        );
+        assert_eq!(
+            render(HtmlEscape {
+                non_ascii: true,
+                ..HtmlEscape::default()
+            }),
+            "<a-s>&#39;&#x5F15;&#x7528;&#39;</a-s> &lt; &quot;x&quot;"
+        );
+        assert_eq!(
+            render(HtmlEscape {
+                custom: Some(|c| (c == '<').then_some(Cow::Borrowed("&#60;"))),
+                ..HtmlEscape::default()
+            }),
+            "<a-s>&#39;引用&#39;</a-s> &#60; &quot;x&quot;"
+        );
+    }
+
+    #[test]
+    fn test_html_source_map() {
+        let source = "fn <a>\nx";
+        let spans = vec![Span {
+            start: 0,
+            end: 2,
+            capture: "keyword".into(),
+        }];
+        let options = HtmlOptions {
+            line_numbers: true,
+            ..HtmlOptions::default()
+        };
+        let (html, map) =
+            spans_to_html_with_source_map(source, spans.clone(), &HtmlFormat::default(), &options);
+        assert_eq!(
+            html,
+            spans_to_html_with_options(source, spans, &HtmlFormat::default(), &options)
+        );
+
+        // Every source byte maps to output that round-trips to it
+        for offset in 0..source.len() {
+            let out = map.output_offset(offset).unwrap();
+            assert_eq!(map.source_offset(out), Some(offset), "offset {offset}");
+        }
+        let x = map.output_offset(7).unwrap();
+        assert_eq!(&html[x..x + 1], "x");
+        let lt = map.output_offset(3).unwrap();
+        assert_eq!(&html[lt..lt + 4], "&lt;");
+        assert_eq!(map.source_offset(lt + 2), Some(3));
+        // The line-number gutter is not source text
+        assert_eq!(map.source_offset(html.find('1').unwrap()), None);
+    }
+
+    #[test]
+    fn test_ansi_source_map() {
+        let theme = arborium_theme::theme::builtin::catppuccin_mocha();
+        let source = "fn\tmain\nx";
+        let spans = vec![Span {
+            start: 0,
+            end: 2,
+            capture: "keyword".into(),
+        }];
+        for color_depth in [ColorDepth::TrueColor, ColorDepth::Ansi16] {
+            let options = AnsiOptions {
+                width: None,
+                color_depth,
+                ..AnsiOptions::default()
+            };
+            let (ansi, map) =
+                spans_to_ansi_with_source_map(source, spans.clone(), &theme, &options);
+            assert_eq!(
+                ansi,
+                spans_to_ansi_with_options(source, spans.clone(), &theme, &options)
+            );
+            for (offset, c) in source.char_indices() {
+                let out = map.output_offset(offset).unwrap();
+                let expected = if c == '\t' { ' ' } else { c };
+                assert_eq!(
+                    ansi[out..].chars().next(),
+                    Some(expected),
+                    "offset {offset}"
+                );
+            }
+            // The expanded tab maps back to the tab
+            let tab = map.output_offset(2).unwrap();
+            assert_eq!(map.source_offset(tab + 1), Some(2));
+            assert_eq!(map.source_offset(0), None);
+        }
+    }
+
+    #[test]
+    fn test_ansi_line_number_gutter() {
+        let theme = arborium_theme::theme::builtin::catppuccin_mocha();
+        let spans = vec![Span {
+            start: 0,
+            end: 9,
+            capture: "comment".into(),
+        }];
+        let source = "/* a\nb */\nc\n";
+        let options = AnsiOptions {
+            width: None,
+            line_numbers: true,
+            line_number_start: 9,
+            ..AnsiOptions::default()
+        };
+        let (ansi, map) = spans_to_ansi_with_source_map(source, spans.clone(), &theme, &options);
+        let lines: Vec<&str> = ansi.split('\n').collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("\x1b[2m  9 │ \x1b[0m"));
+        assert!(lines[2].starts_with("\x1b[2m 11 │ \x1b[0mc"));
+
+        // The comment's style is re-applied after the gutter on its second line
+        let comment = theme.ansi_style(
+            slot_to_highlight_index(capture_to_slot("comment")).expect("comment is styled"),
+        );
+        assert!(lines[1].starts_with(&format!("\x1b[0m\x1b[2m 10 │ \x1b[0m{comment}b */")));
+
+        // Source offsets still point at the source text
+        for (offset, c) in source.trim_end().char_indices() {
+            let out = map.output_offset(offset).unwrap();
+            assert_eq!(ansi[out..].chars().next(), Some(c), "offset {offset}");
+        }
+
+        // A theme's gutter color replaces the dimming
+        let mut theme = theme;
+        theme.gutter = Some(arborium_theme::Color::new(1, 2, 3));
+        let ansi = spans_to_ansi_with_options(source, spans, &theme, &options);
+        assert!(ansi.starts_with("\x1b[38;2;1;2;3m  9 │ \x1b[0m"));
+    }
+
+    #[test]
+    fn test_ansi_file_header_and_wrapped_gutter() {
+        let theme = arborium_theme::theme::builtin::catppuccin_mocha();
+        let spans = vec![Span {
+            start: 0,
+            end: 2,
+            capture: "keyword".into(),
+        }];
+        let options = AnsiOptions {
+            width: Some(16),
+            pad_to_width: false,
+            line_numbers: true,
+            file_name: Some("main.rs".into()),
+            ..AnsiOptions::default()
+        };
+        let ansi = spans_to_ansi_with_options("fn abcdefghijklmn", spans, &theme, &options);
+        let plain = strip_ansi(&ansi);
+        assert_eq!(
+            plain.lines().collect::<Vec<_>>(),
+            [
+                "───┬────────────",
+                "   │ File: main.rs",
+                "───┼────────────",
+                " 1 │ fn abcdefgh",
+                "   │ ijklmn",
+                "───┴────────────",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ansi_truncate_overflow() {
+        let theme = arborium_theme::theme::builtin::catppuccin_mocha();
+        // The string runs past the cut; its style must still be closed
+        let spans = vec![Span {
+            start: 0,
+            end: 16,
+            capture: "string".into(),
+        }];
+        let options = AnsiOptions {
+            width: Some(12),
+            pad_to_width: true,
+            overflow: Overflow::Truncate,
+            ..AnsiOptions::default()
+        };
+        let source = "\"abcdefghijklm\"\nshort\nx\tyyyyyyyyyy";
+        let ansi = spans_to_ansi_with_options(source, spans, &theme, &options);
+        let plain = strip_ansi(&ansi);
+        assert_eq!(
+            plain.lines().collect::<Vec<_>>(),
+            ["\"abcdefghijk", "short       ", "x   yyyyyyyy"]
+        );
+        for line in ansi.lines() {
+            assert!(line.ends_with(Theme::ANSI_RESET), "{line:?}");
+        }
+    }
+
+    #[test]
+    fn test_ansi_lines_are_self_contained() {
+        let theme = arborium_theme::theme::builtin::catppuccin_mocha();
+        let spans = vec![Span {
+            start: 0,
+            end: 9,
+            capture: "comment".into(),
+        }];
+        let source = "/* a\nb */\n\nabcdefghijklmn";
+        let options = AnsiOptions {
+            width: Some(16),
+            pad_to_width: false,
+            line_numbers: true,
+            file_name: Some("x.c".into()),
+            ..AnsiOptions::default()
+        };
+        let lines = spans_to_ansi_lines(source, spans.clone(), &theme, &options);
+
+        // Same rows as the one-shot rendering
+        let ansi = spans_to_ansi_with_options(source, spans, &theme, &options);
+        let rows: Vec<String> = lines.iter().map(|line| strip_ansi(&line.text)).collect();
+        assert_eq!(rows, strip_ansi(&ansi).split('\n').collect::<Vec<_>>());
+
+        let numbers: Vec<Option<usize>> = lines.iter().map(|line| line.line).collect();
+        assert_eq!(
+            numbers,
+            [
+                None,
+                None,
+                None,
+                Some(0),
+                Some(1),
+                Some(2),
+                Some(3),
+                Some(3),
+                None
+            ]
+        );
+
+        // The comment continues on the second source line: its style is
+        // restored after the row's own leading reset, and closed at the end
+        let comment = theme.ansi_style(
+            slot_to_highlight_index(capture_to_slot("comment")).expect("comment is styled"),
+        );
+        assert!(lines[4].text.contains(&format!("{comment}b */")));
+        for line in &lines {
+            assert!(line.text.ends_with(Theme::ANSI_RESET), "{:?}", line.text);
+        }
+    }
+
+    fn strip_ansi(s: &str) -> String {
+        let mut out = String::new();
+        let mut rest = s;
+        while let Some(start) = rest.find('\x1b') {
+            out.push_str(&rest[..start]);
+            let end = rest[start..]
+                .find('m')
+                .map_or(rest.len(), |e| start + e + 1);
+            rest = &rest[end..];
+        }
+        out.push_str(rest);
+        out
+    }
+
+    #[test]
+    fn test_ansi_minimizes_escapes() {
+        use arborium_theme::Style;
+
+        let index = |capture: &str| slot_to_highlight_index(capture_to_slot(capture)).unwrap();
+        let red = Style::new().fg(Color::new(255, 0, 0));
+        let mut theme = Theme::new("test");
+        theme.set_style(index("keyword"), red.clone());
+        theme.set_style(index("function"), red);
+
+        let span = |start, end, capture: &str| Span {
+            start,
+            end,
+            capture: capture.into(),
+        };
+        let spans = vec![
+            span(0, 2, "keyword"),
+            span(2, 6, "function"),
+            span(6, 6, "keyword"),
+            span(7, 8, "variable"),
+        ];
+        let options = AnsiOptions {
+            width: None,
+            ..AnsiOptions::default()
+        };
+        let ansi = spans_to_ansi_with_options("fnmain x", spans, &theme, &options);
+
+        // One style for both same-looking tokens, nothing for the unstyled one
+        let red = theme.ansi_style(index("keyword"));
+        assert_eq!(ansi, format!("{red}fnmain{} x", Theme::ANSI_RESET));
+    }
+
+    #[test]
+    fn test_rendering_is_deterministic() {
+        let source = "let x = \"abc\";";
+        let spans = vec![
+            Span {
+                start: 0,
+                end: 3,
+                capture: "keyword".into(),
+            },
+            Span {
+                start: 8,
+                end: 13,
+                capture: "string".into(),
+            },
+            Span {
+                start: 8,
+                end: 9,
+                capture: "punctuation".into(),
+            },
+            Span {
+                start: 8,
+                end: 12,
+                capture: "variable".into(),
+            },
+        ];
+        let mut reversed = spans.clone();
+        reversed.reverse();
+
+        let theme = arborium_theme::theme::builtin::catppuccin_mocha();
+        let options = AnsiOptions {
+            width: None,
+            ..AnsiOptions::default()
+        };
+        let html = spans_to_html(source, spans.clone(), &HtmlFormat::CustomElements);
+        let ansi = spans_to_ansi_with_options(source, spans.clone(), &theme, &options);
+        let themed: Vec<_> = spans_to_themed(spans)
+            .into_iter()
+            .map(|s| (s.start, s.end, s.theme_index))
+            .collect();
+
+        for _ in 0..10 {
+            assert_eq!(
+                spans_to_html(source, reversed.clone(), &HtmlFormat::CustomElements),
+                html
+            );
+            assert_eq!(
+                spans_to_ansi_with_options(source, reversed.clone(), &theme, &options),
+                ansi
+            );
+            let again: Vec<_> = spans_to_themed(reversed.clone())
+                .into_iter()
+                .map(|s| (s.start, s.end, s.theme_index))
+                .collect();
+            assert_eq!(again, themed);
+        }
+    }
+
     #[test]
     fn test_ansi_coalesces_same_style() {
         let theme = arborium_theme::theme::builtin::catppuccin_mocha();
@@ -1357,7 +3135,7 @@ mod tests {
         ];
 
         let kw_idx = slot_to_highlight_index(capture_to_slot("keyword")).unwrap();
-        let ansi = spans_to_ansi(source, spans, theme);
+        let ansi = spans_to_ansi(source, spans, &theme);
 
         let expected = format!("{}keyword{}", theme.ansi_style(kw_idx), Theme::ANSI_RESET);
         assert_eq!(ansi, expected);
@@ -1475,6 +3253,366 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_html_format_hljs_classes() {
+        let source = "fn main ~~x~~";
+        let spans = vec![
+            Span {
+                start: 0,
+                end: 2,
+                capture: "keyword".into(),
+            },
+            Span {
+                start: 3,
+                end: 7,
+                capture: "function".into(),
+            },
+            Span {
+                start: 8,
+                end: 13,
+                capture: "markup.strikethrough".into(),
+            },
+        ];
+        let html = spans_to_html(source, spans, &HtmlFormat::HljsClasses);
+        assert_eq!(
+            html,
+            "<span class=\"hljs-keyword\">fn</span> \
+             <span class=\"hljs-title function_\">main</span> ~~x~~"
+        );
+    }
+
+    #[test]
+    fn test_html_format_prism_classes() {
+        let source = "fn Foo";
+        let spans = vec![
+            Span {
+                start: 0,
+                end: 2,
+                capture: "keyword".into(),
+            },
+            Span {
+                start: 3,
+                end: 6,
+                capture: "type".into(),
+            },
+        ];
+        let html = spans_to_html(source, spans, &HtmlFormat::PrismClasses);
+        assert_eq!(
+            html,
+            "<span class=\"token keyword\">fn</span> \
+             <span class=\"token class-name\">Foo</span>"
+        );
+    }
+
+    #[test]
+    fn test_html_format_pygments_classes() {
+        let source = "fn main";
+        let spans = vec![
+            Span {
+                start: 0,
+                end: 2,
+                capture: "keyword".into(),
+            },
+            Span {
+                start: 3,
+                end: 7,
+                capture: "function".into(),
+            },
+        ];
+        let html = spans_to_html(source, spans, &HtmlFormat::PygmentsClasses);
+        assert_eq!(
+            html,
+            "<span class=\"k\">fn</span> <span class=\"nf\">main</span>"
+        );
+    }
+
+    #[test]
+    fn test_html_multiline_span_without_line_options() {
+        // Without line-level options, a span keeps one element across lines
+        let spans = vec![Span {
+            start: 0,
+            end: 10,
+            capture: "comment".into(),
+        }];
+        let html = spans_to_html("/* a\n\nb */\n", spans, &HtmlFormat::default());
+        assert_eq!(html, "<a-c>/* a\n\nb */</a-c>\n");
+    }
+
+    #[test]
+    fn test_html_line_numbers() {
+        let options = HtmlOptions {
+            line_numbers: true,
+            ..Default::default()
+        };
+        // A multi-line span is closed and reopened around each gutter cell,
+        // and the trailing newline gets no number.
+        let spans = vec![Span {
+            start: 0,
+            end: 10,
+            capture: "comment".into(),
+        }];
+        let html =
+            spans_to_html_with_options("/* a\n\nb */\n", spans, &HtmlFormat::default(), &options);
+        assert_eq!(
+            html,
+            "<span class=\"line-number\">1</span><a-c>/* a</a-c>\n\
+             <span class=\"line-number\">2</span>\n\
+             <span class=\"line-number\">3</span><a-c>b */</a-c>\n"
+        );
+
+        assert_eq!(
+            spans_to_html_with_options("", vec![], &HtmlFormat::default(), &options),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_html_line_wrapper() {
+        let options = HtmlOptions {
+            line_wrapper: Some("div".into()),
+            ..Default::default()
+        };
+        let spans = vec![Span {
+            start: 0,
+            end: 6,
+            capture: "string".into(),
+        }];
+        let html =
+            spans_to_html_with_options("\"a\nb\"\n\nc", spans, &HtmlFormat::default(), &options);
+        assert_eq!(
+            html,
+            "<div class=\"line\"><a-s>&quot;a</a-s></div>\n\
+             <div class=\"line\"><a-s>b&quot;</a-s></div>\n\
+             <div class=\"line\"></div>\n\
+             <div class=\"line\">c</div>"
+        );
+    }
+
+    #[test]
+    fn test_html_highlighted_lines() {
+        let options = HtmlOptions {
+            line_number_start: 5,
+            highlighted_lines: vec![6],
+            ..Default::default()
+        };
+        let html = spans_to_html_with_options("a\nb\nc", vec![], &HtmlFormat::default(), &options);
+        assert_eq!(
+            html,
+            "<span class=\"line\">a</span>\n\
+             <span class=\"line highlighted\">b</span>\n\
+             <span class=\"line\">c</span>"
+        );
+    }
+
+    #[test]
+    fn test_html_line_anchors() {
+        let options = HtmlOptions {
+            line_anchor_prefix: Some("L".into()),
+            highlighted_lines: vec![2],
+            ..Default::default()
+        };
+        let html = spans_to_html_with_options("a\nb\n", vec![], &HtmlFormat::default(), &options);
+        assert_eq!(
+            html,
+            "<span id=\"L1\" class=\"line\">a</span>\n\
+             <span id=\"L2\" class=\"line highlighted\">b</span>\n"
+        );
+    }
+
+    #[test]
+    fn test_render_block_escapes() {
+        let block = render_block("c++", "x", &[("title", "a \"b\" <c>")]);
+        assert_eq!(
+            block,
+            "<pre><code class=\"language-c++\" title=\"a &quot;b&quot; &lt;c&gt;\">x</code></pre>"
+        );
+    }
+
+    #[test]
+    fn test_html_capture_attributes() {
+        let options = HtmlOptions {
+            capture_attributes: true,
+            ..Default::default()
+        };
+        // Both map to the keyword slot, but keep their own capture names
+        let spans = vec![
+            Span {
+                start: 0,
+                end: 3,
+                capture: "keyword.function".into(),
+            },
+            Span {
+                start: 3,
+                end: 5,
+                capture: "keyword".into(),
+            },
+        ];
+        let html =
+            spans_to_html_with_options("def if", spans.clone(), &HtmlFormat::default(), &options);
+        assert_eq!(
+            html,
+            "<a-k data-capture=\"keyword.function\">def</a-k>\
+             <a-k data-capture=\"keyword\"> i</a-k>f"
+        );
+
+        let html = spans_to_html_with_options("def if", spans, &HtmlFormat::ClassNames, &options);
+        assert!(html.starts_with("<span class=\"keyword\" data-capture=\"keyword.function\">"));
+    }
+
+    #[test]
+    fn test_html_nested() {
+        let options = HtmlOptions {
+            nested: true,
+            ..Default::default()
+        };
+        let span = |start, end, capture: &str| Span {
+            start,
+            end,
+            capture: capture.into(),
+        };
+
+        let spans = vec![span(0, 7, "string"), span(3, 4, "variable")];
+        let html = spans_to_html_with_options("\"a{x}b\"", spans, &HtmlFormat::default(), &options);
+        assert_eq!(html, "<a-s>&quot;a{<a-v>x</a-v>}b&quot;</a-s>");
+
+        // Overlapping spans are split
+        let spans = vec![span(0, 4, "keyword"), span(2, 7, "string")];
+        let html = spans_to_html_with_options("abcd\nfg", spans, &HtmlFormat::default(), &options);
+        assert_eq!(html, "<a-k>ab<a-s>cd</a-s></a-k><a-s>\nfg</a-s>");
+
+        // With line-level options, elements never cross a newline
+        let options = HtmlOptions {
+            line_wrapper: Some("div".into()),
+            ..options
+        };
+        let spans = vec![span(0, 4, "keyword"), span(2, 7, "string")];
+        let html = spans_to_html_with_options("abcd\nfg", spans, &HtmlFormat::default(), &options);
+        assert_eq!(
+            html,
+            "<div class=\"line\"><a-k>ab<a-s>cd</a-s></a-k></div>\n\
+             <div class=\"line\"><a-s>fg</a-s></div>"
+        );
+    }
+
+    /// Records the size of each write.
+    struct Recorder(Vec<u8>, Vec<usize>);
+
+    impl Write for Recorder {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.extend_from_slice(buf);
+            self.1.push(buf.len());
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_spans_as_html_streams() {
+        let source = "fn x() {}\n".repeat(5_000);
+        let spans: Vec<Span> = (0..5_000)
+            .map(|i| Span {
+                start: i * 10,
+                end: i * 10 + 2,
+                capture: "keyword".into(),
+            })
+            .collect();
+        let options = HtmlOptions {
+            line_numbers: true,
+            ..Default::default()
+        };
+
+        let mut recorder = Recorder(Vec::new(), Vec::new());
+        write_spans_as_html_with_options(
+            &mut recorder,
+            &source,
+            spans.clone(),
+            &HtmlFormat::default(),
+            &options,
+        )
+        .unwrap();
+
+        let expected = spans_to_html_with_options(&source, spans, &HtmlFormat::default(), &options);
+        assert_eq!(String::from_utf8(recorder.0).unwrap(), expected);
+        assert!(recorder.1.len() > 1);
+        assert!(recorder.1.iter().all(|&len| len < CHUNK_SIZE * 2));
+    }
+
+    #[test]
+    fn test_write_spans_as_ansi_streams() {
+        let theme = arborium_theme::theme::builtin::catppuccin_mocha();
+        let source = "fn x() {}\n".repeat(5_000);
+        let spans: Vec<Span> = (0..5_000)
+            .map(|i| Span {
+                start: i * 10,
+                end: i * 10 + 2,
+                capture: "keyword".into(),
+            })
+            .collect();
+
+        for options in [
+            AnsiOptions {
+                width: None,
+                color_depth: ColorDepth::Ansi256,
+                ..AnsiOptions::default()
+            },
+            AnsiOptions {
+                width: Some(40),
+                border: true,
+                ..AnsiOptions::default()
+            },
+        ] {
+            let mut recorder = Recorder(Vec::new(), Vec::new());
+            write_spans_as_ansi_with_options(
+                &mut recorder,
+                &source,
+                spans.clone(),
+                &theme,
+                &options,
+            )
+            .unwrap();
+
+            let expected = spans_to_ansi_with_options(&source, spans.clone(), &theme, &options);
+            assert_eq!(String::from_utf8(recorder.0).unwrap(), expected);
+            assert!(recorder.1.len() > 1);
+            assert!(recorder.1.iter().all(|&len| len < CHUNK_SIZE * 2));
+        }
+    }
+
+    #[test]
+    fn test_write_spans_as_html_reports_errors() {
+        struct Failing;
+        impl Write for Failing {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::other("disk full"))
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let err = write_spans_as_html(&mut Failing, "x", vec![], &HtmlFormat::default());
+        assert_eq!(err.unwrap_err().to_string(), "disk full");
+    }
+
+    #[test]
+    fn test_html_format_class_prefix_with_separator() {
+        let spans = vec![Span {
+            start: 0,
+            end: 2,
+            capture: "keyword".into(),
+        }];
+        for (prefix, class) in [("hl-", "hl-keyword"), ("tok_", "tok_keyword")] {
+            let html = spans_to_html(
+                "fn",
+                spans.clone(),
+                &HtmlFormat::ClassNamesWithPrefix(prefix.to_string()),
+            );
+            assert_eq!(html, format!("<span class=\"{class}\">fn</span>"));
+        }
+    }
+
     #[test]
     fn test_html_format_all_tags() {
         // Test a variety of different tags to ensure mapping works
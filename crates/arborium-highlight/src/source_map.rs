@@ -0,0 +1,209 @@
+//! Mapping rendered output back to source offsets.
+//!
+//! Renderers add markup around the source text and may expand it (HTML
+//! entities, tabs expanded to spaces), so an offset in the output does not
+//! correspond directly to an offset in the input. A [`SourceMap`] records, for
+//! every piece of source text written, where it ended up in the output.
+
+use std::ops::Range;
+
+/// One piece of source text and where it was written in the output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceMapping {
+    /// Byte range in the rendered output.
+    pub output: Range<usize>,
+    /// Byte range in the source.
+    pub source: Range<usize>,
+}
+
+/// Maps byte ranges of rendered output back to byte ranges of the source.
+///
+/// Produced by [`spans_to_html_with_source_map`](crate::spans_to_html_with_source_map)
+/// and [`spans_to_ansi_with_source_map`](crate::spans_to_ansi_with_source_map).
+/// Output that does not come from the source, such as tags, escape sequences,
+/// line numbers and padding, is not covered by any mapping.
+///
+/// ```rust
+/// use arborium_highlight::{HtmlFormat, HtmlOptions, Span, spans_to_html_with_source_map};
+///
+/// let spans = vec![Span { start: 0, end: 2, capture: "keyword".into() }];
+/// let (html, map) = spans_to_html_with_source_map(
+///     "fn a<b>",
+///     spans,
+///     &HtmlFormat::default(),
+///     &HtmlOptions::default(),
+/// );
+/// assert_eq!(html, "<a-k>fn</a-k> a&lt;b&gt;");
+///
+/// // "f" inside <a-k>, and the "&lt;" entity standing for '<'
+/// assert_eq!(map.source_offset(5), Some(0));
+/// assert_eq!(map.source_offset(17), Some(4));
+/// // Markup has no source position
+/// assert_eq!(map.source_offset(1), None);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    /// Sorted by output offset; neither output nor source ranges overlap.
+    mappings: Vec<SourceMapping>,
+}
+
+impl SourceMap {
+    /// All mappings, in output order.
+    pub fn mappings(&self) -> &[SourceMapping] {
+        &self.mappings
+    }
+
+    /// The source offset that produced the output byte at `output_offset`.
+    ///
+    /// Within text copied verbatim, offsets map one to one. Output that stands
+    /// for a single source character, such as an HTML entity or an expanded
+    /// tab, maps to the start of that character. Returns `None` for output
+    /// that does not come from the source.
+    pub fn source_offset(&self, output_offset: usize) -> Option<usize> {
+        let idx = self
+            .mappings
+            .partition_point(|m| m.output.end <= output_offset);
+        let m = self.mappings.get(idx)?;
+        if !m.output.contains(&output_offset) {
+            return None;
+        }
+        Some(if m.output.len() == m.source.len() {
+            m.source.start + (output_offset - m.output.start)
+        } else {
+            m.source.start
+        })
+    }
+
+    /// The output offset where the source byte at `source_offset` was written.
+    ///
+    /// The inverse of [`source_offset`](Self::source_offset). Returns `None`
+    /// for source text that was not written, such as trailing newlines the
+    /// ANSI renderer trims.
+    pub fn output_offset(&self, source_offset: usize) -> Option<usize> {
+        let idx = self
+            .mappings
+            .partition_point(|m| m.source.end <= source_offset);
+        let m = self.mappings.get(idx)?;
+        if !m.source.contains(&source_offset) {
+            return None;
+        }
+        Some(if m.output.len() == m.source.len() {
+            m.output.start + (source_offset - m.source.start)
+        } else {
+            m.output.start
+        })
+    }
+
+    /// Record that `source` was written to `output`.
+    ///
+    /// Extends the previous mapping when both are contiguous copies.
+    pub(crate) fn push(&mut self, output: Range<usize>, source: Range<usize>) {
+        if output.is_empty() || source.is_empty() {
+            return;
+        }
+        if let Some(last) = self.mappings.last_mut() {
+            if last.output.end == output.start
+                && last.source.end == source.start
+                && last.output.len() == last.source.len()
+                && output.len() == source.len()
+            {
+                last.output.end = output.end;
+                last.source.end = source.end;
+                return;
+            }
+        }
+        self.mappings.push(SourceMapping { output, source });
+    }
+
+    /// A map for output that is the source itself.
+    pub(crate) fn identity(len: usize) -> Self {
+        let mut map = Self::default();
+        map.push(0..len, 0..len);
+        map
+    }
+
+    /// Shift output offsets after the output was rewritten.
+    ///
+    /// `shifts` holds `(offset, delta)` pairs sorted by offset: output at or
+    /// after `offset` (in the old output) moves by the cumulative `delta`.
+    /// Mappings of verbatim copies are split where output was inserted inside
+    /// them.
+    pub(crate) fn shift_output(&mut self, shifts: &[(usize, isize)]) {
+        let shift = |range: Range<usize>, delta: isize| {
+            range.start.saturating_add_signed(delta)..range.end.saturating_add_signed(delta)
+        };
+        let mut mappings = Vec::with_capacity(self.mappings.len());
+        let mut i = 0;
+        let mut delta = 0isize;
+        for mut m in self.mappings.drain(..) {
+            while i < shifts.len() && shifts[i].0 <= m.output.start {
+                delta += shifts[i].1;
+                i += 1;
+            }
+            while i < shifts.len() && shifts[i].0 < m.output.end && m.output.len() == m.source.len()
+            {
+                let split = m.source.start + (shifts[i].0 - m.output.start);
+                mappings.push(SourceMapping {
+                    output: shift(m.output.start..shifts[i].0, delta),
+                    source: m.source.start..split,
+                });
+                m = SourceMapping {
+                    output: shifts[i].0..m.output.end,
+                    source: split..m.source.end,
+                };
+                delta += shifts[i].1;
+                i += 1;
+            }
+            m.output = shift(m.output, delta);
+            mappings.push(m);
+        }
+        self.mappings = mappings;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_merges_contiguous_copies() {
+        let mut map = SourceMap::default();
+        map.push(0..2, 0..2);
+        map.push(2..4, 2..4);
+        map.push(4..8, 4..5);
+        map.push(10..11, 5..6);
+        assert_eq!(
+            map.mappings(),
+            [
+                SourceMapping {
+                    output: 0..4,
+                    source: 0..4
+                },
+                SourceMapping {
+                    output: 4..8,
+                    source: 4..5
+                },
+                SourceMapping {
+                    output: 10..11,
+                    source: 5..6
+                },
+            ]
+        );
+        assert_eq!(map.source_offset(3), Some(3));
+        assert_eq!(map.source_offset(7), Some(4));
+        assert_eq!(map.source_offset(9), None);
+        assert_eq!(map.output_offset(4), Some(4));
+        assert_eq!(map.output_offset(5), Some(10));
+        assert_eq!(map.output_offset(6), None);
+    }
+
+    #[test]
+    fn test_shift_output_splits_copies() {
+        let mut map = SourceMap::identity(4);
+        map.shift_output(&[(0, 1), (2, 3)]);
+        assert_eq!(map.output_offset(0), Some(1));
+        assert_eq!(map.output_offset(1), Some(2));
+        assert_eq!(map.output_offset(2), Some(6));
+        assert_eq!(map.source_offset(4), None);
+    }
+}
@@ -0,0 +1,161 @@
+//! Output as [`anstyle`] styles, behind the `anstyle` feature.
+//!
+//! CLIs built on the anstyle ecosystem (`clap`, `anstream`, ...) already
+//! handle terminal capabilities, `NO_COLOR` and legacy Windows consoles when
+//! writing styled text. [`spans_to_anstyle`] hands them highlighted segments
+//! to write that way, instead of a string with escape sequences baked in.
+
+use anstyle::{Ansi256Color, AnsiColor, Effects, RgbColor};
+use arborium_theme::{Color, Style as ThemeStyle, Theme, capture_to_slot, slot_to_highlight_index};
+
+use crate::{ColorDepth, Span, spans_to_tokens};
+
+/// Split `source` into segments styled according to `spans` and `theme`.
+///
+/// Segments are in order, cover all of `source`, and adjacent segments never
+/// share a style. Unhighlighted text gets [`anstyle::Style::new()`]. Theme
+/// colors are emitted as RGB, or quantized for smaller palettes according to
+/// `depth`.
+///
+/// ```rust
+/// use arborium_highlight::{ColorDepth, Span, spans_to_anstyle};
+/// use arborium_theme::builtin;
+///
+/// let spans = vec![Span { start: 0, end: 2, capture: "keyword".into() }];
+/// let theme = builtin::catppuccin_mocha();
+/// let segments = spans_to_anstyle("fn main", spans, &theme, ColorDepth::TrueColor);
+///
+/// assert_eq!(segments[0].1, "fn");
+/// assert!(segments[0].0.get_fg_color().is_some());
+/// assert_eq!(segments[1], (anstyle::Style::new(), " main"));
+///
+/// // With anstream, `anstream::print!` would adapt this to the terminal
+/// let text: String = segments
+///     .iter()
+///     .map(|(style, text)| format!("{style}{text}{style:#}"))
+///     .collect();
+/// assert!(text.ends_with(" main"));
+/// ```
+pub fn spans_to_anstyle<'a>(
+    source: &'a str,
+    spans: Vec<Span>,
+    theme: &Theme,
+    depth: ColorDepth,
+) -> Vec<(anstyle::Style, &'a str)> {
+    let mut segments: Vec<(anstyle::Style, &'a str)> = Vec::new();
+    for token in spans_to_tokens(source, spans) {
+        let style = token
+            .capture
+            .as_deref()
+            .and_then(|capture| slot_to_highlight_index(capture_to_slot(capture)))
+            .and_then(|index| theme.style(index))
+            .map(|style| to_anstyle(style, depth))
+            .unwrap_or_default();
+
+        match segments.last_mut() {
+            // Tokens are contiguous, so the texts can be joined in place
+            Some((last_style, last_text)) if *last_style == style => {
+                let start = last_text.as_ptr() as usize - source.as_ptr() as usize;
+                *last_text = &source[start..token.range.end];
+            }
+            _ => segments.push((style, token.text)),
+        }
+    }
+    segments
+}
+
+/// Convert a theme style to an [`anstyle::Style`] for the given color depth.
+pub fn to_anstyle(style: &ThemeStyle, depth: ColorDepth) -> anstyle::Style {
+    let mut effects = Effects::new();
+    let modifiers = &style.modifiers;
+    for (enabled, effect) in [
+        (modifiers.bold, Effects::BOLD),
+        (modifiers.italic, Effects::ITALIC),
+        (modifiers.underline, Effects::UNDERLINE),
+        (modifiers.strikethrough, Effects::STRIKETHROUGH),
+    ] {
+        if enabled {
+            effects |= effect;
+        }
+    }
+
+    anstyle::Style::new()
+        .fg_color(style.fg.and_then(|color| to_anstyle_color(color, depth)))
+        .bg_color(style.bg.and_then(|color| to_anstyle_color(color, depth)))
+        .effects(effects)
+}
+
+fn to_anstyle_color(color: Color, depth: ColorDepth) -> Option<anstyle::Color> {
+    const ANSI16: [AnsiColor; 16] = [
+        AnsiColor::Black,
+        AnsiColor::Red,
+        AnsiColor::Green,
+        AnsiColor::Yellow,
+        AnsiColor::Blue,
+        AnsiColor::Magenta,
+        AnsiColor::Cyan,
+        AnsiColor::White,
+        AnsiColor::BrightBlack,
+        AnsiColor::BrightRed,
+        AnsiColor::BrightGreen,
+        AnsiColor::BrightYellow,
+        AnsiColor::BrightBlue,
+        AnsiColor::BrightMagenta,
+        AnsiColor::BrightCyan,
+        AnsiColor::BrightWhite,
+    ];
+    match depth {
+        ColorDepth::NoColor => None,
+        ColorDepth::Ansi16 => Some(ANSI16[color.to_ansi16() as usize].into()),
+        ColorDepth::Ansi256 => Some(Ansi256Color(color.to_ansi256()).into()),
+        ColorDepth::TrueColor => Some(RgbColor(color.r, color.g, color.b).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_depths() {
+        let style = ThemeStyle::new().fg(Color::new(255, 0, 0)).bold();
+
+        let rgb = to_anstyle(&style, ColorDepth::TrueColor);
+        assert_eq!(rgb.get_fg_color(), Some(RgbColor(255, 0, 0).into()));
+        assert!(rgb.get_effects().contains(Effects::BOLD));
+
+        let ansi256 = to_anstyle(&style, ColorDepth::Ansi256);
+        assert_eq!(ansi256.get_fg_color(), Some(Ansi256Color(196).into()));
+
+        let ansi16 = to_anstyle(&style, ColorDepth::Ansi16);
+        assert_eq!(ansi16.get_fg_color(), Some(AnsiColor::BrightRed.into()));
+
+        let plain = to_anstyle(&style, ColorDepth::NoColor);
+        assert_eq!(plain.get_fg_color(), None);
+        assert!(plain.get_effects().contains(Effects::BOLD));
+    }
+
+    #[test]
+    fn test_segments_cover_source() {
+        let mut theme = Theme::new("test");
+        let index = |capture| slot_to_highlight_index(capture_to_slot(capture)).unwrap();
+        let red = ThemeStyle::new().fg(Color::new(255, 0, 0));
+        theme.set_style(index("keyword"), red.clone());
+        theme.set_style(index("function"), red);
+
+        let span = |start, end, capture: &str| Span {
+            start,
+            end,
+            capture: capture.into(),
+        };
+        let segments = spans_to_anstyle(
+            "fnmain()",
+            vec![span(0, 2, "keyword"), span(2, 6, "function")],
+            &theme,
+            ColorDepth::TrueColor,
+        );
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].1, "fnmain");
+        assert_eq!(segments[1], (anstyle::Style::new(), "()"));
+    }
+}
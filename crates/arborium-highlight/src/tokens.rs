@@ -0,0 +1,151 @@
+//! Flat token output covering the whole source.
+//!
+//! Raw spans may overlap and leave gaps. [`spans_to_tokens`] resolves them
+//! into a sequence of non-overlapping tokens that, concatenated, reproduce the
+//! source exactly, which is the easiest shape for custom renderers to consume.
+
+use std::ops::Range;
+
+use arborium_theme::tag_for_capture;
+
+use crate::Span;
+
+/// A run of source text with at most one capture.
+///
+/// Produced by [`spans_to_tokens`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token<'a> {
+    /// Byte range of the token in the source.
+    pub range: Range<usize>,
+    /// The source text of the token, i.e. `&source[range]`.
+    pub text: &'a str,
+    /// The innermost capture covering the token, or `None` for unhighlighted
+    /// text.
+    pub capture: Option<String>,
+}
+
+/// Split `source` into tokens according to `spans`.
+///
+/// Tokens are in order, don't overlap, and cover all of `source`. Where spans
+/// nest or overlap, the innermost (most recently started) one wins, as in the
+/// HTML renderer. Captures that map to no theme slot (such as `spell`) are
+/// ignored, and adjacent tokens with the same capture are merged.
+///
+/// ```rust
+/// use arborium_highlight::{Span, spans_to_tokens};
+///
+/// let spans = vec![Span { start: 0, end: 2, capture: "keyword".into() }];
+/// let tokens = spans_to_tokens("fn main", spans);
+///
+/// assert_eq!(tokens.len(), 2);
+/// assert_eq!(tokens[0].text, "fn");
+/// assert_eq!(tokens[0].capture.as_deref(), Some("keyword"));
+/// assert_eq!(tokens[1].range, 2..7);
+/// assert_eq!(tokens[1].capture, None);
+/// ```
+pub fn spans_to_tokens(source: &str, spans: Vec<Span>) -> Vec<Token<'_>> {
+    let spans: Vec<Span> = spans
+        .into_iter()
+        .filter(|span| tag_for_capture(&span.capture).is_some())
+        .collect();
+
+    // Boundary events, as in the HTML renderer: ends before starts at the same
+    // position, and the most recently opened span is the one in effect.
+    let mut events: Vec<(usize, bool, usize)> = Vec::with_capacity(spans.len() * 2);
+    for (i, span) in spans.iter().enumerate() {
+        let start = (span.start as usize).min(source.len());
+        let end = (span.end as usize).min(source.len());
+        if start < end {
+            events.push((start, true, i));
+            events.push((end, false, i));
+        }
+    }
+    events.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+
+    let mut tokens: Vec<Token<'_>> = Vec::new();
+    let mut push = |start: usize, end: usize, capture: Option<&str>| {
+        if start >= end || !source.is_char_boundary(start) || !source.is_char_boundary(end) {
+            return;
+        }
+        if let Some(last) = tokens.last_mut() {
+            if last.range.end == start && last.capture.as_deref() == capture {
+                last.range.end = end;
+                last.text = &source[last.range.clone()];
+                return;
+            }
+        }
+        tokens.push(Token {
+            range: start..end,
+            text: &source[start..end],
+            capture: capture.map(str::to_string),
+        });
+    };
+
+    let mut last_pos = 0;
+    let mut stack: Vec<usize> = Vec::new();
+    for (pos, is_start, span_idx) in events {
+        if pos > last_pos {
+            let capture = stack.last().map(|&i| spans[i].capture.as_str());
+            push(last_pos, pos, capture);
+            last_pos = pos;
+        }
+
+        if is_start {
+            stack.push(span_idx);
+        } else if let Some(idx) = stack.iter().rposition(|&x| x == span_idx) {
+            stack.remove(idx);
+        }
+    }
+    push(last_pos, source.len(), None);
+
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(start: u32, end: u32, capture: &str) -> Span {
+        Span {
+            start,
+            end,
+            capture: capture.into(),
+        }
+    }
+
+    #[test]
+    fn test_tokens_cover_source() {
+        let source = "let s = \"a{x}b\";";
+        let spans = vec![
+            span(0, 3, "keyword"),
+            span(8, 15, "string"),
+            span(11, 12, "variable"),
+            span(8, 15, "spell"),
+        ];
+        let tokens = spans_to_tokens(source, spans);
+
+        let text: String = tokens.iter().map(|token| token.text).collect();
+        assert_eq!(text, source);
+
+        let summary: Vec<(&str, Option<&str>)> = tokens
+            .iter()
+            .map(|token| (token.text, token.capture.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("let", Some("keyword")),
+                (" s = ", None),
+                ("\"a{", Some("string")),
+                ("x", Some("variable")),
+                ("}b\"", Some("string")),
+                (";", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_empty_source() {
+        assert!(spans_to_tokens("", vec![span(0, 3, "keyword")]).is_empty());
+    }
+}
@@ -31,8 +31,10 @@
 //! }).collect();
 //! ```
 
+use std::ops::Range;
+
 use crate::types::{Injection, ParseResult, Span};
-use arborium_tree_sitter::{Language, Parser, Query, QueryCursor};
+use arborium_tree_sitter::{Language, ParseOptions, ParseState, Parser, Query, QueryCursor, Tree};
 use streaming_iterator::StreamingIterator;
 
 /// Configuration for creating a [`CompiledGrammar`].
@@ -153,21 +155,96 @@ impl CompiledGrammar {
     /// Requires a [`ParseContext`] which holds the mutable parser state.
     /// Each thread should have its own context.
     pub fn parse(&self, ctx: &mut ParseContext, text: &str) -> ParseResult {
-        // Parse the text
-        let tree = match ctx.parser.parse(text, None) {
-            Some(tree) => tree,
-            None => return ParseResult::default(),
-        };
+        self.parse_with_tree(ctx, text).0
+    }
+
+    /// Parse text like [`parse`](Self::parse), also returning the syntax tree.
+    ///
+    /// Use the tree to run additional queries (e.g. symbol extraction) without
+    /// parsing the source a second time. The tree is `None` only if parsing
+    /// failed, in which case the result is empty.
+    pub fn parse_with_tree(
+        &self,
+        ctx: &mut ParseContext,
+        text: &str,
+    ) -> (ParseResult, Option<Tree>) {
+        match ctx.parser.parse(text, None) {
+            Some(tree) => (self.query_tree(&mut ctx.cursor, &tree, text), Some(tree)),
+            None => (ParseResult::default(), None),
+        }
+    }
+
+    /// Parse text like [`parse`](Self::parse), aborting if `should_cancel` returns `true`.
+    ///
+    /// `should_cancel` is polled periodically while tree-sitter builds the syntax
+    /// tree, so it can check a cancellation flag or a deadline. Returns `None` if
+    /// parsing was aborted; the context is reset and can be reused.
+    pub fn parse_cancellable(
+        &self,
+        ctx: &mut ParseContext,
+        text: &str,
+        should_cancel: &mut dyn FnMut() -> bool,
+    ) -> Option<ParseResult> {
+        self.parse_cancellable_with_tree(ctx, text, should_cancel)
+            .map(|(result, _)| result)
+    }
+
+    /// Parse text like [`parse_cancellable`](Self::parse_cancellable), also
+    /// returning the syntax tree.
+    pub fn parse_cancellable_with_tree(
+        &self,
+        ctx: &mut ParseContext,
+        text: &str,
+        should_cancel: &mut dyn FnMut() -> bool,
+    ) -> Option<(ParseResult, Tree)> {
+        let bytes = text.as_bytes();
+        let len = bytes.len();
+        let mut progress = |_: &ParseState| should_cancel();
+        let options = ParseOptions::new().progress_callback(&mut progress);
+
+        let tree = ctx.parser.parse_with_options(
+            &mut |i, _| if i < len { &bytes[i..] } else { &[] },
+            None,
+            Some(options),
+        );
+
+        match tree {
+            Some(tree) => Some((self.query_tree(&mut ctx.cursor, &tree, text), tree)),
+            None => {
+                // Otherwise the next parse would resume this one
+                ctx.parser.reset();
+                None
+            }
+        }
+    }
+
+    /// Run the highlight and injection queries over a tree the caller parsed.
+    ///
+    /// Use this when you already maintain a syntax tree, for example with your
+    /// own incremental parser, to avoid the re-parse that [`parse`](Self::parse)
+    /// performs. `tree` must have been produced from `text` with this grammar's
+    /// language; otherwise the captured ranges are meaningless.
+    ///
+    /// ```rust,ignore
+    /// let mut parser = Parser::new();
+    /// parser.set_language(grammar.language())?;
+    /// let tree = parser.parse(source, None).unwrap();
+    ///
+    /// let result = grammar.highlight_tree(&tree, source);
+    /// ```
+    pub fn highlight_tree(&self, tree: &Tree, text: &str) -> ParseResult {
+        self.query_tree(&mut QueryCursor::new(), tree, text)
+    }
 
+    /// Run the highlight and injection queries over a parsed tree.
+    fn query_tree(&self, cursor: &mut QueryCursor, tree: &Tree, text: &str) -> ParseResult {
         let root_node = tree.root_node();
         let source = text.as_bytes();
 
         // Collect highlight spans
         let mut spans = Vec::new();
 
-        let mut matches = ctx
-            .cursor
-            .matches(&self.highlights_query, root_node, source);
+        let mut matches = cursor.matches(&self.highlights_query, root_node, source);
 
         while let Some(m) = matches.next() {
             for capture in m.captures {
@@ -196,7 +273,7 @@ impl CompiledGrammar {
         let mut injections = Vec::new();
 
         if let Some(ref injections_query) = self.injections_query {
-            let mut matches = ctx.cursor.matches(injections_query, root_node, source);
+            let mut matches = cursor.matches(injections_query, root_node, source);
 
             while let Some(m) = matches.next() {
                 let mut content_node = None;
@@ -243,7 +320,42 @@ impl CompiledGrammar {
             }
         }
 
-        ParseResult { spans, injections }
+        ParseResult {
+            spans,
+            injections,
+            errors: error_ranges(tree),
+        }
+    }
+}
+
+/// Byte ranges of the `ERROR` and `MISSING` nodes in `tree`.
+///
+/// Only subtrees that contain errors are visited, and the children of an
+/// `ERROR` node are not reported separately.
+fn error_ranges(tree: &Tree) -> Vec<Range<u32>> {
+    let mut errors = Vec::new();
+    if !tree.root_node().has_error() {
+        return errors;
+    }
+
+    let mut cursor = tree.walk();
+    loop {
+        let node = cursor.node();
+        let descend = if node.is_error() || node.is_missing() {
+            errors.push(node.start_byte() as u32..node.end_byte() as u32);
+            false
+        } else {
+            node.has_error()
+        };
+
+        if descend && cursor.goto_first_child() {
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                return errors;
+            }
+        }
     }
 }
 
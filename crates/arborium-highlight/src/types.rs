@@ -1,6 +1,7 @@
 //! Core types for highlighting.
 
 use std::fmt;
+use std::ops::Range;
 
 /// A span of highlighted text.
 ///
@@ -8,6 +9,7 @@ use std::fmt;
 /// (e.g., "keyword.function", "include", "string.special.symbol").
 /// The capture name is later mapped to a theme slot for rendering.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Span {
     /// Byte offset where the span starts (inclusive).
     pub start: u32,
@@ -27,6 +29,7 @@ pub struct Span {
 /// Injections are detected by the grammar's injection query. For example,
 /// HTML can inject CSS and JavaScript into `<style>` and `<script>` tags.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Injection {
     /// Byte offset where the injection starts (inclusive).
     pub start: u32,
@@ -43,12 +46,19 @@ pub struct Injection {
 
 /// Result of parsing a document with a grammar.
 #[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ParseResult {
     /// Highlighted spans from this parse.
     pub spans: Vec<Span>,
 
     /// Injection points for other languages.
     pub injections: Vec<Injection>,
+
+    /// Byte ranges of `ERROR` and `MISSING` nodes in the syntax tree.
+    ///
+    /// Empty if the source parsed cleanly. `MISSING` nodes are zero-width,
+    /// so their range is empty and marks where the parser inserted a token.
+    pub errors: Vec<Range<u32>>,
 }
 
 /// Errors that can occur during highlighting.
@@ -101,6 +101,7 @@ fn parse_js_result(value: JsValue) -> ParseResult {
             return ParseResult {
                 spans,
                 injections: vec![],
+                errors: vec![],
             };
         }
     };
@@ -134,7 +135,11 @@ fn parse_js_result(value: JsValue) -> ParseResult {
         });
     }
 
-    ParseResult { spans, injections }
+    ParseResult {
+        spans,
+        injections,
+        errors: vec![],
+    }
 }
 
 /// A grammar that wraps a JS grammar handle.
@@ -265,6 +270,24 @@ impl HighlightConfig {
     pub fn set_html_format_class_names_with_prefix(&mut self, prefix: String) {
         self.html_format = CoreHtmlFormat::ClassNamesWithPrefix(prefix);
     }
+
+    /// Set HTML format to highlight.js class names: `<span class="hljs-keyword">`, etc.
+    #[wasm_bindgen(js_name = setHtmlFormatHljsClasses)]
+    pub fn set_html_format_hljs_classes(&mut self) {
+        self.html_format = CoreHtmlFormat::HljsClasses;
+    }
+
+    /// Set HTML format to Prism.js token classes: `<span class="token keyword">`, etc.
+    #[wasm_bindgen(js_name = setHtmlFormatPrismClasses)]
+    pub fn set_html_format_prism_classes(&mut self) {
+        self.html_format = CoreHtmlFormat::PrismClasses;
+    }
+
+    /// Set HTML format to Pygments short classes: `<span class="k">`, etc.
+    #[wasm_bindgen(js_name = setHtmlFormatPygmentsClasses)]
+    pub fn set_html_format_pygments_classes(&mut self) {
+        self.html_format = CoreHtmlFormat::PygmentsClasses;
+    }
 }
 
 impl Default for HighlightConfig {
@@ -31,10 +31,11 @@ pub use arborium_tree_sitter as tree_sitter;
 use std::collections::HashSet;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-use arborium_highlight::{CompiledGrammar, GrammarConfig, ParseContext};
+use arborium_highlight::{CompiledGrammar, GrammarConfig, ParseContext, ParseResult, Span};
 use arborium_tree_sitter::Language;
-use arborium_tree_sitter::{Node, Parser, Tree};
+use arborium_tree_sitter::{Node, Parser, Query, QueryCursor, StreamingIterator, Tree, TreeCursor};
 use tree_sitter_language::LanguageFn;
 
 // Re-export CAPTURE_NAMES from arborium-theme as HIGHLIGHT_NAMES for convenience
@@ -45,7 +46,12 @@ struct CorpusTest {
     name: String,
     input: String,
     contains: Vec<String>,
+    not_contains: Vec<String>,
     expected_sexp: Option<String>,
+    snapshot: bool,
+    highlights: Vec<(String, String)>,
+    locals: Vec<(String, String)>,
+    allow_errors: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -54,7 +60,56 @@ pub struct CorpusCase {
     pub name: String,
     pub input: String,
     pub contains: Vec<String>,
+    /// Node kinds that must not appear anywhere in the parse tree.
+    pub not_contains: Vec<String>,
     pub expected_sexp: Option<String>,
+    /// Whether the parse tree is compared against a snapshot file (see
+    /// [`CorpusCase::snapshot_path`]).
+    pub snapshot: bool,
+    /// Token texts and the capture each must be highlighted with.
+    pub highlights: Vec<(String, String)>,
+    /// Node texts and the locals capture each must be captured with.
+    pub locals: Vec<(String, String)>,
+    /// Whether the parse tree may contain `ERROR` and `MISSING` nodes.
+    pub allow_errors: bool,
+}
+
+impl CorpusCase {
+    /// The corpus file's stem and the case name, e.g. `strings/escapes`.
+    pub fn id(&self) -> String {
+        let stem = self
+            .file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        format!("{}/{}", stem, self.name)
+    }
+
+    /// Whether the case matches a corpus filter: a glob, where `*` matches
+    /// any run of characters and `?` any one character. Filters with a `/`
+    /// match the [`id`](Self::id), e.g. `strings/*` for every case in
+    /// `strings.txt`; others match the case name alone.
+    pub fn matches_filter(&self, filter: &str) -> bool {
+        if filter.contains('/') {
+            glob_match(filter, &self.id())
+        } else {
+            glob_match(filter, &self.name)
+        }
+    }
+
+    /// Where this case's snapshot is stored: next to the corpus file, as
+    /// `snapshots/<file stem>/<test name>.snap`.
+    pub fn snapshot_path(&self) -> PathBuf {
+        let stem = self
+            .file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        self.file
+            .with_file_name("snapshots")
+            .join(stem)
+            .join(format!("{}.snap", slug(&self.name)))
+    }
 }
 
 #[derive(Debug)]
@@ -87,70 +142,325 @@ type HarnessResult<T = ()> = Result<T, HarnessError>;
 /// 2. Finds sample files in the samples/ directory
 /// 3. Highlights each sample file and verifies we get highlights
 ///
+/// A sample block can also list the languages its sample must inject, which
+/// checks that the injections query finds them, and texts it must highlight,
+/// optionally with a capture. A capture also accepts its subcaptures, so
+/// `keyword` is satisfied by `keyword.function`:
+///
+/// ```kdl
+/// sample {
+///     path "samples/App.svelte"
+///     must-inject "javascript" "css"
+///     must-highlight "main" capture="tag"
+///     must-highlight "href"
+/// }
+/// ```
+///
+/// When the `QUERY_COVERAGE` environment variable is set (to anything but
+/// `0`), it also prints which highlight patterns the samples and corpus never
+/// match (see [`query_coverage`]); run with `--nocapture` to see it. When
+/// `CHECK_NODE_TYPES` is set, it also checks that the queries only refer to
+/// node types in `node-types.json` (see [`check_query_node_types`]).
+///
 /// # Arguments
 ///
 /// * `language` - The tree-sitter Language
 /// * `name` - The grammar name (e.g., "rust")
 /// * `highlights_query` - The highlights.scm content
 /// * `injections_query` - The injections.scm content
-/// * `locals_query` - The locals.scm content (currently unused by arborium-highlight)
+/// * `locals_query` - The locals.scm content (compiled and checked with [`compile_locals_query`])
 /// * `crate_dir` - Path to the crate directory (use `env!("CARGO_MANIFEST_DIR")`)
 ///
 /// # Panics
 ///
-/// Panics if query validation fails, highlighting produces errors, or no highlights are found.
+/// Panics if query validation fails, highlighting produces errors, no highlights are found,
+/// or a sample doesn't inject a language or highlight a text it must.
 pub fn test_grammar(
     language: impl Into<Language>,
     name: &str,
     highlights_query: &str,
     injections_query: &str,
-    _locals_query: &str,
+    locals_query: &str,
     crate_dir: &str,
 ) {
     let language: Language = language.into();
-    // Create grammar config
-    let config = GrammarConfig {
-        language,
+    let report = try_test_grammar(
+        language.clone(),
+        name,
         highlights_query,
         injections_query,
-        locals_query: "", // Not used by arborium-highlight yet
-    };
+        locals_query,
+        crate_dir,
+    );
+    if !report.passed() {
+        panic!("{report}");
+    }
 
-    // Validate queries compile by creating the grammar
-    let grammar = CompiledGrammar::new(config).unwrap_or_else(|e| {
-        panic!(
-            "Query validation failed for {}: {:?}\n\
-             This usually means highlights.scm references a node type that doesn't exist in the grammar.\n\
-             Check the grammar's node-types.json to see valid node types.",
-            name, e
-        );
-    });
+    if env_flag("QUERY_COVERAGE") {
+        match query_coverage(language, name, highlights_query, crate_dir) {
+            Ok(coverage) => println!("{coverage}"),
+            Err(err) => panic!("Query coverage failed for {}: {}", name, err),
+        }
+    }
+}
 
-    // Create a parse context for this grammar
-    let mut ctx = ParseContext::for_grammar(&grammar).unwrap_or_else(|e| {
-        panic!("Failed to create parse context for {}: {:?}", name, e);
-    });
+/// Runs the checks of [`test_grammar`] and reports every failure instead of
+/// panicking at the first one, for CI wrappers and runners that test many
+/// grammars. The report has a result per sample, with its span count and
+/// how long it took to highlight.
+pub fn try_test_grammar(
+    language: impl Into<Language>,
+    name: &str,
+    highlights_query: &str,
+    injections_query: &str,
+    locals_query: &str,
+    crate_dir: &str,
+) -> TestReport {
+    let mut report = TestReport::new(name);
+    let language: Language = language.into();
+    let (grammar, mut ctx) =
+        match try_compile_grammar(language.clone(), name, highlights_query, injections_query) {
+            Ok(compiled) => compiled,
+            Err(err) => {
+                report.errors.push(err.to_string());
+                return report;
+            }
+        };
+    if let Err(err) = compile_locals_query(language, name, locals_query) {
+        report.errors.push(err.to_string());
+    }
+    if env_flag("CHECK_NODE_TYPES")
+        && let Err(err) = check_query_node_types(
+            crate_dir,
+            &[
+                ("highlights.scm", highlights_query),
+                ("injections.scm", injections_query),
+                ("locals.scm", locals_query),
+            ],
+        )
+    {
+        report
+            .errors
+            .push(format!("Query validation failed for {}: {}", name, err));
+    }
 
-    // Find samples from arborium.kdl
-    let crate_path = Path::new(crate_dir);
-    let kdl_path = crate_path.join("arborium.kdl");
-    let samples: Vec<_> = if kdl_path.exists() {
-        parse_samples_from_kdl(&kdl_path)
-            .into_iter()
-            .map(|p| crate_path.join(p))
-            .collect()
-    } else {
-        vec![]
-    };
+    // Test each sample from arborium.kdl - must produce at least one highlight
+    for sample in samples(crate_dir) {
+        let start = Instant::now();
+        let (spans, outcome) = match fs::read_to_string(&sample.path) {
+            Ok(sample_code) => {
+                let result = grammar.parse(&mut ctx, &sample_code);
+                let outcome = check_sample(name, &sample, &sample_code, &result);
+                (Some(result.spans.len()), outcome)
+            }
+            Err(e) => (
+                None,
+                Err(format!(
+                    "Failed to read sample file {} for {}: {}",
+                    sample.path.display(),
+                    name,
+                    e
+                )),
+            ),
+        };
+        report.results.push(TestResult {
+            name: sample.path.display().to_string(),
+            error: outcome.err(),
+            spans,
+            duration: start.elapsed(),
+        });
+    }
 
-    if samples.is_empty() {
-        // No samples - just verify query compiles (already done above)
-        return;
+    report
+}
+
+/// Check a sample's highlights against what its `sample` block requires.
+fn check_sample(
+    name: &str,
+    sample: &Sample,
+    sample_code: &str,
+    result: &ParseResult,
+) -> Result<(), String> {
+    let sample_path = &sample.path;
+
+    // Verify we got highlights
+    if result.spans.is_empty() {
+        return Err(format!(
+            "No highlights produced for {} in {}.\n\
+             Sample has {} bytes.\n\
+             This likely means the highlights.scm query doesn't match anything in the sample.",
+            sample_path.display(),
+            name,
+            sample_code.len()
+        ));
+    }
+
+    for (text, capture) in &sample.must_highlight {
+        let captures: Vec<&str> = result
+            .spans
+            .iter()
+            .filter(|span| sample_code.get(span.start as usize..span.end as usize) == Some(text))
+            .map(|span| span.capture.as_str())
+            .collect();
+        let highlighted = match capture {
+            Some(capture) => captures.iter().any(|c| {
+                c.strip_prefix(capture.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+            }),
+            None => !captures.is_empty(),
+        };
+        if !highlighted {
+            return Err(format!(
+                "Sample {} for {} must highlight `{}`{}, but it has {:?}.",
+                sample_path.display(),
+                name,
+                text,
+                capture
+                    .as_ref()
+                    .map(|c| format!(" as `{c}`"))
+                    .unwrap_or_default(),
+                captures
+            ));
+        }
+    }
+
+    let missing: Vec<&str> = sample
+        .must_inject
+        .iter()
+        .filter(|lang| !result.injections.iter().any(|i| &i.language == *lang))
+        .map(String::as_str)
+        .collect();
+    if !missing.is_empty() {
+        let mut found: Vec<&str> = result
+            .injections
+            .iter()
+            .map(|i| i.language.as_str())
+            .collect();
+        found.sort_unstable();
+        found.dedup();
+        return Err(format!(
+            "Sample {} for {} has no {} injections (found: {}).\n\
+             Check the injections.scm query.",
+            sample_path.display(),
+            name,
+            missing.join(", "),
+            if found.is_empty() {
+                "none".to_string()
+            } else {
+                found.join(", ")
+            }
+        ));
+    }
+
+    Ok(())
+}
+
+/// The outcome of [`try_test_grammar`] or [`try_test_corpus`].
+///
+/// Displays as a summary line followed by the failures.
+#[derive(Debug, Clone, Default)]
+pub struct TestReport {
+    /// The grammar name.
+    pub name: String,
+    /// Failures that aren't about a single sample or case, such as queries
+    /// that don't compile.
+    pub errors: Vec<String>,
+    /// One result per sample or corpus case, in order.
+    pub results: Vec<TestResult>,
+}
+
+/// How one sample or corpus case fared, in a [`TestReport`].
+#[derive(Debug, Clone)]
+pub struct TestResult {
+    /// The sample's path, or the corpus case's [`id`](CorpusCase::id).
+    pub name: String,
+    /// Why it failed, or `None` if it passed.
+    pub error: Option<String>,
+    /// How many highlight spans the sample produced; `None` for corpus
+    /// cases and samples that couldn't be read.
+    pub spans: Option<usize>,
+    /// How long checking it took.
+    pub duration: Duration,
+}
+
+impl TestReport {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Whether there were no errors and every sample or case passed.
+    pub fn passed(&self) -> bool {
+        self.errors.is_empty() && self.failures().next().is_none()
+    }
+
+    /// The samples or cases that failed.
+    pub fn failures(&self) -> impl Iterator<Item = &TestResult> {
+        self.results.iter().filter(|result| result.error.is_some())
+    }
+}
+
+impl std::fmt::Display for TestReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let failed = self.failures().count();
+        write!(
+            f,
+            "{}: {} passed, {} failed",
+            self.name,
+            self.results.len() - failed,
+            failed
+        )?;
+        if !self.errors.is_empty() {
+            let noun = if self.errors.len() == 1 {
+                "error"
+            } else {
+                "errors"
+            };
+            write!(f, ", {} {}", self.errors.len(), noun)?;
+        }
+        for error in &self.errors {
+            write!(f, "\n\n{error}")?;
+        }
+        for result in self.failures() {
+            write!(
+                f,
+                "\n\n{}: {}",
+                result.name,
+                result.error.as_deref().unwrap_or_default()
+            )?;
+        }
+        Ok(())
     }
+}
 
-    // Test each sample - must produce at least one highlight
-    for sample_path in &samples {
-        let sample_code = fs::read_to_string(sample_path).unwrap_or_else(|e| {
+/// Compares the highlights of every sample against snapshot files.
+///
+/// For each sample listed in `arborium.kdl`, the spans the highlights query
+/// produces are written one per line (byte range, capture name and text) and
+/// compared with `snapshots/<sample file name>.snap` in the crate directory.
+/// Run with `UPDATE_SNAPSHOTS=1` to write the snapshots instead, after a
+/// grammar or query update; the diff then shows what changed.
+///
+/// Takes the same arguments as [`test_grammar`].
+///
+/// # Panics
+///
+/// Panics if the queries don't compile, a sample can't be read, or a
+/// snapshot is missing or differs.
+pub fn snapshot_highlights(
+    language: impl Into<Language>,
+    name: &str,
+    highlights_query: &str,
+    injections_query: &str,
+    crate_dir: &str,
+) {
+    let (grammar, mut ctx) = compile_grammar(language, name, highlights_query, injections_query);
+
+    let snapshots_dir = Path::new(crate_dir).join("snapshots");
+    for sample_path in sample_paths(crate_dir) {
+        let sample_code = fs::read_to_string(&sample_path).unwrap_or_else(|e| {
             panic!(
                 "Failed to read sample file {} for {}: {}",
                 sample_path.display(),
@@ -158,24 +468,395 @@ pub fn test_grammar(
                 e
             );
         });
-
-        // Parse with the grammar
         let result = grammar.parse(&mut ctx, &sample_code);
 
-        // Count highlight spans
-        let highlight_count = result.spans.len();
+        let file_name = sample_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+        let snapshot_path = snapshots_dir.join(format!("{file_name}.snap"));
+        if let Err(err) = assert_snapshot(&snapshot_path, &format_spans(&sample_code, result.spans))
+        {
+            panic!("Highlight snapshot failure for {}: {}", name, err);
+        }
+    }
+}
+
+/// The time [`bench_grammar`] allows per kilobyte of sample by default.
+///
+/// Generous enough for unoptimized test builds; grammars that hit it are
+/// usually backtracking through a pathological regex or query predicate.
+pub const DEFAULT_BENCH_BUDGET_PER_KB: Duration = Duration::from_millis(25);
 
-        // Verify we got highlights
-        if highlight_count == 0 {
+/// How many times [`bench_grammar`] highlights each sample, keeping the
+/// fastest run to smooth out noise from other processes.
+const BENCH_RUNS: usize = 5;
+
+/// Times parsing and highlighting each sample, failing on slow ones.
+///
+/// Every sample listed in `arborium.kdl` is highlighted once to warm up, then
+/// five more times; the fastest run must fit in a budget of
+/// [`DEFAULT_BENCH_BUDGET_PER_KB`] per kilobyte, counting samples under a
+/// kilobyte as one. Set `BENCH_BUDGET_MS_PER_KB` to change the budget, e.g.
+/// lower for release builds. The timings are printed; run with
+/// `--nocapture` to see them.
+///
+/// Takes the same arguments as [`snapshot_highlights`].
+///
+/// # Panics
+///
+/// Panics if the queries don't compile, a sample can't be read, the budget
+/// isn't a whole number of milliseconds, or any sample is over budget.
+pub fn bench_grammar(
+    language: impl Into<Language>,
+    name: &str,
+    highlights_query: &str,
+    injections_query: &str,
+    crate_dir: &str,
+) {
+    let budget = match std::env::var("BENCH_BUDGET_MS_PER_KB") {
+        Ok(ms) => Duration::from_millis(ms.trim().parse().unwrap_or_else(|_| {
+            panic!("BENCH_BUDGET_MS_PER_KB must be a number of milliseconds, not {ms:?}")
+        })),
+        Err(_) => DEFAULT_BENCH_BUDGET_PER_KB,
+    };
+    let (grammar, mut ctx) = compile_grammar(language, name, highlights_query, injections_query);
+
+    let mut over_budget = Vec::new();
+    for sample_path in sample_paths(crate_dir) {
+        let sample_code = fs::read_to_string(&sample_path).unwrap_or_else(|e| {
             panic!(
-                "No highlights produced for {} in {}.\n\
-                 Sample has {} bytes.\n\
-                 This likely means the highlights.scm query doesn't match anything in the sample.",
+                "Failed to read sample file {} for {}: {}",
                 sample_path.display(),
                 name,
-                sample_code.len()
+                e
+            );
+        });
+
+        grammar.parse(&mut ctx, &sample_code);
+        let elapsed = (0..BENCH_RUNS)
+            .map(|_| {
+                let start = Instant::now();
+                grammar.parse(&mut ctx, &sample_code);
+                start.elapsed()
+            })
+            .min()
+            .unwrap_or_default();
+        let allowed = budget.mul_f64((sample_code.len() as f64 / 1024.0).max(1.0));
+
+        let line = format!(
+            "{}: {} bytes in {:.2?} (budget {:.2?})",
+            sample_path.display(),
+            sample_code.len(),
+            elapsed,
+            allowed
+        );
+        println!("{line}");
+        if elapsed > allowed {
+            over_budget.push(line);
+        }
+    }
+
+    if !over_budget.is_empty() {
+        panic!(
+            "Samples for {} took longer than {:?} per KB to highlight:\n{}",
+            name,
+            budget,
+            over_budget.join("\n")
+        );
+    }
+}
+
+/// Compare `actual` with the snapshot stored at `path`.
+///
+/// When the `UPDATE_SNAPSHOTS` environment variable is set (to anything but
+/// `0`), the snapshot is written instead, creating its directory if needed.
+pub fn assert_snapshot(path: &Path, actual: &str) -> HarnessResult<()> {
+    if env_flag("UPDATE_SNAPSHOTS") {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| {
+                HarnessError::new(format!(
+                    "Failed to create snapshot directory {}: {}",
+                    dir.display(),
+                    e
+                ))
+            })?;
+        }
+        return fs::write(path, actual).map_err(|e| {
+            HarnessError::new(format!(
+                "Failed to write snapshot {}: {}",
+                path.display(),
+                e
+            ))
+        });
+    }
+
+    let expected = fs::read_to_string(path).map_err(|e| {
+        HarnessError::new(format!(
+            "Failed to read snapshot {} ({}); run with UPDATE_SNAPSHOTS=1 to create it",
+            path.display(),
+            e
+        ))
+    })?;
+    if expected != actual {
+        return Err(HarnessError::new(format!(
+            "Snapshot {} is out of date; run with UPDATE_SNAPSHOTS=1 to update it\n--- expected ---\n{}\n--- actual ---\n{}",
+            path.display(),
+            expected,
+            actual
+        )));
+    }
+    Ok(())
+}
+
+/// Whether the environment variable `name` is set to anything but `0`.
+fn env_flag(name: &str) -> bool {
+    std::env::var(name).is_ok_and(|v| !v.is_empty() && v != "0")
+}
+
+/// How much of a highlights query a grammar's samples and corpus exercise.
+///
+/// Returned by [`query_coverage`]. Displays as a report listing the patterns
+/// that never matched.
+#[derive(Debug, Clone)]
+pub struct QueryCoverage {
+    /// The grammar name.
+    pub name: String,
+    /// The number of patterns in the query.
+    pub patterns: usize,
+    /// Patterns that matched nothing in any input.
+    pub unmatched_patterns: Vec<UnmatchedPattern>,
+    /// Capture names that no match produced, leaving out internal captures
+    /// (those starting with `_`).
+    pub unmatched_captures: Vec<String>,
+}
+
+/// A pattern in a highlights query that never matched.
+#[derive(Debug, Clone)]
+pub struct UnmatchedPattern {
+    /// The pattern's index in the query.
+    pub index: usize,
+    /// The 1-based line in the query the pattern starts on.
+    pub line: usize,
+    /// The pattern's source text.
+    pub text: String,
+}
+
+impl QueryCoverage {
+    /// The percentage of patterns that matched at least once, from 0 to 100.
+    pub fn percentage(&self) -> f64 {
+        if self.patterns == 0 {
+            return 100.0;
+        }
+        let matched = self.patterns - self.unmatched_patterns.len();
+        matched as f64 * 100.0 / self.patterns as f64
+    }
+}
+
+impl std::fmt::Display for QueryCoverage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Highlights query coverage for {}: {:.1}% ({} of {} patterns matched)",
+            self.name,
+            self.percentage(),
+            self.patterns - self.unmatched_patterns.len(),
+            self.patterns
+        )?;
+        if !self.unmatched_patterns.is_empty() {
+            writeln!(f, "--- unmatched patterns ---")?;
+            for pattern in &self.unmatched_patterns {
+                let first_line = pattern.text.lines().next().unwrap_or_default();
+                writeln!(f, "line {}: {}", pattern.line, first_line)?;
+            }
+        }
+        if !self.unmatched_captures.is_empty() {
+            writeln!(f, "--- unmatched captures ---")?;
+            for capture in &self.unmatched_captures {
+                writeln!(f, "@{}", capture)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run a highlights query over every sample listed in `arborium.kdl` and
+/// every corpus input, and report which patterns and captures never matched.
+///
+/// Patterns that never match are dead weight, or broken: they may refer to
+/// nodes in a shape the grammar never produces, or have predicates that
+/// never hold. Samples and corpus cases that cover more of the language
+/// raise the percentage.
+pub fn query_coverage(
+    language: impl Into<Language>,
+    name: &str,
+    highlights_query: &str,
+    crate_dir: &str,
+) -> HarnessResult<QueryCoverage> {
+    let language: Language = language.into();
+    let query = Query::new(&language, highlights_query)
+        .map_err(|e| HarnessError::new(format!("Query validation failed for {}: {:?}", name, e)))?;
+    let mut parser = Parser::new();
+    parser
+        .set_language(&language)
+        .map_err(|e| HarnessError::new(format!("Failed to set language for {}: {:?}", name, e)))?;
+
+    let mut inputs = Vec::new();
+    for sample_path in sample_paths(crate_dir) {
+        inputs.push(fs::read_to_string(&sample_path).map_err(|e| {
+            HarnessError::new(format!(
+                "Failed to read sample file {}: {}",
+                sample_path.display(),
+                e
+            ))
+        })?);
+    }
+    inputs.extend(
+        collect_corpus_cases(crate_dir)?
+            .into_iter()
+            .map(|case| case.input),
+    );
+
+    let mut pattern_matched = vec![false; query.pattern_count()];
+    let mut capture_matched = vec![false; query.capture_names().len()];
+    let mut cursor = QueryCursor::new();
+    for input in &inputs {
+        let Some(tree) = parser.parse(input, None) else {
+            continue;
+        };
+        let mut matches = cursor.matches(&query, tree.root_node(), input.as_bytes());
+        while let Some(m) = matches.next() {
+            pattern_matched[m.pattern_index] = true;
+            for capture in m.captures {
+                capture_matched[capture.index as usize] = true;
+            }
+        }
+    }
+
+    let unmatched_patterns = (0..query.pattern_count())
+        .filter(|&index| !pattern_matched[index])
+        .map(|index| {
+            let start = query.start_byte_for_pattern(index);
+            let end = query.end_byte_for_pattern(index);
+            UnmatchedPattern {
+                index,
+                line: highlights_query[..start].matches('\n').count() + 1,
+                text: highlights_query[start..end].trim().to_string(),
+            }
+        })
+        .collect();
+    let unmatched_captures = query
+        .capture_names()
+        .iter()
+        .zip(&capture_matched)
+        .filter(|(name, matched)| !**matched && !name.starts_with('_'))
+        .map(|(name, _)| name.to_string())
+        .collect();
+
+    Ok(QueryCoverage {
+        name: name.to_string(),
+        patterns: query.pattern_count(),
+        unmatched_patterns,
+        unmatched_captures,
+    })
+}
+
+/// Fuzz entry point for a grammar's parser.
+///
+/// Parses `data` (as UTF-8, with invalid sequences replaced) and panics if
+/// the syntax tree is inconsistent: a node that ends before it starts,
+/// reaches past the end of the input, or isn't nested in its parent. Call it
+/// from a `cargo fuzz` target; the targets `cargo xtask gen` writes to each
+/// grammar crate's `fuzz/` directory do. Hangs are caught by libFuzzer's
+/// `-timeout` option rather than here.
+///
+/// ```ignore
+/// fuzz_target!(|data: &[u8]| {
+///     arborium_test_harness::fuzz_grammar(arborium_rust::language(), data);
+/// });
+/// ```
+pub fn fuzz_grammar(language: impl Into<Language>, data: &[u8]) {
+    let source = String::from_utf8_lossy(data);
+    let mut parser = Parser::new();
+    parser
+        .set_language(&language.into())
+        .expect("failed to set language");
+    let Some(tree) = parser.parse(source.as_bytes(), None) else {
+        return;
+    };
+
+    let mut cursor = tree.walk();
+    let mut parents: Vec<Node> = Vec::new();
+    loop {
+        let node = cursor.node();
+        let range = node.byte_range();
+        assert!(
+            range.start <= range.end && range.end <= source.len(),
+            "{} node has byte range {:?} in a {}-byte input",
+            node.kind(),
+            range,
+            source.len()
+        );
+        if let Some(parent) = parents.last() {
+            assert!(
+                parent.start_byte() <= range.start && range.end <= parent.end_byte(),
+                "{} node at {:?} isn't inside its {} parent at {:?}",
+                node.kind(),
+                range,
+                parent.kind(),
+                parent.byte_range()
             );
         }
+
+        if cursor.goto_first_child() {
+            parents.push(node);
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                return;
+            }
+            parents.pop();
+        }
+    }
+}
+
+/// Fuzz entry point for a grammar's queries.
+///
+/// Highlights `data` like [`fuzz_grammar`] parses it, and panics if a span,
+/// injection or error range is out of order, reaches past the end of the
+/// input, or splits a character, any of which would make renderers panic
+/// when they slice the source. Compile the grammar once, with
+/// [`compile_queries`], rather than for every input.
+pub fn fuzz_highlights(grammar: &CompiledGrammar, data: &[u8]) {
+    let source = String::from_utf8_lossy(data);
+    let mut ctx = ParseContext::for_grammar(grammar).expect("failed to create parse context");
+    let result = grammar.parse(&mut ctx, &source);
+
+    let check = |what: &str, start: u32, end: u32| {
+        let (start, end) = (start as usize, end as usize);
+        assert!(
+            start <= end && end <= source.len(),
+            "{what} has byte range {start}..{end} in a {}-byte input",
+            source.len()
+        );
+        assert!(
+            source.is_char_boundary(start) && source.is_char_boundary(end),
+            "{what} at {start}..{end} splits a character"
+        );
+    };
+    for span in &result.spans {
+        check(&format!("`{}` span", span.capture), span.start, span.end);
+    }
+    for injection in &result.injections {
+        check(
+            &format!("{} injection", injection.language),
+            injection.start,
+            injection.end,
+        );
+    }
+    for error in &result.errors {
+        check("error", error.start, error.end);
     }
 }
 
@@ -196,18 +877,54 @@ pub fn test_grammar(
 /// (document ...)
 /// ```
 ///
-/// Only `input` is required. `contains` and `sexp` are optional:
+/// Only `input` is required. The other sections are optional:
 /// - `contains`: node kinds that must appear at least once in the parse tree.
-/// - `sexp`: expected root s-expression (exact match).
+/// - `not-contains`: node kinds that must not appear in the parse tree, such
+///   as a fallback `identifier` where a keyword is expected.
+/// - `sexp`: expected root s-expression. Whitespace between nodes doesn't
+///   matter, and field names (`name: (identifier)`) can be left out.
+/// - `highlights`: lines like `fn -> keyword.function`, each asserting that
+///   the token text is highlighted with that capture somewhere in the
+///   input. These need the grammar's queries, so cases with them must be run
+///   with [`test_corpus_with_queries`].
+/// - `locals`: lines like `x -> local.definition.var`, the same for the
+///   captures of the locals query, e.g. to check that a parameter is a
+///   definition and its uses are references.
+/// - `snapshot`: an empty section; compares the parse tree, one node per
+///   line, with a snapshot file (see [`CorpusCase::snapshot_path`]) instead of
+///   a hand-written `sexp`. Run with `UPDATE_SNAPSHOTS=1` to write the
+///   snapshots (see [`assert_snapshot`]).
+/// - `allow-errors`: an empty section; lets the parse tree contain `ERROR`
+///   and `MISSING` nodes, which otherwise fail the case, to test error
+///   recovery. Combine it with `sexp`, `snapshot` or `contains ERROR` to
+///   assert on the recovered tree.
+///
+/// Files in the standard tree-sitter corpus format are accepted too, so
+/// upstream corpora can be vendored unchanged:
+///
+/// ```text
+/// ==================
+/// test name
+/// ==================
+///
+/// node 1;
+///
+/// ---
+///
+/// (document ...)
+/// ```
+///
+/// A `:skip` attribute under the name skips the test, and `:error` makes it
+/// expect an `ERROR` node instead of checking the tree. Trees may contain
+/// errors when the expected tree does.
+///
+/// Set `ARBORIUM_CORPUS_FILTER` to run only the cases that match it, such
+/// as `strings/*` (see [`CorpusCase::matches_filter`]), while working on a
+/// failing case.
 ///
 /// This does **not** use `tree-sitter test`; it's a lightweight Rust runner.
 pub fn test_corpus(language: LanguageFn, name: &str, crate_dir: &str) {
-    let cases = collect_corpus_cases(crate_dir).unwrap_or_else(|e| {
-        panic!(
-            "Failed to gather corpus cases for {} (crate dir {}): {}",
-            name, crate_dir, e
-        )
-    });
+    let cases = filtered_corpus_cases(name, crate_dir).unwrap_or_else(|e| panic!("{}", e));
 
     for case in &cases {
         if let Err(err) = run_corpus_case(language, name, case) {
@@ -222,6 +939,248 @@ pub fn test_corpus(language: LanguageFn, name: &str, crate_dir: &str) {
     }
 }
 
+/// Runs corpus tests like [`test_corpus`], also checking `highlights` and
+/// `locals` sections against the captures the grammar's queries produce.
+///
+/// # Panics
+///
+/// Panics if the queries don't compile or any case fails, listing every
+/// failing case.
+pub fn test_corpus_with_queries(
+    language: LanguageFn,
+    name: &str,
+    highlights_query: &str,
+    injections_query: &str,
+    locals_query: &str,
+    crate_dir: &str,
+) {
+    let report = try_test_corpus(
+        language,
+        name,
+        highlights_query,
+        injections_query,
+        locals_query,
+        crate_dir,
+    );
+    if !report.passed() {
+        panic!("{report}");
+    }
+}
+
+/// Runs the corpus tests of [`test_corpus_with_queries`] and reports every
+/// failing case instead of panicking, with a result and timing per case.
+pub fn try_test_corpus(
+    language: LanguageFn,
+    name: &str,
+    highlights_query: &str,
+    injections_query: &str,
+    locals_query: &str,
+    crate_dir: &str,
+) -> TestReport {
+    let mut report = TestReport::new(name);
+    let load = || -> HarnessResult<_> {
+        Ok((
+            compile_queries(language, name, highlights_query, injections_query)?,
+            compile_locals_query(language, name, locals_query)?,
+            filtered_corpus_cases(name, crate_dir)?,
+        ))
+    };
+    let (grammar, locals, cases) = match load() {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            report.errors.push(err.to_string());
+            return report;
+        }
+    };
+
+    for case in &cases {
+        let start = Instant::now();
+        let outcome = run_corpus_case_with_grammar(language, name, &grammar, case)
+            .and_then(|_| check_corpus_locals(language, name, &locals, case));
+        report.results.push(TestResult {
+            name: case.id(),
+            error: outcome.err().map(|err| {
+                format!(
+                    "Corpus failure for {} / {} (file {}): {}",
+                    name,
+                    case.name,
+                    case.file.display(),
+                    err
+                )
+            }),
+            spans: None,
+            duration: start.elapsed(),
+        });
+    }
+
+    report
+}
+
+/// The corpus filter from the `ARBORIUM_CORPUS_FILTER` environment
+/// variable, if it's set and not empty.
+pub fn corpus_filter() -> Option<String> {
+    std::env::var("ARBORIUM_CORPUS_FILTER")
+        .ok()
+        .filter(|filter| !filter.is_empty())
+}
+
+/// The corpus cases of a crate that match [`corpus_filter`].
+fn filtered_corpus_cases(name: &str, crate_dir: &str) -> HarnessResult<Vec<CorpusCase>> {
+    let mut cases = collect_corpus_cases(crate_dir).map_err(|e| {
+        HarnessError::new(format!(
+            "Failed to gather corpus cases for {} (crate dir {}): {}",
+            name, crate_dir, e
+        ))
+    })?;
+    if let Some(filter) = corpus_filter() {
+        let total = cases.len();
+        cases.retain(|case| case.matches_filter(&filter));
+        println!(
+            "Running {} of {} corpus cases for {} matching `{}`",
+            cases.len(),
+            total,
+            name,
+            filter
+        );
+    }
+    Ok(cases)
+}
+
+/// Whether `text` matches `pattern`, where `*` matches any run of characters
+/// and `?` any one character.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Where the last `*` was, and the text position it currently ends at
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            // Let the `*` swallow one more character
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, t));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Compile a grammar's highlights and injections queries, for
+/// [`run_corpus_case_with_grammar`].
+pub fn compile_queries(
+    language: impl Into<Language>,
+    name: &str,
+    highlights_query: &str,
+    injections_query: &str,
+) -> HarnessResult<CompiledGrammar> {
+    CompiledGrammar::new(GrammarConfig {
+        language: language.into(),
+        highlights_query,
+        injections_query,
+        locals_query: "",
+    })
+    .map_err(|e| HarnessError::new(format!("Query validation failed for {}: {:?}", name, e)))
+}
+
+/// Compile a grammar's locals query and check its capture names.
+///
+/// arborium-highlight doesn't use locals yet, so nothing else would notice a
+/// broken `locals.scm`. Captures must be the ones tree-sitter's locals
+/// support understands (`local.scope`, `local.definition`,
+/// `local.definition.*` and `local.reference`) or their Neovim spellings
+/// without the `local.` prefix; names starting with `_` are internal and
+/// allowed. An empty query compiles to one with no patterns.
+pub fn compile_locals_query(
+    language: impl Into<Language>,
+    name: &str,
+    locals_query: &str,
+) -> HarnessResult<Query> {
+    let query = Query::new(&language.into(), locals_query).map_err(|e| {
+        HarnessError::new(format!(
+            "Locals query validation failed for {}: {:?}",
+            name, e
+        ))
+    })?;
+
+    let unknown: Vec<&str> = query
+        .capture_names()
+        .iter()
+        .copied()
+        .filter(|capture| !capture.starts_with('_'))
+        .filter(|capture| {
+            let kind = capture.strip_prefix("local.").unwrap_or(capture);
+            !matches!(kind, "scope" | "definition" | "reference")
+                && !kind.starts_with("definition.")
+        })
+        .collect();
+    if !unknown.is_empty() {
+        return Err(HarnessError::new(format!(
+            "Locals query for {} has unknown captures: {}\n\
+             Expected @local.scope, @local.definition, @local.definition.<kind> or @local.reference.",
+            name,
+            unknown.join(", ")
+        )));
+    }
+    Ok(query)
+}
+
+/// Check a corpus case's `locals` section against the captures of the
+/// locals query, from [`compile_locals_query`].
+pub fn check_corpus_locals(
+    language: LanguageFn,
+    name: &str,
+    locals: &Query,
+    case: &CorpusCase,
+) -> HarnessResult<()> {
+    if case.locals.is_empty() {
+        return Ok(());
+    }
+
+    let tree = parse_case(language, name, case)?;
+    let source = case.input.as_bytes();
+    let mut found: Vec<(&str, &str)> = Vec::new();
+    let mut cursor = QueryCursor::new();
+    let mut matches = cursor.matches(locals, tree.root_node(), source);
+    while let Some(m) = matches.next() {
+        for capture in m.captures {
+            let text = case
+                .input
+                .get(capture.node.byte_range())
+                .unwrap_or_default();
+            found.push((text, locals.capture_names()[capture.index as usize]));
+        }
+    }
+
+    for (text, capture) in &case.locals {
+        let captures: Vec<&str> = found
+            .iter()
+            .filter(|(found_text, _)| found_text == text)
+            .map(|(_, capture)| *capture)
+            .collect();
+        if !captures.contains(&capture.as_str()) {
+            return Err(HarnessError::new(format!(
+                "Expected `{}` to be captured as `{}` by the locals query for {} / {} (file {}), but it has {:?}\n--- input ---\n{}",
+                text,
+                capture,
+                name,
+                case.name,
+                case.file.display(),
+                captures,
+                case.input
+            )));
+        }
+    }
+    Ok(())
+}
+
 /// Return all `.txt` corpus files for a grammar crate.
 pub fn corpus_files(crate_dir: &str) -> Vec<PathBuf> {
     let crate_path = Path::new(crate_dir);
@@ -280,7 +1239,12 @@ pub fn collect_corpus_cases(crate_dir: &str) -> HarnessResult<Vec<CorpusCase>> {
                 name: test.name,
                 input: test.input,
                 contains: test.contains,
+                not_contains: test.not_contains,
                 expected_sexp: test.expected_sexp,
+                snapshot: test.snapshot,
+                highlights: test.highlights,
+                locals: test.locals,
+                allow_errors: test.allow_errors,
             });
         }
     }
@@ -322,31 +1286,102 @@ pub fn run_corpus_file(language: LanguageFn, name: &str, path: &Path) -> Harness
             name: test.name,
             input: test.input,
             contains: test.contains,
+            not_contains: test.not_contains,
             expected_sexp: test.expected_sexp,
+            snapshot: test.snapshot,
+            highlights: test.highlights,
+            locals: test.locals,
+            allow_errors: test.allow_errors,
         };
         run_corpus_case(language, name, &case)?;
     }
 
-    Ok(())
-}
-
-/// Execute a single corpus test case.
-pub fn run_corpus_case(language: LanguageFn, name: &str, case: &CorpusCase) -> HarnessResult<()> {
-    run_corpus_case_with_tree(language, name, case).map(|_| ())
+    Ok(())
+}
+
+/// Execute a single corpus test case.
+pub fn run_corpus_case(language: LanguageFn, name: &str, case: &CorpusCase) -> HarnessResult<()> {
+    run_corpus_case_with_tree(language, name, case).map(|_| ())
+}
+
+/// Run a corpus test case, including its `highlights` section, and return
+/// the parsed tree's s-expression.
+///
+/// `grammar` is the grammar with its queries, from [`compile_queries`].
+pub fn run_corpus_case_with_grammar(
+    language: LanguageFn,
+    name: &str,
+    grammar: &CompiledGrammar,
+    case: &CorpusCase,
+) -> HarnessResult<String> {
+    let sexp = check_tree(language, name, case)?;
+    if case.highlights.is_empty() {
+        return Ok(sexp);
+    }
+
+    let mut ctx = ParseContext::for_grammar(grammar).map_err(|e| {
+        HarnessError::new(format!(
+            "Failed to create parse context for {}: {:?}",
+            name, e
+        ))
+    })?;
+    let spans = grammar.parse(&mut ctx, &case.input).spans;
+    for (text, capture) in &case.highlights {
+        let captures: Vec<&str> = spans
+            .iter()
+            .filter(|span| case.input.get(span.start as usize..span.end as usize) == Some(text))
+            .map(|span| span.capture.as_str())
+            .collect();
+        if !captures.contains(&capture.as_str()) {
+            return Err(HarnessError::new(format!(
+                "Expected `{}` to be highlighted as `{}` for {} / {} (file {}), but it has {:?}\n--- input ---\n{}\n--- highlights ---\n{}",
+                text,
+                capture,
+                name,
+                case.name,
+                case.file.display(),
+                captures,
+                case.input,
+                format_spans(&case.input, spans.clone())
+            )));
+        }
+    }
+
+    Ok(sexp)
 }
 
 /// Run a corpus test case and return the parsed tree's s-expression.
+///
+/// Cases with a `highlights` or `locals` section fail, since checking them
+/// needs the grammar's queries; run those with
+/// [`run_corpus_case_with_grammar`] and [`check_corpus_locals`].
 pub fn run_corpus_case_with_tree(
     language: LanguageFn,
     name: &str,
     case: &CorpusCase,
 ) -> HarnessResult<String> {
+    for (section, assertions) in [("highlights", &case.highlights), ("locals", &case.locals)] {
+        if !assertions.is_empty() {
+            return Err(HarnessError::new(format!(
+                "Corpus test {} / {} (file {}) has a `{}` section, which needs the grammar's queries",
+                name,
+                case.name,
+                case.file.display(),
+                section
+            )));
+        }
+    }
+    check_tree(language, name, case)
+}
+
+/// Check a case's parse tree, and return its s-expression.
+fn check_tree(language: LanguageFn, name: &str, case: &CorpusCase) -> HarnessResult<String> {
     let tree = parse_case(language, name, case)?;
     let root = tree.root_node();
 
     if let Some(expected) = &case.expected_sexp {
         let actual = root.to_sexp();
-        if actual.trim() != expected.trim() {
+        if normalize_sexp(&actual, has_fields(expected)) != normalize_sexp(expected, true) {
             return Err(HarnessError::new(format!(
                 "S-expression mismatch for {} / {} (file {})\n--- input ---\n{}\n--- expected ---\n{}\n--- actual ---\n{}",
                 name,
@@ -359,10 +1394,36 @@ pub fn run_corpus_case_with_tree(
         }
     }
 
-    if !case.contains.is_empty() {
+    if case.snapshot {
+        assert_snapshot(&case.snapshot_path(), &pretty_sexp(root)).map_err(|e| {
+            HarnessError::new(format!(
+                "{} / {} (file {}): {}",
+                name,
+                case.name,
+                case.file.display(),
+                e
+            ))
+        })?;
+    }
+
+    if !case.contains.is_empty() || !case.not_contains.is_empty() {
         let mut seen: HashSet<&str> = HashSet::new();
         collect_kinds(root, &mut seen);
 
+        for kind in &case.not_contains {
+            if seen.contains(kind.as_str()) {
+                return Err(HarnessError::new(format!(
+                    "Unexpected node kind `{}` found for {} / {} (file {})\n--- input ---\n{}\n--- sexp ---\n{}",
+                    kind,
+                    name,
+                    case.name,
+                    case.file.display(),
+                    case.input,
+                    root.to_sexp()
+                )));
+            }
+        }
+
         for kind in &case.contains {
             if !seen.contains(kind.as_str()) {
                 return Err(HarnessError::new(format!(
@@ -408,7 +1469,7 @@ fn parse_case(language: LanguageFn, name: &str, case: &CorpusCase) -> HarnessRes
     })?;
 
     let root = tree.root_node();
-    if root.has_error() {
+    if root.has_error() && !case.allow_errors {
         return Err(HarnessError::new(format!(
             "Parse errors for {} / {} (file {})\n--- input ---\n{}\n--- sexp ---\n{}",
             name,
@@ -422,6 +1483,85 @@ fn parse_case(language: LanguageFn, name: &str, case: &CorpusCase) -> HarnessRes
     Ok(tree)
 }
 
+/// Format a syntax tree like [`Node::to_sexp`], but with each named node on
+/// its own line, indented by depth, so that snapshot diffs are readable.
+fn pretty_sexp(root: Node) -> String {
+    let mut out = String::new();
+    write_sexp(&mut root.walk(), 0, &mut out);
+    out.push('\n');
+    out
+}
+
+fn write_sexp(cursor: &mut TreeCursor, depth: usize, out: &mut String) {
+    let node = cursor.node();
+    if depth > 0 {
+        out.push('\n');
+        out.push_str(&"  ".repeat(depth));
+    }
+    if let Some(field) = cursor.field_name() {
+        out.push_str(field);
+        out.push_str(": ");
+    }
+    if node.is_missing() {
+        out.push_str(&format!("(MISSING {})", node.kind()));
+        return;
+    }
+
+    out.push('(');
+    out.push_str(node.kind());
+    if cursor.goto_first_child() {
+        loop {
+            let child = cursor.node();
+            if child.is_named() || child.is_missing() {
+                write_sexp(cursor, depth + 1, out);
+            }
+            if !cursor.goto_next_sibling() {
+                break;
+            }
+        }
+        cursor.goto_parent();
+    }
+    out.push(')');
+}
+
+/// Format highlight spans one per line, in source order, as
+/// `start..end capture "text"`.
+fn format_spans(source: &str, mut spans: Vec<Span>) -> String {
+    spans.sort_by(|a, b| {
+        (a.start, std::cmp::Reverse(a.end), &a.capture).cmp(&(
+            b.start,
+            std::cmp::Reverse(b.end),
+            &b.capture,
+        ))
+    });
+    let mut out = String::new();
+    for span in spans {
+        let text = source
+            .get(span.start as usize..span.end as usize)
+            .unwrap_or_default();
+        out.push_str(&format!(
+            "{}..{} {} {:?}\n",
+            span.start, span.end, span.capture, text
+        ));
+    }
+    out
+}
+
+/// A file name for a test name: lowercase ASCII letters and digits, with
+/// runs of anything else replaced by `-`.
+fn slug(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.truncate(slug.trim_end_matches('-').len());
+    slug
+}
+
 fn collect_kinds(node: Node, out: &mut HashSet<&str>) {
     out.insert(node.kind());
     let mut cursor = node.walk();
@@ -431,6 +1571,11 @@ fn collect_kinds(node: Node, out: &mut HashSet<&str>) {
 }
 
 fn parse_corpus(content: &str) -> HarnessResult<Vec<CorpusTest>> {
+    let first_line = content.lines().map(str::trim_end).find(|l| !l.is_empty());
+    if first_line.is_some_and(|line| is_rule(line, '=')) {
+        return Ok(parse_upstream_corpus(content));
+    }
+
     let mut tests: Vec<CorpusTest> = Vec::new();
     let mut current: Option<CorpusTest> = None;
     let mut section: Option<String> = None;
@@ -455,7 +1600,16 @@ fn parse_corpus(content: &str) -> HarnessResult<Vec<CorpusTest>> {
         }
 
         if let Some(sec) = trimmed.strip_prefix("---") {
-            section = Some(sec.trim().to_string());
+            let sec = sec.trim();
+            // Marker sections take effect by being present
+            if let Some(test) = current.as_mut() {
+                match sec {
+                    "snapshot" => test.snapshot = true,
+                    "allow-errors" => test.allow_errors = true,
+                    _ => {}
+                }
+            }
+            section = Some(sec.to_string());
             continue;
         }
 
@@ -477,11 +1631,45 @@ fn parse_corpus(content: &str) -> HarnessResult<Vec<CorpusTest>> {
                 let expected = test.expected_sexp.get_or_insert_with(String::new);
                 expected.push_str(chunk);
             }
+            Some(marker @ ("snapshot" | "allow-errors")) => {
+                if !trimmed.is_empty() {
+                    return Err(HarnessError::new(format!(
+                        "Unexpected content in `{}` section at line {}: {}",
+                        marker,
+                        idx + 1,
+                        trimmed
+                    )));
+                }
+            }
+            Some(sec @ ("highlights" | "locals")) => {
+                if trimmed.is_empty() || trimmed.starts_with('#') {
+                    continue;
+                }
+                let Some((text, capture)) = trimmed.rsplit_once("->") else {
+                    return Err(HarnessError::new(format!(
+                        "Expected `text -> capture` in `{}` section at line {}: {}",
+                        sec,
+                        idx + 1,
+                        trimmed
+                    )));
+                };
+                let assertions = if sec == "locals" {
+                    &mut test.locals
+                } else {
+                    &mut test.highlights
+                };
+                assertions.push((text.trim().to_string(), capture.trim().to_string()));
+            }
             Some("contains") => {
                 for tok in trimmed.split_whitespace() {
                     test.contains.push(tok.to_string());
                 }
             }
+            Some("not-contains") => {
+                for tok in trimmed.split_whitespace() {
+                    test.not_contains.push(tok.to_string());
+                }
+            }
             Some(other) => {
                 return Err(HarnessError::new(format!(
                     "Unknown section `{}` at line {}",
@@ -509,17 +1697,410 @@ fn parse_corpus(content: &str) -> HarnessResult<Vec<CorpusTest>> {
     Ok(tests)
 }
 
-/// Parse sample paths from arborium.kdl
+/// Check that every node type the queries refer to exists in the grammar's
+/// `grammar/src/node-types.json`.
+///
+/// tree-sitter's query compiler catches most unknown node types, but not
+/// all: a query compiled against an older build of the grammar, or one that
+/// is inherited from another grammar, can mention nodes this grammar no
+/// longer has. `queries` pairs a label for error messages, such as
+/// `"highlights.scm"`, with the query source. Named nodes (`(identifier)`),
+/// anonymous nodes (`"fn"`) and supertypes (`(expression/identifier)`) are
+/// checked; strings inside predicates are not nodes and are skipped.
+///
+/// Does nothing if the crate has no `node-types.json`, which is generated
+/// along with the parser.
+pub fn check_query_node_types(crate_dir: &str, queries: &[(&str, &str)]) -> HarnessResult<()> {
+    let path = Path::new(crate_dir).join("grammar/src/node-types.json");
+    let Ok(json) = fs::read_to_string(&path) else {
+        return Ok(());
+    };
+    let known = parse_node_types(&json)
+        .map_err(|e| HarnessError::new(format!("Failed to parse {}: {}", path.display(), e)))?;
+
+    let mut unknown = Vec::new();
+    for (label, query) in queries {
+        for node in query_node_types(query) {
+            if !known.contains(&(node.kind.clone(), node.named)) {
+                let kind = if node.named {
+                    format!("({})", node.kind)
+                } else {
+                    format!("{:?}", node.kind)
+                };
+                unknown.push(format!("  {}:{}: {}", label, node.line, kind));
+            }
+        }
+    }
+    if !unknown.is_empty() {
+        return Err(HarnessError::new(format!(
+            "Queries refer to node types that aren't in {}:\n{}",
+            path.display(),
+            unknown.join("\n")
+        )));
+    }
+    Ok(())
+}
+
+/// The `(type, named)` pairs a `node-types.json` file declares, including
+/// those only mentioned as children, fields or subtypes.
+fn parse_node_types(json: &str) -> Result<HashSet<(String, bool)>, serde_json::Error> {
+    fn collect(value: &serde_json::Value, out: &mut HashSet<(String, bool)>) {
+        match value {
+            serde_json::Value::Array(items) => items.iter().for_each(|item| collect(item, out)),
+            serde_json::Value::Object(object) => {
+                if let (Some(kind), Some(named)) = (
+                    object.get("type").and_then(|v| v.as_str()),
+                    object.get("named").and_then(|v| v.as_bool()),
+                ) {
+                    out.insert((kind.to_string(), named));
+                }
+                object.values().for_each(|v| collect(v, out));
+            }
+            _ => {}
+        }
+    }
+
+    let value: serde_json::Value = serde_json::from_str(json)?;
+    let mut types = HashSet::new();
+    collect(&value, &mut types);
+    Ok(types)
+}
+
+/// A node type referred to by a query.
+#[derive(Debug, PartialEq)]
+struct QueryNode {
+    kind: String,
+    named: bool,
+    /// The 1-based line it's on.
+    line: usize,
+}
+
+/// The node types a query refers to, in order.
 ///
-/// Looks for `sample { path "..." }` blocks and extracts the path values.
-fn parse_samples_from_kdl(path: &Path) -> Vec<String> {
-    let content = match fs::read_to_string(path) {
-        Ok(c) => c,
-        Err(_) => return vec![],
+/// The wildcards `_` and `(_)`, and the built-in `ERROR` and `MISSING`
+/// nodes, aren't included.
+fn query_node_types(query: &str) -> Vec<QueryNode> {
+    let mut nodes = Vec::new();
+    // Whether each open parenthesis starts a predicate, like `(#eq? ...)`
+    let mut predicates: Vec<bool> = Vec::new();
+    let mut line = 1;
+    let mut chars = query.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            ';' => while chars.next_if(|&c| c != '\n').is_some() {},
+            '(' => {
+                while chars.next_if(|c| c.is_whitespace() && *c != '\n').is_some() {}
+                let mut name = String::new();
+                while let Some(c) = chars.next_if(|&c| is_query_ident_char(c) || c == '/') {
+                    name.push(c);
+                }
+                predicates.push(name.starts_with('#'));
+                if name.starts_with('#') {
+                    continue;
+                }
+                for kind in name.split('/') {
+                    if !matches!(kind, "" | "_" | "ERROR" | "MISSING") {
+                        nodes.push(QueryNode {
+                            kind: kind.to_string(),
+                            named: true,
+                            line,
+                        });
+                    }
+                }
+            }
+            ')' => {
+                predicates.pop();
+            }
+            '"' => {
+                let mut text = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some('n') => text.push('\n'),
+                            Some('r') => text.push('\r'),
+                            Some('t') => text.push('\t'),
+                            Some('0') => text.push('\0'),
+                            Some(c) => text.push(c),
+                            None => {}
+                        },
+                        '\n' => {
+                            line += 1;
+                            text.push(c);
+                        }
+                        c => text.push(c),
+                    }
+                }
+                if predicates.last() != Some(&true) {
+                    nodes.push(QueryNode {
+                        kind: text,
+                        named: false,
+                        line,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    nodes
+}
+
+/// Whether `c` can be part of a node, field or predicate name in a query.
+fn is_query_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '?' | '!' | '#')
+}
+
+/// Compile a grammar's queries, panicking with a hint if they don't compile.
+fn compile_grammar(
+    language: impl Into<Language>,
+    name: &str,
+    highlights_query: &str,
+    injections_query: &str,
+) -> (CompiledGrammar, ParseContext) {
+    try_compile_grammar(language, name, highlights_query, injections_query)
+        .unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Compile a grammar's queries and create a parse context for it, with a
+/// hint in the error if the queries don't compile.
+fn try_compile_grammar(
+    language: impl Into<Language>,
+    name: &str,
+    highlights_query: &str,
+    injections_query: &str,
+) -> HarnessResult<(CompiledGrammar, ParseContext)> {
+    let language: Language = language.into();
+    // Create grammar config
+    let config = GrammarConfig {
+        language,
+        highlights_query,
+        injections_query,
+        locals_query: "", // Not used by arborium-highlight yet
+    };
+
+    // Validate queries compile by creating the grammar
+    let grammar = CompiledGrammar::new(config).map_err(|e| {
+        HarnessError::new(format!(
+            "Query validation failed for {}: {:?}\n\
+             This usually means highlights.scm references a node type that doesn't exist in the grammar.\n\
+             Check the grammar's node-types.json to see valid node types.",
+            name, e
+        ))
+    })?;
+
+    // Create a parse context for this grammar
+    let ctx = ParseContext::for_grammar(&grammar).map_err(|e| {
+        HarnessError::new(format!(
+            "Failed to create parse context for {}: {:?}",
+            name, e
+        ))
+    })?;
+
+    Ok((grammar, ctx))
+}
+
+/// A `sample` block from `arborium.kdl`.
+#[derive(Debug, Default, PartialEq)]
+struct Sample {
+    /// The sample file; relative to the crate directory until [`samples`]
+    /// resolves it.
+    path: PathBuf,
+    /// Languages from the `must-inject` line.
+    must_inject: Vec<String>,
+    /// Texts from `must-highlight` lines, with their `capture` property.
+    must_highlight: Vec<(String, Option<String>)>,
+}
+
+/// The samples listed in a grammar crate's `arborium.kdl`.
+fn samples(crate_dir: &str) -> Vec<Sample> {
+    let crate_path = Path::new(crate_dir);
+    let kdl_path = crate_path.join("arborium.kdl");
+    if !kdl_path.exists() {
+        return Vec::new();
+    }
+    let content = fs::read_to_string(&kdl_path).unwrap_or_default();
+    parse_samples_from_kdl(&content)
+        .into_iter()
+        .map(|sample| Sample {
+            path: crate_path.join(sample.path),
+            ..sample
+        })
+        .collect()
+}
+
+/// The sample files listed in a grammar crate's `arborium.kdl`.
+fn sample_paths(crate_dir: &str) -> Vec<PathBuf> {
+    samples(crate_dir)
+        .into_iter()
+        .map(|sample| sample.path)
+        .collect()
+}
+
+/// Whether `line` consists of at least three `c` characters and nothing else.
+fn is_rule(line: &str, c: char) -> bool {
+    line.len() >= 3 && line.chars().all(|ch| ch == c)
+}
+
+/// Parse a corpus in the standard tree-sitter format: each test is a name
+/// between two `===` rules, then the input, a `---` rule, and the expected
+/// tree.
+fn parse_upstream_corpus(content: &str) -> Vec<CorpusTest> {
+    let lines: Vec<&str> = content
+        .split_inclusive('\n')
+        .map(|chunk| chunk.strip_suffix('\n').unwrap_or(chunk))
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .collect();
+    let is_header_rule = |idx: usize| lines.get(idx).is_some_and(|l| is_rule(l.trim_end(), '='));
+    // A test starts with a rule, its name and attributes, and another rule
+    let starts_test = |idx: usize| {
+        is_header_rule(idx)
+            && lines[idx + 1..]
+                .iter()
+                .position(|l| l.trim().is_empty() || is_rule(l.trim_end(), '='))
+                .is_some_and(|len| len > 0 && is_header_rule(idx + 1 + len))
     };
 
+    let mut tests = Vec::new();
+    let mut idx = 0;
+    while idx < lines.len() {
+        if !starts_test(idx) {
+            idx += 1;
+            continue;
+        }
+
+        // The name and attributes, up to the closing rule
+        let header_start = idx + 1;
+        idx = header_start;
+        while idx < lines.len() && !is_header_rule(idx) {
+            idx += 1;
+        }
+        let header = &lines[header_start..idx.min(lines.len())];
+        idx += 1;
+
+        // The body, up to the next test
+        let body_start = idx;
+        while idx < lines.len() && !starts_test(idx) {
+            idx += 1;
+        }
+        let body = &lines[body_start.min(lines.len())..idx];
+
+        let attributes: Vec<&str> = header
+            .iter()
+            .map(|l| l.trim())
+            .filter(|l| l.starts_with(':'))
+            .collect();
+        if attributes.contains(&":skip") {
+            continue;
+        }
+        let name = header
+            .iter()
+            .map(|l| l.trim())
+            .find(|l| !l.is_empty() && !l.starts_with(':'))
+            .unwrap_or_default();
+
+        // The expected tree follows the last `---` rule, since the input
+        // may contain one too
+        let separator = body.iter().rposition(|l| is_rule(l.trim_end(), '-'));
+        let (input, expected) = match separator {
+            Some(sep) => (&body[..sep], Some(&body[sep + 1..])),
+            None => (body, None),
+        };
+        let input_end = input
+            .iter()
+            .rposition(|l| !l.trim().is_empty())
+            .map_or(0, |i| i + 1);
+        let input_start = input
+            .iter()
+            .position(|l| !l.trim().is_empty())
+            .unwrap_or(input_end);
+        let mut input = input[input_start..input_end].join("\n");
+        input.push('\n');
+        let expected = expected
+            .map(|lines| lines.join("\n").trim().to_string())
+            .filter(|sexp| !sexp.is_empty());
+
+        let mut test = CorpusTest {
+            name: name.to_string(),
+            input,
+            allow_errors: expected
+                .as_deref()
+                .is_some_and(|sexp| sexp.contains("(ERROR") || sexp.contains("(MISSING")),
+            expected_sexp: expected,
+            ..CorpusTest::default()
+        };
+        if attributes.contains(&":error") {
+            // Any tree with an error passes
+            test.allow_errors = true;
+            test.expected_sexp = None;
+            test.contains.push("ERROR".to_string());
+        }
+        tests.push(test);
+    }
+    tests
+}
+
+/// Split an s-expression into parentheses, words and quoted strings.
+fn sexp_tokens(sexp: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut rest = sexp.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = match c {
+            '(' | ')' => 1,
+            '"' => {
+                // Up to the closing quote, skipping escaped characters
+                let mut escaped = false;
+                rest[1..]
+                    .find(|c: char| {
+                        let end = !escaped && c == '"';
+                        escaped = !escaped && c == '\\';
+                        end
+                    })
+                    .map_or(rest.len(), |end| end + 2)
+            }
+            _ => rest
+                .find(|c: char| c.is_whitespace() || c == '(' || c == ')')
+                .unwrap_or(rest.len()),
+        };
+        tokens.push(&rest[..len]);
+        rest = rest[len..].trim_start();
+    }
+    tokens
+}
+
+fn is_field(token: &str) -> bool {
+    token.len() > 1 && token.ends_with(':') && !token.starts_with('"')
+}
+
+/// Whether an s-expression names fields, like `name: (identifier)`.
+fn has_fields(sexp: &str) -> bool {
+    sexp_tokens(sexp).into_iter().any(is_field)
+}
+
+/// Normalize the whitespace in an s-expression, dropping field names unless
+/// `keep_fields` is set.
+fn normalize_sexp(sexp: &str, keep_fields: bool) -> String {
+    let mut out = String::new();
+    for token in sexp_tokens(sexp) {
+        if !keep_fields && is_field(token) {
+            continue;
+        }
+        if !out.is_empty() && !out.ends_with('(') && token != ")" {
+            out.push(' ');
+        }
+        out.push_str(token);
+    }
+    out
+}
+
+/// Parse sample blocks from the contents of an arborium.kdl file.
+///
+/// Looks for `sample { path "..." }` blocks and extracts the path values,
+/// along with the languages on `must-inject` lines.
+fn parse_samples_from_kdl(content: &str) -> Vec<Sample> {
     let mut samples = Vec::new();
-    let mut in_sample_block = false;
+    let mut current: Option<Sample> = None;
     let mut brace_depth = 0;
 
     for line in content.lines() {
@@ -527,30 +2108,45 @@ fn parse_samples_from_kdl(path: &Path) -> Vec<String> {
 
         // Track sample blocks
         if trimmed.starts_with("sample") && trimmed.contains('{') {
-            in_sample_block = true;
+            current = Some(Sample::default());
             brace_depth = 1;
             continue;
         }
 
-        if in_sample_block {
+        if let Some(sample) = current.as_mut() {
             // Track brace depth
             brace_depth += trimmed.matches('{').count();
             brace_depth = brace_depth.saturating_sub(trimmed.matches('}').count());
 
             if brace_depth == 0 {
-                in_sample_block = false;
+                if let Some(sample) = current.take()
+                    && !sample.path.as_os_str().is_empty()
+                {
+                    samples.push(sample);
+                }
                 continue;
             }
 
-            // Look for path "..."
-            if trimmed.starts_with("path")
-                && let Some(start) = trimmed.find('"')
-                && let Some(end) = trimmed[start + 1..].find('"')
-            {
-                let path_value = &trimmed[start + 1..start + 1 + end];
-                if !path_value.is_empty() {
-                    samples.push(path_value.to_string());
+            let (node, _) = trimmed.split_once(' ').unwrap_or((trimmed, ""));
+            match node {
+                // Look for path "..."
+                "path" => {
+                    if let Some(path) = kdl_arguments(trimmed).0.first() {
+                        sample.path = PathBuf::from(path);
+                    }
                 }
+                "must-inject" => sample.must_inject.extend(kdl_arguments(trimmed).0),
+                "must-highlight" => {
+                    let (arguments, properties) = kdl_arguments(trimmed);
+                    let capture = properties
+                        .into_iter()
+                        .find(|(key, _)| key == "capture")
+                        .map(|(_, value)| value);
+                    if let Some(text) = arguments.into_iter().next() {
+                        sample.must_highlight.push((text, capture));
+                    }
+                }
+                _ => {}
             }
         }
     }
@@ -558,6 +2154,29 @@ fn parse_samples_from_kdl(path: &Path) -> Vec<String> {
     samples
 }
 
+/// The quoted string arguments and properties of a KDL node on a single
+/// line, e.g. `fn` and `capture="keyword"` in
+/// `must-highlight "fn" capture="keyword"`. Escapes aren't supported.
+fn kdl_arguments(line: &str) -> (Vec<String>, Vec<(String, String)>) {
+    let mut arguments = Vec::new();
+    let mut properties = Vec::new();
+    let mut before = "";
+    for (i, part) in line.split('"').enumerate() {
+        if i % 2 == 0 {
+            before = part;
+            continue;
+        }
+        match before.trim_end().strip_suffix('=') {
+            Some(key) => {
+                let key = key.rsplit(char::is_whitespace).next().unwrap_or_default();
+                properties.push((key.to_string(), part.to_string()));
+            }
+            None => arguments.push(part.to_string()),
+        }
+    }
+    (arguments, properties)
+}
+
 /// Standard highlight names used by arborium.
 ///
 /// **Deprecated**: Use [`arborium_theme::CAPTURE_NAMES`] instead, which is the
@@ -565,3 +2184,336 @@ fn parse_samples_from_kdl(path: &Path) -> Vec<String> {
 ///
 /// This constant is kept for backwards compatibility.
 pub const HIGHLIGHT_NAMES: &[&str] = arborium_theme::CAPTURE_NAMES;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlights_sections() {
+        let tests = parse_corpus(
+            "=== Keywords\n--- input\nfn a() -> b {}\n--- highlights\nfn -> keyword.function\n-> -> operator\n",
+        )
+        .unwrap();
+        assert_eq!(
+            tests[0].highlights,
+            [
+                ("fn".to_string(), "keyword.function".to_string()),
+                ("->".to_string(), "operator".to_string()),
+            ]
+        );
+        assert!(parse_corpus("=== Bad\n--- highlights\nfn keyword\n").is_err());
+
+        let tests = parse_corpus(
+            "=== Locals\n--- input\nfn a(x) { x }\n--- locals\nx -> local.definition.parameter\n",
+        )
+        .unwrap();
+        assert_eq!(
+            tests[0].locals,
+            [("x".to_string(), "local.definition.parameter".to_string())]
+        );
+        assert!(tests[0].highlights.is_empty());
+    }
+
+    #[test]
+    fn test_upstream_corpus() {
+        let tests = parse_corpus(
+            "==================\n\
+             Simple function\n\
+             ==================\n\
+             \n\
+             fn main() {}\n\
+             \n\
+             ---\n\
+             \n\
+             (source_file\n  (function_item\n    name: (identifier)))\n\
+             \n\
+             ==========\n\
+             Skipped\n\
+             :skip\n\
+             ==========\n\
+             x\n\
+             ---\n\
+             (y)\n\
+             ==========\n\
+             Broken\n\
+             :error\n\
+             ==========\n\
+             fn (\n\
+             ---\n",
+        )
+        .unwrap();
+
+        assert_eq!(tests.len(), 2);
+        assert_eq!(tests[0].name, "Simple function");
+        assert_eq!(tests[0].input, "fn main() {}\n");
+        assert!(!tests[0].allow_errors);
+        assert_eq!(
+            tests[0].expected_sexp.as_deref(),
+            Some("(source_file\n  (function_item\n    name: (identifier)))")
+        );
+        assert_eq!(tests[1].name, "Broken");
+        assert!(tests[1].allow_errors);
+        assert_eq!(tests[1].contains, ["ERROR"]);
+    }
+
+    #[test]
+    fn test_normalize_sexp() {
+        let expected = "(source_file\n  (function_item\n    (identifier) ))";
+        let actual = "(source_file (function_item name: (identifier)))";
+        assert!(!has_fields(expected));
+        assert_eq!(
+            normalize_sexp(actual, has_fields(expected)),
+            normalize_sexp(expected, true)
+        );
+        assert_eq!(normalize_sexp(actual, true), actual);
+        assert_eq!(
+            normalize_sexp("(a (MISSING \")\"))", true),
+            "(a (MISSING \")\"))"
+        );
+    }
+
+    #[test]
+    fn test_query_coverage_report() {
+        let coverage = QueryCoverage {
+            name: "test".to_string(),
+            patterns: 4,
+            unmatched_patterns: vec![UnmatchedPattern {
+                index: 2,
+                line: 7,
+                text: "(macro_invocation\n  macro: (identifier) @function.macro)".to_string(),
+            }],
+            unmatched_captures: vec!["function.macro".to_string()],
+        };
+        assert_eq!(coverage.percentage(), 75.0);
+        let report = coverage.to_string();
+        assert!(report.contains("75.0% (3 of 4 patterns matched)"));
+        assert!(report.contains("line 7: (macro_invocation\n"));
+        assert!(report.contains("@function.macro"));
+    }
+
+    #[test]
+    fn test_snapshot_sections() {
+        let tests = parse_corpus(
+            "=== Plain\n--- input\na\n\n=== Snapshotted: Case #2\n--- input\nb\n--- snapshot\n",
+        )
+        .unwrap();
+        assert!(!tests[0].snapshot);
+        assert!(tests[1].snapshot);
+        assert!(parse_corpus("=== Bad\n--- snapshot\n(x)\n").is_err());
+
+        let negative =
+            parse_corpus("=== Negative\n--- input\nx\n--- not-contains\nERROR identifier\n")
+                .unwrap();
+        assert_eq!(negative[0].not_contains, ["ERROR", "identifier"]);
+
+        let recovery =
+            parse_corpus("=== Recovery\n--- input\nfn (\n--- allow-errors\n--- contains\nERROR\n")
+                .unwrap();
+        assert!(recovery[0].allow_errors);
+        assert_eq!(recovery[0].contains, ["ERROR"]);
+        assert!(!negative[0].allow_errors);
+
+        let case = CorpusCase {
+            file: PathBuf::from("crate/corpus/basics.txt"),
+            name: tests[1].name.clone(),
+            input: tests[1].input.clone(),
+            contains: Vec::new(),
+            not_contains: Vec::new(),
+            expected_sexp: None,
+            snapshot: true,
+            highlights: Vec::new(),
+            locals: Vec::new(),
+            allow_errors: false,
+        };
+        assert_eq!(
+            case.snapshot_path(),
+            Path::new("crate/corpus/snapshots/basics/snapshotted-case-2.snap")
+        );
+    }
+
+    #[test]
+    fn test_parse_samples_from_kdl() {
+        let kdl = r#"
+grammar {
+    id "svelte"
+    injections "javascript" "css"
+
+    sample {
+        path "samples/App.svelte"
+        description "Svelte starter component"
+        must-inject "javascript" "css"
+        must-highlight "export" capture="keyword"
+        must-highlight "name"
+    }
+    sample {
+        path "samples/Plain.svelte"
+    }
+}
+"#;
+        assert_eq!(
+            parse_samples_from_kdl(kdl),
+            vec![
+                Sample {
+                    path: PathBuf::from("samples/App.svelte"),
+                    must_inject: vec!["javascript".to_string(), "css".to_string()],
+                    must_highlight: vec![
+                        ("export".to_string(), Some("keyword".to_string())),
+                        ("name".to_string(), None),
+                    ],
+                },
+                Sample {
+                    path: PathBuf::from("samples/Plain.svelte"),
+                    ..Sample::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_query_node_types() {
+        let query = r#"
+; "comment" isn't a node
+(function_item
+  "fn" @keyword
+  name: (identifier) @function)
+[(ERROR) (_) "\"" (expression/call_expression)] @x
+((identifier) @constant
+ (#match? @constant "^[A-Z]"))
+"#;
+        let nodes = query_node_types(query);
+        let nodes: Vec<(&str, bool, usize)> = nodes
+            .iter()
+            .map(|node| (node.kind.as_str(), node.named, node.line))
+            .collect();
+        assert_eq!(
+            nodes,
+            [
+                ("function_item", true, 3),
+                ("fn", false, 4),
+                ("identifier", true, 5),
+                ("\"", false, 6),
+                ("expression", true, 6),
+                ("call_expression", true, 6),
+                ("identifier", true, 7),
+            ]
+        );
+
+        let types = parse_node_types(
+            r#"[{"type": "function_item", "named": true, "fields": {"name": {"multiple": false, "required": true, "types": [{"type": "identifier", "named": true}]}}}, {"type": "fn", "named": false}]"#,
+        )
+        .unwrap();
+        assert!(types.contains(&("identifier".to_string(), true)));
+        assert!(types.contains(&("fn".to_string(), false)));
+        assert!(!types.contains(&("fn".to_string(), true)));
+    }
+
+    #[test]
+    fn test_check_query_node_types() {
+        // A node-types.json excerpt from tree-sitter-rust
+        let crate_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures");
+        let highlights = r#"
+(type_identifier) @type
+(primitive_type) @type.builtin
+
+; Assume all-caps names are constants
+((identifier) @constant
+ (#match? @constant "^[A-Z][A-Z\\d_]+$"))
+
+(call_expression
+  function: (scoped_identifier
+    "::"
+    name: (identifier) @function))
+
+(macro_invocation
+  macro: (identifier) @function.macro
+  "!" @function.macro)
+
+(function_item !return_type
+  name: (identifier) @function)
+
+[(line_comment) (_expression/identifier)] @comment
+["async" "fn" "->"] @keyword
+"(" @punctuation.bracket
+(escape_sequence) @escape
+"#;
+        let injections = r#"
+((macro_invocation
+  (token_tree) @injection.content)
+ (#set! injection.language "rust")
+ (#set! injection.include-children))
+"#;
+        check_query_node_types(
+            crate_dir,
+            &[
+                ("highlights.scm", highlights),
+                ("injections.scm", injections),
+            ],
+        )
+        .unwrap();
+
+        let stale = "(block_comment) @comment\n[\"as\" \"fn\"] @keyword";
+        let err = check_query_node_types(crate_dir, &[("highlights.scm", stale)]).unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.contains("highlights.scm:1: (block_comment)"),
+            "{message}"
+        );
+        assert!(message.contains("highlights.scm:2: \"as\""), "{message}");
+        assert!(!message.contains("\"fn\""), "{message}");
+
+        // Crates without a node-types.json aren't checked
+        check_query_node_types(env!("CARGO_MANIFEST_DIR"), &[("highlights.scm", stale)]).unwrap();
+    }
+
+    #[test]
+    fn test_corpus_filter() {
+        assert!(glob_match("strings/*", "strings/escapes"));
+        assert!(glob_match("*/esc?pes", "strings/escapes"));
+        assert!(glob_match("a*b*c", "aXbYbc"));
+        assert!(!glob_match("strings/*", "numbers/escapes"));
+        assert!(!glob_match("esc", "escapes"));
+
+        let case = CorpusCase {
+            file: PathBuf::from("crate/corpus/strings.txt"),
+            name: "raw strings".to_string(),
+            input: String::new(),
+            contains: Vec::new(),
+            not_contains: Vec::new(),
+            expected_sexp: None,
+            snapshot: false,
+            highlights: Vec::new(),
+            locals: Vec::new(),
+            allow_errors: false,
+        };
+        assert_eq!(case.id(), "strings/raw strings");
+        assert!(case.matches_filter("strings/*"));
+        assert!(case.matches_filter("raw*"));
+        assert!(!case.matches_filter("strings"));
+    }
+
+    #[test]
+    fn test_report() {
+        let result = |name: &str, error: Option<&str>| TestResult {
+            name: name.to_string(),
+            error: error.map(str::to_string),
+            spans: Some(3),
+            duration: Duration::from_millis(1),
+        };
+        let mut report = TestReport::new("rust");
+        report.results.push(result("samples/a.rs", None));
+        assert!(report.passed());
+        assert_eq!(report.to_string(), "rust: 1 passed, 0 failed");
+
+        report
+            .results
+            .push(result("samples/b.rs", Some("No highlights")));
+        report.errors.push("Locals query is broken".to_string());
+        assert!(!report.passed());
+        assert_eq!(report.failures().count(), 1);
+        assert_eq!(
+            report.to_string(),
+            "rust: 1 passed, 1 failed, 1 error\n\nLocals query is broken\n\nsamples/b.rs: No highlights"
+        );
+    }
+}
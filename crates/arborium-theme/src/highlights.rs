@@ -150,6 +150,146 @@ impl ThemeSlot {
     }
 }
 
+impl ThemeSlot {
+    /// Every slot that produces styling, in declaration order.
+    ///
+    /// [`ThemeSlot::None`] is left out.
+    pub const ALL: [ThemeSlot; 27] = [
+        ThemeSlot::Keyword,
+        ThemeSlot::Function,
+        ThemeSlot::String,
+        ThemeSlot::Comment,
+        ThemeSlot::Type,
+        ThemeSlot::Variable,
+        ThemeSlot::Constant,
+        ThemeSlot::Number,
+        ThemeSlot::Operator,
+        ThemeSlot::Punctuation,
+        ThemeSlot::Property,
+        ThemeSlot::Attribute,
+        ThemeSlot::Tag,
+        ThemeSlot::Macro,
+        ThemeSlot::Label,
+        ThemeSlot::Namespace,
+        ThemeSlot::Constructor,
+        ThemeSlot::Title,
+        ThemeSlot::Strong,
+        ThemeSlot::Emphasis,
+        ThemeSlot::Link,
+        ThemeSlot::Literal,
+        ThemeSlot::Strikethrough,
+        ThemeSlot::DiffAdd,
+        ThemeSlot::DiffDelete,
+        ThemeSlot::Embedded,
+        ThemeSlot::Error,
+    ];
+
+    /// A human-readable description of what this slot colors, for theme
+    /// editors and documentation.
+    pub fn description(self) -> &'static str {
+        match self {
+            ThemeSlot::Keyword => {
+                "Reserved words: control flow, declarations, imports and modifiers"
+            }
+            ThemeSlot::Function => "Function and method names, at definitions and calls",
+            ThemeSlot::String => "String and character literals, including escapes and regexes",
+            ThemeSlot::Comment => "Comments, including documentation comments",
+            ThemeSlot::Type => "Type names, built-in types and type qualifiers",
+            ThemeSlot::Variable => "Variables, parameters and fields",
+            ThemeSlot::Constant => "Constants, including built-in ones and booleans",
+            ThemeSlot::Number => "Integer and floating-point literals",
+            ThemeSlot::Operator => "Operators such as `+`, `=` and `&&`",
+            ThemeSlot::Punctuation => "Brackets, delimiters and markup list markers",
+            ThemeSlot::Property => "Properties and object keys",
+            ThemeSlot::Attribute => "Attributes and annotations, such as `#[derive]`",
+            ThemeSlot::Tag => "HTML and XML tag names and their delimiters",
+            ThemeSlot::Macro => "Macro names and preprocessor macros",
+            ThemeSlot::Label => "Labels, such as jump targets and loop labels",
+            ThemeSlot::Namespace => "Namespace and module names",
+            ThemeSlot::Constructor => "Constructors and enum variants used as constructors",
+            ThemeSlot::Title => "Markup headings",
+            ThemeSlot::Strong => "Markup bold text",
+            ThemeSlot::Emphasis => "Markup italic text",
+            ThemeSlot::Link => "Markup links and URLs",
+            ThemeSlot::Literal => "Markup inline code and code blocks",
+            ThemeSlot::Strikethrough => "Markup strikethrough text",
+            ThemeSlot::DiffAdd => "Added lines in diffs",
+            ThemeSlot::DiffDelete => "Removed lines in diffs",
+            ThemeSlot::Embedded => "Embedded code, such as template interpolations",
+            ThemeSlot::Error => "Syntax errors and invalid code",
+            ThemeSlot::None => "Captures that are never styled, such as spell-checking regions",
+        }
+    }
+
+    /// The capture names that map to this slot: the names in
+    /// [`CAPTURE_NAMES`] and the highlight categories with their aliases,
+    /// sorted. [`capture_to_slot`] also accepts names not listed here by
+    /// their prefix, e.g. `keyword.control.loop`.
+    pub fn captures(self) -> Vec<&'static str> {
+        let mut captures: Vec<&'static str> = CAPTURE_NAMES
+            .iter()
+            .copied()
+            .chain(
+                HIGHLIGHTS
+                    .iter()
+                    .flat_map(|def| std::iter::once(def.name).chain(def.aliases.iter().copied())),
+            )
+            .filter(|capture| capture_to_slot(capture) == self)
+            .collect();
+        captures.sort_unstable();
+        captures.dedup();
+        captures
+    }
+
+    /// Everything there is to know about this slot, in one value.
+    pub fn info(self) -> SlotInfo {
+        SlotInfo {
+            slot: self,
+            name: self.name().unwrap_or("none"),
+            tag: self.tag().unwrap_or(""),
+            highlight: slot_to_highlight_index(self).map_or("none", |index| HIGHLIGHTS[index].name),
+            description: self.description(),
+            captures: self.captures(),
+        }
+    }
+}
+
+/// Metadata describing a theme slot, from [`ThemeSlot::info`] or [`slots`].
+///
+/// With the `serde` feature this serializes to a plain object, ready to hand
+/// to a theme editor's UI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SlotInfo {
+    /// The slot itself.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub slot: ThemeSlot,
+    /// Canonical name, as used for CSS class names (e.g. `"keyword"`).
+    pub name: &'static str,
+    /// Short tag of the slot's HTML elements (e.g. `"k"` for `<a-k>`).
+    pub tag: &'static str,
+    /// The highlight category whose style themes use for this slot (e.g.
+    /// `"text.title"` for [`ThemeSlot::Title`]).
+    pub highlight: &'static str,
+    /// What the slot colors.
+    pub description: &'static str,
+    /// The capture names that map to the slot (see [`ThemeSlot::captures`]).
+    pub captures: Vec<&'static str>,
+}
+
+/// Metadata for every slot that produces styling, in the order of
+/// [`ThemeSlot::ALL`].
+///
+/// ```rust
+/// let keyword = &arborium_theme::slots()[0];
+/// assert_eq!(keyword.name, "keyword");
+/// assert_eq!(keyword.tag, "k");
+/// assert!(keyword.captures.contains(&"keyword.conditional"));
+/// ```
+pub fn slots() -> Vec<SlotInfo> {
+    ThemeSlot::ALL.iter().map(|slot| slot.info()).collect()
+}
+
 /// Map a theme slot to a canonical highlight index.
 ///
 /// This is useful for ANSI rendering, where we want to
@@ -914,6 +1054,99 @@ pub fn tag_for_capture(capture: &str) -> Option<&'static str> {
     capture_to_slot(capture).tag()
 }
 
+/// A capture-to-slot mapping with user overrides.
+///
+/// Overrides are checked before the built-in table of [`capture_to_slot`],
+/// so grammars with unusual capture names can be styled, and captures the
+/// built-in table lumps together can be told apart. An override applies to
+/// its capture name and every dotted sub-name of it; the longest matching
+/// override wins.
+///
+/// # Example
+/// ```
+/// use arborium_theme::highlights::{CaptureMap, ThemeSlot};
+///
+/// let map = CaptureMap::new()
+///     .with("function.builtin", ThemeSlot::Macro)
+///     .with("sigil", ThemeSlot::Operator);
+///
+/// assert_eq!(map.slot("function.builtin"), ThemeSlot::Macro);
+/// assert_eq!(map.slot("sigil.scalar"), ThemeSlot::Operator);
+/// // Everything else uses the built-in table
+/// assert_eq!(map.slot("function.method"), ThemeSlot::Function);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CaptureMap {
+    overrides: Vec<(String, ThemeSlot)>,
+}
+
+impl CaptureMap {
+    /// Create a map with no overrides, equivalent to [`capture_to_slot`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Map `capture` (and its dotted sub-names) to `slot`.
+    pub fn with(mut self, capture: impl Into<String>, slot: ThemeSlot) -> Self {
+        self.insert(capture, slot);
+        self
+    }
+
+    /// Map `capture` (and its dotted sub-names) to `slot`, replacing any
+    /// earlier override for the same name.
+    pub fn insert(&mut self, capture: impl Into<String>, slot: ThemeSlot) {
+        let capture = capture.into();
+        let capture = capture.strip_prefix('@').unwrap_or(&capture).to_string();
+        match self.overrides.iter_mut().find(|(name, _)| *name == capture) {
+            Some((_, existing)) => *existing = slot,
+            None => self.overrides.push((capture, slot)),
+        }
+    }
+
+    /// Whether the map has no overrides.
+    pub fn is_empty(&self) -> bool {
+        self.overrides.is_empty()
+    }
+
+    /// The overriding slot for `capture`, if any override applies.
+    pub fn lookup(&self, capture: &str) -> Option<ThemeSlot> {
+        let capture = capture.strip_prefix('@').unwrap_or(capture);
+        self.overrides
+            .iter()
+            .filter(|(name, _)| {
+                capture
+                    .strip_prefix(name.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+            })
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, slot)| *slot)
+    }
+
+    /// Map a capture name to its theme slot, checking overrides first.
+    pub fn slot(&self, capture: &str) -> ThemeSlot {
+        self.lookup(capture)
+            .unwrap_or_else(|| capture_to_slot(capture))
+    }
+
+    /// Get the HTML tag for a capture name, checking overrides first.
+    pub fn tag(&self, capture: &str) -> Option<&'static str> {
+        self.slot(capture).tag()
+    }
+
+    /// A capture name that the built-in table maps to the overriding slot
+    /// for `capture`, or `None` if no override applies.
+    ///
+    /// Renaming spans' captures with this applies the overrides to every
+    /// renderer, which only consult the built-in table.
+    pub fn canonical_capture(&self, capture: &str) -> Option<&'static str> {
+        let slot = self.lookup(capture)?;
+        Some(match slot_to_highlight_index(slot) {
+            Some(index) => HIGHLIGHTS[index].name,
+            None => "none",
+        })
+    }
+}
+
 /// Map a short tag to its full name.
 ///
 /// This is useful for class-based HTML output where you need
@@ -1205,6 +1438,27 @@ mod tests {
         assert_eq!(capture_to_slot("nospell"), ThemeSlot::None);
     }
 
+    #[test]
+    fn test_capture_map_overrides() {
+        let map = CaptureMap::new()
+            .with("@keyword.operator", ThemeSlot::Operator)
+            .with("keyword", ThemeSlot::Function)
+            .with("custom", ThemeSlot::None);
+
+        // The longest override wins, matching whole dotted segments
+        assert_eq!(map.slot("keyword.operator.new"), ThemeSlot::Operator);
+        assert_eq!(map.slot("keyword.return"), ThemeSlot::Function);
+        assert_eq!(map.slot("keywords"), ThemeSlot::Keyword);
+        assert_eq!(map.tag("custom.thing"), None);
+
+        // Canonical captures map back to the overriding slot
+        for capture in ["keyword.operator", "keyword.return", "custom"] {
+            let canonical = map.canonical_capture(capture).unwrap();
+            assert_eq!(capture_to_slot(canonical), map.slot(capture), "{capture}");
+        }
+        assert_eq!(map.canonical_capture("string"), None);
+    }
+
     #[test]
     fn test_tag_for_capture() {
         // Keywords all get "k"
@@ -1235,6 +1489,30 @@ mod tests {
         assert_eq!(ThemeSlot::None.tag(), None);
     }
 
+    #[test]
+    fn test_slot_info() {
+        let slots = slots();
+        assert_eq!(slots.len(), ThemeSlot::ALL.len());
+        for info in &slots {
+            assert!(!info.tag.is_empty(), "{}", info.name);
+            assert!(!info.description.is_empty(), "{}", info.name);
+            assert!(info.captures.contains(&info.highlight), "{}", info.name);
+        }
+
+        let title = ThemeSlot::Title.info();
+        assert_eq!(title.name, "title");
+        assert_eq!(title.tag, "tt");
+        assert_eq!(title.highlight, "text.title");
+        assert!(title.captures.contains(&"markup.heading"));
+        assert!(!title.captures.contains(&"keyword"));
+
+        // Every slot but None is listed
+        for capture in CAPTURE_NAMES {
+            let slot = capture_to_slot(capture);
+            assert_eq!(ThemeSlot::ALL.contains(&slot), slot != ThemeSlot::None);
+        }
+    }
+
     #[test]
     fn test_capture_names_all_map_to_slot() {
         // Every name in CAPTURE_NAMES should produce a valid mapping
@@ -2,10 +2,14 @@
 //!
 //! This crate provides:
 //! - Highlight category definitions (the canonical list of syntax categories)
-//! - Capture name to theme slot mapping
-//! - Theme parsing from Helix-style TOML files
+//! - Capture name to theme slot mapping, and a description of every slot
+//! - Theme parsing from Helix-style TOML files, VS Code JSON themes and
+//!   TextMate `.tmTheme` files, and export to Helix and `.tmTheme` files
 //! - CSS and ANSI output generation
 //! - Built-in themes (catppuccin, dracula, tokyo-night, etc.)
+//! - A registry for looking themes up by name, including your own
+//! - Validation of themes for missing styles and unreadable colors, and
+//!   WCAG contrast checks
 //!
 //! # Capture Name Mapping
 //!
@@ -14,11 +18,21 @@
 //! See [`highlights::capture_to_slot`] and [`highlights::tag_for_capture`] for details.
 
 pub mod highlights;
+mod registry;
+#[cfg(any(feature = "vscode", feature = "tmtheme"))]
+mod textmate;
 pub mod theme;
+#[cfg(feature = "tmtheme")]
+mod tmtheme;
+mod validate;
+#[cfg(feature = "vscode")]
+mod vscode;
 
 pub use highlights::{
-    CAPTURE_NAMES, COUNT, HIGHLIGHTS, HighlightDef, ThemeSlot, capture_to_slot,
-    slot_to_highlight_index, tag_for_capture, tag_to_name,
+    CAPTURE_NAMES, COUNT, CaptureMap, HIGHLIGHTS, HighlightDef, SlotInfo, ThemeSlot,
+    capture_to_slot, slot_to_highlight_index, slots, tag_for_capture, tag_to_name,
 };
 
-pub use theme::{Color, Modifiers, Style, Theme, ThemeError, builtin};
+pub use registry::{ThemeRegistry, registry};
+pub use theme::{Ansi256Style, Color, Modifiers, Style, Theme, ThemeError, ThemePair, builtin};
+pub use validate::{Diagnostic, MIN_READABLE_CONTRAST, WCAG_AA, WCAG_AAA};
@@ -0,0 +1,144 @@
+//! A process-wide registry of themes by name.
+//!
+//! Built-in themes are always available under their IDs (`catppuccin-mocha`,
+//! `dracula`, ...). Applications can add their own themes, or replace
+//! built-in ones, with [`ThemeRegistry::register`], and then offer every
+//! name from [`ThemeRegistry::names`] on the command line.
+
+use std::sync::RwLock;
+
+use crate::theme::{Theme, builtin};
+
+/// Themes available by name. Get the shared instance with [`registry`].
+#[derive(Debug)]
+pub struct ThemeRegistry {
+    /// Registered themes, by normalized name.
+    themes: RwLock<Vec<(String, Theme)>>,
+}
+
+static REGISTRY: ThemeRegistry = ThemeRegistry {
+    themes: RwLock::new(Vec::new()),
+};
+
+/// The process-wide theme registry.
+///
+/// ```rust
+/// use arborium_theme::{Theme, registry};
+///
+/// assert_eq!(registry().get("dracula").unwrap().name, "Dracula");
+/// assert!(registry().names().iter().any(|name| name == "catppuccin-mocha"));
+///
+/// registry().register("my-corp", Theme::new("My Corp"));
+/// assert_eq!(registry().get("My Corp").unwrap().name, "My Corp");
+/// ```
+pub fn registry() -> &'static ThemeRegistry {
+    &REGISTRY
+}
+
+impl ThemeRegistry {
+    /// Look up a theme by name.
+    ///
+    /// Names are matched ignoring case and `-`/`_`/space differences, so
+    /// `catppuccin-mocha`, `catppuccin_mocha` and `Catppuccin Mocha` are the
+    /// same theme. A theme's display name finds it too. Registered themes
+    /// take precedence over built-in ones.
+    pub fn get(&self, name: &str) -> Option<Theme> {
+        let wanted = normalize(name);
+        let themes = self.themes.read().unwrap_or_else(|e| e.into_inner());
+        if let Some((_, theme)) = themes.iter().find(|(key, _)| *key == wanted) {
+            return Some(theme.clone());
+        }
+        if let Some(theme) = themes
+            .iter()
+            .map(|(_, theme)| theme)
+            .find(|theme| normalize(&theme.name) == wanted)
+        {
+            return Some(theme.clone());
+        }
+        drop(themes);
+
+        if let Some((_, theme)) = builtin::BY_ID.iter().find(|(id, _)| *id == wanted) {
+            return Some(theme());
+        }
+        builtin::all()
+            .into_iter()
+            .find(|theme| normalize(&theme.name) == wanted)
+    }
+
+    /// Make `theme` available as `name`, replacing any theme already
+    /// registered under that name or built in with that ID.
+    pub fn register(&self, name: impl Into<String>, theme: Theme) {
+        let name = normalize(&name.into());
+        let mut themes = self.themes.write().unwrap_or_else(|e| e.into_inner());
+        match themes.iter_mut().find(|(key, _)| *key == name) {
+            Some((_, existing)) => *existing = theme,
+            None => themes.push((name, theme)),
+        }
+    }
+
+    /// The names of all available themes, built-in and registered, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let themes = self.themes.read().unwrap_or_else(|e| e.into_inner());
+        let mut names: Vec<String> = builtin::BY_ID
+            .iter()
+            .map(|(id, _)| id.to_string())
+            .chain(themes.iter().map(|(name, _)| name.clone()))
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+}
+
+/// Lowercase `name` and use `-` as the only word separator.
+fn normalize(name: &str) -> String {
+    name.trim()
+        .chars()
+        .map(|c| match c {
+            '_' | ' ' => '-',
+            c => c.to_ascii_lowercase(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_lookup() {
+        let registry = ThemeRegistry {
+            themes: RwLock::new(Vec::new()),
+        };
+        assert_eq!(
+            registry.get("catppuccin-mocha").unwrap().name,
+            "Catppuccin Mocha"
+        );
+        assert_eq!(
+            registry.get("Catppuccin_Mocha").unwrap().name,
+            "Catppuccin Mocha"
+        );
+        assert_eq!(
+            registry.get("Rosé Pine Moon").unwrap().name,
+            "Rosé Pine Moon"
+        );
+        assert!(registry.get("no-such-theme").is_none());
+        assert_eq!(registry.names().len(), builtin::BY_ID.len());
+    }
+
+    #[test]
+    fn test_register_overrides_builtin() {
+        let registry = ThemeRegistry {
+            themes: RwLock::new(Vec::new()),
+        };
+        registry.register("Dracula", Theme::new("Corporate Dracula"));
+        registry.register("my-corp", Theme::new("My Corp"));
+        registry.register("MY_CORP", Theme::new("My Corp v2"));
+
+        assert_eq!(registry.get("dracula").unwrap().name, "Corporate Dracula");
+        assert_eq!(registry.get("my-corp").unwrap().name, "My Corp v2");
+        let names = registry.names();
+        assert_eq!(names.len(), builtin::BY_ID.len() + 1);
+        assert!(names.windows(2).all(|w| w[0] < w[1]));
+    }
+}
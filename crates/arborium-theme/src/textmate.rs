@@ -0,0 +1,345 @@
+//! Mapping TextMate scope rules onto arborium's highlight categories.
+//!
+//! VS Code and Sublime Text themes style TextMate scopes (`keyword.control`,
+//! `entity.name.function`, ...) rather than tree-sitter captures. Each
+//! highlight category is given the scopes a TextMate grammar would assign to
+//! the same kind of token, and styled the way the theme would style a token
+//! with that scope.
+
+use crate::highlights::HIGHLIGHTS;
+use crate::theme::{Color, Modifiers, Style, Theme};
+
+/// TextMate scopes for each highlight category, most representative first.
+///
+/// A category takes its style from the first scope that any rule matches.
+/// Since a rule for `keyword` also matches `keyword.control.conditional`,
+/// later scopes only matter for themes that style none of the earlier ones.
+pub(crate) const SCOPES: &[(&str, &[&str])] = &[
+    ("attribute", &["entity.other.attribute-name"]),
+    ("constant", &["variable.other.constant", "constant.other"]),
+    (
+        "constant.builtin",
+        &["constant.language", "support.constant"],
+    ),
+    (
+        "constructor",
+        &["entity.name.function.constructor", "entity.name.type.class"],
+    ),
+    (
+        "function.builtin",
+        &["support.function", "entity.name.function"],
+    ),
+    ("function", &["entity.name.function"]),
+    (
+        "function.method",
+        &["entity.name.function.member", "entity.name.function"],
+    ),
+    ("keyword", &["keyword.control", "keyword"]),
+    ("keyword.conditional", &["keyword.control.conditional"]),
+    ("keyword.coroutine", &["keyword.control.flow"]),
+    ("keyword.debug", &["keyword.other.debugger"]),
+    (
+        "keyword.exception",
+        &["keyword.control.exception", "keyword.control.trycatch"],
+    ),
+    ("keyword.function", &["storage.type.function", "keyword"]),
+    ("keyword.import", &["keyword.control.import"]),
+    (
+        "keyword.operator",
+        &["keyword.operator.word", "keyword.operator"],
+    ),
+    ("keyword.repeat", &["keyword.control.loop"]),
+    (
+        "keyword.return",
+        &["keyword.control.return", "keyword.control.flow"],
+    ),
+    ("keyword.type", &["storage.type", "keyword"]),
+    ("operator", &["keyword.operator"]),
+    (
+        "property",
+        &[
+            "variable.other.property",
+            "variable.other.object.property",
+            "support.type.property-name",
+        ],
+    ),
+    ("punctuation", &["punctuation"]),
+    (
+        "punctuation.bracket",
+        &[
+            "punctuation.section.brackets",
+            "punctuation.section",
+            "punctuation",
+        ],
+    ),
+    (
+        "punctuation.delimiter",
+        &[
+            "punctuation.separator",
+            "punctuation.terminator",
+            "punctuation",
+        ],
+    ),
+    (
+        "punctuation.special",
+        &["punctuation.definition.template-expression", "punctuation"],
+    ),
+    ("string", &["string"]),
+    ("string.special", &["string.other", "string"]),
+    ("tag", &["entity.name.tag"]),
+    ("tag.delimiter", &["punctuation.definition.tag"]),
+    ("tag.error", &["invalid.illegal"]),
+    ("type", &["entity.name.type", "support.type"]),
+    (
+        "type.builtin",
+        &["support.type.primitive", "support.type", "storage.type"],
+    ),
+    ("type.qualifier", &["storage.modifier"]),
+    ("variable", &["variable.other", "variable"]),
+    (
+        "variable.builtin",
+        &["variable.language", "support.variable"],
+    ),
+    ("variable.parameter", &["variable.parameter"]),
+    ("comment", &["comment"]),
+    ("comment.documentation", &["comment.block.documentation"]),
+    (
+        "macro",
+        &[
+            "entity.name.function.macro",
+            "entity.name.function.preprocessor",
+        ],
+    ),
+    ("label", &["entity.name.label"]),
+    ("diff.addition", &["markup.inserted"]),
+    ("diff.deletion", &["markup.deleted"]),
+    ("number", &["constant.numeric"]),
+    ("text.literal", &["markup.inline.raw", "markup.raw"]),
+    ("text.emphasis", &["markup.italic"]),
+    ("text.strong", &["markup.bold"]),
+    ("text.uri", &["markup.underline.link"]),
+    ("text.reference", &["string.other.link", "markup.link"]),
+    ("string.escape", &["constant.character.escape"]),
+    ("text.title", &["markup.heading", "entity.name.section"]),
+    ("text.strikethrough", &["markup.strikethrough"]),
+    ("embedded", &["meta.embedded"]),
+    ("error", &["invalid"]),
+    (
+        "namespace",
+        &["entity.name.namespace", "entity.name.type.module"],
+    ),
+    (
+        "include",
+        &[
+            "keyword.control.directive.include",
+            "keyword.control.import",
+        ],
+    ),
+    ("storageclass", &["storage.modifier"]),
+    ("repeat", &["keyword.control.loop"]),
+    ("conditional", &["keyword.control.conditional"]),
+    (
+        "exception",
+        &["keyword.control.exception", "keyword.control.trycatch"],
+    ),
+    (
+        "preproc",
+        &["meta.preprocessor", "keyword.control.directive"],
+    ),
+    ("character", &["constant.character", "string"]),
+    (
+        "character.special",
+        &["constant.character.escape", "constant.character"],
+    ),
+    (
+        "variable.member",
+        &["variable.other.member", "variable.other.property"],
+    ),
+    ("function.definition", &["entity.name.function"]),
+    ("type.definition", &["entity.name.type"]),
+    ("function.call", &["entity.name.function"]),
+    ("keyword.modifier", &["storage.modifier"]),
+    (
+        "keyword.directive",
+        &["keyword.control.directive", "meta.preprocessor"],
+    ),
+    ("string.regexp", &["string.regexp"]),
+    ("float", &["constant.numeric.float", "constant.numeric"]),
+    (
+        "boolean",
+        &["constant.language.boolean", "constant.language"],
+    ),
+];
+
+/// One theme rule: the scopes it applies to and the style it sets.
+///
+/// Unset fields leave the attribute to less specific rules.
+#[derive(Debug, Default)]
+pub(crate) struct ScopeRule {
+    /// Scope selectors, e.g. `keyword.control` or `string.quoted`.
+    pub selectors: Vec<String>,
+    pub foreground: Option<Color>,
+    pub background: Option<Color>,
+    pub font_style: Option<Modifiers>,
+}
+
+impl ScopeRule {
+    /// How specifically this rule matches `scope`: the number of segments of
+    /// the longest matching selector, or `None` if it doesn't match.
+    ///
+    /// Selectors with descendant or exclusion parts (`meta.tag string`,
+    /// `string - comment`) depend on context that arborium doesn't track, and
+    /// never match.
+    fn specificity(&self, scope: &str) -> Option<usize> {
+        self.selectors
+            .iter()
+            .filter(|selector| !selector.contains(' '))
+            .filter(|selector| {
+                scope
+                    .strip_prefix(selector.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+            })
+            .map(|selector| selector.split('.').count())
+            .max()
+    }
+}
+
+/// Split a comma-separated scope list into selectors.
+pub(crate) fn parse_selectors(scope: &str) -> Vec<String> {
+    scope
+        .split(',')
+        .map(|selector| selector.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|selector| !selector.is_empty())
+        .collect()
+}
+
+/// Style every highlight category that `rules` cover.
+///
+/// As in TextMate, each attribute comes from the most specific rule that sets
+/// it, with later rules winning ties.
+pub(crate) fn apply_rules(theme: &mut Theme, rules: &[ScopeRule]) {
+    for (i, def) in HIGHLIGHTS.iter().enumerate() {
+        let Some((_, scopes)) = SCOPES.iter().find(|(name, _)| *name == def.name) else {
+            continue;
+        };
+        for scope in *scopes {
+            let mut matching: Vec<(usize, usize)> = rules
+                .iter()
+                .enumerate()
+                .filter_map(|(order, rule)| Some((rule.specificity(scope)?, order)))
+                .collect();
+            if matching.is_empty() {
+                continue;
+            }
+            matching.sort_unstable();
+
+            let mut style = Style::new();
+            for (_, order) in matching {
+                let rule = &rules[order];
+                style.fg = rule.foreground.or(style.fg);
+                style.bg = rule.background.or(style.bg);
+                style.modifiers = rule.font_style.unwrap_or(style.modifiers);
+            }
+            theme.styles[i] = style;
+            break;
+        }
+    }
+}
+
+/// Parse a TextMate color: `#rgb`, `#rrggbb`, or either with an alpha
+/// component.
+pub(crate) fn parse_color(s: &str) -> Option<Color> {
+    let hex = s.trim().strip_prefix('#')?;
+    match hex.len() {
+        3 | 4 => {
+            let digit = |i: usize| {
+                hex.get(i..i + 1)
+                    .and_then(|d| u8::from_str_radix(d, 16).ok())
+                    .map(|d| d * 17)
+            };
+            let a = if hex.len() == 4 { digit(3)? } else { 255 };
+            Some(Color::rgba(digit(0)?, digit(1)?, digit(2)?, a))
+        }
+        6 | 8 => Color::from_hex(hex),
+        _ => None,
+    }
+}
+
+/// Whether text on `background` calls for a dark theme, by its luminance.
+pub(crate) fn is_dark_background(background: Color) -> bool {
+    let luminance = 0.2126 * f32::from(background.r)
+        + 0.7152 * f32::from(background.g)
+        + 0.0722 * f32::from(background.b);
+    luminance < 128.0
+}
+
+/// Parse a TextMate font style such as `"bold italic"`. An empty string
+/// explicitly clears all modifiers.
+pub(crate) fn parse_font_style(s: &str) -> Modifiers {
+    let mut modifiers = Modifiers::default();
+    for word in s.split_whitespace() {
+        match word {
+            "bold" => modifiers.bold = true,
+            "italic" => modifiers.italic = true,
+            "underline" => modifiers.underline = true,
+            "strikethrough" => modifiers.strikethrough = true,
+            _ => {}
+        }
+    }
+    modifiers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scopes_name_known_categories() {
+        for (name, _) in SCOPES {
+            assert!(
+                HIGHLIGHTS.iter().any(|def| def.name == *name),
+                "unknown highlight {name}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_most_specific_rule_wins_per_attribute() {
+        let rule = |scope: &str, fg: Option<Color>, font_style: Option<&str>| ScopeRule {
+            selectors: parse_selectors(scope),
+            foreground: fg,
+            font_style: font_style.map(parse_font_style),
+            ..ScopeRule::default()
+        };
+        let red = Color::new(255, 0, 0);
+        let blue = Color::new(0, 0, 255);
+        let rules = [
+            rule("keyword, storage", Some(red), Some("italic")),
+            rule("keyword.control.conditional", Some(blue), None),
+            rule("source.rust keyword", Some(blue), Some("bold")),
+        ];
+
+        let mut theme = Theme::new("test");
+        apply_rules(&mut theme, &rules);
+        let style =
+            |name: &str| &theme.styles[HIGHLIGHTS.iter().position(|d| d.name == name).unwrap()];
+
+        assert_eq!(style("keyword").fg, Some(red));
+        assert!(style("keyword").modifiers.italic);
+        // Color from the specific rule, font style from the general one
+        assert_eq!(style("keyword.conditional").fg, Some(blue));
+        assert!(style("keyword.conditional").modifiers.italic);
+        assert!(style("comment").is_empty());
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#f00"), Some(Color::new(255, 0, 0)));
+        assert_eq!(parse_color("#ff000080"), Some(Color::rgba(255, 0, 0, 0x80)));
+        assert_eq!(parse_color("#f008"), Some(Color::rgba(255, 0, 0, 0x88)));
+        assert_eq!(parse_color("red"), None);
+        // Non-ASCII input is rejected rather than sliced mid-character
+        assert_eq!(parse_color("#é1"), None);
+        assert_eq!(parse_color("#aé"), None);
+    }
+}
@@ -23,36 +23,95 @@
 //! blue1 = "#61afef"
 //! ```
 
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::fmt::Write as FmtWrite;
 
-/// RGB color.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+use crate::highlights::{COUNT, HighlightDef};
+
+/// RGB color with an alpha channel.
+///
+/// Colors are opaque unless given an alpha. CSS output keeps the alpha;
+/// outputs without transparency, such as ANSI, [`blend`](Self::blend)
+/// translucent colors over the theme background.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
     pub b: u8,
+    /// Opacity, from 0 (transparent) to 255 (opaque).
+    pub a: u8,
 }
 
 impl Color {
+    /// An opaque color.
     pub const fn new(r: u8, g: u8, b: u8) -> Self {
-        Self { r, g, b }
+        Self { r, g, b, a: 255 }
+    }
+
+    /// A color with the given opacity.
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// This color with its opacity replaced by `a`.
+    pub const fn with_alpha(self, a: u8) -> Self {
+        Self { a, ..self }
     }
 
-    /// Parse a hex color string like "#ff0000" or "ff0000".
+    /// Whether the color is fully opaque.
+    pub const fn is_opaque(&self) -> bool {
+        self.a == 255
+    }
+
+    /// Parse a hex color string like "#ff0000" or "ff0000", optionally
+    /// followed by an alpha component ("#ff000080").
     pub fn from_hex(s: &str) -> Option<Self> {
         let s = s.strip_prefix('#').unwrap_or(s);
-        if s.len() != 6 {
+        if s.len() != 6 && s.len() != 8 {
             return None;
         }
-        let r = u8::from_str_radix(&s[0..2], 16).ok()?;
-        let g = u8::from_str_radix(&s[2..4], 16).ok()?;
-        let b = u8::from_str_radix(&s[4..6], 16).ok()?;
-        Some(Self { r, g, b })
+        let channel = |i: usize| s.get(i..i + 2).and_then(|c| u8::from_str_radix(c, 16).ok());
+        let a = if s.len() == 8 { channel(6)? } else { 255 };
+        Some(Self::rgba(channel(0)?, channel(2)?, channel(4)?, a))
     }
 
-    /// Convert to hex string with # prefix.
+    /// Convert to hex string with # prefix. The alpha component is only
+    /// included for translucent colors.
     pub fn to_hex(&self) -> String {
-        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+        if self.is_opaque() {
+            format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+        } else {
+            format!("#{:02x}{:02x}{:02x}{:02x}", self.r, self.g, self.b, self.a)
+        }
+    }
+
+    /// Composite this color over `bg`, as it appears drawn on top of it.
+    ///
+    /// Over an opaque background the result is opaque.
+    ///
+    /// ```rust
+    /// use arborium_theme::Color;
+    ///
+    /// let selection = Color::from_hex("#ffffff80").unwrap();
+    /// assert_eq!(selection.blend(Color::new(0, 0, 0)), Color::new(128, 128, 128));
+    /// ```
+    pub fn blend(&self, bg: Color) -> Color {
+        let alpha = f32::from(self.a) / 255.0;
+        let bg_alpha = f32::from(bg.a) / 255.0 * (1.0 - alpha);
+        let out_alpha = alpha + bg_alpha;
+        if out_alpha == 0.0 {
+            return Color::rgba(0, 0, 0, 0);
+        }
+        let mix = |fg: u8, bg: u8| {
+            ((f32::from(fg) * alpha + f32::from(bg) * bg_alpha) / out_alpha).round() as u8
+        };
+        Color::rgba(
+            mix(self.r, bg.r),
+            mix(self.g, bg.g),
+            mix(self.b, bg.b),
+            (out_alpha * 255.0).round() as u8,
+        )
     }
 
     /// Lighten the color by a factor (0.0 to 1.0).
@@ -62,6 +121,20 @@ impl Color {
             r: (self.r as f32 + (255.0 - self.r as f32) * factor).round() as u8,
             g: (self.g as f32 + (255.0 - self.g as f32) * factor).round() as u8,
             b: (self.b as f32 + (255.0 - self.b as f32) * factor).round() as u8,
+            a: self.a,
+        }
+    }
+
+    /// Interpolate between this color (at `t = 0.0`) and `other` (at
+    /// `t = 1.0`), channel by channel including alpha.
+    pub fn lerp(&self, other: &Color, t: f32) -> Self {
+        let t = t.clamp(0.0, 1.0);
+        let mix = |a: u8, b: u8| (f32::from(a) + (f32::from(b) - f32::from(a)) * t).round() as u8;
+        Self {
+            r: mix(self.r, other.r),
+            g: mix(self.g, other.g),
+            b: mix(self.b, other.b),
+            a: mix(self.a, other.a),
         }
     }
 
@@ -72,24 +145,145 @@ impl Color {
             r: (self.r as f32 * (1.0 - factor)).round() as u8,
             g: (self.g as f32 * (1.0 - factor)).round() as u8,
             b: (self.b as f32 * (1.0 - factor)).round() as u8,
+            a: self.a,
         }
     }
+
+    /// Nearest color in the xterm 256-color palette.
+    ///
+    /// Only the 6×6×6 color cube (16–231) and the grayscale ramp (232–255)
+    /// are considered, since the first 16 entries vary between terminals.
+    pub fn to_ansi256(&self) -> u8 {
+        const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+        let nearest_level = |c: u8| {
+            (0..LEVELS.len())
+                .min_by_key(|&i| (LEVELS[i] as i32 - c as i32).abs())
+                .unwrap()
+        };
+
+        let (r, g, b) = (
+            nearest_level(self.r),
+            nearest_level(self.g),
+            nearest_level(self.b),
+        );
+        let cube = Color::new(LEVELS[r], LEVELS[g], LEVELS[b]);
+        let cube_index = 16 + 36 * r + 6 * g + b;
+
+        // Grayscale ramp: 8, 18, ..., 238
+        let avg = (self.r as u32 + self.g as u32 + self.b as u32) / 3;
+        let gray_step = (avg.saturating_sub(3) / 10).min(23) as u8;
+        let gray_level = 8 + gray_step * 10;
+        let gray = Color::new(gray_level, gray_level, gray_level);
+
+        if self.distance_sq(&gray) < self.distance_sq(&cube) {
+            232 + gray_step
+        } else {
+            cube_index as u8
+        }
+    }
+
+    /// Nearest of the 16 standard ANSI colors (0–7 normal, 8–15 bright),
+    /// using the xterm default palette.
+    pub fn to_ansi16(&self) -> u8 {
+        const PALETTE: [Color; 16] = [
+            Color::new(0, 0, 0),
+            Color::new(205, 0, 0),
+            Color::new(0, 205, 0),
+            Color::new(205, 205, 0),
+            Color::new(0, 0, 238),
+            Color::new(205, 0, 205),
+            Color::new(0, 205, 205),
+            Color::new(229, 229, 229),
+            Color::new(127, 127, 127),
+            Color::new(255, 0, 0),
+            Color::new(0, 255, 0),
+            Color::new(255, 255, 0),
+            Color::new(92, 92, 255),
+            Color::new(255, 0, 255),
+            Color::new(0, 255, 255),
+            Color::new(255, 255, 255),
+        ];
+        (0..PALETTE.len())
+            .min_by_key(|&i| self.distance_sq(&PALETTE[i]))
+            .unwrap() as u8
+    }
+
+    /// Relative luminance as defined by WCAG, from 0 (black) to 1 (white).
+    ///
+    /// The alpha component is ignored.
+    pub fn relative_luminance(&self) -> f32 {
+        let channel = |v: u8| {
+            let v = f32::from(v) / 255.0;
+            if v <= 0.04045 {
+                v / 12.92
+            } else {
+                ((v + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        0.2126 * channel(self.r) + 0.7152 * channel(self.g) + 0.0722 * channel(self.b)
+    }
+
+    /// WCAG contrast ratio between this color and `other`, from 1 (none) to
+    /// 21 (black on white). The order of the colors doesn't matter.
+    ///
+    /// WCAG asks for at least 4.5 for body text ([`WCAG_AA`]) and 3 for
+    /// large text. Blend translucent colors over their background first.
+    ///
+    /// ```rust
+    /// use arborium_theme::Color;
+    ///
+    /// let ratio = Color::new(0x76, 0x76, 0x76).contrast_ratio(&Color::new(255, 255, 255));
+    /// assert!(ratio >= arborium_theme::WCAG_AA);
+    /// ```
+    ///
+    /// [`WCAG_AA`]: crate::WCAG_AA
+    pub fn contrast_ratio(&self, other: &Color) -> f32 {
+        let (a, b) = (self.relative_luminance(), other.relative_luminance());
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+
+    /// Squared Euclidean distance in RGB space.
+    fn distance_sq(&self, other: &Color) -> u32 {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+        d(self.r, other.r) + d(self.g, other.g) + d(self.b, other.b)
+    }
 }
 
 /// Text style modifiers.
+///
+/// With the `serde` feature, only the modifiers that are set are serialized.
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
 pub struct Modifiers {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "is_false"))]
     pub bold: bool,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "is_false"))]
     pub italic: bool,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "is_false"))]
     pub underline: bool,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "is_false"))]
     pub strikethrough: bool,
 }
 
 /// A complete style for a highlight category.
+///
+/// With the `serde` feature, unset colors and modifiers are omitted.
 #[derive(Debug, Clone, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
 pub struct Style {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub fg: Option<Color>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub bg: Option<Color>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Modifiers::is_empty"))]
     pub modifiers: Modifiers,
 }
 
@@ -134,30 +328,61 @@ impl Style {
 
     /// Check if this style has any effect.
     pub fn is_empty(&self) -> bool {
-        self.fg.is_none()
-            && self.bg.is_none()
-            && !self.modifiers.bold
-            && !self.modifiers.italic
-            && !self.modifiers.underline
-            && !self.modifiers.strikethrough
+        self.fg.is_none() && self.bg.is_none() && self.modifiers.is_empty()
+    }
+}
+
+impl Modifiers {
+    /// Check if no modifier is set.
+    pub fn is_empty(&self) -> bool {
+        !self.bold && !self.italic && !self.underline && !self.strikethrough
     }
 }
 
 /// A complete syntax highlighting theme.
+///
+/// With the `serde` feature, `styles` is (de)serialized as a map from
+/// highlight names (or their aliases) to styles, leaving out empty styles;
+/// see [`Theme::to_toml_string`] for what that looks like.
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
 pub struct Theme {
     /// Theme name for display.
     pub name: String,
     /// Whether this is a dark or light theme.
     pub is_dark: bool,
     /// URL to the original theme source (for attribution).
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub source_url: Option<String>,
     /// Background color for the code block.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub background: Option<Color>,
     /// Foreground (default text) color.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub foreground: Option<Color>,
+    /// Color of line numbers in the gutter.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub gutter: Option<Color>,
+    /// Background of highlighted (current) lines.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub line_highlight: Option<Color>,
+    /// Background of selected text.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub selection: Option<Color>,
     /// Styles for each highlight category, indexed by HIGHLIGHT_NAMES.
+    #[cfg_attr(feature = "serde", serde(with = "serde_styles"))]
     pub styles: [Style; crate::highlights::COUNT],
+    /// Style overrides for code in particular languages, keyed by language
+    /// ID (e.g. `"json"`). See [`Theme::for_language`].
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip_serializing_if = "BTreeMap::is_empty", with = "serde_languages")
+    )]
+    pub languages: BTreeMap<String, [Style; COUNT]>,
 }
 
 impl Default for Theme {
@@ -168,7 +393,11 @@ impl Default for Theme {
             source_url: None,
             background: None,
             foreground: None,
+            gutter: None,
+            line_highlight: None,
+            selection: None,
             styles: std::array::from_fn(|_| Style::new()),
+            languages: BTreeMap::new(),
         }
     }
 }
@@ -183,8 +412,27 @@ impl Theme {
     }
 
     /// Get the style for a highlight index.
+    ///
+    /// A category the theme doesn't style falls back along its dotted name:
+    /// `keyword.function` takes the style of `keyword` when it has none of
+    /// its own. Categories outside that scheme follow their
+    /// [`parent_tag`](crate::HighlightDef::parent_tag), so `repeat` falls back
+    /// to `keyword` too. If no ancestor is styled either, the category's own
+    /// (empty) style is returned, and the text is drawn in the default
+    /// foreground. Returns `None` only for an index out of range.
     pub fn style(&self, index: usize) -> Option<&Style> {
-        self.styles.get(index)
+        let own = self.styles.get(index)?;
+        let mut current = index;
+        while own.is_empty() {
+            let Some(parent) = parent_category(current) else {
+                break;
+            };
+            if !self.styles[parent].is_empty() {
+                return Some(&self.styles[parent]);
+            }
+            current = parent;
+        }
+        Some(own)
     }
 
     /// Set the style for a highlight index.
@@ -194,11 +442,154 @@ impl Theme {
         }
     }
 
+    /// Set the style for a highlight index in code of one language only,
+    /// e.g. to dim punctuation in JSON.
+    pub fn set_language_style(&mut self, language: &str, index: usize, style: Style) {
+        if index < COUNT {
+            self.languages
+                .entry(language.to_string())
+                .or_insert_with(|| std::array::from_fn(|_| Style::new()))[index] = style;
+        }
+    }
+
+    /// The theme to render code in `language` with: this theme with the
+    /// language's overrides in place of the styles they replace.
+    ///
+    /// An override replaces the style of its own category only, so the usual
+    /// [fallback](Self::style) still applies: overriding `punctuation` also
+    /// changes `punctuation.bracket` unless the theme styles brackets
+    /// separately. Borrows the theme when the language has no overrides.
+    ///
+    /// ```rust
+    /// use arborium_theme::{Color, Style, Theme, ThemeSlot, slot_to_highlight_index};
+    ///
+    /// let punctuation = slot_to_highlight_index(ThemeSlot::Punctuation).unwrap();
+    /// let mut theme = Theme::new("example");
+    /// theme.set_style(punctuation, Style::new().fg(Color::new(0xc0, 0xc0, 0xc0)));
+    /// theme.set_language_style("json", punctuation, Style::new().fg(Color::new(0x60, 0x60, 0x60)));
+    ///
+    /// let json = theme.for_language("json");
+    /// assert_eq!(json.style(punctuation).unwrap().fg, Some(Color::new(0x60, 0x60, 0x60)));
+    /// let rust = theme.for_language("rust");
+    /// assert_eq!(rust.style(punctuation).unwrap().fg, Some(Color::new(0xc0, 0xc0, 0xc0)));
+    /// ```
+    pub fn for_language(&self, language: &str) -> Cow<'_, Theme> {
+        let Some(overrides) = self.languages.get(language) else {
+            return Cow::Borrowed(self);
+        };
+        let mut theme = self.clone();
+        for (style, over) in theme.styles.iter_mut().zip(overrides) {
+            if !over.is_empty() {
+                *style = over.clone();
+            }
+        }
+        Cow::Owned(theme)
+    }
+
+    /// Blend this theme (at `t = 0.0`) into `other` (at `t = 1.0`), e.g. to
+    /// animate a switch between a light and a dark theme, or to derive an
+    /// intermediate palette.
+    ///
+    /// Colors are interpolated with [`Color::lerp`]. A category styled in
+    /// only one theme blends from the other theme's foreground, so it fades
+    /// in rather than popping. Colors set in only one theme otherwise, and
+    /// modifiers, name, darkness and language overrides, switch over at
+    /// `t = 0.5`.
+    ///
+    /// ```rust
+    /// use arborium_theme::{Color, Theme};
+    ///
+    /// let mut light = Theme::new("light");
+    /// light.background = Some(Color::new(255, 255, 255));
+    /// let mut dark = Theme::new("dark");
+    /// dark.background = Some(Color::new(0, 0, 0));
+    ///
+    /// let dusk = light.lerp(&dark, 0.5);
+    /// assert_eq!(dusk.background, Some(Color::new(128, 128, 128)));
+    /// ```
+    pub fn lerp(&self, other: &Theme, t: f32) -> Theme {
+        let t = t.clamp(0.0, 1.0);
+        let nearest = if t < 0.5 { self } else { other };
+        let mix = |a: Option<Color>, b: Option<Color>| match (a, b) {
+            (Some(a), Some(b)) => Some(a.lerp(&b, t)),
+            _ if t < 0.5 => a,
+            _ => b,
+        };
+        // Unset text colors are drawn in the foreground
+        let mix_fg = |a: Option<Color>, b: Option<Color>| match (a, b) {
+            (None, None) => None,
+            _ => mix(a.or(self.foreground), b.or(other.foreground)),
+        };
+
+        let styles = std::array::from_fn(|i| {
+            let (a, b) = (&self.styles[i], &other.styles[i]);
+            Style {
+                fg: mix_fg(a.fg, b.fg),
+                bg: mix(a.bg, b.bg),
+                modifiers: nearest.styles[i].modifiers,
+            }
+        });
+        Theme {
+            name: nearest.name.clone(),
+            is_dark: nearest.is_dark,
+            source_url: None,
+            background: mix(self.background, other.background),
+            foreground: mix(self.foreground, other.foreground),
+            gutter: mix(self.gutter, other.gutter),
+            line_highlight: mix(self.line_highlight, other.line_highlight),
+            selection: mix(self.selection, other.selection),
+            styles,
+            languages: nearest.languages.clone(),
+        }
+    }
+
     /// Parse a theme from Helix-style TOML.
     ///
+    /// Same as [`from_helix_toml`](Self::from_helix_toml).
+    ///
     /// This method is only available when the `toml` feature is enabled.
     #[cfg(feature = "toml")]
     pub fn from_toml(toml_str: &str) -> Result<Self, ThemeError> {
+        Self::from_helix_toml(toml_str)
+    }
+
+    /// Parse a [Helix](https://helix-editor.com) theme file.
+    ///
+    /// Scopes are resolved as Helix resolves them: a category takes the style
+    /// of the longest theme key that is a prefix of its capture name or of the
+    /// name Helix uses for it, so `keyword.control.conditional` styles
+    /// conditionals and a plain `keyword` covers every keyword without a more
+    /// specific entry. Colors may name entries of the `[palette]` table.
+    ///
+    /// `inherits` is honored when it names a built-in or
+    /// [registered](crate::registry) theme (e.g. `catppuccin_mocha`); other
+    /// base themes are not available and are ignored. `ui.background` and
+    /// `ui.text` set the background and foreground, `ui.linenr` the gutter,
+    /// `ui.cursorline.primary` the line highlight and `ui.selection` the
+    /// selection.
+    ///
+    /// This method is only available when the `toml` feature is enabled.
+    ///
+    /// ```rust
+    /// use arborium_theme::{Color, Theme, highlights::HIGHLIGHTS};
+    ///
+    /// let theme = Theme::from_helix_toml(r##"
+    ///     "keyword" = "purple"
+    ///     "keyword.control.conditional" = { fg = "#ff0000", modifiers = ["bold"] }
+    ///     "ui.background" = { bg = "#282c34" }
+    ///
+    ///     [palette]
+    ///     purple = "#c678dd"
+    /// "##)?;
+    ///
+    /// let style = |name| &theme.styles[HIGHLIGHTS.iter().position(|h| h.name == name).unwrap()];
+    /// assert_eq!(style("keyword.repeat").fg, Some(Color::new(0xc6, 0x78, 0xdd)));
+    /// assert_eq!(style("keyword.conditional").fg, Some(Color::new(0xff, 0, 0)));
+    /// assert_eq!(theme.background, Some(Color::new(0x28, 0x2c, 0x34)));
+    /// # Ok::<(), arborium_theme::ThemeError>(())
+    /// ```
+    #[cfg(feature = "toml")]
+    pub fn from_helix_toml(toml_str: &str) -> Result<Self, ThemeError> {
         let value: toml::Value = toml_str
             .parse()
             .map_err(|e| ThemeError::Parse(format!("{e}")))?;
@@ -206,7 +597,14 @@ impl Theme {
             .as_table()
             .ok_or(ThemeError::Parse("Expected table".into()))?;
 
-        let mut theme = Theme::default();
+        // Start from the inherited theme, if it is one we ship
+        let mut theme = table
+            .get("inherits")
+            .and_then(|v| v.as_str())
+            .and_then(|name| crate::registry().get(name))
+            .unwrap_or_default();
+        theme.name = String::new();
+        theme.source_url = None;
 
         // Extract metadata
         if let Some(name) = table.get("name").and_then(|v| v.as_str()) {
@@ -259,65 +657,222 @@ impl Theme {
                 theme.foreground = resolve_color(fg_str);
             }
         }
-        // Also check for simple "foreground" key
+        // Also check for simple "foreground" key, and Helix's "ui.text"
         if let Some(fg_str) = table.get("foreground").and_then(|v| v.as_str()) {
             theme.foreground = resolve_color(fg_str);
+        } else if let Some(text) = table.get("ui.text") {
+            theme.foreground = parse_style_value(text, &resolve_color)?.fg;
+        }
+
+        // Editor colors, as Helix keys or arborium's own top-level keys
+        let ui_color = |helix_keys: &[&str], attr: &str, key: &str| -> Option<Color> {
+            if let Some(s) = table.get(key).and_then(|v| v.as_str()) {
+                return resolve_color(s);
+            }
+            let value = helix_keys.iter().find_map(|k| table.get(*k))?;
+            match value {
+                toml::Value::String(s) if attr == "fg" => resolve_color(s),
+                toml::Value::Table(t) => {
+                    t.get(attr).and_then(|v| v.as_str()).and_then(resolve_color)
+                }
+                _ => None,
+            }
+        };
+        if let Some(color) = ui_color(&["ui.linenr"], "fg", "gutter") {
+            theme.gutter = Some(color);
+        }
+        if let Some(color) = ui_color(
+            &["ui.cursorline.primary", "ui.cursorline"],
+            "bg",
+            "line_highlight",
+        ) {
+            theme.line_highlight = Some(color);
+        }
+        if let Some(color) = ui_color(&["ui.selection", "ui.selection.primary"], "bg", "selection")
+        {
+            theme.selection = Some(color);
         }
 
-        // Build mapping from Helix names to our indices using highlights module
         use crate::highlights::HIGHLIGHTS;
 
-        // Parse each highlight rule - try main name and aliases
         for (i, def) in HIGHLIGHTS.iter().enumerate() {
-            // Try main name
-            if let Some(rule) = table.get(def.name) {
-                let style = parse_style_value(rule, &resolve_color)?;
-                theme.styles[i] = style;
-                continue;
-            }
-
-            // Try aliases
-            for alias in def.aliases {
-                if let Some(rule) = table.get(*alias) {
-                    let style = parse_style_value(rule, &resolve_color)?;
-                    theme.styles[i] = style;
-                    break;
+            let helix_names = HELIX_SCOPES
+                .iter()
+                .find(|(name, _)| *name == def.name)
+                .map_or(&[][..], |(_, scopes)| *scopes);
+            let candidates = std::iter::once(def.name)
+                .chain(def.aliases.iter().copied())
+                .chain(helix_names.iter().copied());
+
+            // The most specific key covering any of the candidate scopes
+            let mut best: Option<(usize, &toml::Value)> = None;
+            for scope in candidates {
+                if let Some((depth, rule)) = lookup_helix_scope(table, scope)
+                    && best.is_none_or(|(best_depth, _)| depth > best_depth)
+                {
+                    best = Some((depth, rule));
                 }
             }
+            if let Some((_, rule)) = best {
+                theme.styles[i] = parse_style_value(rule, &resolve_color)?;
+            }
         }
 
-        // Also handle some common Helix-specific mappings that aren't direct matches
-        let extra_mappings: &[(&str, &str)] = &[
-            ("keyword.control", "keyword"),
-            ("keyword.storage", "keyword"),
-            ("comment.line", "comment"),
-            ("comment.block", "comment"),
-            ("function.macro", "macro"),
-        ];
+        Ok(theme)
+    }
 
-        for (helix_name, our_name) in extra_mappings {
-            if let Some(rule) = table.get(*helix_name) {
-                // Find our index
-                if let Some(i) = HIGHLIGHTS.iter().position(|h| h.name == *our_name) {
-                    // Only apply if we don't already have a style
-                    if theme.styles[i].is_empty() {
-                        let style = parse_style_value(rule, &resolve_color)?;
-                        theme.styles[i] = style;
-                    }
+    /// Parse a theme from arborium's own TOML format, as written by
+    /// [`to_toml_string`](Self::to_toml_string).
+    ///
+    /// Unlike [`from_toml`](Self::from_toml), which reads Helix themes, this
+    /// round-trips every field of the theme exactly. Unknown keys and
+    /// highlight names are errors, so typos in hand-edited files are caught.
+    ///
+    /// This method is only available when the `toml` feature is enabled.
+    #[cfg(feature = "toml")]
+    pub fn from_toml_str(toml_str: &str) -> Result<Self, ThemeError> {
+        toml::from_str(toml_str).map_err(|e| ThemeError::Parse(format!("{e}")))
+    }
+
+    /// Serialize the theme to arborium's own TOML format.
+    ///
+    /// Only categories with a style are written, keyed by highlight name:
+    ///
+    /// ```toml
+    /// name = "Example"
+    /// is_dark = true
+    /// background = "#1e1e2e"
+    ///
+    /// [styles.comment]
+    /// fg = "#6c7086"
+    ///
+    /// [styles.comment.modifiers]
+    /// italic = true
+    /// ```
+    ///
+    /// This method is only available when the `toml` feature is enabled.
+    #[cfg(feature = "toml")]
+    pub fn to_toml_string(&self) -> String {
+        toml::to_string_pretty(self).expect("themes always serialize to TOML")
+    }
+
+    /// Export the theme as a [Helix](https://helix-editor.com) theme file.
+    ///
+    /// Each styled category is written under the scope Helix uses for it,
+    /// the base and editor colors as `ui.background`, `ui.text`,
+    /// `ui.linenr`, `ui.cursorline.primary` and `ui.selection`. The name,
+    /// variant and source are written as the theme files arborium ships do;
+    /// Helix ignores them. Helix colors can't be translucent, so translucent
+    /// colors are blended over the background. Language overrides are left
+    /// out.
+    ///
+    /// [`from_helix_toml`](Self::from_helix_toml) reads the result back.
+    ///
+    /// This method is only available when the `toml` feature is enabled.
+    ///
+    /// ```rust
+    /// use arborium_theme::builtin;
+    ///
+    /// let toml = builtin::dracula().to_helix_toml();
+    /// assert!(toml.contains("\n\"ui.background\" = { bg = \"#282a36\" }\n"));
+    /// ```
+    #[cfg(feature = "toml")]
+    pub fn to_helix_toml(&self) -> String {
+        use crate::highlights::HIGHLIGHTS;
+
+        let string = |s: &str| toml::Value::String(s.to_string()).to_string();
+        let color = |c: &Color| string(&self.opaque(*c).to_hex());
+        let mut out = String::new();
+
+        if !self.name.is_empty() {
+            writeln!(out, "name = {}", string(&self.name)).unwrap();
+        }
+        let variant = if self.is_dark { "dark" } else { "light" };
+        writeln!(out, "variant = \"{variant}\"").unwrap();
+        if let Some(source) = &self.source_url {
+            writeln!(out, "source = {}", string(source)).unwrap();
+        }
+        writeln!(out).unwrap();
+
+        if let Some(bg) = &self.background {
+            writeln!(out, "\"ui.background\" = {{ bg = {} }}", color(bg)).unwrap();
+        }
+        if let Some(fg) = &self.foreground {
+            writeln!(out, "\"ui.text\" = {}", color(fg)).unwrap();
+        }
+        if let Some(gutter) = &self.gutter {
+            writeln!(out, "\"ui.linenr\" = {}", color(gutter)).unwrap();
+        }
+        if let Some(line_highlight) = &self.line_highlight {
+            writeln!(
+                out,
+                "\"ui.cursorline.primary\" = {{ bg = {} }}",
+                color(line_highlight)
+            )
+            .unwrap();
+        }
+        if let Some(selection) = &self.selection {
+            writeln!(out, "\"ui.selection\" = {{ bg = {} }}", color(selection)).unwrap();
+        }
+        writeln!(out).unwrap();
+
+        // Categories named as in Helix come first, so that they win over
+        // categories that only map onto the same scope
+        let helix_scope = |name: &'static str| {
+            HELIX_SCOPES
+                .iter()
+                .find(|(category, _)| *category == name)
+                .map(|(_, scopes)| scopes[0])
+        };
+        let mut keys: Vec<(&str, &Style)> = Vec::new();
+        for own_name in [true, false] {
+            for (def, style) in HIGHLIGHTS.iter().zip(&self.styles) {
+                let key = helix_scope(def.name).unwrap_or(def.name);
+                if style.is_empty()
+                    || (key == def.name) != own_name
+                    || keys.iter().any(|(k, _)| *k == key)
+                {
+                    continue;
                 }
+                keys.push((key, style));
             }
         }
 
-        Ok(theme)
+        for (key, style) in keys {
+            let mut fields = Vec::new();
+            if let Some(fg) = &style.fg {
+                fields.push(format!("fg = {}", color(fg)));
+            }
+            if let Some(bg) = &style.bg {
+                fields.push(format!("bg = {}", color(bg)));
+            }
+            let modifiers: Vec<&str> = [
+                (style.modifiers.bold, "\"bold\""),
+                (style.modifiers.italic, "\"italic\""),
+                (style.modifiers.underline, "\"underlined\""),
+                (style.modifiers.strikethrough, "\"crossed_out\""),
+            ]
+            .into_iter()
+            .filter_map(|(set, name)| set.then_some(name))
+            .collect();
+            if !modifiers.is_empty() {
+                fields.push(format!("modifiers = [{}]", modifiers.join(", ")));
+            }
+            writeln!(out, "\"{key}\" = {{ {} }}", fields.join(", ")).unwrap();
+        }
+        out
     }
 
     /// Generate CSS for this theme.
     ///
     /// Uses CSS nesting for compact output. The selector_prefix is prepended
     /// to scope the rules (e.g., `[data-theme="mocha"]`).
+    ///
+    /// [Language overrides](Self::for_language) are scoped to the
+    /// `language-{id}` class that `<code>` blocks conventionally carry, on
+    /// the prefixed element or inside it.
     pub fn to_css(&self, selector_prefix: &str) -> String {
         use crate::highlights::HIGHLIGHTS;
-        use std::collections::HashMap;
 
         let mut css = String::new();
 
@@ -340,6 +895,16 @@ impl Theme {
             writeln!(css, "  --fg: {};", fg.to_hex()).unwrap();
         }
 
+        if let Some(gutter) = &self.gutter {
+            writeln!(css, "  --gutter: {};", gutter.to_hex()).unwrap();
+        }
+        if let Some(line_highlight) = &self.line_highlight {
+            writeln!(css, "  --line-highlight: {};", line_highlight.to_hex()).unwrap();
+        }
+        if let Some(selection) = &self.selection {
+            writeln!(css, "  --selection: {};", selection.to_hex()).unwrap();
+        }
+
         // Find indices for accent and muted colors
         let function_idx = HIGHLIGHTS.iter().position(|h| h.name == "function");
         let keyword_idx = HIGHLIGHTS.iter().position(|h| h.name == "keyword");
@@ -367,44 +932,130 @@ impl Theme {
             writeln!(css, "  --muted: {};", muted.to_hex()).unwrap();
         }
 
-        // Build a map from tag -> style for parent lookups
-        let mut tag_to_style: HashMap<&str, &Style> = HashMap::new();
-        for (i, def) in HIGHLIGHTS.iter().enumerate() {
-            if !def.tag.is_empty() && !self.styles[i].is_empty() {
-                tag_to_style.insert(def.tag, &self.styles[i]);
-            }
+        // Generate rules for each highlight category
+        let rules = |theme: &Theme| -> Vec<(&'static str, String)> {
+            theme
+                .css_styles()
+                .into_iter()
+                .map(|(def, style)| (def.tag, css_style_declarations(style)))
+                .collect()
+        };
+        let base_rules = rules(self);
+        for (tag, declarations) in &base_rules {
+            writeln!(css, "  a-{tag} {{{declarations} }}").unwrap();
         }
 
-        // Generate rules for each highlight category
-        for (i, def) in HIGHLIGHTS.iter().enumerate() {
-            if def.tag.is_empty() {
-                continue; // Skip categories like "none" that have no tag
+        // Language overrides apply inside `<code class="language-…">`
+        for language in self.languages.keys() {
+            let changed: Vec<_> = rules(&self.for_language(language))
+                .into_iter()
+                .filter(|rule| !base_rules.contains(rule))
+                .collect();
+            if changed.is_empty() {
+                continue;
+            }
+            writeln!(css, "  &.language-{language}, .language-{language} {{").unwrap();
+            for (tag, declarations) in changed {
+                writeln!(css, "    a-{tag} {{{declarations} }}").unwrap();
             }
+            writeln!(css, "  }}").unwrap();
+        }
 
-            // Use own style, or fall back to parent style
-            let style = if !self.styles[i].is_empty() {
-                &self.styles[i]
-            } else if !def.parent_tag.is_empty() {
-                // Look up parent style
-                tag_to_style
-                    .get(def.parent_tag)
-                    .copied()
-                    .unwrap_or(&self.styles[i])
-            } else {
-                continue; // No style and no parent
-            };
+        // Editor colors, for the markup of the HTML renderer
+        if let Some(gutter) = &self.gutter {
+            writeln!(css, "  .line-number {{ color: {}; }}", gutter.to_hex()).unwrap();
+        }
+        if let Some(line_highlight) = &self.line_highlight {
+            writeln!(
+                css,
+                "  .highlighted {{ background: {}; }}",
+                line_highlight.to_hex()
+            )
+            .unwrap();
+        }
+        if let Some(selection) = &self.selection {
+            writeln!(
+                css,
+                "  &::selection, & ::selection {{ background: {}; }}",
+                selection.to_hex()
+            )
+            .unwrap();
+        }
 
-            if style.is_empty() {
-                continue;
-            }
+        writeln!(css, "}}").unwrap();
+
+        css
+    }
+
+    /// The style of each highlight category that has a tag, with the
+    /// fallback of [`style`](Self::style). Categories with no style are
+    /// skipped.
+    fn css_styles(&self) -> Vec<(&'static HighlightDef, &Style)> {
+        use crate::highlights::HIGHLIGHTS;
+
+        HIGHLIGHTS
+            .iter()
+            .enumerate()
+            // Skip categories like "none" that have no tag
+            .filter(|(_, def)| !def.tag.is_empty())
+            .filter_map(|(i, def)| Some((def, self.style(i)?)))
+            .filter(|(_, style)| !style.is_empty())
+            .collect()
+    }
+
+    /// Generate CSS custom properties holding this theme's colors.
+    ///
+    /// Pair with the theme-independent rules from
+    /// [`css_variable_rules`](Self::css_variable_rules) to switch themes on
+    /// the client without re-rendering HTML: emit one variable block per
+    /// theme, scoped by `selector` (e.g. `[data-theme="mocha"]`), and toggle
+    /// the selector.
+    ///
+    /// Each highlight category gets `--arb-{name}` for its color, with dots
+    /// in the name replaced by dashes (`--arb-keyword`,
+    /// `--arb-function-builtin`), plus `-bg`, `-weight`, `-style` and
+    /// `-decoration` variants where the theme sets them. The base colors are
+    /// `--arb-bg` and `--arb-fg`; the editor colors are `--arb-gutter`,
+    /// `--arb-line-highlight` and `--arb-selection`.
+    pub fn to_css_variables(&self, selector: &str) -> String {
+        css_block(selector, &self.css_variable_declarations())
+    }
 
-            write!(css, "  a-{} {{", def.tag).unwrap();
+    /// The declarations of [`to_css_variables`](Self::to_css_variables), as
+    /// `(property, value)` pairs.
+    fn css_variable_declarations(&self) -> Vec<(String, String)> {
+        let mut decls = Vec::new();
+        let scheme = if self.is_dark { "dark" } else { "light" };
+        decls.push(("color-scheme".to_string(), scheme.to_string()));
+        if let Some(bg) = &self.background {
+            decls.push(("--arb-bg".to_string(), bg.to_hex()));
+        }
+        if let Some(fg) = &self.foreground {
+            decls.push(("--arb-fg".to_string(), fg.to_hex()));
+        }
+        if let Some(gutter) = &self.gutter {
+            decls.push(("--arb-gutter".to_string(), gutter.to_hex()));
+        }
+        if let Some(line_highlight) = &self.line_highlight {
+            decls.push(("--arb-line-highlight".to_string(), line_highlight.to_hex()));
+        }
+        if let Some(selection) = &self.selection {
+            decls.push(("--arb-selection".to_string(), selection.to_hex()));
+        }
 
+        for (def, style) in self.css_styles() {
+            let var = css_variable_name(def.name);
             if let Some(fg) = &style.fg {
-                write!(css, " color: {};", fg.to_hex()).unwrap();
+                decls.push((var.clone(), fg.to_hex()));
             }
             if let Some(bg) = &style.bg {
-                write!(css, " background: {};", bg.to_hex()).unwrap();
+                decls.push((format!("{var}-bg"), bg.to_hex()));
+            }
+            if style.modifiers.bold {
+                decls.push((format!("{var}-weight"), "bold".to_string()));
+            }
+            if style.modifiers.italic {
+                decls.push((format!("{var}-style"), "italic".to_string()));
             }
 
             let mut decorations = Vec::new();
@@ -415,17 +1066,52 @@ impl Theme {
                 decorations.push("line-through");
             }
             if !decorations.is_empty() {
-                write!(css, " text-decoration: {};", decorations.join(" ")).unwrap();
+                decls.push((format!("{var}-decoration"), decorations.join(" ")));
             }
+        }
 
-            if style.modifiers.bold {
-                write!(css, " font-weight: bold;").unwrap();
-            }
-            if style.modifiers.italic {
-                write!(css, " font-style: italic;").unwrap();
-            }
+        decls
+    }
+
+    /// Generate highlighting rules that take their colors from CSS variables.
+    ///
+    /// The rules are the same for every theme; the values come from a block
+    /// generated by [`to_css_variables`](Self::to_css_variables). Properties
+    /// whose variable a theme doesn't set fall back to the surrounding text.
+    pub fn css_variable_rules(selector_prefix: &str) -> String {
+        use crate::highlights::HIGHLIGHTS;
+
+        let mut css = String::new();
 
-            writeln!(css, " }}").unwrap();
+        writeln!(css, "{selector_prefix} {{").unwrap();
+        writeln!(css, "  background: var(--arb-bg);").unwrap();
+        writeln!(css, "  color: var(--arb-fg);").unwrap();
+        writeln!(
+            css,
+            "  .line-number {{ color: var(--arb-gutter, inherit); }}"
+        )
+        .unwrap();
+        writeln!(
+            css,
+            "  .highlighted {{ background: var(--arb-line-highlight, none); }}"
+        )
+        .unwrap();
+        writeln!(
+            css,
+            "  &::selection, & ::selection {{ background: var(--arb-selection, Highlight); }}"
+        )
+        .unwrap();
+
+        for def in HIGHLIGHTS.iter().filter(|def| !def.tag.is_empty()) {
+            let var = css_variable_name(def.name);
+            writeln!(
+                css,
+                "  a-{} {{ color: var({var}, inherit); background: var({var}-bg, none); \
+                 font-weight: var({var}-weight, inherit); font-style: var({var}-style, inherit); \
+                 text-decoration: var({var}-decoration, none); }}",
+                def.tag
+            )
+            .unwrap();
         }
 
         writeln!(css, "}}").unwrap();
@@ -433,9 +1119,40 @@ impl Theme {
         css
     }
 
+    /// `color` as drawn on the theme background: translucent colors are
+    /// [blended](Color::blend) over it, for outputs without transparency.
+    /// Without a background, the alpha is dropped.
+    pub fn opaque(&self, color: Color) -> Color {
+        match self.background {
+            _ if color.is_opaque() => color,
+            Some(bg) => color.blend(bg.with_alpha(255)),
+            None => color.with_alpha(255),
+        }
+    }
+
+    /// Nearest xterm 256-color palette indices for the colors of every
+    /// style, indexed like [`styles`](Self::styles).
+    ///
+    /// Styles resolve as with [`style`](Self::style), and translucent colors
+    /// are drawn over the background as in [`ansi_style`](Self::ansi_style).
+    /// Quantizing is comparatively slow; compute this once per theme rather
+    /// than per token.
+    pub fn ansi256(&self) -> Vec<Ansi256Style> {
+        (0..self.styles.len())
+            .map(|index| {
+                let style = self.style(index).unwrap_or(&self.styles[index]);
+                let quantize = |color: Option<Color>| Some(self.opaque(color?).to_ansi256());
+                Ansi256Style {
+                    fg: quantize(style.fg),
+                    bg: quantize(style.bg),
+                }
+            })
+            .collect()
+    }
+
     /// Generate ANSI escape sequence for a style.
     pub fn ansi_style(&self, index: usize) -> String {
-        let Some(style) = self.styles.get(index) else {
+        let Some(style) = self.style(index) else {
             return String::new();
         };
 
@@ -458,10 +1175,10 @@ impl Theme {
             codes.push("9".to_string());
         }
 
-        if let Some(fg) = &style.fg {
+        if let Some(fg) = style.fg.map(|c| self.opaque(c)) {
             codes.push(format!("38;2;{};{};{}", fg.r, fg.g, fg.b));
         }
-        if let Some(bg) = &style.bg {
+        if let Some(bg) = style.bg.map(|c| self.opaque(c)) {
             codes.push(format!("48;2;{};{};{}", bg.r, bg.g, bg.b));
         }
 
@@ -480,7 +1197,7 @@ impl Theme {
     /// disappearing when switching between styled and unstyled text, and ensures colors
     /// are complete.
     pub fn ansi_style_with_base_bg(&self, index: usize) -> String {
-        let Some(style) = self.styles.get(index) else {
+        let Some(style) = self.style(index) else {
             return String::new();
         };
 
@@ -504,14 +1221,14 @@ impl Theme {
         }
 
         // Use style's foreground if defined, otherwise fall back to theme foreground
-        if let Some(fg) = &style.fg {
+        if let Some(fg) = style.fg.map(|c| self.opaque(c)) {
             codes.push(format!("38;2;{};{};{}", fg.r, fg.g, fg.b));
         } else if let Some(fg) = &self.foreground {
             codes.push(format!("38;2;{};{};{}", fg.r, fg.g, fg.b));
         }
 
         // Use style's background if defined, otherwise fall back to theme background
-        if let Some(bg) = &style.bg {
+        if let Some(bg) = style.bg.map(|c| self.opaque(c)) {
             codes.push(format!("48;2;{};{};{}", bg.r, bg.g, bg.b));
         } else if let Some(bg) = &self.background {
             codes.push(format!("48;2;{};{};{}", bg.r, bg.g, bg.b));
@@ -576,6 +1293,63 @@ impl Theme {
     pub const ANSI_RESET: &'static str = "\x1b[0m";
 }
 
+/// Scopes Helix themes use for highlight categories whose arborium name
+/// differs from Helix's.
+#[cfg(feature = "toml")]
+const HELIX_SCOPES: &[(&str, &[&str])] = &[
+    ("keyword.conditional", &["keyword.control.conditional"]),
+    ("keyword.repeat", &["keyword.control.repeat"]),
+    ("keyword.return", &["keyword.control.return"]),
+    ("keyword.exception", &["keyword.control.exception"]),
+    ("keyword.import", &["keyword.control.import"]),
+    ("keyword.type", &["keyword.storage.type"]),
+    ("keyword.modifier", &["keyword.storage.modifier"]),
+    ("type.qualifier", &["keyword.storage.modifier"]),
+    ("storageclass", &["keyword.storage.modifier"]),
+    ("conditional", &["keyword.control.conditional"]),
+    ("repeat", &["keyword.control.repeat"]),
+    ("exception", &["keyword.control.exception"]),
+    ("include", &["keyword.control.import"]),
+    ("preproc", &["keyword.directive"]),
+    ("macro", &["function.macro"]),
+    ("constructor", &["constructor"]),
+    ("variable.member", &["variable.other.member"]),
+    ("property", &["variable.other.member"]),
+    (
+        "comment.documentation",
+        &["comment.block.documentation", "comment.line.documentation"],
+    ),
+    ("character", &["constant.character"]),
+    ("character.special", &["constant.character.escape"]),
+    ("string.escape", &["constant.character.escape"]),
+    ("boolean", &["constant.builtin.boolean"]),
+    ("number", &["constant.numeric.integer"]),
+    ("float", &["constant.numeric.float"]),
+    ("function.definition", &["function"]),
+    ("function.call", &["function"]),
+    ("type.definition", &["type"]),
+    ("text.literal", &["markup.raw.inline"]),
+    ("text.title", &["markup.heading"]),
+    ("text.uri", &["markup.link.url"]),
+    ("text.reference", &["markup.link.text"]),
+];
+
+/// Find the theme key Helix would use for `scope`: the scope itself or its
+/// longest dotted prefix. Returns the key's depth in segments and its value.
+#[cfg(feature = "toml")]
+fn lookup_helix_scope<'a>(
+    table: &'a toml::map::Map<String, toml::Value>,
+    scope: &str,
+) -> Option<(usize, &'a toml::Value)> {
+    let mut key = scope;
+    loop {
+        if let Some(value) = table.get(key).filter(|v| v.is_str() || v.is_table()) {
+            return Some((key.split('.').count(), value));
+        }
+        key = key.rsplit_once('.')?.0;
+    }
+}
+
 /// Parse a style value from TOML (either string or table).
 #[cfg(feature = "toml")]
 fn parse_style_value(
@@ -597,6 +1371,10 @@ fn parse_style_value(
             if let Some(bg) = t.get("bg").and_then(|v| v.as_str()) {
                 style.bg = resolve_color(bg);
             }
+            // Helix's `underline = { style = "curl", color = "red" }`
+            if t.get("underline").is_some_and(|v| v.is_table()) {
+                style.modifiers.underline = true;
+            }
             if let Some(mods) = t.get("modifiers").and_then(|v| v.as_array()) {
                 for m in mods {
                     if let Some(s) = m.as_str() {
@@ -617,6 +1395,257 @@ fn parse_style_value(
     Ok(style)
 }
 
+#[cfg(feature = "serde")]
+impl serde::Serialize for Color {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_hex())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Color {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        Color::from_hex(&hex).ok_or_else(|| {
+            serde::de::Error::invalid_value(
+                serde::de::Unexpected::Str(&hex),
+                &"a hex color like \"#ff0000\"",
+            )
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// (De)serialize `Theme::styles` as a map from highlight name to style.
+#[cfg(feature = "serde")]
+mod serde_styles {
+    use std::collections::BTreeMap;
+
+    use serde::de::Error as _;
+    use serde::ser::SerializeMap;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::Style;
+    use crate::highlights::{COUNT, HIGHLIGHTS};
+
+    pub fn serialize<S: Serializer>(
+        styles: &[Style; COUNT],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let styled = styles.iter().filter(|style| !style.is_empty()).count();
+        let mut map = serializer.serialize_map(Some(styled))?;
+        for (def, style) in HIGHLIGHTS.iter().zip(styles) {
+            if !style.is_empty() {
+                map.serialize_entry(def.name, style)?;
+            }
+        }
+        map.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<[Style; COUNT], D::Error> {
+        let map = BTreeMap::<String, Style>::deserialize(deserializer)?;
+        let mut styles = std::array::from_fn(|_| Style::new());
+        for (name, style) in map {
+            let index = HIGHLIGHTS
+                .iter()
+                .position(|def| def.name == name || def.aliases.contains(&name.as_str()))
+                .ok_or_else(|| D::Error::custom(format!("unknown highlight name `{name}`")))?;
+            styles[index] = style;
+        }
+        Ok(styles)
+    }
+}
+
+/// (De)serialize `Theme::languages` as a map from language to a map of
+/// styles like `Theme::styles`.
+#[cfg(feature = "serde")]
+mod serde_languages {
+    use std::collections::BTreeMap;
+
+    use serde::ser::SerializeMap;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::{Style, serde_styles};
+    use crate::highlights::COUNT;
+
+    struct Styles<'a>(&'a [Style; COUNT]);
+
+    impl Serialize for Styles<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serde_styles::serialize(self.0, serializer)
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct OwnedStyles(#[serde(with = "serde_styles")] [Style; COUNT]);
+
+    pub fn serialize<S: Serializer>(
+        languages: &BTreeMap<String, [Style; COUNT]>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(languages.len()))?;
+        for (language, styles) in languages {
+            map.serialize_entry(language, &Styles(styles))?;
+        }
+        map.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<String, [Style; COUNT]>, D::Error> {
+        let map = BTreeMap::<String, OwnedStyles>::deserialize(deserializer)?;
+        Ok(map
+            .into_iter()
+            .map(|(language, styles)| (language, styles.0))
+            .collect())
+    }
+}
+
+/// The colors of a style as xterm 256-color palette indices, from
+/// [`Theme::ansi256`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Ansi256Style {
+    /// Foreground palette index, if the style sets a foreground.
+    pub fg: Option<u8>,
+    /// Background palette index, if the style sets a background.
+    pub bg: Option<u8>,
+}
+
+/// A light and a dark theme, for output that follows the viewer's color
+/// scheme.
+///
+/// The generated CSS uses the CSS variables of
+/// [`Theme::to_css_variables`], so the same HTML renders in either palette
+/// and only the variable values change.
+///
+/// ```rust
+/// use arborium_theme::{ThemePair, builtin};
+///
+/// let pair = ThemePair::new(builtin::github_light(), builtin::github_dark());
+///
+/// // Follow the system preference...
+/// let css = pair.to_css(".code");
+/// assert!(css.contains("@media (prefers-color-scheme: dark)"));
+///
+/// // ...or switch with a class on a parent element
+/// let css = pair.to_css_with_dark_selector(".code", ".dark .code");
+/// assert!(css.contains(".dark .code {"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ThemePair {
+    /// Theme used when the viewer prefers a light color scheme.
+    pub light: Theme,
+    /// Theme used when the viewer prefers a dark color scheme.
+    pub dark: Theme,
+}
+
+impl ThemePair {
+    /// Pair a light theme with a dark one.
+    pub fn new(light: Theme, dark: Theme) -> Self {
+        Self { light, dark }
+    }
+
+    /// The theme for a light or dark color scheme.
+    pub fn theme(&self, dark: bool) -> &Theme {
+        if dark { &self.dark } else { &self.light }
+    }
+
+    /// Generate CSS that uses the light theme by default and the dark theme
+    /// under `@media (prefers-color-scheme: dark)`.
+    ///
+    /// The selector_prefix scopes the rules, as in [`Theme::to_css`].
+    pub fn to_css(&self, selector_prefix: &str) -> String {
+        let mut css = self.light_css(selector_prefix);
+        writeln!(css, "@media (prefers-color-scheme: dark) {{").unwrap();
+        for line in self.dark_css(selector_prefix).lines() {
+            writeln!(css, "  {line}").unwrap();
+        }
+        writeln!(css, "}}").unwrap();
+        css
+    }
+
+    /// Generate CSS that uses the light theme by default and the dark theme
+    /// wherever `dark_selector` matches, e.g. `.dark .code` to switch with a
+    /// `dark` class on a parent element.
+    ///
+    /// `dark_selector` should match the same elements as selector_prefix,
+    /// since the dark palette only supplies values for its rules.
+    pub fn to_css_with_dark_selector(&self, selector_prefix: &str, dark_selector: &str) -> String {
+        let mut css = self.light_css(selector_prefix);
+        css.push_str(&self.dark_css(dark_selector));
+        css
+    }
+
+    /// The highlighting rules and the light theme's variables.
+    fn light_css(&self, selector_prefix: &str) -> String {
+        let mut css = Theme::css_variable_rules(selector_prefix);
+        css.push_str(&self.light.to_css_variables(selector_prefix));
+        css
+    }
+
+    /// The dark theme's variables, unsetting any the light theme sets and the
+    /// dark theme doesn't, so light styles don't leak into the dark palette.
+    fn dark_css(&self, selector: &str) -> String {
+        let mut decls = self.dark.css_variable_declarations();
+        let unset: Vec<_> = self
+            .light
+            .css_variable_declarations()
+            .into_iter()
+            .filter(|(name, _)| !decls.iter().any(|(dark, _)| dark == name))
+            .map(|(name, _)| (name, "initial".to_string()))
+            .collect();
+        decls.extend(unset);
+        css_block(selector, &decls)
+    }
+}
+
+/// The CSS declarations for `style`, each preceded by a space.
+fn css_style_declarations(style: &Style) -> String {
+    let mut css = String::new();
+    if let Some(fg) = &style.fg {
+        write!(css, " color: {};", fg.to_hex()).unwrap();
+    }
+    if let Some(bg) = &style.bg {
+        write!(css, " background: {};", bg.to_hex()).unwrap();
+    }
+
+    let mut decorations = Vec::new();
+    if style.modifiers.underline {
+        decorations.push("underline");
+    }
+    if style.modifiers.strikethrough {
+        decorations.push("line-through");
+    }
+    if !decorations.is_empty() {
+        write!(css, " text-decoration: {};", decorations.join(" ")).unwrap();
+    }
+
+    if style.modifiers.bold {
+        write!(css, " font-weight: bold;").unwrap();
+    }
+    if style.modifiers.italic {
+        write!(css, " font-style: italic;").unwrap();
+    }
+    css
+}
+
+/// A CSS rule setting each of `decls` on `selector`.
+fn css_block(selector: &str, decls: &[(String, String)]) -> String {
+    let mut css = String::new();
+    writeln!(css, "{selector} {{").unwrap();
+    for (name, value) in decls {
+        writeln!(css, "  {name}: {value};").unwrap();
+    }
+    writeln!(css, "}}").unwrap();
+    css
+}
+
 /// Error type for theme parsing.
 #[derive(Debug)]
 pub enum ThemeError {
@@ -633,6 +1662,30 @@ impl std::fmt::Display for ThemeError {
 
 impl std::error::Error for ThemeError {}
 
+/// The index of the category that `index` falls back to: the nearest dotted
+/// prefix of its name that is a category, or else its `parent_tag`.
+fn parent_category(index: usize) -> Option<usize> {
+    use crate::highlights::HIGHLIGHTS;
+
+    let def = HIGHLIGHTS.get(index)?;
+    let mut name = def.name;
+    while let Some((parent, _)) = name.rsplit_once('.') {
+        if let Some(i) = HIGHLIGHTS.iter().position(|def| def.name == parent) {
+            return Some(i);
+        }
+        name = parent;
+    }
+    if def.parent_tag.is_empty() {
+        return None;
+    }
+    HIGHLIGHTS.iter().position(|d| d.tag == def.parent_tag)
+}
+
+/// The CSS variable for a highlight category, e.g. `--arb-function-builtin`.
+fn css_variable_name(name: &str) -> String {
+    format!("--arb-{}", name.replace('.', "-"))
+}
+
 // ============================================================================
 // Built-in themes - generated from TOML files at build time
 // ============================================================================
@@ -654,6 +1707,345 @@ mod tests {
         assert_eq!(Color::from_hex("#ff0000"), Some(Color::new(255, 0, 0)));
         assert_eq!(Color::from_hex("00ff00"), Some(Color::new(0, 255, 0)));
         assert_eq!(Color::from_hex("#invalid"), None);
+        assert_eq!(
+            Color::from_hex("#ff000080"),
+            Some(Color::rgba(255, 0, 0, 0x80))
+        );
+        assert_eq!(Color::rgba(255, 0, 0, 0x80).to_hex(), "#ff000080");
+    }
+
+    #[test]
+    fn test_translucent_colors_in_ansi() {
+        use crate::highlights::HIGHLIGHTS;
+
+        let keyword = HIGHLIGHTS.iter().position(|h| h.name == "keyword").unwrap();
+        let mut theme = Theme::new("x");
+        theme.set_style(
+            keyword,
+            Style {
+                bg: Some(Color::rgba(255, 255, 255, 0x80)),
+                ..Style::new()
+            },
+        );
+        // Without a background, the alpha is dropped
+        assert_eq!(theme.ansi_style(keyword), "\x1b[48;2;255;255;255m");
+
+        theme.background = Some(Color::new(0, 0, 0));
+        assert_eq!(theme.ansi_style(keyword), "\x1b[48;2;128;128;128m");
+        assert_eq!(
+            Color::rgba(255, 0, 0, 0).blend(Color::new(1, 2, 3)),
+            Color::new(1, 2, 3)
+        );
+    }
+
+    #[test]
+    fn test_color_to_ansi256() {
+        assert_eq!(Color::new(255, 0, 0).to_ansi256(), 196);
+        assert_eq!(Color::new(0, 0, 0).to_ansi256(), 16);
+        assert_eq!(Color::new(255, 255, 255).to_ansi256(), 231);
+        // Mid grays land on the grayscale ramp rather than the cube
+        assert_eq!(Color::new(128, 128, 128).to_ansi256(), 244);
+        assert_eq!(Color::new(0x61, 0xaf, 0xef).to_ansi256(), 75);
+    }
+
+    #[test]
+    fn test_theme_ansi256() {
+        use crate::highlights::HIGHLIGHTS;
+
+        let index = |name: &str| HIGHLIGHTS.iter().position(|h| h.name == name).unwrap();
+        let mut theme = Theme::new("x");
+        theme.set_style(index("keyword"), Style::new().fg(Color::new(255, 0, 0)));
+
+        let palette = theme.ansi256();
+        assert_eq!(palette.len(), theme.styles.len());
+        assert_eq!(palette[index("keyword")].fg, Some(196));
+        assert_eq!(palette[index("keyword")].bg, None);
+        // Resolved through the parent category
+        assert_eq!(palette[index("keyword.return")].fg, Some(196));
+        assert_eq!(palette[index("string")], Ansi256Style::default());
+    }
+
+    #[test]
+    fn test_color_to_ansi16() {
+        assert_eq!(Color::new(250, 10, 10).to_ansi16(), 9);
+        assert_eq!(Color::new(200, 0, 0).to_ansi16(), 1);
+        assert_eq!(Color::new(30, 30, 30).to_ansi16(), 0);
+        assert_eq!(Color::new(120, 130, 125).to_ansi16(), 8);
+    }
+
+    #[test]
+    fn test_css_variables() {
+        use crate::highlights::HIGHLIGHTS;
+
+        let keyword = HIGHLIGHTS.iter().position(|h| h.name == "keyword").unwrap();
+        let mut theme = Theme::new("x");
+        theme.background = Some(Color::new(0, 0, 0));
+        theme.set_style(keyword, Style::new().fg(Color::new(255, 0, 0)).bold());
+        theme.selection = Some(Color::new(0, 0, 255));
+
+        let vars = theme.to_css_variables("[data-theme=\"x\"]");
+        assert!(vars.starts_with("[data-theme=\"x\"] {\n"));
+        assert!(vars.contains("  --arb-keyword: #ff0000;\n"));
+        assert!(vars.contains("  --arb-keyword-weight: bold;\n"));
+        assert!(vars.contains("  --arb-bg: #000000;\n"));
+        assert!(vars.contains("  --arb-selection: #0000ff;\n"));
+        assert!(
+            theme
+                .to_css(".code")
+                .contains("::selection { background: #0000ff; }")
+        );
+
+        let rules = Theme::css_variable_rules(".code");
+        assert!(rules.contains("a-k { color: var(--arb-keyword, inherit);"));
+        assert!(rules.contains("a-fb { color: var(--arb-function-builtin, inherit);"));
+        assert!(rules.contains(".line-number { color: var(--arb-gutter, inherit); }"));
+    }
+
+    #[test]
+    fn test_style_falls_back_to_parent() {
+        use crate::highlights::HIGHLIGHTS;
+
+        let index = |name: &str| HIGHLIGHTS.iter().position(|h| h.name == name).unwrap();
+        let red = Color::new(255, 0, 0);
+        let blue = Color::new(0, 0, 255);
+        let mut theme = Theme::new("partial");
+        theme.set_style(index("keyword"), Style::new().fg(red));
+        theme.set_style(index("keyword.return"), Style::new().fg(blue));
+
+        assert_eq!(
+            theme.style(index("keyword.function")).unwrap().fg,
+            Some(red)
+        );
+        assert_eq!(theme.style(index("keyword.return")).unwrap().fg, Some(blue));
+        assert_eq!(
+            theme.ansi_style(index("keyword.function")),
+            theme.ansi_style(index("keyword"))
+        );
+        // Legacy names fall back through their parent tag
+        assert_eq!(theme.style(index("repeat")).unwrap().fg, Some(red));
+        // Neither the category nor an ancestor is styled
+        assert!(theme.style(index("function.method")).unwrap().is_empty());
+        assert!(theme.style(HIGHLIGHTS.len()).is_none());
+    }
+
+    #[test]
+    fn test_theme_pair_css() {
+        use crate::highlights::HIGHLIGHTS;
+
+        let index = |name: &str| HIGHLIGHTS.iter().position(|h| h.name == name).unwrap();
+        let mut light = Theme::new("light");
+        light.is_dark = false;
+        light.set_style(index("keyword"), Style::new().fg(Color::new(0, 0, 255)));
+        light.set_style(index("comment"), Style::new().italic());
+        let mut dark = Theme::new("dark");
+        dark.set_style(index("keyword"), Style::new().fg(Color::new(255, 0, 0)));
+        let pair = ThemePair::new(light, dark);
+
+        let css = pair.to_css(".code");
+        let (light_css, dark_css) = css
+            .split_once("@media (prefers-color-scheme: dark) {\n")
+            .unwrap();
+        assert!(light_css.contains("a-k { color: var(--arb-keyword, inherit);"));
+        assert!(light_css.contains("  color-scheme: light;\n"));
+        assert!(light_css.contains("  --arb-keyword: #0000ff;\n"));
+        assert!(dark_css.starts_with("  .code {\n"));
+        assert!(dark_css.contains("    color-scheme: dark;\n"));
+        assert!(dark_css.contains("    --arb-keyword: #ff0000;\n"));
+        // The light theme's italic comments don't carry over
+        assert!(dark_css.contains("    --arb-comment-style: initial;\n"));
+
+        let css = pair.to_css_with_dark_selector(".code", "html.dark .code");
+        assert!(!css.contains("@media"));
+        assert!(css.contains("html.dark .code {\n  color-scheme: dark;\n"));
+    }
+
+    #[test]
+    fn test_theme_lerp() {
+        use crate::highlights::HIGHLIGHTS;
+
+        let index = |name: &str| HIGHLIGHTS.iter().position(|h| h.name == name).unwrap();
+        let mut a = Theme::new("a");
+        a.foreground = Some(Color::new(0, 0, 0));
+        a.set_style(
+            index("keyword"),
+            Style::new().fg(Color::new(200, 0, 0)).bold(),
+        );
+        let mut b = Theme::new("b");
+        b.foreground = Some(Color::new(100, 100, 100));
+        b.set_style(index("keyword"), Style::new().fg(Color::new(0, 0, 200)));
+        b.set_style(index("string"), Style::new().fg(Color::new(0, 200, 0)));
+        b.selection = Some(Color::new(1, 2, 3));
+
+        let start = a.lerp(&b, 0.0);
+        assert_eq!(
+            start.styles[index("keyword")].fg,
+            Some(Color::new(200, 0, 0))
+        );
+        assert_eq!(start.styles[index("string")].fg, Some(Color::new(0, 0, 0)));
+
+        let mid = a.lerp(&b, 0.25);
+        assert_eq!(mid.name, "a");
+        assert_eq!(mid.foreground, Some(Color::new(25, 25, 25)));
+        assert_eq!(
+            mid.styles[index("keyword")].fg,
+            Some(Color::new(150, 0, 50))
+        );
+        assert!(mid.styles[index("keyword")].modifiers.bold);
+        // Fades in from the foreground of the theme that lacks it
+        assert_eq!(mid.styles[index("string")].fg, Some(Color::new(0, 50, 0)));
+        assert_eq!(mid.selection, None);
+        assert!(mid.styles[index("comment")].is_empty());
+
+        let end = a.lerp(&b, 2.0);
+        assert_eq!(end.name, "b");
+        assert_eq!(end.selection, Some(Color::new(1, 2, 3)));
+        assert!(!end.styles[index("keyword")].modifiers.bold);
+    }
+
+    #[test]
+    fn test_language_overrides() {
+        use crate::highlights::HIGHLIGHTS;
+
+        let index = |name: &str| HIGHLIGHTS.iter().position(|h| h.name == name).unwrap();
+        let mut theme = Theme::new("test");
+        theme.set_style(index("punctuation"), Style::new().fg(Color::new(0, 0, 255)));
+        theme.set_style(index("string"), Style::new().fg(Color::new(0, 255, 0)));
+        theme.set_language_style("json", index("punctuation"), Style::new().italic());
+
+        let json = theme.for_language("json");
+        assert!(json.style(index("punctuation")).unwrap().modifiers.italic);
+        // The override falls back like any other style
+        assert!(
+            json.style(index("punctuation.bracket"))
+                .unwrap()
+                .modifiers
+                .italic
+        );
+        assert!(matches!(theme.for_language("rust"), Cow::Borrowed(_)));
+
+        let css = theme.to_css(".code");
+        assert!(css.contains("  a-p { color: #0000ff; }\n"));
+        assert!(
+            css.contains("  &.language-json, .language-json {\n    a-p { font-style: italic; }\n")
+        );
+        assert!(css.contains("    a-pb { font-style: italic; }\n"));
+        // Unchanged categories aren't repeated
+        assert!(!css.contains("    a-s "));
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_from_helix_toml() {
+        use crate::highlights::HIGHLIGHTS;
+
+        let theme = Theme::from_helix_toml(
+            r##"
+            inherits = "dracula"
+            "ui.text" = "fg"
+            "keyword" = { fg = "#0000ff" }
+            "keyword.control" = { fg = "#00ff00" }
+            "keyword.control.repeat" = { fg = "#ff0000", modifiers = ["italic"] }
+            "comment" = { fg = "gray", underline = { style = "curl" } }
+            "ui.linenr" = "gray"
+            "ui.cursorline.primary" = { bg = "#101010" }
+
+            [palette]
+            fg = "#eeeeee"
+            gray = "#808080"
+            "##,
+        )
+        .unwrap();
+        let style =
+            |name: &str| &theme.styles[HIGHLIGHTS.iter().position(|h| h.name == name).unwrap()];
+
+        assert_eq!(theme.foreground, Some(Color::new(0xee, 0xee, 0xee)));
+        assert_eq!(theme.gutter, Some(Color::new(0x80, 0x80, 0x80)));
+        assert_eq!(theme.line_highlight, Some(Color::new(0x10, 0x10, 0x10)));
+        // Longest matching key wins, whichever name it is reached through
+        assert_eq!(style("keyword.repeat").fg, Some(Color::new(255, 0, 0)));
+        assert!(style("keyword.repeat").modifiers.italic);
+        assert_eq!(style("keyword.conditional").fg, Some(Color::new(0, 255, 0)));
+        assert_eq!(style("keyword.function").fg, Some(Color::new(0, 0, 255)));
+        assert!(style("comment").modifiers.underline);
+        // Categories the file doesn't mention come from the inherited theme
+        let dracula = builtin::dracula();
+        let string = HIGHLIGHTS.iter().position(|h| h.name == "string").unwrap();
+        assert_eq!(style("string").fg, dracula.styles[string].fg);
+        assert_eq!(theme.background, dracula.background);
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_toml_round_trip() {
+        for theme in builtin::all() {
+            let toml = theme.to_toml_string();
+            let parsed = Theme::from_toml_str(&toml).unwrap();
+            assert_eq!(parsed.to_toml_string(), toml, "{}", theme.name);
+            assert_eq!(parsed.background, theme.background);
+        }
+
+        let theme = builtin::catppuccin_mocha();
+        let toml = theme.to_toml_string();
+        assert!(toml.starts_with("name = \"Catppuccin Mocha\"\n"));
+        assert!(toml.contains("\n[styles.keyword]\nfg = \"#"));
+
+        let mut theme = Theme::new("languages");
+        let punctuation = crate::highlights::HIGHLIGHTS
+            .iter()
+            .position(|h| h.name == "punctuation")
+            .unwrap();
+        theme.set_language_style("json", punctuation, Style::new().fg(Color::new(0, 0, 0)));
+        let toml = theme.to_toml_string();
+        assert!(toml.contains("[languages.json.punctuation]\nfg = \"#000000\""));
+        let parsed = Theme::from_toml_str(&toml).unwrap();
+        assert_eq!(parsed.to_toml_string(), toml);
+
+        assert!(Theme::from_toml_str("[styles.kyeword]\nfg = \"#ff0000\"").is_err());
+        assert!(Theme::from_toml_str("[styles.keyword]\nfg = \"red\"").is_err());
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_helix_toml_round_trip() {
+        use crate::highlights::HIGHLIGHTS;
+
+        let index = |name: &str| HIGHLIGHTS.iter().position(|h| h.name == name).unwrap();
+        let mut theme = Theme::new("Round Trip");
+        theme.is_dark = false;
+        theme.background = Some(Color::new(0xff, 0xff, 0xff));
+        theme.foreground = Some(Color::new(0x10, 0x10, 0x10));
+        theme.selection = Some(Color::rgba(0, 0, 0, 0x80));
+        theme.set_style(index("keyword"), Style::new().fg(Color::new(0xa0, 0, 0xa0)));
+        theme.set_style(
+            index("keyword.conditional"),
+            Style::new().fg(Color::new(0xff, 0, 0)).bold().underline(),
+        );
+        theme.set_style(index("conditional"), Style::new().italic());
+        theme.set_style(index("comment"), Style::new().italic());
+
+        let toml = theme.to_helix_toml();
+        assert!(toml.starts_with("name = \"Round Trip\"\nvariant = \"light\"\n"));
+        assert!(toml.contains("\"ui.selection\" = { bg = \"#7f7f7f\" }\n"));
+        assert!(toml.contains(
+            "\"keyword.control.conditional\" = { fg = \"#ff0000\", modifiers = [\"bold\", \"underlined\"] }\n"
+        ));
+
+        let parsed = Theme::from_helix_toml(&toml).unwrap();
+        assert_eq!(parsed.name, theme.name);
+        assert!(!parsed.is_dark);
+        assert_eq!(parsed.foreground, theme.foreground);
+        for name in [
+            "keyword",
+            "keyword.conditional",
+            "keyword.repeat",
+            "comment",
+        ] {
+            let (a, b) = (
+                theme.style(index(name)).unwrap(),
+                parsed.style(index(name)).unwrap(),
+            );
+            assert_eq!((a.fg, a.modifiers), (b.fg, b.modifiers), "{name}");
+        }
     }
 
     #[test]
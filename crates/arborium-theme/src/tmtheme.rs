@@ -0,0 +1,319 @@
+//! Importing TextMate and Sublime Text `.tmTheme` files.
+
+use plist::{Dictionary, Value};
+
+use crate::highlights::HIGHLIGHTS;
+use crate::textmate::{
+    SCOPES, ScopeRule, apply_rules, is_dark_background, parse_color, parse_font_style,
+    parse_selectors,
+};
+use crate::theme::{Color, Theme, ThemeError};
+
+impl Theme {
+    /// Parse a TextMate `.tmTheme` file (an XML property list).
+    ///
+    /// The scoped `settings` entries are mapped from TextMate scopes onto
+    /// highlight categories, including their `fontStyle`; the unscoped entry
+    /// supplies the background, foreground, gutter, line highlight and
+    /// selection colors. Whether the theme is dark is judged from its
+    /// background.
+    ///
+    /// This method is only available when the `tmtheme` feature is enabled.
+    ///
+    /// ```rust
+    /// use arborium_theme::Theme;
+    ///
+    /// let theme = Theme::from_tmtheme(r#"<?xml version="1.0" encoding="UTF-8"?>
+    /// <plist version="1.0">
+    /// <dict>
+    ///     <key>name</key>
+    ///     <string>Example</string>
+    ///     <key>settings</key>
+    ///     <array>
+    ///         <dict>
+    ///             <key>settings</key>
+    ///             <dict>
+    ///                 <key>background</key>
+    ///                 <string>#272822</string>
+    ///             </dict>
+    ///         </dict>
+    ///         <dict>
+    ///             <key>scope</key>
+    ///             <string>comment</string>
+    ///             <key>settings</key>
+    ///             <dict>
+    ///                 <key>foreground</key>
+    ///                 <string>#75715E</string>
+    ///             </dict>
+    ///         </dict>
+    ///     </array>
+    /// </dict>
+    /// </plist>"#)?;
+    ///
+    /// assert_eq!(theme.name, "Example");
+    /// assert!(theme.is_dark);
+    /// # Ok::<(), arborium_theme::ThemeError>(())
+    /// ```
+    pub fn from_tmtheme(xml: &str) -> Result<Self, ThemeError> {
+        let value = Value::from_reader_xml(xml.as_bytes())
+            .map_err(|e| ThemeError::Parse(format!("{e}")))?;
+        let dict = value
+            .as_dictionary()
+            .ok_or(ThemeError::Parse("Expected dict".into()))?;
+
+        let mut theme = Theme::default();
+        if let Some(name) = dict.get("name").and_then(|v| v.as_string()) {
+            theme.name = name.to_string();
+        }
+
+        let entries = dict
+            .get("settings")
+            .and_then(|v| v.as_array())
+            .ok_or(ThemeError::Parse("Expected settings array".into()))?;
+
+        let mut rules = Vec::with_capacity(entries.len());
+        for entry in entries.iter().filter_map(|v| v.as_dictionary()) {
+            let settings = entry.get("settings").and_then(|v| v.as_dictionary());
+            let string = |key: &str| {
+                settings
+                    .and_then(|s: &Dictionary| s.get(key))
+                    .and_then(|v| v.as_string())
+            };
+            let rule = ScopeRule {
+                selectors: entry
+                    .get("scope")
+                    .and_then(|v| v.as_string())
+                    .map(parse_selectors)
+                    .unwrap_or_default(),
+                foreground: string("foreground").and_then(parse_color),
+                background: string("background").and_then(parse_color),
+                font_style: string("fontStyle").map(parse_font_style),
+            };
+
+            // The entry without a scope holds the editor's default colors
+            if entry.get("scope").is_none() {
+                theme.background = theme.background.or(rule.background);
+                theme.foreground = theme.foreground.or(rule.foreground);
+                let editor_color = |key: &str| string(key).and_then(parse_color);
+                theme.gutter = theme.gutter.or(editor_color("gutterForeground"));
+                theme.line_highlight = theme.line_highlight.or(editor_color("lineHighlight"));
+                theme.selection = theme.selection.or(editor_color("selection"));
+                continue;
+            }
+            rules.push(rule);
+        }
+        apply_rules(&mut theme, &rules);
+
+        if let Some(background) = theme.background {
+            theme.is_dark = is_dark_background(background);
+        }
+        Ok(theme)
+    }
+
+    /// Export the theme as a TextMate `.tmTheme` file, for Sublime Text,
+    /// `bat`, `syntect` and other TextMate-compatible tools.
+    ///
+    /// Each styled category becomes a rule for the TextMate scope that
+    /// represents it best, with every attribute set so that the rule doesn't
+    /// inherit from less specific ones. The base and editor colors go in the
+    /// unscoped entry. Language overrides are left out.
+    ///
+    /// [`from_tmtheme`](Self::from_tmtheme) reads the result back.
+    ///
+    /// This method is only available when the `tmtheme` feature is enabled.
+    ///
+    /// ```rust
+    /// use arborium_theme::builtin;
+    ///
+    /// let xml = builtin::dracula().to_tmtheme();
+    /// assert!(xml.contains("<string>entity.name.function</string>"));
+    /// ```
+    pub fn to_tmtheme(&self) -> String {
+        let color = |dict: &mut Dictionary, key: &str, color: Option<Color>| {
+            if let Some(color) = color {
+                dict.insert(key.to_string(), Value::String(color.to_hex()));
+            }
+        };
+
+        let mut editor = Dictionary::new();
+        color(&mut editor, "background", self.background);
+        color(&mut editor, "foreground", self.foreground);
+        color(&mut editor, "gutterForeground", self.gutter);
+        color(&mut editor, "lineHighlight", self.line_highlight);
+        color(&mut editor, "selection", self.selection);
+        let mut entries = vec![Value::Dictionary(Dictionary::from_iter([(
+            "settings".to_string(),
+            Value::Dictionary(editor),
+        )]))];
+
+        let mut exported: Vec<&str> = Vec::new();
+        for (name, scopes) in SCOPES {
+            let Some(style) = HIGHLIGHTS
+                .iter()
+                .position(|def| def.name == *name)
+                .and_then(|index| self.style(index))
+                .filter(|style| !style.is_empty())
+            else {
+                continue;
+            };
+            // Categories that share a scope are styled by its first rule
+            let scope = scopes[0];
+            if exported.contains(&scope) {
+                continue;
+            }
+            exported.push(scope);
+
+            let mut settings = Dictionary::new();
+            color(&mut settings, "foreground", style.fg.or(self.foreground));
+            color(&mut settings, "background", style.bg);
+            let font_style: Vec<&str> = [
+                (style.modifiers.bold, "bold"),
+                (style.modifiers.italic, "italic"),
+                (style.modifiers.underline, "underline"),
+                (style.modifiers.strikethrough, "strikethrough"),
+            ]
+            .into_iter()
+            .filter_map(|(set, word)| set.then_some(word))
+            .collect();
+            settings.insert("fontStyle".to_string(), Value::String(font_style.join(" ")));
+
+            entries.push(Value::Dictionary(Dictionary::from_iter([
+                ("name".to_string(), Value::String(name.to_string())),
+                ("scope".to_string(), Value::String(scope.to_string())),
+                ("settings".to_string(), Value::Dictionary(settings)),
+            ])));
+        }
+
+        let root = Dictionary::from_iter([
+            ("name".to_string(), Value::String(self.name.clone())),
+            ("settings".to_string(), Value::Array(entries)),
+        ]);
+        let mut xml = Vec::new();
+        Value::Dictionary(root)
+            .to_writer_xml(&mut xml)
+            .expect("writing to a Vec can't fail");
+        String::from_utf8(xml).expect("plist writes UTF-8")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::highlights::HIGHLIGHTS;
+    use crate::theme::{Color, Style, Theme, builtin};
+
+    const THEME: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>name</key>
+    <string>Test Light</string>
+    <key>settings</key>
+    <array>
+        <dict>
+            <key>settings</key>
+            <dict>
+                <key>background</key>
+                <string>#FAFAFA</string>
+                <key>foreground</key>
+                <string>#383A42</string>
+                <key>caret</key>
+                <string>#526FFF</string>
+                <key>lineHighlight</key>
+                <string>#383A420C</string>
+            </dict>
+        </dict>
+        <dict>
+            <key>name</key>
+            <string>Keywords</string>
+            <key>scope</key>
+            <string>keyword, storage.type</string>
+            <key>settings</key>
+            <dict>
+                <key>foreground</key>
+                <string>#A626A4</string>
+                <key>fontStyle</key>
+                <string>bold italic</string>
+            </dict>
+        </dict>
+        <dict>
+            <key>scope</key>
+            <string>markup.strikethrough</string>
+            <key>settings</key>
+            <dict>
+                <key>fontStyle</key>
+                <string>strikethrough</string>
+            </dict>
+        </dict>
+    </array>
+    <key>uuid</key>
+    <string>00000000-0000-0000-0000-000000000000</string>
+</dict>
+</plist>"#;
+
+    #[test]
+    fn test_from_tmtheme() {
+        let theme = Theme::from_tmtheme(THEME).unwrap();
+        assert_eq!(theme.name, "Test Light");
+        assert!(!theme.is_dark);
+        assert_eq!(theme.background, Some(Color::new(0xfa, 0xfa, 0xfa)));
+        assert_eq!(theme.foreground, Some(Color::new(0x38, 0x3a, 0x42)));
+        assert_eq!(
+            theme.line_highlight,
+            Some(Color::rgba(0x38, 0x3a, 0x42, 0x0c))
+        );
+
+        let style =
+            |name: &str| &theme.styles[HIGHLIGHTS.iter().position(|h| h.name == name).unwrap()];
+        let keyword = style("keyword.repeat");
+        assert_eq!(keyword.fg, Some(Color::new(0xa6, 0x26, 0xa4)));
+        assert!(keyword.modifiers.bold && keyword.modifiers.italic);
+        assert_eq!(style("type.builtin").fg, keyword.fg);
+        assert!(style("text.strikethrough").modifiers.strikethrough);
+        assert!(style("string").is_empty());
+    }
+
+    #[test]
+    fn test_tmtheme_round_trip() {
+        let index = |name: &str| HIGHLIGHTS.iter().position(|h| h.name == name).unwrap();
+        let mut theme = Theme::new("Round Trip");
+        theme.background = Some(Color::new(0x10, 0x10, 0x10));
+        theme.foreground = Some(Color::new(0xe0, 0xe0, 0xe0));
+        theme.line_highlight = Some(Color::rgba(255, 255, 255, 0x10));
+        theme.set_style(
+            index("keyword"),
+            Style::new().fg(Color::new(0xff, 0, 0)).bold(),
+        );
+        theme.set_style(index("keyword.conditional"), Style::new().italic());
+        theme.set_style(
+            index("comment"),
+            Style::new().fg(Color::new(0x80, 0x80, 0x80)),
+        );
+
+        let parsed = Theme::from_tmtheme(&theme.to_tmtheme()).unwrap();
+        assert_eq!(parsed.name, "Round Trip");
+        assert!(parsed.is_dark);
+        assert_eq!(parsed.background, theme.background);
+        assert_eq!(parsed.line_highlight, theme.line_highlight);
+
+        let style = |theme: &Theme, name: &str| theme.style(index(name)).unwrap().clone();
+        let conditional = style(&parsed, "keyword.conditional");
+        assert_eq!(conditional.fg, theme.foreground);
+        assert!(conditional.modifiers.italic && !conditional.modifiers.bold);
+        for name in ["keyword", "keyword.repeat", "comment"] {
+            let (a, b) = (style(&theme, name), style(&parsed, name));
+            assert_eq!(a.fg, b.fg, "{name}");
+            assert_eq!(a.modifiers.bold, b.modifiers.bold, "{name}");
+        }
+
+        // Every built-in theme exports to something that parses
+        for theme in builtin::all() {
+            assert!(Theme::from_tmtheme(&theme.to_tmtheme()).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_invalid_tmtheme() {
+        assert!(Theme::from_tmtheme("<plist><dict>").is_err());
+        assert!(Theme::from_tmtheme("<plist version=\"1.0\"><dict></dict></plist>").is_err());
+    }
+}
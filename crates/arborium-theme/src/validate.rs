@@ -0,0 +1,296 @@
+//! Checking themes for problems that only show up in rendered output.
+
+use std::fmt;
+
+use crate::highlights::{CAPTURE_NAMES, HIGHLIGHTS, capture_to_slot, slot_to_highlight_index};
+use crate::theme::{Color, Theme};
+
+/// Foreground colors with a lower contrast ratio against their background
+/// than this are reported as hard to read by [`Theme::validate`].
+///
+/// This is the WCAG minimum for large text, which code in most themes is
+/// not; comments are commonly designed to sit just above it.
+pub const MIN_READABLE_CONTRAST: f32 = 3.0;
+
+/// The WCAG level AA minimum contrast ratio for body text.
+pub const WCAG_AA: f32 = 4.5;
+
+/// The WCAG level AAA minimum contrast ratio for body text.
+pub const WCAG_AAA: f32 = 7.0;
+
+/// A problem found by [`Theme::validate`].
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Diagnostic {
+    /// The theme has no background or no foreground color, so output
+    /// depends on the surrounding page or terminal.
+    MissingBaseColor {
+        /// `"background"` or `"foreground"`.
+        which: &'static str,
+    },
+    /// A category that captures are rendered with has no style, not even
+    /// one inherited from a parent category, so it renders as plain text.
+    MissingStyle {
+        /// The highlight category, e.g. `"keyword"`.
+        highlight: &'static str,
+    },
+    /// A category's text color has too little contrast against its
+    /// background to read comfortably.
+    LowContrast {
+        /// The highlight category, e.g. `"comment"`, or `"foreground"` for
+        /// the theme's base text color.
+        highlight: &'static str,
+        /// The contrast ratio, from 1 (none) to 21 (black on white).
+        ratio: f32,
+    },
+    /// A category has a style, but no capture is ever rendered with it, so
+    /// the style has no effect.
+    UnusedStyle {
+        /// The highlight category, e.g. `"keyword.function"`.
+        highlight: &'static str,
+    },
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnostic::MissingBaseColor { which } => write!(f, "no {which} color"),
+            Diagnostic::MissingStyle { highlight } => write!(f, "`{highlight}` has no style"),
+            Diagnostic::LowContrast { highlight, ratio } => write!(
+                f,
+                "`{highlight}` has a contrast ratio of {ratio:.2}:1 against its background"
+            ),
+            Diagnostic::UnusedStyle { highlight } => {
+                write!(f, "`{highlight}` is styled but never rendered")
+            }
+        }
+    }
+}
+
+impl Theme {
+    /// Check the theme for problems: missing base colors, categories that
+    /// render unstyled, text that is hard to read against its background
+    /// (below [`MIN_READABLE_CONTRAST`]), and styles that are never used.
+    ///
+    /// Captures are rendered with one representative category per theme
+    /// slot (`keyword`, `function`, ...), so only those categories need
+    /// styles; styles on the others are reported as unused.
+    ///
+    /// ```rust
+    /// use arborium_theme::{Diagnostic, Theme};
+    ///
+    /// let diagnostics = Theme::new("empty").validate();
+    /// assert!(diagnostics.contains(&Diagnostic::MissingBaseColor { which: "background" }));
+    /// assert!(diagnostics.contains(&Diagnostic::MissingStyle { highlight: "keyword" }));
+    /// ```
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        if self.background.is_none() {
+            diagnostics.push(Diagnostic::MissingBaseColor {
+                which: "background",
+            });
+        }
+        if self.foreground.is_none() {
+            diagnostics.push(Diagnostic::MissingBaseColor {
+                which: "foreground",
+            });
+        }
+
+        let rendered = rendered_categories();
+        for (index, def) in HIGHLIGHTS.iter().enumerate() {
+            if !rendered.contains(&index) {
+                if !self.styles[index].is_empty() {
+                    diagnostics.push(Diagnostic::UnusedStyle {
+                        highlight: def.name,
+                    });
+                }
+                continue;
+            }
+
+            if self.style(index).is_none_or(|style| style.is_empty()) {
+                diagnostics.push(Diagnostic::MissingStyle {
+                    highlight: def.name,
+                });
+            }
+        }
+        diagnostics.extend(self.check_contrast(MIN_READABLE_CONTRAST));
+
+        diagnostics
+    }
+
+    /// Find text colors whose contrast ratio against their background is
+    /// below `min_ratio`, such as [`WCAG_AA`].
+    ///
+    /// Checks the base foreground and every category that captures are
+    /// rendered with, as drawn: with its own background or the theme's, and
+    /// with translucent colors blended. Nothing can be checked without a
+    /// background color. Returns a [`Diagnostic::LowContrast`] per color.
+    ///
+    /// ```rust
+    /// use arborium_theme::{WCAG_AA, builtin};
+    ///
+    /// for issue in builtin::github_light().check_contrast(WCAG_AA) {
+    ///     println!("{issue}");
+    /// }
+    /// ```
+    pub fn check_contrast(&self, min_ratio: f32) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let mut check = |highlight: &'static str, fg: Option<Color>, bg: Option<Color>| {
+            let (Some(fg), Some(bg)) = (fg, bg) else {
+                return;
+            };
+            let bg = self.opaque(bg);
+            let ratio = fg.blend(bg).contrast_ratio(&bg);
+            if ratio < min_ratio {
+                diagnostics.push(Diagnostic::LowContrast { highlight, ratio });
+            }
+        };
+
+        check("foreground", self.foreground, self.background);
+        for index in rendered_categories() {
+            if let Some(style) = self.style(index) {
+                check(
+                    HIGHLIGHTS[index].name,
+                    style.fg.or(self.foreground),
+                    style.bg.or(self.background),
+                );
+            }
+        }
+        diagnostics
+    }
+}
+
+/// The indices of the categories that renderers style captures with.
+fn rendered_categories() -> Vec<usize> {
+    let mut indices: Vec<usize> = CAPTURE_NAMES
+        .iter()
+        .filter_map(|capture| slot_to_highlight_index(capture_to_slot(capture)))
+        .collect();
+    indices.sort_unstable();
+    indices.dedup();
+    indices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::theme::{Style, builtin};
+
+    fn index(name: &str) -> usize {
+        HIGHLIGHTS.iter().position(|h| h.name == name).unwrap()
+    }
+
+    #[test]
+    fn test_validate_reports_problems() {
+        let mut theme = Theme::new("test");
+        theme.background = Some(Color::new(0x20, 0x20, 0x20));
+        theme.foreground = Some(Color::new(0xe0, 0xe0, 0xe0));
+        theme.set_style(
+            index("keyword"),
+            Style::new().fg(Color::new(0xff, 0x80, 0x00)),
+        );
+        theme.set_style(
+            index("comment"),
+            Style::new().fg(Color::new(0x30, 0x30, 0x30)),
+        );
+        theme.set_style(index("keyword.function"), Style::new().bold());
+
+        let diagnostics = theme.validate();
+        assert!(
+            !diagnostics
+                .iter()
+                .any(|d| matches!(d, Diagnostic::MissingBaseColor { .. }))
+        );
+        assert!(diagnostics.contains(&Diagnostic::MissingStyle {
+            highlight: "string"
+        }));
+        assert!(!diagnostics.contains(&Diagnostic::MissingStyle {
+            highlight: "keyword"
+        }));
+        assert!(diagnostics.contains(&Diagnostic::UnusedStyle {
+            highlight: "keyword.function"
+        }));
+        assert!(diagnostics.iter().any(|d| matches!(
+            d,
+            Diagnostic::LowContrast { highlight: "comment", ratio } if *ratio < 1.5
+        )));
+        assert!(!diagnostics.iter().any(|d| matches!(
+            d,
+            Diagnostic::LowContrast {
+                highlight: "keyword",
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn test_contrast_ratio() {
+        let black = Color::new(0, 0, 0);
+        let white = Color::new(255, 255, 255);
+        assert!((black.contrast_ratio(&white) - 21.0).abs() < 0.01);
+        assert!((white.contrast_ratio(&white) - 1.0).abs() < 0.01);
+        // Order doesn't matter
+        let gray = Color::new(0x76, 0x76, 0x76);
+        assert!((gray.contrast_ratio(&white) - 4.54).abs() < 0.01);
+        assert_eq!(white.contrast_ratio(&gray), gray.contrast_ratio(&white));
+    }
+
+    #[test]
+    fn test_check_contrast() {
+        let mut theme = Theme::new("test");
+        theme.background = Some(Color::new(255, 255, 255));
+        theme.foreground = Some(Color::new(0x76, 0x76, 0x76));
+        theme.set_style(index("string"), Style::new().fg(Color::new(0, 0, 0)));
+        // Readable only thanks to its own dark background
+        theme.set_style(
+            index("comment"),
+            Style {
+                bg: Some(Color::new(0, 0, 0)),
+                ..Style::new().fg(Color::new(255, 255, 255))
+            },
+        );
+
+        assert!(theme.check_contrast(WCAG_AA).is_empty());
+        let issues = theme.check_contrast(WCAG_AAA);
+        assert!(issues.iter().any(|d| matches!(
+            d,
+            Diagnostic::LowContrast {
+                highlight: "foreground",
+                ..
+            }
+        )));
+        // Unstyled categories are drawn in the foreground color
+        assert!(issues.iter().any(|d| matches!(
+            d,
+            Diagnostic::LowContrast {
+                highlight: "keyword",
+                ..
+            }
+        )));
+        assert!(!issues.iter().any(|d| matches!(
+            d,
+            Diagnostic::LowContrast {
+                highlight: "string" | "comment",
+                ..
+            }
+        )));
+
+        theme.background = None;
+        // Only the comment has a background left to measure against
+        assert!(theme.check_contrast(WCAG_AAA).is_empty());
+    }
+
+    #[test]
+    fn test_builtin_themes_have_base_colors() {
+        for theme in builtin::all() {
+            let diagnostics = theme.validate();
+            assert!(
+                !diagnostics
+                    .iter()
+                    .any(|d| matches!(d, Diagnostic::MissingBaseColor { .. })),
+                "{}: {diagnostics:?}",
+                theme.name
+            );
+        }
+    }
+}
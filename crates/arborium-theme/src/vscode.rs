@@ -0,0 +1,260 @@
+//! Importing VS Code color themes.
+
+use serde_json::Value;
+
+use crate::textmate::{
+    ScopeRule, apply_rules, is_dark_background, parse_color, parse_font_style, parse_selectors,
+};
+use crate::theme::{Theme, ThemeError};
+
+impl Theme {
+    /// Parse a VS Code color theme (the JSON file a theme extension ships).
+    ///
+    /// `tokenColors` rules are mapped from TextMate scopes onto highlight
+    /// categories, and `editor.background`/`editor.foreground` from `colors`
+    /// become the theme's background and foreground, along with the line
+    /// number, line highlight and selection colors. Comments and trailing
+    /// commas are accepted, as VS Code does.
+    ///
+    /// Themes that `include` a base theme or keep `tokenColors` in a separate
+    /// file must be merged into a single file first. Semantic token colors
+    /// are ignored.
+    ///
+    /// This method is only available when the `vscode` feature is enabled.
+    ///
+    /// ```rust
+    /// use arborium_theme::Theme;
+    ///
+    /// let theme = Theme::from_vscode_json(r##"{
+    ///     "name": "Example",
+    ///     "type": "light",
+    ///     "colors": { "editor.background": "#ffffff" },
+    ///     "tokenColors": [
+    ///         // Comments are fine
+    ///         { "scope": "comment", "settings": { "foreground": "#808080", "fontStyle": "italic" } },
+    ///     ]
+    /// }"##)?;
+    ///
+    /// assert_eq!(theme.name, "Example");
+    /// assert!(!theme.is_dark);
+    /// # Ok::<(), arborium_theme::ThemeError>(())
+    /// ```
+    pub fn from_vscode_json(json: &str) -> Result<Self, ThemeError> {
+        let value: Value = serde_json::from_str(&strip_jsonc(json))
+            .map_err(|e| ThemeError::Parse(format!("{e}")))?;
+        let object = value
+            .as_object()
+            .ok_or(ThemeError::Parse("Expected object".into()))?;
+
+        let mut theme = Theme::default();
+        if let Some(name) = object.get("name").and_then(|v| v.as_str()) {
+            theme.name = name.to_string();
+        }
+
+        let token_colors = match object.get("tokenColors") {
+            None => &[][..],
+            Some(Value::Array(rules)) => rules.as_slice(),
+            Some(Value::String(path)) => {
+                return Err(ThemeError::Parse(format!(
+                    "tokenColors refers to {path}; merge it into the theme first"
+                )));
+            }
+            Some(_) => return Err(ThemeError::Parse("Expected tokenColors array".into())),
+        };
+
+        let mut rules = Vec::with_capacity(token_colors.len());
+        for entry in token_colors {
+            let settings = entry.get("settings");
+            let color = |key: &str| {
+                settings
+                    .and_then(|s| s.get(key))
+                    .and_then(|v| v.as_str())
+                    .and_then(parse_color)
+            };
+            let rule = ScopeRule {
+                selectors: match entry.get("scope") {
+                    Some(Value::String(scope)) => parse_selectors(scope),
+                    Some(Value::Array(scopes)) => scopes
+                        .iter()
+                        .filter_map(|v| v.as_str())
+                        .flat_map(parse_selectors)
+                        .collect(),
+                    _ => Vec::new(),
+                },
+                foreground: color("foreground"),
+                background: color("background"),
+                font_style: settings
+                    .and_then(|s| s.get("fontStyle"))
+                    .and_then(|v| v.as_str())
+                    .map(parse_font_style),
+            };
+
+            // A rule without a scope holds the editor's default colors
+            if entry.get("scope").is_none() {
+                theme.background = theme.background.or(rule.background);
+                theme.foreground = theme.foreground.or(rule.foreground);
+                continue;
+            }
+            rules.push(rule);
+        }
+        apply_rules(&mut theme, &rules);
+
+        // Workbench colors take precedence over the default rule
+        let workbench = |key: &str| {
+            object
+                .get("colors")
+                .and_then(|colors| colors.get(key))
+                .and_then(|v| v.as_str())
+                .and_then(parse_color)
+        };
+        if let Some(background) = workbench("editor.background") {
+            theme.background = Some(background);
+        }
+        if let Some(foreground) = workbench("editor.foreground") {
+            theme.foreground = Some(foreground);
+        }
+        theme.gutter = workbench("editorLineNumber.foreground");
+        theme.line_highlight = workbench("editor.lineHighlightBackground");
+        theme.selection = workbench("editor.selectionBackground");
+
+        // `type` is optional; without it, judge by the background
+        if let Some(kind) = object.get("type").and_then(|v| v.as_str()) {
+            theme.is_dark = !kind.contains("light");
+        } else if let Some(background) = theme.background {
+            theme.is_dark = is_dark_background(background);
+        }
+
+        Ok(theme)
+    }
+}
+
+/// Remove comments and trailing commas, turning JSONC into plain JSON.
+fn strip_jsonc(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                '\\' => out.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                out.push(c);
+            }
+            ('/', Some('/')) => while chars.next_if(|&c| c != '\n').is_some() {},
+            ('/', Some('*')) => {
+                chars.next();
+                let mut prev = '\0';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            (']' | '}', _) => {
+                // Drop a comma left dangling before the closing bracket
+                let content_end = out.trim_end().len();
+                if out[..content_end].ends_with(',') {
+                    out.remove(content_end - 1);
+                }
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::highlights::HIGHLIGHTS;
+    use crate::theme::Color;
+
+    const THEME: &str = r##"{
+        "$schema": "vscode://schemas/color-theme",
+        "name": "Test Dark",
+        "type": "dark",
+        "colors": {
+            "editor.background": "#1e1e1e",
+            "editor.foreground": "#d4d4d4", // trailing comment
+            "editor.selectionBackground": "#264f78",
+            "editor.lineHighlightBackground": "#ffffff80",
+        },
+        /* Token colors */
+        "tokenColors": [
+            { "settings": { "foreground": "#ffffff", "background": "#000000" } },
+            {
+                "name": "Comments",
+                "scope": ["comment", "punctuation.definition.comment"],
+                "settings": { "foreground": "#6a9955", "fontStyle": "italic" }
+            },
+            { "scope": "string, string.quoted", "settings": { "foreground": "#ce9178" } },
+            { "scope": "keyword.control", "settings": { "foreground": "#c586c0", "fontStyle": "bold" } },
+            { "scope": "keyword.control.conditional", "settings": { "fontStyle": "" } },
+            { "scope": "entity.name.function", "settings": { "foreground": "#dcdcaa" } },
+            { "scope": "source.js string", "settings": { "foreground": "#ff0000" } },
+        ],
+    }"##;
+
+    fn style<'a>(theme: &'a Theme, name: &str) -> &'a crate::theme::Style {
+        &theme.styles[HIGHLIGHTS.iter().position(|h| h.name == name).unwrap()]
+    }
+
+    #[test]
+    fn test_from_vscode_json() {
+        let theme = Theme::from_vscode_json(THEME).unwrap();
+        assert_eq!(theme.name, "Test Dark");
+        assert!(theme.is_dark);
+        assert_eq!(theme.background, Some(Color::new(0x1e, 0x1e, 0x1e)));
+        assert_eq!(theme.foreground, Some(Color::new(0xd4, 0xd4, 0xd4)));
+        assert_eq!(theme.selection, Some(Color::new(0x26, 0x4f, 0x78)));
+        assert_eq!(theme.line_highlight, Some(Color::rgba(255, 255, 255, 0x80)));
+        assert_eq!(theme.gutter, None);
+
+        let comment = style(&theme, "comment");
+        assert_eq!(comment.fg, Some(Color::new(0x6a, 0x99, 0x55)));
+        assert!(comment.modifiers.italic);
+        assert_eq!(comment.fg, style(&theme, "comment.documentation").fg);
+
+        // The context-dependent `source.js string` rule is skipped
+        assert_eq!(
+            style(&theme, "string").fg,
+            Some(Color::new(0xce, 0x91, 0x78))
+        );
+
+        assert!(style(&theme, "keyword").modifiers.bold);
+        let conditional = style(&theme, "keyword.conditional");
+        assert_eq!(conditional.fg, Some(Color::new(0xc5, 0x86, 0xc0)));
+        assert!(!conditional.modifiers.bold);
+
+        assert_eq!(
+            style(&theme, "function.method").fg,
+            Some(Color::new(0xdc, 0xdc, 0xaa))
+        );
+        assert!(style(&theme, "number").is_empty());
+    }
+
+    #[test]
+    fn test_strip_jsonc_keeps_strings() {
+        assert_eq!(
+            strip_jsonc(r#"{"a": "// not a comment, ]", /* x */ "b": [1, 2,],}"#),
+            r#"{"a": "// not a comment, ]",  "b": [1, 2]}"#
+        );
+    }
+
+    #[test]
+    fn test_token_colors_file_reference() {
+        let err = Theme::from_vscode_json(r#"{ "tokenColors": "./tokens.tmTheme" }"#);
+        assert!(err.is_err());
+    }
+}
@@ -109,7 +109,7 @@ $radius: 0.5rem;
             ..Default::default()
         };
 
-        let mut hl = AnsiHighlighter::with_options(theme, config, options);
+        let hl = AnsiHighlighter::with_options(theme, config, options);
         let output = hl.highlight(lang, code).unwrap();
         println!("{output}\n");
     }
@@ -44,10 +44,37 @@ pub use arborium_highlight::tree_sitter::{
 // Data types
 pub use arborium_highlight::{Injection, ParseResult, Span};
 
+// Offset and line/column conversion (UTF-16 / char positions for editors and LSP)
+pub use arborium_highlight::{
+    LineIndex, OffsetEncoding, OffsetMap, Position, PositionedSpan, with_positions,
+};
+
 // Low-level rendering utilities
 pub use arborium_highlight::{
-    html_escape, spans_to_ansi, spans_to_ansi_with_options, spans_to_html, write_spans_as_html,
+    html_escape, render_block, spans_to_ansi, spans_to_ansi_lines, spans_to_ansi_with_options,
+    spans_to_ansi_with_source_map, spans_to_html, spans_to_html_with_options,
+    spans_to_html_with_source_map, write_spans_as_ansi, write_spans_as_ansi_with_options,
+    write_spans_as_html, write_spans_as_html_with_options,
 };
 
+// Structured, line-oriented output
+pub use arborium_highlight::{StyledToken, spans_to_document};
+
+// tree-sitter-highlight style event stream
+pub use arborium_highlight::spans_to_events;
+
+// Flat tokens covering the whole source
+pub use arborium_highlight::spans_to_tokens;
+
+// Pango markup for GTK
+pub use arborium_highlight::spans_to_pango;
+
+// anstyle segments
+#[cfg(feature = "anstyle")]
+pub use arborium_highlight::{spans_to_anstyle, to_anstyle};
+
+// Syntax-highlighted diffs
+pub use arborium_highlight::{diff_to_html, diff_to_side_by_side_html};
+
 // ANSI rendering options
-pub use arborium_highlight::AnsiOptions;
+pub use arborium_highlight::{AnsiOptions, ColorDepth, Overflow};
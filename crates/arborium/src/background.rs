@@ -0,0 +1,194 @@
+//! Detecting whether the terminal has a light or a dark background.
+//!
+//! Terminals that support it report their background color in reply to an
+//! OSC 11 query. Others may describe their colors in `COLORFGBG`, which
+//! rxvt and Konsole set. Either way, the answer picks the variant of a
+//! [`ThemePair`] to render with.
+
+use arborium_theme::{Color, Theme, ThemePair};
+
+use crate::env;
+
+/// Query the terminal for its background color with OSC 11.
+///
+/// Asks the controlling terminal (`/dev/tty`), so it works even when stdout
+/// is redirected. Returns `None` when there is no terminal, when it doesn't
+/// answer (it is asked for its device attributes too, which every terminal
+/// reports, so an unsupported query doesn't wait out the timeout), or on
+/// platforms other than Unix.
+///
+/// Don't call this while another thread reads from the terminal: the reply
+/// arrives as input.
+///
+/// This function is only available when the `terminal-background` feature
+/// is enabled.
+pub fn terminal_background() -> Option<Color> {
+    #[cfg(unix)]
+    {
+        unix::query(b"\x1b]11;?\x1b\\\x1b[c").and_then(|reply| parse_osc11_reply(&reply))
+    }
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+/// Whether the terminal's background is dark: judged by the color from
+/// [`terminal_background`], or by `COLORFGBG` when the terminal doesn't
+/// report one. `None` if neither is available.
+///
+/// This function is only available when the `terminal-background` feature
+/// is enabled.
+pub fn terminal_is_dark() -> Option<bool> {
+    match terminal_background() {
+        Some(background) => Some(background.relative_luminance() < 0.5),
+        None => env::colorfgbg_is_dark(),
+    }
+}
+
+/// The variant of `pair` that suits the terminal's background, or the dark
+/// one if the background can't be detected.
+///
+/// This function is only available when the `terminal-background` feature
+/// is enabled.
+///
+/// ```rust,no_run
+/// use arborium::theme::{ThemePair, builtin};
+///
+/// let pair = ThemePair::new(builtin::github_light(), builtin::github_dark());
+/// let hl = arborium::AnsiHighlighter::new(arborium::terminal_theme(&pair).clone());
+/// ```
+pub fn terminal_theme(pair: &ThemePair) -> &Theme {
+    pair.theme(terminal_is_dark().unwrap_or(true))
+}
+
+/// Parse a reply to an OSC 11 query, e.g. `ESC ] 11 ; rgb:1e1e/1e1e/2e2e BEL`.
+///
+/// Each component has one to four hex digits, scaled to their own range.
+fn parse_osc11_reply(reply: &[u8]) -> Option<Color> {
+    let reply = std::str::from_utf8(reply).ok()?;
+    let start = reply.find("\x1b]11;")? + "\x1b]11;".len();
+    let rest = &reply[start..];
+    let end = rest.find(['\x07', '\x1b'])?;
+    let spec = rest[..end].strip_prefix("rgb:")?;
+
+    let mut components = spec.split('/').map(|hex| {
+        if hex.is_empty() || hex.len() > 4 {
+            return None;
+        }
+        let value = u32::from_str_radix(hex, 16).ok()?;
+        let max = (1u32 << (4 * hex.len())) - 1;
+        Some((value * 255 / max) as u8)
+    });
+    let color = Color::new(
+        components.next()??,
+        components.next()??,
+        components.next()??,
+    );
+    components.next().is_none().then_some(color)
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::fs::{File, OpenOptions};
+    use std::io::{Read, Write};
+    use std::mem::MaybeUninit;
+    use std::os::unix::io::AsRawFd;
+    use std::time::{Duration, Instant};
+
+    /// How long to wait for the terminal to answer.
+    const TIMEOUT: Duration = Duration::from_millis(200);
+
+    /// Send `query` to the controlling terminal in raw mode, and return what
+    /// it replies up to the end of its device attributes report.
+    pub(super) fn query(query: &[u8]) -> Option<Vec<u8>> {
+        let tty = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+            .ok()?;
+        let fd = tty.as_raw_fd();
+
+        let mut original = MaybeUninit::<libc::termios>::uninit();
+        // SAFETY: `fd` is open for the whole call and `original` is a valid
+        // place for the terminal attributes.
+        if unsafe { libc::tcgetattr(fd, original.as_mut_ptr()) } != 0 {
+            return None;
+        }
+        // SAFETY: `tcgetattr` succeeded, so it initialized `original`.
+        let original = unsafe { original.assume_init() };
+        let mut raw = original;
+        // SAFETY: `raw` is a valid, initialized `termios`.
+        unsafe { libc::cfmakeraw(&mut raw) };
+        // SAFETY: `fd` is open and `raw` is a valid `termios`.
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+            return None;
+        }
+
+        let reply = exchange(&tty, query);
+
+        // SAFETY: as above; this restores the attributes read earlier.
+        unsafe { libc::tcsetattr(fd, libc::TCSANOW, &original) };
+        reply
+    }
+
+    fn exchange(mut tty: &File, query: &[u8]) -> Option<Vec<u8>> {
+        tty.write_all(query).ok()?;
+        tty.flush().ok()?;
+
+        let deadline = Instant::now() + TIMEOUT;
+        let mut reply = Vec::new();
+        // The device attributes report, `ESC [ ? ... c`, comes last
+        while !ends_with_device_attributes(&reply) {
+            let remaining = deadline.checked_duration_since(Instant::now())?;
+            let mut poll = libc::pollfd {
+                fd: tty.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let timeout = remaining.as_millis().clamp(1, i32::MAX as u128) as i32;
+            // SAFETY: `poll` points to one valid `pollfd`.
+            if unsafe { libc::poll(&mut poll, 1, timeout) } <= 0 {
+                return None;
+            }
+            let mut buf = [0; 64];
+            let read = tty.read(&mut buf).ok()?;
+            if read == 0 {
+                return None;
+            }
+            reply.extend_from_slice(&buf[..read]);
+        }
+        Some(reply)
+    }
+
+    fn ends_with_device_attributes(reply: &[u8]) -> bool {
+        let Some(start) = reply.windows(3).rposition(|w| w == b"\x1b[?") else {
+            return false;
+        };
+        reply[start..].ends_with(b"c")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_osc11_reply() {
+        assert_eq!(
+            parse_osc11_reply(b"\x1b]11;rgb:1e1e/1e1e/2e2e\x07"),
+            Some(Color::new(0x1e, 0x1e, 0x2e))
+        );
+        // String terminator, followed by the device attributes
+        assert_eq!(
+            parse_osc11_reply(b"\x1b]11;rgb:ffff/ffff/ffff\x1b\\\x1b[?62;22c"),
+            Some(Color::new(255, 255, 255))
+        );
+        assert_eq!(
+            parse_osc11_reply(b"\x1b]11;rgb:f/8/0\x07"),
+            Some(Color::new(255, 136, 0))
+        );
+        assert_eq!(parse_osc11_reply(b"\x1b[?62;22c"), None);
+        assert_eq!(parse_osc11_reply(b"\x1b]11;rgb:ff/ff\x07"), None);
+    }
+}
@@ -0,0 +1,386 @@
+//! Highlighting configuration.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use arborium_highlight::{HtmlFormat, HtmlOptions};
+use arborium_theme::CaptureMap;
+
+use crate::error::Error;
+use crate::store::GrammarStore;
+
+/// Configuration for highlighting.
+///
+/// Controls injection depth, which injected languages are processed, and HTML
+/// output format.
+///
+/// With the `serde` feature, `Config` can be loaded from an application's
+/// settings file. Missing fields take their default values;
+/// [`injection_resolver`](Self::injection_resolver), which holds a closure,
+/// and [`capture_map`](Self::capture_map) are skipped.
+/// [`timeout`](Self::timeout) uses serde's `Duration` format
+/// (`{ secs, nanos }`).
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Config {
+    /// Maximum depth for processing language injections.
+    ///
+    /// - `0`: No injections (just primary language)
+    /// - `3`: Default, handles most cases (HTML with CSS/JS, Markdown with code blocks)
+    /// - Higher: For deeply nested content
+    pub max_injection_depth: u32,
+
+    /// Per-language overrides of [`max_injection_depth`](Self::max_injection_depth).
+    ///
+    /// Keys are language names or aliases. An override sets the depth below
+    /// any document or injection of that language, raising or lowering the
+    /// depth left at that point: `markdown => 5` allows deeply nested fences
+    /// even in Markdown injected into another language, while `yaml => 0`
+    /// never highlights injections inside YAML. If a language is listed under
+    /// both its name and an alias, the name wins. See
+    /// [`injection_depth_for`](Self::injection_depth_for).
+    pub injection_depth_overrides: BTreeMap<String, u32>,
+
+    /// Languages that may be highlighted when injected into another language.
+    ///
+    /// `None` (the default) allows every supported language. With `Some(set)`,
+    /// injections of languages outside the set are left unhighlighted. Names
+    /// may be canonical IDs or aliases (`"js"` matches `"javascript"`).
+    pub injection_languages: Option<HashSet<String>>,
+
+    /// Languages that are never highlighted when injected, even if listed in
+    /// [`injection_languages`](Self::injection_languages).
+    ///
+    /// Useful for skipping expensive or unwanted parsers, e.g. never running
+    /// the SQL grammar inside Markdown code fences.
+    pub denied_injection_languages: HashSet<String>,
+
+    /// Maps injected language hints to grammar names.
+    ///
+    /// Runs before the allow/deny lists, so those apply to the resolved name.
+    /// See [`InjectionResolver`].
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub injection_resolver: Option<InjectionResolver>,
+
+    /// Maximum source size in bytes. `None` (the default) means unlimited.
+    ///
+    /// Larger inputs are not parsed; see [`oversize_policy`](Self::oversize_policy).
+    pub max_input_bytes: Option<usize>,
+
+    /// Maximum length in bytes of any single line. `None` (the default) means
+    /// unlimited.
+    ///
+    /// Minified JavaScript and similar single-line blobs can make parsing
+    /// pathologically slow. Inputs with a longer line are not parsed; see
+    /// [`oversize_policy`](Self::oversize_policy).
+    pub max_line_length: Option<usize>,
+
+    /// What to do when input exceeds [`max_input_bytes`](Self::max_input_bytes)
+    /// or [`max_line_length`](Self::max_line_length).
+    pub oversize_policy: OversizePolicy,
+
+    /// Maximum time to spend highlighting a single call. `None` (the default)
+    /// means unlimited.
+    ///
+    /// The budget covers parsing the document and all of its injections; when
+    /// it runs out, highlighting fails with [`Error::Cancelled`]. Ignored on
+    /// `wasm32-unknown-unknown`, which has no clock; use
+    /// [`Highlighter::highlight_with_cancel`](crate::Highlighter::highlight_with_cancel)
+    /// there instead.
+    pub timeout: Option<Duration>,
+
+    /// What to do when asked to highlight a language with no grammar.
+    ///
+    /// Injections of unsupported languages are always left unhighlighted;
+    /// this only affects the primary language.
+    pub fallback: Fallback,
+
+    /// HTML output format.
+    ///
+    /// See [`HtmlFormat`] for options. To match an existing CSS naming
+    /// convention, use e.g. `HtmlFormat::ClassNamesWithPrefix("tok-".into())`
+    /// for `<span class="tok-keyword">`.
+    pub html_format: HtmlFormat,
+
+    /// Line-level HTML options, such as a line-number gutter.
+    ///
+    /// See [`HtmlOptions`]. The defaults add nothing around the highlighted code.
+    pub html_options: HtmlOptions,
+
+    /// Overrides of which theme slot a capture name is styled with.
+    ///
+    /// Applies to every output format. Spans whose capture an override
+    /// matches are renamed to a capture of the overriding slot, which is what
+    /// [`HtmlOptions::capture_attributes`] then shows. See [`CaptureMap`].
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub capture_map: CaptureMap,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_injection_depth: 3,
+            injection_depth_overrides: BTreeMap::new(),
+            injection_languages: None,
+            denied_injection_languages: HashSet::new(),
+            injection_resolver: None,
+            max_input_bytes: None,
+            max_line_length: None,
+            oversize_policy: OversizePolicy::default(),
+            timeout: None,
+            fallback: Fallback::default(),
+            html_format: HtmlFormat::default(),
+            html_options: HtmlOptions::default(),
+            capture_map: CaptureMap::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Override the injection depth for one language.
+    ///
+    /// Aliases are stored under the language's canonical name, so a later
+    /// call for `"md"` replaces an earlier one for `"markdown"`.
+    ///
+    /// ```rust
+    /// use arborium::Config;
+    ///
+    /// let config = Config::default()
+    ///     .injection_depth_for("markdown", 5)
+    ///     .injection_depth_for("yaml", 0);
+    /// assert_eq!(config.injection_depth_overrides["markdown"], 5);
+    /// assert_eq!(config.injection_depth_overrides["yaml"], 0);
+    /// ```
+    pub fn injection_depth_for(mut self, language: &str, depth: u32) -> Self {
+        let language = GrammarStore::normalize_language(language).into_owned();
+        self.injection_depth_overrides.insert(language, depth);
+        self
+    }
+
+    /// The depth override for `language`, if one is set.
+    pub(crate) fn injection_depth_override(&self, language: &str) -> Option<u32> {
+        let normalized = GrammarStore::normalize_language(language);
+        self.injection_depth_overrides
+            .get(normalized.as_ref())
+            .or_else(|| {
+                self.injection_depth_overrides
+                    .iter()
+                    .find(|(name, _)| GrammarStore::normalize_language(name) == normalized)
+                    .map(|(_, depth)| depth)
+            })
+            .copied()
+    }
+
+    /// Check `source` against the size limits.
+    ///
+    /// Returns `Ok(true)` if it may be parsed, `Ok(false)` if it should be
+    /// rendered as plain text, or an error under [`OversizePolicy::Error`].
+    pub(crate) fn check_input_size(&self, language: &str, source: &str) -> Result<bool, Error> {
+        let exceeded = if let Some(limit) = self.max_input_bytes.filter(|&l| source.len() > l) {
+            Some((source.len(), limit))
+        } else if let Some(limit) = self.max_line_length {
+            source
+                .lines()
+                .map(str::len)
+                .max()
+                .filter(|&longest| longest > limit)
+                .map(|longest| (longest, limit))
+        } else {
+            None
+        };
+
+        match (exceeded, self.oversize_policy) {
+            (None, _) => Ok(true),
+            (Some(_), OversizePolicy::PlainText) => Ok(false),
+            (Some((size, limit)), OversizePolicy::Error) => Err(Error::InputTooLarge {
+                language: language.to_string(),
+                size,
+                limit,
+            }),
+        }
+    }
+
+    /// Resolve the language hint of an injection to the grammar name to use.
+    pub(crate) fn resolve_injection<'a>(&self, hint: &'a str) -> &'a str {
+        self.injection_resolver
+            .as_ref()
+            .and_then(|resolver| resolver.resolve(hint))
+            .unwrap_or(hint)
+    }
+
+    /// Whether an injection of `language` should be highlighted.
+    pub(crate) fn allows_injection(&self, language: &str) -> bool {
+        let normalized = GrammarStore::normalize_language(language);
+        let matches = |set: &HashSet<String>| {
+            set.iter()
+                .any(|name| GrammarStore::normalize_language(name) == normalized)
+        };
+
+        if matches(&self.denied_injection_languages) {
+            return false;
+        }
+        self.injection_languages.as_ref().is_none_or(matches)
+    }
+}
+
+impl From<Config> for arborium_highlight::HighlightConfig {
+    fn from(config: Config) -> Self {
+        arborium_highlight::HighlightConfig {
+            max_injection_depth: config.max_injection_depth,
+            html_format: config.html_format,
+        }
+    }
+}
+
+/// Behavior for unsupported languages, set via [`Config::fallback`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum Fallback {
+    /// Fail with [`Error::UnsupportedLanguage`] (default).
+    #[default]
+    Error,
+
+    /// Emit the source escaped but unstyled, as if it had no highlights.
+    PlainText,
+}
+
+/// Behavior for input that exceeds the size limits in [`Config`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum OversizePolicy {
+    /// Skip parsing and emit the source escaped but unstyled (default).
+    #[default]
+    PlainText,
+
+    /// Fail with [`Error::InputTooLarge`].
+    Error,
+}
+
+/// Callback that maps an injected language hint to a grammar name.
+///
+/// Injection queries report whatever the document says, such as a Markdown
+/// fence info string. Hints that no grammar recognizes (e.g. `pycon` or
+/// `console`) are normally skipped; a resolver can redirect them to a
+/// supported language. Returning `None` keeps the original hint.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use arborium::{Config, InjectionResolver};
+///
+/// let config = Config {
+///     injection_resolver: Some(InjectionResolver::new(|hint| match hint {
+///         "pycon" => Some("python"),
+///         "console" => Some("bash"),
+///         _ => None,
+///     })),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone)]
+pub struct InjectionResolver(Arc<ResolveFn>);
+
+type ResolveFn = dyn for<'a> Fn(&'a str) -> Option<&'a str> + Send + Sync;
+
+impl InjectionResolver {
+    /// Create a resolver from a closure.
+    pub fn new<F>(resolve: F) -> Self
+    where
+        F: for<'a> Fn(&'a str) -> Option<&'a str> + Send + Sync + 'static,
+    {
+        Self(Arc::new(resolve))
+    }
+
+    /// Map a language hint to a grammar name, or `None` to keep the hint.
+    pub fn resolve<'a>(&self, hint: &'a str) -> Option<&'a str> {
+        (self.0)(hint)
+    }
+}
+
+impl fmt::Debug for InjectionResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("InjectionResolver(..)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_injection_allow_and_deny_lists() {
+        let mut config = Config::default();
+        assert!(config.allows_injection("sql"));
+
+        config.injection_languages = Some(["css".to_string(), "sql".to_string()].into());
+        config.denied_injection_languages = ["sql".to_string()].into();
+        assert!(config.allows_injection("css"));
+        assert!(!config.allows_injection("sql"));
+        assert!(!config.allows_injection("javascript"));
+    }
+
+    #[test]
+    fn test_injection_resolver() {
+        let config = Config {
+            injection_resolver: Some(InjectionResolver::new(|hint| match hint {
+                "pycon" => Some("python"),
+                _ => None,
+            })),
+            ..Config::default()
+        };
+        assert_eq!(config.resolve_injection("pycon"), "python");
+        assert_eq!(config.resolve_injection("rust"), "rust");
+    }
+
+    #[test]
+    fn test_input_size_guards() {
+        let mut config = Config {
+            max_input_bytes: Some(16),
+            max_line_length: Some(8),
+            ..Config::default()
+        };
+        assert!(!config.check_input_size("rust", "let x = 1;").unwrap());
+        assert!(!config.check_input_size("rust", &"x\n".repeat(10)).unwrap());
+        assert!(config.check_input_size("rust", "let x;\nx\n").unwrap());
+
+        config.oversize_policy = OversizePolicy::Error;
+        assert!(matches!(
+            config.check_input_size("rust", "let x = 1;"),
+            Err(Error::InputTooLarge {
+                size: 10,
+                limit: 8,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_injection_depth_overrides() {
+        let config = Config::default()
+            .injection_depth_for("markdown", 5)
+            .injection_depth_for("yaml", 0);
+        assert_eq!(config.injection_depth_override("markdown"), Some(5));
+        assert_eq!(config.injection_depth_override("yaml"), Some(0));
+        assert_eq!(config.injection_depth_override("rust"), None);
+
+        // The canonical name wins over an alias set directly in the map
+        let mut config = Config::default();
+        config.injection_depth_overrides =
+            [("md".to_string(), 1), ("markdown".to_string(), 2)].into();
+        assert_eq!(config.injection_depth_override("markdown"), Some(2));
+    }
+}
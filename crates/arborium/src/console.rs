@@ -0,0 +1,71 @@
+//! Terminal setup for ANSI output on Windows.
+//!
+//! The Windows console only interprets escape sequences once virtual terminal
+//! processing is switched on for it; until then, `cmd.exe` and older
+//! PowerShell hosts print them as literal garbage. Windows Terminal and
+//! terminals on other platforms need no setup.
+
+/// Enable ANSI escape sequence processing for stdout and stderr.
+///
+/// On Windows this switches on virtual terminal processing for each of the two
+/// handles that is attached to a console, which every console since Windows 10
+/// supports. Elsewhere it does nothing and always succeeds.
+///
+/// [`AnsiHighlighter::auto`](crate::AnsiHighlighter::auto) calls this itself;
+/// call it before printing output from highlighters built any other way.
+///
+/// # Errors
+///
+/// Returns the OS error if the console rejects the mode, as legacy consoles
+/// do. Output will then not render, so switch the highlighter to
+/// [`ColorDepth::NoColor`](crate::advanced::ColorDepth::NoColor).
+///
+/// ```rust,no_run
+/// let mut hl = arborium::AnsiHighlighter::new(arborium::theme::builtin::catppuccin_mocha());
+/// if arborium::enable_ansi_support().is_err() {
+///     hl.options_mut().color_depth = arborium::advanced::ColorDepth::NoColor;
+/// }
+/// ```
+pub fn enable_ansi_support() -> std::io::Result<()> {
+    #[cfg(windows)]
+    {
+        windows::enable_virtual_terminal(&std::io::stdout())?;
+        windows::enable_virtual_terminal(&std::io::stderr())?;
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::c_void;
+    use std::io;
+    use std::os::windows::io::AsRawHandle;
+
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetConsoleMode(console: *mut c_void, mode: *mut u32) -> i32;
+        fn SetConsoleMode(console: *mut c_void, mode: u32) -> i32;
+    }
+
+    /// Switch on virtual terminal processing for `stream` if it is a console.
+    pub(super) fn enable_virtual_terminal(stream: &impl AsRawHandle) -> io::Result<()> {
+        let handle = stream.as_raw_handle();
+        let mut mode = 0;
+        // SAFETY: `handle` is owned by the standard stream and stays open for
+        // the whole call; `mode` is a valid place to write the current mode.
+        if unsafe { GetConsoleMode(handle, &mut mode) } == 0 {
+            // Not a console (redirected to a file or pipe): nothing to enable
+            return Ok(());
+        }
+        if mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0 {
+            return Ok(());
+        }
+        // SAFETY: as above.
+        if unsafe { SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,202 @@
+//! Defaults read from environment variables.
+//!
+//! See [`Highlighter::from_env`](crate::Highlighter::from_env),
+//! [`AnsiHighlighter::from_env`](crate::AnsiHighlighter::from_env) and
+//! [`AnsiHighlighter::auto`](crate::AnsiHighlighter::auto). Unset or
+//! unrecognized values fall back to the regular defaults, so a typo never
+//! makes a tool fail to start.
+
+use std::io::IsTerminal;
+
+use arborium_highlight::{ColorDepth, HtmlFormat};
+use arborium_theme::{Theme, registry};
+
+/// `ARBORIUM_THEME`: name of a built-in or registered theme, e.g. `catppuccin-mocha`.
+const THEME: &str = "ARBORIUM_THEME";
+
+/// `ARBORIUM_COLOR`: `16`, `256`, `truecolor` (or `24bit`), or `none`.
+const COLOR: &str = "ARBORIUM_COLOR";
+
+/// `ARBORIUM_HTML_FORMAT`: `custom-elements`, `class-names`, `hljs`, `prism`, or `pygments`.
+const HTML_FORMAT: &str = "ARBORIUM_HTML_FORMAT";
+
+/// `NO_COLOR`: disables colors when set to a non-empty value (<https://no-color.org>).
+const NO_COLOR: &str = "NO_COLOR";
+
+/// `CLICOLOR_FORCE`: enables colors even when not writing to a terminal,
+/// unless set to `0` (<https://bixense.com/clicolors/>).
+const CLICOLOR_FORCE: &str = "CLICOLOR_FORCE";
+
+/// `COLORTERM`: `truecolor` or `24bit` when the terminal supports 24-bit color.
+const COLORTERM: &str = "COLORTERM";
+
+/// `TERM`: the terminal type, e.g. `xterm-256color` or `dumb`.
+const TERM: &str = "TERM";
+
+/// `WT_SESSION`: set by Windows Terminal, which supports 24-bit color but
+/// sets neither `COLORTERM` nor `TERM`.
+const WT_SESSION: &str = "WT_SESSION";
+
+/// `COLORFGBG`: the terminal's foreground and background as palette
+/// indices, e.g. `15;0`, set by rxvt and Konsole.
+#[cfg(feature = "terminal-background")]
+const COLORFGBG: &str = "COLORFGBG";
+
+fn var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// The theme named by `ARBORIUM_THEME`, if any (see [`registry`]).
+pub(crate) fn theme() -> Option<Theme> {
+    var(THEME).and_then(|name| registry().get(&name))
+}
+
+/// The color depth requested by `NO_COLOR` or `ARBORIUM_COLOR`, if any.
+///
+/// `NO_COLOR` takes precedence.
+pub(crate) fn color_depth() -> Option<ColorDepth> {
+    if var(NO_COLOR).is_some() {
+        return Some(ColorDepth::NoColor);
+    }
+    var(COLOR).and_then(|value| parse_color_depth(&value))
+}
+
+/// The color depth the terminal on stdout supports, judging by `NO_COLOR`,
+/// `CLICOLOR_FORCE`, `COLORTERM`, `TERM` and `WT_SESSION`.
+///
+/// Enables escape sequence processing on Windows consoles, and disables
+/// colors for consoles that can't process them.
+pub(crate) fn detect_color_depth() -> ColorDepth {
+    let is_terminal = std::io::stdout().is_terminal();
+    match detect_color_depth_with(var, is_terminal) {
+        ColorDepth::NoColor => ColorDepth::NoColor,
+        _ if is_terminal && crate::enable_ansi_support().is_err() => ColorDepth::NoColor,
+        depth => depth,
+    }
+}
+
+/// [`detect_color_depth`], reading variables through `var`.
+pub(crate) fn detect_color_depth_with(
+    var: impl Fn(&str) -> Option<String>,
+    is_terminal: bool,
+) -> ColorDepth {
+    if var(NO_COLOR).is_some() {
+        return ColorDepth::NoColor;
+    }
+    let forced = var(CLICOLOR_FORCE).is_some_and(|value| value != "0");
+    let term = var(TERM).map(|term| term.to_ascii_lowercase());
+    if !forced && (!is_terminal || term.as_deref() == Some("dumb")) {
+        return ColorDepth::NoColor;
+    }
+
+    let colorterm = var(COLORTERM).map(|value| value.to_ascii_lowercase());
+    if matches!(colorterm.as_deref(), Some("truecolor" | "24bit")) || var(WT_SESSION).is_some() {
+        return ColorDepth::TrueColor;
+    }
+    match term.as_deref() {
+        Some(term) if term.ends_with("-direct") || term.contains("truecolor") => {
+            ColorDepth::TrueColor
+        }
+        Some(term) if term.contains("256color") => ColorDepth::Ansi256,
+        // Any terminal that supports color at all has the basic 16
+        _ => ColorDepth::Ansi16,
+    }
+}
+
+/// Whether `COLORFGBG` describes a dark background, if it is set.
+#[cfg(feature = "terminal-background")]
+pub(crate) fn colorfgbg_is_dark() -> Option<bool> {
+    var(COLORFGBG).and_then(|value| parse_colorfgbg(&value))
+}
+
+/// Whether the background in a `COLORFGBG` value is dark. The background is
+/// the last field (some terminals put `default` in between); the light gray
+/// and white palette entries, 7 and 9 to 15, are light.
+#[cfg(feature = "terminal-background")]
+pub(crate) fn parse_colorfgbg(value: &str) -> Option<bool> {
+    let background: u8 = value.rsplit(';').next()?.parse().ok()?;
+    Some(!matches!(background, 7 | 9..=15))
+}
+
+pub(crate) fn parse_color_depth(value: &str) -> Option<ColorDepth> {
+    match value.to_ascii_lowercase().as_str() {
+        "none" | "0" => Some(ColorDepth::NoColor),
+        "16" => Some(ColorDepth::Ansi16),
+        "256" => Some(ColorDepth::Ansi256),
+        "truecolor" | "24bit" => Some(ColorDepth::TrueColor),
+        _ => None,
+    }
+}
+
+/// The HTML format requested by `ARBORIUM_HTML_FORMAT`, if any.
+pub(crate) fn html_format() -> Option<HtmlFormat> {
+    match var(HTML_FORMAT)?.to_ascii_lowercase().as_str() {
+        "custom-elements" => Some(HtmlFormat::CustomElements),
+        "class-names" => Some(HtmlFormat::ClassNames),
+        "hljs" => Some(HtmlFormat::HljsClasses),
+        "prism" => Some(HtmlFormat::PrismClasses),
+        "pygments" => Some(HtmlFormat::PygmentsClasses),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_values() {
+        assert_eq!(parse_color_depth("256"), Some(ColorDepth::Ansi256));
+        assert_eq!(parse_color_depth("TrueColor"), Some(ColorDepth::TrueColor));
+        assert_eq!(parse_color_depth("lots"), None);
+    }
+
+    #[test]
+    fn test_detect_color_depth() {
+        let detect = |vars: &[(&str, &str)], is_terminal| {
+            detect_color_depth_with(
+                |name| {
+                    vars.iter()
+                        .find(|(key, _)| *key == name)
+                        .map(|(_, value)| value.to_string())
+                },
+                is_terminal,
+            )
+        };
+
+        let truecolor = [("TERM", "xterm-256color"), ("COLORTERM", "truecolor")];
+        assert_eq!(detect(&truecolor, true), ColorDepth::TrueColor);
+        assert_eq!(detect(&truecolor, false), ColorDepth::NoColor);
+        assert_eq!(
+            detect(&[("TERM", "xterm-256color")], true),
+            ColorDepth::Ansi256
+        );
+        assert_eq!(detect(&[("TERM", "xterm")], true), ColorDepth::Ansi16);
+        assert_eq!(detect(&[("TERM", "dumb")], true), ColorDepth::NoColor);
+        // Windows consoles set no TERM; Windows Terminal identifies itself
+        assert_eq!(detect(&[], true), ColorDepth::Ansi16);
+        assert_eq!(detect(&[("WT_SESSION", "1")], true), ColorDepth::TrueColor);
+        assert_eq!(
+            detect(&[("TERM", "xterm"), ("CLICOLOR_FORCE", "1")], false),
+            ColorDepth::Ansi16
+        );
+        assert_eq!(
+            detect(&[("TERM", "xterm"), ("CLICOLOR_FORCE", "0")], false),
+            ColorDepth::NoColor
+        );
+        assert_eq!(
+            detect(&[("NO_COLOR", "1"), ("CLICOLOR_FORCE", "1")], true),
+            ColorDepth::NoColor
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "terminal-background")]
+    fn test_parse_colorfgbg() {
+        assert_eq!(parse_colorfgbg("15;0"), Some(true));
+        assert_eq!(parse_colorfgbg("0;15"), Some(false));
+        assert_eq!(parse_colorfgbg("0;default;7"), Some(false));
+        assert_eq!(parse_colorfgbg("12;8"), Some(true));
+        assert_eq!(parse_colorfgbg("default"), None);
+    }
+}
@@ -40,6 +40,29 @@ pub enum Error {
         message: String,
     },
 
+    /// The input exceeded a size limit set in [`Config`](crate::Config).
+    ///
+    /// Only returned when the config's `oversize_policy` is
+    /// [`OversizePolicy::Error`](crate::OversizePolicy::Error).
+    InputTooLarge {
+        /// The language being highlighted.
+        language: String,
+        /// The input size in bytes, or the length of the longest line if
+        /// the line length limit was exceeded.
+        size: usize,
+        /// The limit that was exceeded.
+        limit: usize,
+    },
+
+    /// Highlighting was aborted before it finished.
+    ///
+    /// Either the flag passed to `highlight_with_cancel` was set, or the
+    /// [`Config::timeout`](crate::Config::timeout) budget ran out.
+    Cancelled {
+        /// The language being parsed when highlighting stopped.
+        language: String,
+    },
+
     /// An I/O error occurred during highlighting.
     ///
     /// This typically happens when writing to a `Write` destination fails.
@@ -58,6 +81,20 @@ impl fmt::Display for Error {
             Error::QueryError { language, message } => {
                 write!(f, "query error for {}: {}", language, message)
             }
+            Error::InputTooLarge {
+                language,
+                size,
+                limit,
+            } => {
+                write!(
+                    f,
+                    "input too large for {}: {} bytes exceeds limit of {}",
+                    language, size, limit
+                )
+            }
+            Error::Cancelled { language } => {
+                write!(f, "highlighting cancelled for {}", language)
+            }
             Error::Io(e) => write!(f, "I/O error: {}", e),
         }
     }
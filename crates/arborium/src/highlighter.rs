@@ -7,9 +7,11 @@
 //!
 //! # Thread Safety
 //!
-//! Grammars are compiled once and shared via `Arc<GrammarStore>`. Each highlighter
-//! has its own parse context (cheap to create). Use [`Highlighter::fork`] to create
-//! a new highlighter that shares the grammar store.
+//! Both highlighters are `Send + Sync` and highlight through `&self`. Grammars are
+//! compiled once and shared via `Arc<GrammarStore>`; the only mutable state is the
+//! per-call parse context (cheap to create), which is checked out of a small pool
+//! for the duration of a call. A single highlighter can therefore be shared across
+//! threads directly, without a lock.
 //!
 //! # Example
 //!
@@ -17,24 +19,35 @@
 //! use arborium::Highlighter;
 //! use rayon::prelude::*;
 //!
-//! // Create initial highlighter
+//! // Create one highlighter...
 //! let hl = Highlighter::new();
 //!
-//! // Parallel highlighting - each thread gets its own forked highlighter
+//! // ...and use it from every thread
 //! let results: Vec<_> = code_blocks.par_iter().map(|code| {
-//!     let mut hl = hl.fork();
 //!     hl.highlight("rust", code)
 //! }).collect();
 //! ```
 
+use std::borrow::Cow;
 use std::io::Write;
-use std::sync::Arc;
-
-use arborium_highlight::tree_sitter::{CompiledGrammar, ParseContext};
-use arborium_highlight::{AnsiOptions, Span, spans_to_ansi_with_options, spans_to_html};
-use arborium_theme::Theme;
-
-use crate::Config;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use arborium_highlight::tree_sitter::{CompiledGrammar, GrammarConfig, ParseContext};
+use arborium_highlight::{
+    AnsiLine, AnsiOptions, HighlightEvent, HighlightedDocument, ParseResult, SourceMap, Span,
+    Token, diff_to_html, diff_to_side_by_side_html, render_block, spans_to_ansi_lines,
+    spans_to_ansi_with_options, spans_to_ansi_with_source_map, spans_to_document, spans_to_events,
+    spans_to_html_with_options, spans_to_html_with_source_map, spans_to_tokens,
+    write_spans_as_ansi_with_options, write_spans_as_html_with_options,
+};
+use arborium_theme::{Theme, builtin};
+use arborium_tree_sitter::Tree;
+
+use crate::config::{Config, Fallback};
+use crate::env;
 use crate::error::Error;
 use crate::store::GrammarStore;
 
@@ -46,22 +59,23 @@ use crate::store::GrammarStore;
 ///
 /// # Thread Safety
 ///
-/// The highlighter can be forked to create copies that share the grammar store
-/// but have independent parse contexts. This enables efficient parallel highlighting.
+/// The highlighter is `Send + Sync`, and highlighting only needs `&self`, so it
+/// can be shared across threads (e.g. in an `Arc` or a `static`) without a lock.
+/// Concurrent calls each use their own parse context.
 ///
 /// ```rust,ignore
-/// let hl = Highlighter::new();
+/// let hl = Arc::new(Highlighter::new());
 ///
-/// // Fork for another thread
-/// let hl2 = hl.fork();
-/// std::thread::spawn(move || {
-///     let mut hl = hl2;
-///     hl.highlight("rust", code)
-/// });
+/// let hl2 = hl.clone();
+/// std::thread::spawn(move || hl2.highlight("rust", code));
+/// hl.highlight("python", other_code)?;
 /// ```
+///
+/// [`fork`](Self::fork) creates a separate highlighter sharing the grammar store,
+/// which is useful for giving a thread its own configuration.
 pub struct Highlighter {
     store: Arc<GrammarStore>,
-    ctx: Option<ParseContext>,
+    contexts: ContextPool,
     config: Config,
 }
 
@@ -78,7 +92,7 @@ impl Clone for Highlighter {
     fn clone(&self) -> Self {
         Self {
             store: self.store.clone(),
-            ctx: None, // New context will be created on first use
+            contexts: ContextPool::default(),
             config: self.config.clone(),
         }
     }
@@ -88,10 +102,13 @@ impl Highlighter {
     /// Create a new highlighter with default configuration.
     ///
     /// Uses custom elements (`<a-k>`, `<a-f>`, etc.) for HTML output.
+    ///
+    /// The highlighter compiles grammars into its own store. To share compiled
+    /// grammars with other highlighters, use [`with_store`](Self::with_store).
     pub fn new() -> Self {
         Self {
             store: Arc::new(GrammarStore::new()),
-            ctx: None,
+            contexts: ContextPool::default(),
             config: Config::default(),
         }
     }
@@ -100,18 +117,37 @@ impl Highlighter {
     pub fn with_config(config: Config) -> Self {
         Self {
             store: Arc::new(GrammarStore::new()),
-            ctx: None,
+            contexts: ContextPool::default(),
             config,
         }
     }
 
+    /// Create a highlighter configured from environment variables.
+    ///
+    /// Reads `ARBORIUM_HTML_FORMAT` (`custom-elements`, `class-names`, `hljs`, `prism`,
+    /// or `pygments`).
+    /// Unset or unrecognized values keep the [`Config`] default.
+    pub fn from_env() -> Self {
+        let mut config = Config::default();
+        if let Some(format) = env::html_format() {
+            config.html_format = format;
+        }
+        Self::with_config(config)
+    }
+
     /// Create a new highlighter with a shared grammar store.
     ///
     /// Use this when you want multiple highlighters to share compiled grammars.
+    /// Pass [`GrammarStore::global`] to share them process-wide:
+    ///
+    /// ```rust,ignore
+    /// // Each request gets its own highlighter, but grammars compile once
+    /// let hl = Highlighter::with_store(GrammarStore::global());
+    /// ```
     pub fn with_store(store: Arc<GrammarStore>) -> Self {
         Self {
             store,
-            ctx: None,
+            contexts: ContextPool::default(),
             config: Config::default(),
         }
     }
@@ -120,19 +156,19 @@ impl Highlighter {
     pub fn with_store_and_config(store: Arc<GrammarStore>, config: Config) -> Self {
         Self {
             store,
-            ctx: None,
+            contexts: ContextPool::default(),
             config,
         }
     }
 
     /// Fork this highlighter, creating a new one that shares the grammar store.
     ///
-    /// The forked highlighter has its own parse context, making it safe to use
-    /// from another thread.
+    /// The forked highlighter has its own parse contexts and a copy of the
+    /// configuration.
     pub fn fork(&self) -> Self {
         Self {
             store: self.store.clone(),
-            ctx: None,
+            contexts: ContextPool::default(),
             config: self.config.clone(),
         }
     }
@@ -144,44 +180,499 @@ impl Highlighter {
         &self.store
     }
 
+    /// Register an external tree-sitter grammar under a language name.
+    ///
+    /// The grammar's queries are compiled immediately and inserted into the shared
+    /// [`GrammarStore`], so every highlighter sharing the store (including forks)
+    /// can use it. Injections that name this language will resolve to it as well.
+    ///
+    /// ```rust,ignore
+    /// use arborium::advanced::GrammarConfig;
+    ///
+    /// let hl = Highlighter::new();
+    /// hl.register_grammar("mylang", GrammarConfig {
+    ///     language: tree_sitter_mylang::LANGUAGE.into(),
+    ///     highlights_query: tree_sitter_mylang::HIGHLIGHTS_QUERY,
+    ///     injections_query: "",
+    ///     locals_query: "",
+    /// })?;
+    /// ```
+    pub fn register_grammar(&self, language: &str, config: GrammarConfig<'_>) -> Result<(), Error> {
+        let grammar = CompiledGrammar::new(config).map_err(|e| Error::QueryError {
+            language: language.to_string(),
+            message: e.to_string(),
+        })?;
+        self.store.register(language, Arc::new(grammar));
+        Ok(())
+    }
+
     /// Highlight source code and return HTML string.
     ///
     /// This automatically handles language injections (e.g., CSS/JS in HTML,
     /// SQL in Python strings, etc.).
-    pub fn highlight(&mut self, language: &str, source: &str) -> Result<String, Error> {
+    pub fn highlight(&self, language: &str, source: &str) -> Result<String, Error> {
+        let spans = self.highlight_spans(language, source)?;
+        Ok(render_html(&self.config, source, spans))
+    }
+
+    /// Highlight source code to HTML, with a map from the HTML back to the source.
+    ///
+    /// The HTML is the same as [`highlight`](Self::highlight)'s. Use the
+    /// [`SourceMap`] to turn a position in the rendered output, such as a
+    /// click, into a source offset.
+    ///
+    /// ```rust,ignore
+    /// let (html, map) = hl.highlight_with_source_map("rust", "fn main() {}")?;
+    /// let start = map.output_offset(3).unwrap();
+    /// assert!(html[start..].starts_with("main"));
+    /// ```
+    pub fn highlight_with_source_map(
+        &self,
+        language: &str,
+        source: &str,
+    ) -> Result<(String, SourceMap), Error> {
         let spans = self.highlight_spans(language, source)?;
-        Ok(spans_to_html(source, spans, &self.config.html_format))
+        Ok(spans_to_html_with_source_map(
+            source,
+            spans,
+            &self.config.html_format,
+            &self.config.html_options,
+        ))
+    }
+
+    /// Highlight source code and wrap it in a `<pre><code>` block.
+    ///
+    /// The `code` element gets a `language-{language}` class, the convention
+    /// used by Markdown renderers and client-side highlighters. For extra
+    /// attributes, use [`render_block`](crate::advanced::render_block) with
+    /// the output of [`highlight`](Self::highlight).
+    ///
+    /// ```rust,ignore
+    /// let block = hl.highlight_block("rust", "fn main() {}")?;
+    /// assert!(block.starts_with("<pre><code class=\"language-rust\">"));
+    /// ```
+    pub fn highlight_block(&self, language: &str, source: &str) -> Result<String, Error> {
+        let html = self.highlight(language, source)?;
+        Ok(render_block(language, &html, &[]))
+    }
+
+    /// Highlight two versions of a file and render their differences as HTML.
+    ///
+    /// Both sides are highlighted as `language` and compared line by line.
+    /// See [`diff_to_html`](crate::advanced::diff_to_html) for the markup:
+    /// each line carries `diff-added`, `diff-removed` or `diff-context`, a
+    /// gutter with both line numbers, and changed characters within modified
+    /// lines are wrapped in `diff-change`.
+    ///
+    /// ```rust,ignore
+    /// let html = hl.highlight_diff("rust", "let x = 1;", "let x = 2;")?;
+    /// assert!(html.contains("diff-removed"));
+    /// ```
+    pub fn highlight_diff(&self, language: &str, old: &str, new: &str) -> Result<String, Error> {
+        let old_spans = self.highlight_spans(language, old)?;
+        let new_spans = self.highlight_spans(language, new)?;
+        Ok(diff_to_html(
+            old,
+            old_spans,
+            new,
+            new_spans,
+            &self.config.html_format,
+        ))
+    }
+
+    /// Like [`highlight_diff`](Self::highlight_diff), but renders the two
+    /// versions side by side in a `<table>`.
+    ///
+    /// Unchanged lines and lines that replace each other share a row; see
+    /// [`diff_to_side_by_side_html`](crate::advanced::diff_to_side_by_side_html)
+    /// for the markup.
+    pub fn highlight_diff_side_by_side(
+        &self,
+        language: &str,
+        old: &str,
+        new: &str,
+    ) -> Result<String, Error> {
+        let old_spans = self.highlight_spans(language, old)?;
+        let new_spans = self.highlight_spans(language, new)?;
+        Ok(diff_to_side_by_side_html(
+            old,
+            old_spans,
+            new,
+            new_spans,
+            &self.config.html_format,
+        ))
+    }
+
+    /// Highlight source code as the first supported language in `languages`.
+    ///
+    /// Useful when only some `lang-*` features are enabled: list the preferred
+    /// grammar first and more widely available ones after it. If none of them
+    /// is supported, fails with [`Error::UnsupportedLanguage`], or returns
+    /// plain text under [`Fallback::PlainText`].
+    ///
+    /// ```rust,ignore
+    /// let html = hl.highlight_with_fallback(&["tsx", "typescript", "javascript"], source)?;
+    /// ```
+    pub fn highlight_with_fallback(
+        &self,
+        languages: &[&str],
+        source: &str,
+    ) -> Result<String, Error> {
+        let language = languages
+            .iter()
+            .find(|language| self.store.get(language).is_some());
+        match (language, self.config.fallback) {
+            (Some(language), _) => self.highlight(language, source),
+            (None, Fallback::PlainText) => Ok(render_html(&self.config, source, Vec::new())),
+            (None, Fallback::Error) => Err(Error::UnsupportedLanguage {
+                language: languages.join(", "),
+            }),
+        }
+    }
+
+    /// Highlight source that may not be valid UTF-8.
+    ///
+    /// Invalid byte sequences are replaced with `U+FFFD REPLACEMENT CHARACTER`
+    /// before highlighting, as with [`String::from_utf8_lossy`], so mixed or
+    /// unknown encodings render instead of failing. Valid UTF-8 is not copied.
+    ///
+    /// ```rust,ignore
+    /// let bytes = std::fs::read("server.log")?;
+    /// let html = hl.highlight_bytes("log", &bytes)?;
+    /// ```
+    pub fn highlight_bytes(&self, language: &str, source: &[u8]) -> Result<String, Error> {
+        self.highlight(language, &String::from_utf8_lossy(source))
+    }
+
+    /// Highlight source code with a one-off configuration.
+    ///
+    /// `config` replaces this highlighter's own [`Config`] for this call only.
+    /// Compiled grammars and the parse context are reused, so this is much
+    /// cheaper than constructing a second highlighter.
+    ///
+    /// ```rust,ignore
+    /// use arborium::{Config, Highlighter, HtmlFormat};
+    ///
+    /// let hl = Highlighter::new();
+    /// let shallow = Config {
+    ///     max_injection_depth: 0,
+    ///     html_format: HtmlFormat::ClassNames,
+    ///     ..Default::default()
+    /// };
+    /// let html = hl.highlight_with("html", source, &shallow)?;
+    /// ```
+    pub fn highlight_with(
+        &self,
+        language: &str,
+        source: &str,
+        config: &Config,
+    ) -> Result<String, Error> {
+        let (result, _) =
+            Self::collect_spans(&self.store, &self.contexts, config, None, language, source)?;
+        Ok(render_html(config, source, result.spans))
+    }
+
+    /// Highlight source code, aborting if `cancel` is set to `true`.
+    ///
+    /// The flag is checked periodically while parsing, so another thread can
+    /// stop a runaway highlight. Returns [`Error::Cancelled`] if it was set.
+    /// For a fixed time budget, set [`Config::timeout`] instead (or as well).
+    ///
+    /// ```rust,ignore
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    ///
+    /// let cancel = AtomicBool::new(false);
+    /// // ...hand `&cancel` to a watchdog that stores `true` when needed...
+    /// match hl.highlight_with_cancel("rust", source, &cancel) {
+    ///     Err(arborium::Error::Cancelled { .. }) => { /* fall back to plain text */ }
+    ///     result => { /* ... */ }
+    /// }
+    /// ```
+    pub fn highlight_with_cancel(
+        &self,
+        language: &str,
+        source: &str,
+        cancel: &AtomicBool,
+    ) -> Result<String, Error> {
+        let (result, _) = Self::collect_spans(
+            &self.store,
+            &self.contexts,
+            &self.config,
+            Some(cancel),
+            language,
+            source,
+        )?;
+        Ok(render_html(&self.config, source, result.spans))
+    }
+
+    /// Highlight source code and report where it failed to parse.
+    ///
+    /// Tree-sitter recovers from syntax errors, so [`highlight`](Self::highlight)
+    /// succeeds on broken input. This returns the same HTML along with the
+    /// byte ranges of `ERROR` and `MISSING` nodes, including those inside
+    /// injected languages, so callers can warn about or underline invalid code.
+    ///
+    /// ```rust,ignore
+    /// let report = hl.highlight_with_report("rust", "fn main( {}")?;
+    /// if report.has_errors() {
+    ///     eprintln!("syntax errors at {:?}", report.error_ranges);
+    /// }
+    /// ```
+    pub fn highlight_with_report(
+        &self,
+        language: &str,
+        source: &str,
+    ) -> Result<HighlightReport, Error> {
+        let (result, _) = Self::collect_spans(
+            &self.store,
+            &self.contexts,
+            &self.config,
+            None,
+            language,
+            source,
+        )?;
+        Ok(HighlightReport {
+            html: render_html(&self.config, source, result.spans),
+            error_ranges: result
+                .errors
+                .into_iter()
+                .map(|range| range.start as usize..range.end as usize)
+                .collect(),
+        })
+    }
+
+    /// Highlight source code and also return its syntax tree.
+    ///
+    /// The tree is the one built for highlighting, so running additional
+    /// queries on it (e.g. to extract symbols) avoids parsing the source a
+    /// second time. Injected languages are parsed separately and their trees
+    /// are not included. The tree is `None` if the source was not parsed, such
+    /// as when it exceeds the size limits in [`Config`].
+    ///
+    /// ```rust,ignore
+    /// let (html, tree) = hl.highlight_with_tree("rust", source)?;
+    /// if let Some(tree) = tree {
+    ///     let mut cursor = arborium::tree_sitter::QueryCursor::new();
+    ///     // ...run a symbols query over tree.root_node()...
+    /// }
+    /// ```
+    pub fn highlight_with_tree(
+        &self,
+        language: &str,
+        source: &str,
+    ) -> Result<(String, Option<Tree>), Error> {
+        let (result, tree) = Self::collect_spans(
+            &self.store,
+            &self.contexts,
+            &self.config,
+            None,
+            language,
+            source,
+        )?;
+        let html = render_html(&self.config, source, result.spans);
+        Ok((html, tree))
     }
 
     /// Highlight source code and write HTML directly to a writer.
     ///
     /// More efficient than [`highlight`](Self::highlight) when writing to a file or socket,
-    /// as it avoids an intermediate string allocation.
+    /// as the HTML is streamed in chunks rather than built up in one string.
     pub fn highlight_to_writer<W: Write>(
-        &mut self,
+        &self,
         writer: &mut W,
         language: &str,
         source: &str,
     ) -> Result<(), Error> {
-        let html = self.highlight(language, source)?;
-        writer.write_all(html.as_bytes())?;
+        let spans = self.highlight_spans(language, source)?;
+        write_spans_as_html_with_options(
+            writer,
+            source,
+            spans,
+            &self.config.html_format,
+            &self.config.html_options,
+        )?;
         Ok(())
     }
 
+    /// Highlight source code and return it as lines of styled tokens.
+    ///
+    /// Use this for GUI and TUI rendering instead of parsing HTML output or
+    /// splitting raw spans by line yourself. Each token's `theme_index` can be
+    /// passed to [`Theme::style`] to get its colors.
+    ///
+    /// ```rust,ignore
+    /// let doc = hl.highlight_document("rust", "fn main() {}")?;
+    /// for line in &doc.lines {
+    ///     for token in line {
+    ///         let style = token.theme_index.and_then(|i| theme.style(i));
+    ///         draw(&token.text, style);
+    ///     }
+    /// }
+    /// ```
+    pub fn highlight_document(
+        &self,
+        language: &str,
+        source: &str,
+    ) -> Result<HighlightedDocument, Error> {
+        let spans = self.highlight_spans(language, source)?;
+        Ok(spans_to_document(language, source, spans))
+    }
+
+    /// Highlight source code as a sequence of tokens covering the whole input.
+    ///
+    /// Unlike raw spans, tokens never overlap and include the unhighlighted
+    /// text between them, so a custom renderer can simply walk them in order.
+    /// See [`Token`](crate::Token).
+    ///
+    /// ```rust,ignore
+    /// for token in hl.tokens("rust", source)? {
+    ///     match token.capture.as_deref() {
+    ///         Some(capture) => out.push_str(&styled(capture, token.text)),
+    ///         None => out.push_str(token.text),
+    ///     }
+    /// }
+    /// ```
+    pub fn tokens<'a>(
+        &self,
+        language: &str,
+        source: &'a str,
+    ) -> Result<impl Iterator<Item = Token<'a>> + use<'a>, Error> {
+        let spans = self.highlight_spans(language, source)?;
+        Ok(spans_to_tokens(source, spans).into_iter())
+    }
+
+    /// Highlight source code as a stream of `tree-sitter-highlight` style events.
+    ///
+    /// Eases migrating renderers written against `tree_sitter_highlight`: each
+    /// [`Highlight`](crate::Highlight) is an index into
+    /// [`HIGHLIGHT_NAMES`](crate::HIGHLIGHT_NAMES), and starts and ends are
+    /// always balanced. Unlike `tree_sitter_highlight`, errors are reported
+    /// up front rather than per event.
+    ///
+    /// ```rust,ignore
+    /// use arborium::HighlightEvent;
+    ///
+    /// for event in hl.highlight_events("rust", source)? {
+    ///     match event {
+    ///         HighlightEvent::Source { start, end } => out.push_str(&source[start..end]),
+    ///         HighlightEvent::HighlightStart(h) => out.push_str(&open_tag(h.0)),
+    ///         HighlightEvent::HighlightEnd => out.push_str("</span>"),
+    ///     }
+    /// }
+    /// ```
+    pub fn highlight_events(
+        &self,
+        language: &str,
+        source: &str,
+    ) -> Result<impl Iterator<Item = HighlightEvent> + use<>, Error> {
+        let spans = self.highlight_spans(language, source)?;
+        Ok(spans_to_events(source, spans).into_iter())
+    }
+
+    /// Highlight source code and return its spans as JSON.
+    ///
+    /// For applying styles on the client: the output is an array of
+    /// `{"start", "end", "capture", "slot"}` objects, where `start` and `end`
+    /// are byte offsets, `capture` is the raw capture name and `slot` is the
+    /// theme slot it maps to (e.g. `"keyword"`), or `null` if it is unstyled.
+    ///
+    /// ```rust,ignore
+    /// let json = hl.highlight_to_json("rust", "fn main() {}")?;
+    /// // [{"start":0,"end":2,"capture":"keyword","slot":"keyword"}, ...]
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn highlight_to_json(&self, language: &str, source: &str) -> Result<String, Error> {
+        #[derive(serde::Serialize)]
+        struct JsonSpan<'a> {
+            start: u32,
+            end: u32,
+            capture: &'a str,
+            slot: Option<&'static str>,
+        }
+
+        let spans = self.highlight_spans(language, source)?;
+        let json_spans: Vec<JsonSpan<'_>> = spans
+            .iter()
+            .map(|span| JsonSpan {
+                start: span.start,
+                end: span.end,
+                capture: &span.capture,
+                slot: arborium_theme::capture_to_slot(&span.capture).name(),
+            })
+            .collect();
+        Ok(serde_json::to_string(&json_spans).expect("spans always serialize"))
+    }
+
     /// Highlight and return raw spans (for custom rendering).
-    pub fn highlight_spans(&mut self, language: &str, source: &str) -> Result<Vec<Span>, Error> {
-        // Get the primary grammar
-        let grammar = self
-            .store
-            .get(language)
-            .ok_or_else(|| Error::UnsupportedLanguage {
-                language: language.to_string(),
-            })?;
+    pub fn highlight_spans(&self, language: &str, source: &str) -> Result<Vec<Span>, Error> {
+        let (result, _) = Self::collect_spans(
+            &self.store,
+            &self.contexts,
+            &self.config,
+            None,
+            language,
+            source,
+        )?;
+        Ok(result.spans)
+    }
 
-        // Ensure we have a parse context
-        self.ensure_context(&grammar)?;
-        let ctx = self.ctx.as_mut().unwrap();
+    /// Parse `source` and collect spans and error ranges, including those of
+    /// injections, using `config`.
+    ///
+    /// Injections are resolved, so the returned `injections` is always empty.
+    /// The tree is that of the primary language, or `None` if it wasn't parsed.
+    /// Takes the highlighter's fields separately so callers can pass either
+    /// `&self.config` or a per-call override.
+    fn collect_spans(
+        store: &GrammarStore,
+        contexts: &ContextPool,
+        config: &Config,
+        cancel: Option<&AtomicBool>,
+        language: &str,
+        source: &str,
+    ) -> Result<(ParseResult, Option<Tree>), Error> {
+        // Get the primary grammar; without one, fall back to plain text if allowed
+        let Some(grammar) = store.get(language) else {
+            return match config.fallback {
+                Fallback::Error => Err(Error::UnsupportedLanguage {
+                    language: language.to_string(),
+                }),
+                Fallback::PlainText => Ok((ParseResult::default(), None)),
+            };
+        };
+
+        // Oversized input is left unparsed (no spans renders as plain text)
+        if !config.check_input_size(language, source)? {
+            return Ok((ParseResult::default(), None));
+        }
+
+        // Check out a parse context for the duration of this call
+        let mut ctx = contexts.take(&grammar)?;
+        let result =
+            Self::collect_spans_in(store, &mut ctx, &grammar, config, cancel, language, source);
+        contexts.put(ctx);
+
+        let (mut result, tree) = result?;
+        if !config.capture_map.is_empty() {
+            for span in &mut result.spans {
+                if let Some(capture) = config.capture_map.canonical_capture(&span.capture) {
+                    span.capture = capture.to_string();
+                }
+            }
+        }
+        Ok((result, tree))
+    }
 
+    /// Parse with a checked-out context (see [`collect_spans`](Self::collect_spans)).
+    fn collect_spans_in(
+        store: &GrammarStore,
+        ctx: &mut ParseContext,
+        grammar: &CompiledGrammar,
+        config: &Config,
+        cancel: Option<&AtomicBool>,
+        language: &str,
+        source: &str,
+    ) -> Result<(ParseResult, Option<Tree>), Error> {
         // Set the language for this grammar
         ctx.set_language(grammar.language())
             .map_err(|_| Error::ParseError {
@@ -189,37 +680,161 @@ impl Highlighter {
                 message: "Failed to set parser language".to_string(),
             })?;
 
+        let mut collector = SpanCollector {
+            store,
+            ctx,
+            config,
+            cancellation: Cancellation {
+                flag: cancel,
+                deadline: deadline(config.timeout),
+            },
+        };
+
         // Parse the primary language
-        let result = grammar.parse(ctx, source);
+        let (result, tree) = collector.parse(grammar, language, source)?;
 
-        // Collect all spans (including from injections)
-        let mut all_spans = result.spans;
+        // Collect all spans and errors (including from injections)
+        let mut all = ParseResult {
+            spans: result.spans,
+            injections: Vec::new(),
+            errors: result.errors,
+        };
 
         // Process injections recursively
-        if self.config.max_injection_depth > 0 {
-            self.process_injections(
-                source,
-                result.injections,
-                0,
-                self.config.max_injection_depth,
-                &mut all_spans,
-            )?;
+        let depth = config
+            .injection_depth_override(language)
+            .unwrap_or(config.max_injection_depth);
+        if depth > 0 {
+            collector.process_injections(source, result.injections, 0, depth, &mut all)?;
         }
 
-        Ok(all_spans)
+        Ok((all, tree))
     }
+}
 
-    /// Ensure we have a parse context, creating one if needed.
-    fn ensure_context(&mut self, grammar: &CompiledGrammar) -> Result<(), Error> {
-        if self.ctx.is_none() {
-            self.ctx = Some(
-                ParseContext::for_grammar(grammar).map_err(|e| Error::ParseError {
-                    language: String::new(),
-                    message: e.to_string(),
-                })?,
-            );
+/// Render spans as HTML with `config`'s format and options.
+fn render_html(config: &Config, source: &str, spans: Vec<Span>) -> String {
+    spans_to_html_with_options(source, spans, &config.html_format, &config.html_options)
+}
+
+/// HTML output together with the parse errors found while highlighting.
+///
+/// Returned by [`Highlighter::highlight_with_report`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HighlightReport {
+    /// The highlighted HTML, identical to [`Highlighter::highlight`]'s output.
+    pub html: String,
+
+    /// Byte ranges in the source of `ERROR` and `MISSING` syntax nodes.
+    ///
+    /// `MISSING` nodes are zero-width: the range is empty and marks where the
+    /// parser expected a token. Errors inside injected languages are included.
+    pub error_ranges: Vec<Range<usize>>,
+}
+
+impl HighlightReport {
+    /// Whether the source contained any syntax errors.
+    pub fn has_errors(&self) -> bool {
+        !self.error_ranges.is_empty()
+    }
+}
+
+// Highlighters are shared across threads by reference.
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Highlighter>();
+    assert_send_sync::<AnsiHighlighter>();
+};
+
+/// Idle parse contexts, reused across calls.
+///
+/// A context is taken out for each highlight call and returned afterwards, so
+/// concurrent calls through `&Highlighter` never share parser state.
+#[derive(Default)]
+struct ContextPool(Mutex<Vec<ParseContext>>);
+
+impl ContextPool {
+    /// Take an idle context, or create one for `grammar` if none is available.
+    fn take(&self, grammar: &CompiledGrammar) -> Result<ParseContext, Error> {
+        if let Some(ctx) = self.0.lock().unwrap().pop() {
+            return Ok(ctx);
         }
-        Ok(())
+        ParseContext::for_grammar(grammar).map_err(|e| Error::ParseError {
+            language: String::new(),
+            message: e.to_string(),
+        })
+    }
+
+    /// Return a context to the pool.
+    fn put(&self, ctx: ParseContext) {
+        self.0.lock().unwrap().push(ctx);
+    }
+}
+
+/// When to abort a highlight in progress.
+#[derive(Clone, Copy)]
+struct Cancellation<'a> {
+    flag: Option<&'a AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl Cancellation<'_> {
+    fn is_enabled(&self) -> bool {
+        self.flag.is_some() || self.deadline.is_some()
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.flag.is_some_and(|flag| flag.load(Ordering::Relaxed))
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+/// The deadline for a highlight starting now with `timeout`, if set.
+///
+/// `wasm32-unknown-unknown` has no clock (`Instant::now` panics), so there
+/// the timeout is ignored and only the cancellation flag applies.
+fn deadline(timeout: Option<Duration>) -> Option<Instant> {
+    if cfg!(all(target_arch = "wasm32", target_os = "unknown")) {
+        return None;
+    }
+    timeout.map(|timeout| Instant::now() + timeout)
+}
+
+/// Per-call state for parsing a document and its injections.
+struct SpanCollector<'a> {
+    store: &'a GrammarStore,
+    ctx: &'a mut ParseContext,
+    config: &'a Config,
+    cancellation: Cancellation<'a>,
+}
+
+impl SpanCollector<'_> {
+    /// Parse `text`, honoring the cancellation flag and timeout if set.
+    ///
+    /// The tree is `None` only if tree-sitter failed without being cancelled.
+    fn parse(
+        &mut self,
+        grammar: &CompiledGrammar,
+        language: &str,
+        text: &str,
+    ) -> Result<(ParseResult, Option<Tree>), Error> {
+        if !self.cancellation.is_enabled() {
+            return Ok(grammar.parse_with_tree(self.ctx, text));
+        }
+
+        let cancellation = self.cancellation;
+        let cancelled = || Error::Cancelled {
+            language: language.to_string(),
+        };
+        if cancellation.is_cancelled() {
+            return Err(cancelled());
+        }
+        grammar
+            .parse_cancellable_with_tree(self.ctx, text, &mut || cancellation.is_cancelled())
+            .map(|(result, tree)| (result, Some(tree)))
+            .ok_or_else(cancelled)
     }
 
     /// Process injections recursively.
@@ -229,7 +844,7 @@ impl Highlighter {
         injections: Vec<arborium_highlight::Injection>,
         base_offset: u32,
         remaining_depth: u32,
-        all_spans: &mut Vec<Span>,
+        all: &mut ParseResult,
     ) -> Result<(), Error> {
         if remaining_depth == 0 {
             return Ok(());
@@ -245,36 +860,52 @@ impl Highlighter {
 
             let injected_source = &source[start..end];
 
+            // Map the language hint through the resolver, then apply allow/deny lists
+            let language = self.config.resolve_injection(&injection.language);
+            if !self.config.allows_injection(language) {
+                continue;
+            }
+
             // Try to get grammar for injected language
-            let Some(grammar) = self.store.get(&injection.language) else {
+            let Some(grammar) = self.store.get(language) else {
                 continue;
             };
 
             // Set language for this grammar
-            let ctx = self.ctx.as_mut().unwrap();
-            if ctx.set_language(grammar.language()).is_err() {
+            if self.ctx.set_language(grammar.language()).is_err() {
                 continue;
             }
 
             // Parse injected content
-            let result = grammar.parse(ctx, injected_source);
+            let (result, _) = self.parse(&grammar, language, injected_source)?;
 
-            // Offset spans to document coordinates
+            // Offset spans and errors to document coordinates
             let offset = base_offset + injection.start;
             for mut span in result.spans {
                 span.start += offset;
                 span.end += offset;
-                all_spans.push(span);
+                all.spans.push(span);
             }
+            all.errors.extend(
+                result
+                    .errors
+                    .into_iter()
+                    .map(|range| range.start + offset..range.end + offset),
+            );
 
-            // Recurse into nested injections
-            self.process_injections(
-                injected_source,
-                result.injections,
-                offset,
-                remaining_depth - 1,
-                all_spans,
-            )?;
+            // Recurse into nested injections. An override for this language sets
+            // the depth below it, unless the injection covers its whole parent:
+            // then it can only lower the depth, so a language injecting itself
+            // can't recurse forever.
+            let mut depth = remaining_depth - 1;
+            if let Some(limit) = self.config.injection_depth_override(language) {
+                depth = if injected_source.len() < source.len() {
+                    limit
+                } else {
+                    depth.min(limit)
+                };
+            }
+            self.process_injections(injected_source, result.injections, offset, depth, all)?;
         }
 
         Ok(())
@@ -333,7 +964,72 @@ impl AnsiHighlighter {
         }
     }
 
+    /// Create an ANSI highlighter configured from environment variables.
+    ///
+    /// | Variable | Values | Default |
+    /// |----------|--------|---------|
+    /// | `ARBORIUM_THEME` | built-in theme name, e.g. `dracula` or `catppuccin-latte` | Catppuccin Mocha |
+    /// | `ARBORIUM_COLOR` | `16`, `256`, `truecolor` (or `24bit`), `none` | `truecolor` |
+    /// | `NO_COLOR` | any non-empty value disables colors | unset |
+    ///
+    /// `NO_COLOR` takes precedence over `ARBORIUM_COLOR`. Unrecognized values
+    /// fall back to the default rather than failing.
+    pub fn from_env() -> Self {
+        let theme = env::theme().unwrap_or_else(builtin::catppuccin_mocha);
+        let mut options = AnsiOptions::default();
+        if let Some(depth) = env::color_depth() {
+            options.color_depth = depth;
+        }
+        Self::with_options(theme, Config::default(), options)
+    }
+
+    /// Create an ANSI highlighter whose color depth suits the terminal on stdout.
+    ///
+    /// Colors are disabled when `NO_COLOR` is set, or when stdout is not a
+    /// terminal (or `TERM` is `dumb`) and `CLICOLOR_FORCE` is not set to a
+    /// value other than `0`. Otherwise `COLORTERM=truecolor` (or `24bit`)
+    /// (or running in Windows Terminal) selects truecolor, a `TERM` containing
+    /// `256color` selects 256 colors, and anything else the 16 standard colors.
+    ///
+    /// On Windows this also enables escape sequence processing for the console
+    /// (see [`enable_ansi_support`](crate::enable_ansi_support)), and disables
+    /// colors on legacy consoles that don't support it.
+    ///
+    /// `ARBORIUM_THEME` and `ARBORIUM_COLOR` are honored as in
+    /// [`from_env`](Self::from_env), with `ARBORIUM_COLOR` overriding the
+    /// detected depth.
+    pub fn auto() -> Self {
+        let theme = env::theme().unwrap_or_else(builtin::catppuccin_mocha);
+        let options = AnsiOptions {
+            color_depth: env::color_depth().unwrap_or_else(env::detect_color_depth),
+            ..AnsiOptions::default()
+        };
+        Self::with_options(theme, Config::default(), options)
+    }
+
+    /// Like [`auto`](Self::auto), but with the variant of `pair` that suits
+    /// the terminal's background, as detected by
+    /// [`terminal_theme`](crate::terminal_theme). `ARBORIUM_THEME` still
+    /// takes precedence, and the terminal isn't queried when colors are off.
+    ///
+    /// This method is only available when the `terminal-background` feature
+    /// is enabled.
+    #[cfg(feature = "terminal-background")]
+    pub fn auto_with_theme_pair(pair: &arborium_theme::ThemePair) -> Self {
+        let mut highlighter = Self::auto();
+        if !matches!(
+            highlighter.options.color_depth,
+            arborium_highlight::ColorDepth::NoColor
+        ) && env::theme().is_none()
+        {
+            highlighter.theme = crate::terminal_theme(pair).clone();
+        }
+        highlighter
+    }
+
     /// Create a new ANSI highlighter with a shared grammar store.
+    ///
+    /// Pass [`GrammarStore::global`] to share compiled grammars process-wide.
     pub fn with_store(store: Arc<GrammarStore>, theme: Theme) -> Self {
         Self {
             inner: Highlighter::with_store(store),
@@ -356,6 +1052,13 @@ impl AnsiHighlighter {
         self.inner.store()
     }
 
+    /// Register an external tree-sitter grammar under a language name.
+    ///
+    /// See [`Highlighter::register_grammar`].
+    pub fn register_grammar(&self, language: &str, config: GrammarConfig<'_>) -> Result<(), Error> {
+        self.inner.register_grammar(language, config)
+    }
+
     /// Get a reference to the current theme.
     pub fn theme(&self) -> &Theme {
         &self.theme
@@ -366,6 +1069,21 @@ impl AnsiHighlighter {
         self.theme = theme;
     }
 
+    /// The theme with the [language overrides](Theme::for_language) for
+    /// `language`, which may be given by alias.
+    fn theme_for(&self, language: &str) -> Cow<'_, Theme> {
+        let normalized = GrammarStore::normalize_language(language);
+        match self
+            .theme
+            .languages
+            .keys()
+            .find(|name| GrammarStore::normalize_language(name) == normalized)
+        {
+            Some(name) => self.theme.for_language(name),
+            None => Cow::Borrowed(&self.theme),
+        }
+    }
+
     /// Get a reference to the current ANSI rendering options.
     pub fn options(&self) -> &AnsiOptions {
         &self.options
@@ -379,39 +1097,145 @@ impl AnsiHighlighter {
     /// Highlight source code and return ANSI-colored string.
     ///
     /// This automatically handles language injections.
-    pub fn highlight(&mut self, language: &str, source: &str) -> Result<String, Error> {
+    pub fn highlight(&self, language: &str, source: &str) -> Result<String, Error> {
         let spans = self.inner.highlight_spans(language, source)?;
         Ok(spans_to_ansi_with_options(
             source,
             spans,
-            &self.theme,
+            &self.theme_for(language),
+            &self.options,
+        ))
+    }
+
+    /// Highlight source code to ANSI, with a map from the output back to the source.
+    ///
+    /// See [`Highlighter::highlight_with_source_map`].
+    pub fn highlight_with_source_map(
+        &self,
+        language: &str,
+        source: &str,
+    ) -> Result<(String, SourceMap), Error> {
+        let spans = self.inner.highlight_spans(language, source)?;
+        Ok(spans_to_ansi_with_source_map(
+            source,
+            spans,
+            &self.theme_for(language),
             &self.options,
         ))
     }
 
+    /// Highlight source code to ANSI, split into self-contained rows.
+    ///
+    /// Every row starts by restoring its style and ends with a reset, so a
+    /// pager or TUI viewer can highlight once and then print any slice of rows
+    /// while scrolling. Each row also records which source line it shows.
+    ///
+    /// ```rust,ignore
+    /// let lines = hl.highlight_lines("rust", source)?;
+    /// for line in &lines[top..(top + height).min(lines.len())] {
+    ///     println!("{}", line.text);
+    /// }
+    /// ```
+    pub fn highlight_lines(&self, language: &str, source: &str) -> Result<Vec<AnsiLine>, Error> {
+        let spans = self.inner.highlight_spans(language, source)?;
+        Ok(spans_to_ansi_lines(
+            source,
+            spans,
+            &self.theme_for(language),
+            &self.options,
+        ))
+    }
+
+    /// Highlight source code, aborting if `cancel` is set to `true`.
+    ///
+    /// See [`Highlighter::highlight_with_cancel`].
+    pub fn highlight_with_cancel(
+        &self,
+        language: &str,
+        source: &str,
+        cancel: &AtomicBool,
+    ) -> Result<String, Error> {
+        let inner = &self.inner;
+        let (result, _) = Highlighter::collect_spans(
+            &inner.store,
+            &inner.contexts,
+            &inner.config,
+            Some(cancel),
+            language,
+            source,
+        )?;
+        Ok(spans_to_ansi_with_options(
+            source,
+            result.spans,
+            &self.theme_for(language),
+            &self.options,
+        ))
+    }
+
+    /// Highlight source code into [`anstyle`] styled segments.
+    ///
+    /// The segments cover the whole source; write them through `anstream` to
+    /// get its terminal capability and Windows console handling. Colors follow
+    /// [`AnsiOptions::color_depth`]; the other ANSI options don't apply.
+    ///
+    /// ```rust,ignore
+    /// use std::io::Write;
+    ///
+    /// let mut stdout = anstream::stdout();
+    /// for (style, text) in hl.highlight_anstyle("rust", "fn main() {}")? {
+    ///     write!(stdout, "{style}{text}{style:#}")?;
+    /// }
+    /// ```
+    #[cfg(feature = "anstyle")]
+    pub fn highlight_anstyle<'a>(
+        &self,
+        language: &str,
+        source: &'a str,
+    ) -> Result<Vec<(anstyle::Style, &'a str)>, Error> {
+        let spans = self.inner.highlight_spans(language, source)?;
+        Ok(arborium_highlight::spans_to_anstyle(
+            source,
+            spans,
+            &self.theme_for(language),
+            self.options.color_depth,
+        ))
+    }
+
     /// Highlight source code and write ANSI output directly to a writer.
+    ///
+    /// The colored text is streamed in chunks as it is rendered rather than
+    /// built up as a `String`, which suits large files piped to a pager. See
+    /// [`write_spans_as_ansi_with_options`](crate::advanced::write_spans_as_ansi_with_options).
     pub fn highlight_to_writer<W: Write>(
-        &mut self,
+        &self,
         writer: &mut W,
         language: &str,
         source: &str,
     ) -> Result<(), Error> {
-        let ansi = self.highlight(language, source)?;
-        writer.write_all(ansi.as_bytes())?;
+        let spans = self.inner.highlight_spans(language, source)?;
+        write_spans_as_ansi_with_options(
+            writer,
+            source,
+            spans,
+            &self.theme_for(language),
+            &self.options,
+        )?;
         Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     #[cfg(feature = "lang-rust")]
     fn test_highlighter_fork() {
         let hl = Highlighter::new();
 
         // Fork creates independent highlighters sharing the store
-        let mut hl1 = hl.fork();
-        let mut hl2 = hl.fork();
+        let hl1 = hl.fork();
+        let hl2 = hl.fork();
 
         // Both can highlight independently
         let html1 = hl1.highlight("rust", "fn main() {}").unwrap();
@@ -424,7 +1248,7 @@ mod tests {
     #[test]
     #[cfg(feature = "lang-commonlisp")]
     fn test_commonlisp_highlighting() {
-        let mut highlighter = Highlighter::new();
+        let highlighter = Highlighter::new();
         let html = highlighter
             .highlight("commonlisp", "(defun hello () (print \"Hello\"))")
             .unwrap();
@@ -435,7 +1259,7 @@ mod tests {
     #[cfg(feature = "lang-rust")]
     fn test_ansi_highlighting() {
         let theme = builtin::catppuccin_mocha().clone();
-        let mut highlighter = AnsiHighlighter::new(theme);
+        let highlighter = AnsiHighlighter::new(theme);
 
         let source = r#"
 fn main() {
@@ -467,7 +1291,7 @@ fn main() {
         options.padding_y = 1;
         options.border = true;
 
-        let mut highlighter = AnsiHighlighter::with_options(theme, config, options);
+        let highlighter = AnsiHighlighter::with_options(theme, config, options);
 
         let source = r#"fn fibonacci(n: u64) -> u64 {
     match n {
@@ -501,6 +1325,23 @@ fn main() {
         assert_ne!(output1, output2);
     }
 
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_language_style_overrides() {
+        use arborium_theme::{Color, Style, ThemeSlot, slot_to_highlight_index};
+
+        let keyword = slot_to_highlight_index(ThemeSlot::Keyword).unwrap();
+        let mut theme = builtin::catppuccin_mocha();
+        theme.set_language_style("rust", keyword, Style::new().fg(Color::new(1, 2, 3)));
+        let highlighter = AnsiHighlighter::new(theme);
+
+        // Matched by alias too
+        for language in ["rust", "rs"] {
+            let output = highlighter.highlight(language, "let x = 42;").unwrap();
+            assert!(output.contains("38;2;1;2;3"), "{output:?}");
+        }
+    }
+
     #[test]
     #[cfg(feature = "lang-rust")]
     fn test_shared_store() {
@@ -508,8 +1349,8 @@ fn main() {
         let store = Arc::new(GrammarStore::new());
 
         // Multiple highlighters sharing the store
-        let mut hl1 = Highlighter::with_store(store.clone());
-        let mut hl2 = Highlighter::with_store(store.clone());
+        let hl1 = Highlighter::with_store(store.clone());
+        let hl2 = Highlighter::with_store(store.clone());
 
         // Both use the same compiled grammar
         let _html1 = hl1.highlight("rust", "fn a() {}").unwrap();
@@ -519,6 +1360,337 @@ fn main() {
         assert!(store.get("rust").is_some());
     }
 
+    #[test]
+    fn test_global_store_is_shared() {
+        let hl1 = Highlighter::with_store(GrammarStore::global());
+        let hl2 = AnsiHighlighter::with_store(GrammarStore::global(), Theme::default());
+        assert!(Arc::ptr_eq(hl1.store(), hl2.store()));
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_global_store_compiles_once() {
+        let hl1 = Highlighter::with_store(GrammarStore::global());
+        let hl2 = Highlighter::with_store(GrammarStore::global());
+        hl1.highlight("rust", "fn a() {}").unwrap();
+        hl2.highlight("rust", "fn b() {}").unwrap();
+
+        // Both highlighters use the grammar compiled for the first
+        let grammar1 = hl1.store().get("rust").unwrap();
+        let grammar2 = hl2.store().get("rust").unwrap();
+        assert!(Arc::ptr_eq(&grammar1, &grammar2));
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_highlight_document() {
+        let hl = Highlighter::new();
+        let doc = hl
+            .highlight_document("rust", "fn main() {\n    let x = 1;\n}")
+            .unwrap();
+
+        assert_eq!(doc.language, "rust");
+        assert_eq!(doc.lines.len(), 3);
+        assert_eq!(doc.lines[0][0].text, "fn");
+        assert!(doc.lines[0][0].theme_index.is_some());
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_highlight_with_report() {
+        let hl = Highlighter::new();
+
+        let report = hl.highlight_with_report("rust", "fn main() {}").unwrap();
+        assert!(!report.has_errors());
+        assert_eq!(report.html, hl.highlight("rust", "fn main() {}").unwrap());
+
+        let source = "fn main() { let = ; }";
+        let report = hl.highlight_with_report("rust", source).unwrap();
+        assert!(report.has_errors());
+        for range in &report.error_ranges {
+            assert!(range.end <= source.len());
+        }
+    }
+
+    #[test]
+    fn test_highlight_with_fallback_unsupported() {
+        let hl = Highlighter::new();
+        let err = hl.highlight_with_fallback(&["no-such-lang", "nor-this"], "x");
+        assert!(matches!(
+            err,
+            Err(Error::UnsupportedLanguage { language }) if language == "no-such-lang, nor-this"
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_highlight_with_fallback() {
+        let hl = Highlighter::new();
+        let html = hl
+            .highlight_with_fallback(&["no-such-lang", "rust"], "fn main() {}")
+            .unwrap();
+        assert_eq!(html, hl.highlight("rust", "fn main() {}").unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_highlight_tree_matches_parse() {
+        let store = GrammarStore::new();
+        let grammar = store.get("rust").unwrap();
+        let source = "fn main() { let x = 1; }";
+
+        let mut parser = crate::tree_sitter::Parser::new();
+        parser.set_language(grammar.language()).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+
+        let mut ctx = ParseContext::for_grammar(&grammar).unwrap();
+        let parsed = grammar.parse(&mut ctx, source);
+        let from_tree = grammar.highlight_tree(&tree, source);
+        assert_eq!(from_tree.spans, parsed.spans);
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_highlight_with_tree() {
+        let hl = Highlighter::new();
+        let (html, tree) = hl.highlight_with_tree("rust", "fn main() {}").unwrap();
+        assert_eq!(html, hl.highlight("rust", "fn main() {}").unwrap());
+
+        let tree = tree.unwrap();
+        assert_eq!(tree.root_node().kind(), "source_file");
+        assert_eq!(tree.root_node().end_byte(), 12);
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_highlight_bytes_replaces_invalid_utf8() {
+        let hl = Highlighter::new();
+        let html = hl
+            .highlight_bytes("rust", b"fn main() { \"\xff\" }")
+            .unwrap();
+        assert!(html.contains("<a-k>fn</a-k>"));
+        assert!(html.contains('\u{FFFD}'));
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_highlight_events() {
+        let hl = Highlighter::new();
+        let source = "fn main() {}";
+        let events: Vec<_> = hl.highlight_events("rust", source).unwrap().collect();
+
+        assert!(matches!(events[0], HighlightEvent::HighlightStart(_)));
+        assert_eq!(events[1], HighlightEvent::Source { start: 0, end: 2 });
+        assert_eq!(events[2], HighlightEvent::HighlightEnd);
+    }
+
+    #[test]
+    #[cfg(all(feature = "json", feature = "lang-rust"))]
+    fn test_highlight_to_json() {
+        let hl = Highlighter::new();
+        let json = hl.highlight_to_json("rust", "fn main() {}").unwrap();
+        assert!(json.starts_with(r#"[{"start":0,"end":2,"capture":"keyword"#));
+        assert!(json.contains(r#""slot":"keyword""#));
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_register_grammar() {
+        let hl = Highlighter::new();
+        hl.register_grammar(
+            "my-rust",
+            GrammarConfig {
+                language: crate::lang_rust::language().into(),
+                highlights_query: &crate::lang_rust::HIGHLIGHTS_QUERY,
+                injections_query: crate::lang_rust::INJECTIONS_QUERY,
+                locals_query: crate::lang_rust::LOCALS_QUERY,
+            },
+        )
+        .unwrap();
+
+        // Forks share the store, so they see the registered grammar too
+        let fork = hl.fork();
+        let html = fork.highlight("my-rust", "fn main() {}").unwrap();
+        assert!(html.contains("<a-k>fn</a-k>"));
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_highlight_with_overrides_config() {
+        let hl = Highlighter::new();
+        let config = Config {
+            html_format: arborium_highlight::HtmlFormat::ClassNames,
+            ..Config::default()
+        };
+
+        let html = hl.highlight_with("rust", "fn main() {}", &config).unwrap();
+        assert!(html.contains("<span class="));
+
+        // The highlighter's own config is untouched
+        let html = hl.highlight("rust", "fn main() {}").unwrap();
+        assert!(html.contains("<a-"));
+    }
+
+    #[test]
+    fn test_plain_text_fallback() {
+        let hl = Highlighter::new();
+        assert!(matches!(
+            hl.highlight("no-such-lang", "a < b"),
+            Err(Error::UnsupportedLanguage { .. })
+        ));
+
+        let hl = Highlighter::with_config(Config {
+            fallback: Fallback::PlainText,
+            ..Config::default()
+        });
+        assert_eq!(hl.highlight("no-such-lang", "a < b").unwrap(), "a &lt; b");
+        assert_eq!(
+            hl.highlight_with_fallback(&["no-such-lang"], "a < b")
+                .unwrap(),
+            "a &lt; b"
+        );
+    }
+
+    #[test]
+    fn test_tokens_cover_plain_text() {
+        let hl = Highlighter::with_config(Config {
+            fallback: Fallback::PlainText,
+            ..Config::default()
+        });
+        let tokens: Vec<_> = hl.tokens("plain", "a < b").unwrap().collect();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].text, "a < b");
+        assert_eq!(tokens[0].capture, None);
+    }
+
+    #[test]
+    fn test_highlight_block() {
+        let hl = Highlighter::with_config(Config {
+            fallback: Fallback::PlainText,
+            ..Config::default()
+        });
+        assert_eq!(
+            hl.highlight_block("plain", "a < b").unwrap(),
+            "<pre><code class=\"language-plain\">a &lt; b</code></pre>"
+        );
+    }
+
+    #[test]
+    fn test_highlight_with_source_map() {
+        let hl = Highlighter::with_config(Config {
+            fallback: Fallback::PlainText,
+            ..Config::default()
+        });
+        let (html, map) = hl.highlight_with_source_map("plain", "a & b").unwrap();
+        assert_eq!(html, hl.highlight("plain", "a & b").unwrap());
+        assert_eq!(map.source_offset(html.find("b").unwrap()), Some(4));
+        assert_eq!(map.source_offset(html.find("&amp;").unwrap() + 1), Some(2));
+    }
+
+    #[test]
+    fn test_highlight_diff() {
+        let hl = Highlighter::with_config(Config {
+            fallback: Fallback::PlainText,
+            ..Config::default()
+        });
+        let html = hl
+            .highlight_diff("plain", "a\nb < c\n", "a\nb > c\n")
+            .unwrap();
+        assert_eq!(html.matches("diff-context").count(), 1);
+        assert!(html.contains("b <span class=\"diff-change\">&lt;</span> c"));
+        assert!(html.contains("b <span class=\"diff-change\">&gt;</span> c"));
+
+        let table = hl
+            .highlight_diff_side_by_side("plain", "a\nb < c\n", "a\nb > c\n")
+            .unwrap();
+        assert_eq!(table.matches("<tr>").count(), 2);
+    }
+
+    #[test]
+    #[cfg(all(feature = "lang-markdown", feature = "lang-rust"))]
+    fn test_zero_injection_depth_override() {
+        let source = "# Title\n\n```rust\nfn main() {}\n```\n";
+        let hl = Highlighter::with_config(Config::default().injection_depth_for("markdown", 0));
+        let html = hl.highlight("markdown", source).unwrap();
+        assert!(!html.contains("<a-k>fn</a-k>"));
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_oversized_input_falls_back_to_plain_text() {
+        let config = Config {
+            max_line_length: Some(4),
+            ..Config::default()
+        };
+        let hl = Highlighter::new();
+        let html = hl.highlight_with("rust", "fn main() {}", &config).unwrap();
+        assert_eq!(html, "fn main() {}");
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_highlight_with_cancel() {
+        let hl = Highlighter::new();
+
+        let cancel = AtomicBool::new(false);
+        assert!(
+            hl.highlight_with_cancel("rust", "fn main() {}", &cancel)
+                .is_ok()
+        );
+
+        cancel.store(true, Ordering::Relaxed);
+        let err = hl.highlight_with_cancel("rust", "fn main() {}", &cancel);
+        assert!(matches!(err, Err(Error::Cancelled { .. })));
+
+        // The parse context is still usable afterwards
+        let html = hl.highlight("rust", "fn main() {}").unwrap();
+        assert!(html.contains("<a-k>fn</a-k>"));
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_timeout() {
+        let config = Config {
+            timeout: Some(std::time::Duration::ZERO),
+            ..Config::default()
+        };
+        let hl = Highlighter::new();
+        let err = hl.highlight_with("rust", "fn main() {}", &config);
+        assert!(matches!(err, Err(Error::Cancelled { .. })));
+    }
+
+    #[test]
+    #[cfg(all(feature = "lang-markdown", feature = "lang-rust"))]
+    fn test_denied_injection_is_not_highlighted() {
+        let source = "# Title\n\n```rust\nfn main() {}\n```\n";
+        let config = Config {
+            denied_injection_languages: ["rust".to_string()].into(),
+            ..Config::default()
+        };
+
+        let hl = Highlighter::new();
+        let html = hl.highlight_with("markdown", source, &config).unwrap();
+        assert!(!html.contains("<a-k>fn</a-k>"));
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_shared_highlighter_across_threads() {
+        // One highlighter, used by reference from several threads at once
+        let hl = Highlighter::new();
+
+        std::thread::scope(|scope| {
+            for i in 0..4 {
+                let hl = &hl;
+                scope.spawn(move || {
+                    let code = format!("fn f{}() {{}}", i);
+                    let html = hl.highlight("rust", &code).unwrap();
+                    assert!(html.contains("<a-k>fn</a-k>"));
+                });
+            }
+        });
+    }
+
     #[test]
     #[cfg(feature = "lang-rust")]
     fn test_multithreaded_highlighting() {
@@ -533,7 +1705,7 @@ fn main() {
             .map(|i| {
                 let store = store.clone();
                 thread::spawn(move || {
-                    let mut hl = Highlighter::with_store(store);
+                    let hl = Highlighter::with_store(store);
                     let code = format!("fn thread{}() {{ let x = {}; }}", i, i * 10);
                     let html = hl.highlight("rust", &code).unwrap();
                     assert!(
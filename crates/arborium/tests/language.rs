@@ -16,3 +16,18 @@ fn get_unsupported() {
     // a fictional (and therefore unsupported) language named "bartholomew"
     assert!(arborium::get_language("bartholomew").is_none());
 }
+
+#[test]
+fn languages_lists_enabled_features() {
+    assert!(arborium::languages().any(|id| id == "rust"));
+    assert!(arborium::languages().all(|id| arborium::get_language(id).is_some()));
+}
+
+#[test]
+fn language_info_resolves_aliases() {
+    let info = arborium::language_info("rs").expect("rust should be enabled");
+    assert_eq!(info.id, "rust");
+    assert!(info.extensions.contains(&"rs"));
+    assert!(!info.license.is_empty());
+    assert!(arborium::language_info("bartholomew").is_none());
+}
@@ -19,10 +19,12 @@
 //!
 //! # Features
 //!
-//! - **Language detection**: Automatically detects language from file extension or accepts
-//!   an explicit language name
+//! - **Language detection**: Uses the language a source is tagged with, or detects it
+//!   from the file extension, or for anonymous snippets from the content (shebangs, XML
+//!   and HTML prologues, JSON)
 //! - **Full arborium language support**: Supports all languages enabled via Cargo features
 //!   (passthrough to arborium)
+//! - **Caching**: Sources shown by several snippets or reports are only highlighted once
 //! - **Minimal dependencies**: Uses arborium's tree-sitter-based highlighter
 //! - **ANSI terminal output**: Renders highlighted code with terminal colors
 //!
@@ -56,42 +58,201 @@
 //! }
 //! ```
 
-use std::sync::RwLock;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 
 use arborium::Highlighter;
-use arborium_highlight::{ThemedSpan, spans_to_themed};
-use arborium_theme::{Style as ThemeStyle, Theme};
+use arborium::advanced::ColorDepth;
+use arborium_highlight::StyledToken;
+use arborium_theme::{Color, Style as ThemeStyle, Theme};
 use miette::highlighters::Highlighter as MietteHighlighterTrait;
-use owo_colors::Style;
+use owo_colors::{AnsiColors, DynColors, Style, XtermColors};
 
 /// A syntax highlighter for miette that uses arborium for tree-sitter based highlighting.
 ///
 /// This highlighter can be installed globally using [`install_global`] or used directly
 /// by setting it on miette's `GraphicalReportHandler`.
 pub struct MietteHighlighter {
-    inner: RwLock<Highlighter>,
+    inner: Highlighter,
     theme: Theme,
+    color_depth: ColorDepth,
+    /// Whether to highlight at all; shared with [`HighlightSwitch`]es.
+    enabled: Arc<AtomicBool>,
+    /// Adjusts the theme's styles per snippet; see
+    /// [`set_style_hook`](Self::set_style_hook).
+    style_hook: Option<Box<StyleHook>>,
+    /// Language names registered with [`alias`](Self::alias), by the hint or
+    /// extension they stand for.
+    aliases: HashMap<String, String>,
+    /// Recently highlighted sources, so that reports showing the same source
+    /// again don't parse it again.
+    cache: Mutex<SourceCache>,
 }
 
+/// A function that adjusts a style before it is applied; see
+/// [`MietteHighlighter::set_style_hook`].
+type StyleHook = dyn Fn(&Snippet<'_>, usize, &ThemeStyle) -> ThemeStyle + Send + Sync;
+
+/// The snippet a [style hook](MietteHighlighter::set_style_hook) is styling.
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct Snippet<'a> {
+    /// The name of the source, such as a file name, if it has one.
+    pub name: Option<&'a str>,
+    /// The language the snippet is highlighted as.
+    pub language: &'a str,
+}
+
+/// Turns highlighting by a [`MietteHighlighter`] on and off after it has been
+/// handed to miette.
+///
+/// Get one with [`MietteHighlighter::switch`]. Clones control the same
+/// highlighter.
+#[derive(Debug, Clone)]
+pub struct HighlightSwitch(Arc<AtomicBool>);
+
+impl HighlightSwitch {
+    /// Turn highlighting on or off, from the next snippet rendered.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.0.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Returns whether highlighting is on.
+    pub fn is_enabled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// How many highlighted sources a [`MietteHighlighter`] keeps by default.
+const DEFAULT_CACHE_CAPACITY: usize = 16;
+
 impl MietteHighlighter {
     /// Create a new miette highlighter with the default theme.
     pub fn new() -> Self {
-        Self::with_theme(arborium_theme::builtin::catppuccin_mocha().clone())
+        Self::from_parts(
+            Highlighter::new(),
+            arborium_theme::builtin::catppuccin_mocha(),
+        )
     }
 
     /// Create a new miette highlighter with a custom theme.
     pub fn with_theme(theme: Theme) -> Self {
+        Self::from_parts(Highlighter::new(), theme)
+    }
+
+    /// Create a new miette highlighter that highlights with `highlighter`,
+    /// with the default theme.
+    ///
+    /// Use this to share the grammars and [`Config`](arborium::Config) an
+    /// application has already set up, such as custom grammars registered
+    /// with its [`GrammarStore`](arborium::GrammarStore).
+    ///
+    /// ```rust,ignore
+    /// let core = arborium::Highlighter::with_config(config);
+    /// let mut highlighter = MietteHighlighter::with_highlighter(core.fork());
+    /// highlighter.set_theme(theme);
+    /// ```
+    pub fn with_highlighter(highlighter: Highlighter) -> Self {
+        Self::from_parts(highlighter, arborium_theme::builtin::catppuccin_mocha())
+    }
+
+    /// The constructor every other one goes through.
+    fn from_parts(highlighter: Highlighter, theme: Theme) -> Self {
         Self {
-            inner: RwLock::new(Highlighter::new()),
+            inner: highlighter,
             theme,
+            color_depth: ColorDepth::TrueColor,
+            enabled: Arc::new(AtomicBool::new(true)),
+            style_hook: None,
+            aliases: HashMap::new(),
+            cache: Mutex::new(SourceCache::new(DEFAULT_CACHE_CAPACITY)),
         }
     }
 
+    /// Create a new miette highlighter with the variant of `pair` that suits
+    /// the terminal's background (see [`arborium::terminal_theme`]).
+    ///
+    /// This method is only available when the `terminal-background` feature
+    /// is enabled.
+    #[cfg(feature = "terminal-background")]
+    pub fn with_theme_pair(pair: &arborium_theme::ThemePair) -> Self {
+        Self::with_theme(arborium::terminal_theme(pair).clone())
+    }
+
     /// Returns whether a language is supported by this highlighter.
     pub fn is_supported(&self, language: &str) -> bool {
-        // Check if arborium has this language available
-        let mut inner = self.inner.write().unwrap();
-        inner.highlight_spans(language, "").is_ok()
+        // Ask the store directly: highlighting succeeds for any language
+        // when the highlighter falls back to plain text
+        self.inner.store().get(language).is_some()
+    }
+
+    /// Returns whether sources tagged with `language` are highlighted.
+    ///
+    /// Unlike [`is_supported`](Self::is_supported), this follows aliases
+    /// registered with [`alias`](Self::alias). Use it to decide whether to
+    /// tag a `NamedSource` with `.with_language(...)`.
+    pub fn supports(&self, language: &str) -> bool {
+        self.is_supported(self.resolve_alias(language))
+    }
+
+    /// The languages this highlighter can highlight, in alphabetical order:
+    /// those compiled in through `lang-*` features (see
+    /// [`arborium::languages`]), and aliases registered with
+    /// [`alias`](Self::alias) for a supported language.
+    ///
+    /// Grammars registered directly with the
+    /// [`GrammarStore`](arborium::GrammarStore) aren't listed, but
+    /// [`supports`](Self::supports) recognizes them.
+    pub fn languages(&self) -> Vec<&str> {
+        let mut languages: Vec<&str> = arborium::languages().collect();
+        languages.extend(
+            self.aliases
+                .iter()
+                .filter(|(_, language)| self.is_supported(language))
+                .map(|(alias, _)| alias.as_str()),
+        );
+        languages.sort_unstable();
+        languages.dedup();
+        languages
+    }
+
+    /// Highlight sources tagged with the language `alias`, or named with the
+    /// extension `alias`, as `language`.
+    ///
+    /// This maps an application's own names onto arborium's, for hints and
+    /// extensions arborium doesn't know:
+    ///
+    /// ```rust,ignore
+    /// let mut highlighter = MietteHighlighter::new();
+    /// highlighter.alias("proto", "textproto");
+    /// // Both of these are now highlighted as textproto
+    /// NamedSource::new("config.proto", text.clone());
+    /// NamedSource::new("config", text).with_language("proto");
+    /// ```
+    ///
+    /// Aliases match exactly and take precedence over arborium's own
+    /// names. Registering an alias again replaces it.
+    pub fn alias(&mut self, alias: &str, language: &str) {
+        self.aliases.insert(alias.to_string(), language.to_string());
+    }
+
+    /// The language a source tagged with `language` is highlighted as.
+    fn resolve_alias<'a>(&'a self, language: &'a str) -> &'a str {
+        self.aliases.get(language).map_or(language, String::as_str)
+    }
+
+    /// The language of a source named `name`: the one aliased to its
+    /// extension, or the one [`detect_language`](Self::detect_language)
+    /// finds.
+    fn language_for_name(&self, name: &str) -> Option<&str> {
+        let file_name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+        file_name
+            .rsplit_once('.')
+            .and_then(|(_, extension)| self.aliases.get(extension))
+            .map(String::as_str)
+            .or_else(|| Self::detect_language(name))
     }
 
     /// Detect language from a source name (file path or extension).
@@ -102,6 +263,37 @@ impl MietteHighlighter {
         arborium::detect_language(source_name)
     }
 
+    /// Guess the language of `source` from its content, for snippets that
+    /// have no file name.
+    ///
+    /// Recognizes shebang lines (`#!/usr/bin/env python3`), XML and HTML
+    /// prologues, `<?php`, diffs, and JSON objects and arrays. Returns `None`
+    /// when nothing gives the language away.
+    pub fn detect_language_from_content(source: &str) -> Option<&'static str> {
+        if let Some(shebang) = source.strip_prefix("#!") {
+            return shebang_language(shebang.lines().next().unwrap_or(""));
+        }
+
+        let text = source.trim_start_matches('\u{feff}').trim_start();
+        let starts_with_ignore_case = |prefix: &str| {
+            text.get(..prefix.len())
+                .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+        };
+        if text.starts_with("<?xml") {
+            Some("xml")
+        } else if text.starts_with("<?php") {
+            Some("php")
+        } else if starts_with_ignore_case("<!doctype html") || starts_with_ignore_case("<html") {
+            Some("html")
+        } else if text.starts_with("diff --git ") || text.starts_with("--- a/") {
+            Some("diff")
+        } else if looks_like_json(text) {
+            Some("json")
+        } else {
+            None
+        }
+    }
+
     /// Get a reference to the current theme.
     pub fn theme(&self) -> &Theme {
         &self.theme
@@ -111,6 +303,151 @@ impl MietteHighlighter {
     pub fn set_theme(&mut self, theme: Theme) {
         self.theme = theme;
     }
+
+    /// Get the palette theme colors are emitted in.
+    pub fn color_depth(&self) -> ColorDepth {
+        self.color_depth
+    }
+
+    /// Set the palette theme colors are emitted in (truecolor by default).
+    ///
+    /// Terminals without 24-bit color support show RGB colors wrongly or not
+    /// at all, so for them colors are quantized to the nearest entry of the
+    /// 256 or 16 color palette. With [`ColorDepth::NoColor`] only modifiers
+    /// such as bold and italic are emitted.
+    ///
+    /// To match what arborium detects for the terminal:
+    ///
+    /// ```rust,ignore
+    /// let depth = arborium::AnsiHighlighter::auto().options().color_depth;
+    /// highlighter.set_color_depth(depth);
+    /// ```
+    pub fn set_color_depth(&mut self, depth: ColorDepth) {
+        self.color_depth = depth;
+    }
+
+    /// Turn highlighting on or off (it is on by default).
+    ///
+    /// While off, snippets are rendered as plain text without being parsed,
+    /// and the theme, aliases and other settings are kept for when it is
+    /// turned back on. This suits flags like `--no-syntax-highlight`.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Returns whether highlighting is on.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Get a switch that turns this highlighter on and off, for use after
+    /// it has been moved into miette's report handler.
+    ///
+    /// ```rust,ignore
+    /// let highlighter = MietteHighlighter::new();
+    /// let switch = highlighter.switch();
+    /// miette::set_hook(Box::new(move |_| {
+    ///     Box::new(MietteHandlerOpts::new().with_syntax_highlighting(highlighter).build())
+    /// }))?;
+    ///
+    /// // Later, e.g. when a `--no-syntax-highlight` flag is parsed
+    /// switch.set_enabled(false);
+    /// ```
+    pub fn switch(&self) -> HighlightSwitch {
+        HighlightSwitch(self.enabled.clone())
+    }
+
+    /// Adjust styles per snippet with `hook`, replacing any previous hook.
+    ///
+    /// The hook is called with the snippet, the index of the highlight
+    /// category in the theme (as for [`Theme::style`]) and the theme's style
+    /// for it, and returns the style to use instead. Categories the theme
+    /// doesn't style are passed an empty style.
+    ///
+    /// miette hands highlighters the source of a snippet but not the
+    /// diagnostic it belongs to, so hooks tell snippets apart by their name
+    /// and language. A diagnostic can name its sources to be styled
+    /// differently:
+    ///
+    /// ```rust,ignore
+    /// let found = arborium_theme::builtin::github_dark();
+    /// highlighter.set_style_hook(move |snippet, index, style| {
+    ///     match snippet.name {
+    ///         // `NamedSource::new("found", ...)` uses another theme
+    ///         Some("found") => found.style(index).cloned().unwrap_or_default(),
+    ///         _ => style.clone(),
+    ///     }
+    /// });
+    /// ```
+    pub fn set_style_hook(
+        &mut self,
+        hook: impl Fn(&Snippet<'_>, usize, &ThemeStyle) -> ThemeStyle + Send + Sync + 'static,
+    ) {
+        self.style_hook = Some(Box::new(hook));
+    }
+
+    /// Remove the hook set with [`set_style_hook`](Self::set_style_hook).
+    pub fn clear_style_hook(&mut self) {
+        self.style_hook = None;
+    }
+
+    /// Get a reference to the arborium highlighter used for parsing.
+    pub fn highlighter(&self) -> &Highlighter {
+        &self.inner
+    }
+
+    /// Set how many highlighted sources to keep (16 by default).
+    ///
+    /// miette asks for a source to be highlighted once per snippet it shows,
+    /// so a report with many labels in one file, or many reports about the
+    /// same file, highlight the same text again and again. Sources are
+    /// remembered by name, language and a hash of their content, and the
+    /// least recently used are dropped first. Use 0 to turn caching off.
+    pub fn set_cache_capacity(&mut self, capacity: usize) {
+        self.cache
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .set_capacity(capacity);
+    }
+
+    /// Highlight `source` as `language`, split into lines of styled tokens,
+    /// reusing the lines from an earlier call for the same source.
+    fn highlight_lines(
+        &self,
+        name: Option<&str>,
+        language: &str,
+        source: &str,
+    ) -> Option<Arc<[Vec<StyledToken>]>> {
+        let mut hasher = DefaultHasher::new();
+        source.hash(&mut hasher);
+        let key = SourceKey {
+            name: name.map(str::to_string),
+            language: language.to_string(),
+            hash: hasher.finish(),
+        };
+
+        let cached = self.lock_cache().get(&key);
+        if cached.is_some() {
+            return cached;
+        }
+
+        // Highlight without holding the lock, so other threads rendering
+        // reports aren't held up
+        let lines: Arc<[_]> = self
+            .inner
+            .highlight_document(language, source)
+            .ok()?
+            .lines
+            .into();
+        self.lock_cache().insert(key, lines.clone());
+        Some(lines)
+    }
+
+    fn lock_cache(&self) -> MutexGuard<'_, SourceCache> {
+        // The cache is consistent between calls, so a panic elsewhere
+        // doesn't make it unusable
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
 }
 
 impl Default for MietteHighlighter {
@@ -124,146 +461,236 @@ impl MietteHighlighterTrait for MietteHighlighter {
         &'h self,
         source: &dyn miette::SpanContents<'_>,
     ) -> Box<dyn miette::highlighters::HighlighterState + 'h> {
-        // Try to detect language from source name
-        let language = source.name().and_then(Self::detect_language);
-
-        // Check if we support the language
-        let language = match language {
-            Some(lang) if self.is_supported(lang) => Some(lang),
-            _ => None,
-        };
+        if !self.is_enabled() {
+            return Box::new(MietteHighlighterState {
+                highlighter: self,
+                lines: Arc::default(),
+                next_line: 0,
+                snippet: None,
+            });
+        }
 
         // Get the full source text
         let source_text = std::str::from_utf8(source.data()).unwrap_or("").to_string();
 
-        // Highlight the entire source once to get themed spans
-        let themed_spans = if let Some(lang) = language {
-            let mut inner = self.inner.write().unwrap();
-            if let Ok(spans) = inner.highlight_spans(lang, &source_text) {
-                spans_to_themed(spans)
-            } else {
-                Vec::new()
-            }
-        } else {
-            Vec::new()
-        };
+        // Use the language the source was tagged with, or detect it from its
+        // name, or failing that from its content
+        let language = source
+            .language()
+            .map(|lang| self.resolve_alias(lang))
+            .filter(|lang| self.is_supported(lang))
+            .or_else(|| {
+                source
+                    .name()
+                    .and_then(|name| self.language_for_name(name))
+                    .filter(|lang| self.is_supported(lang))
+            })
+            .or_else(|| {
+                Self::detect_language_from_content(&source_text)
+                    .filter(|lang| self.is_supported(lang))
+            });
+
+        // Highlight the entire source once, split into lines of styled tokens
+        let lines = language
+            .and_then(|lang| self.highlight_lines(source.name(), lang, &source_text))
+            .unwrap_or_default();
+
+        // The style hook needs to know which snippet it is styling
+        let snippet = language
+            .filter(|_| self.style_hook.is_some())
+            .map(|lang| (source.name().map(str::to_string), lang.to_string()));
 
         Box::new(MietteHighlighterState {
             highlighter: self,
-            themed_spans,
-            line_start: 0,
+            lines,
+            next_line: 0,
+            snippet,
         })
     }
 }
 
 struct MietteHighlighterState<'h> {
     highlighter: &'h MietteHighlighter,
-    themed_spans: Vec<ThemedSpan>,
-    line_start: usize,
+    /// The highlighted lines of the source.
+    lines: Arc<[Vec<StyledToken>]>,
+    /// The index of the line miette will ask for next.
+    next_line: usize,
+    /// The source's name and language, when there is a style hook to pass
+    /// them to.
+    snippet: Option<(Option<String>, String)>,
 }
 
 impl miette::highlighters::HighlighterState for MietteHighlighterState<'_> {
     fn highlight_line<'s>(&mut self, line: &'s str) -> Vec<owo_colors::Styled<&'s str>> {
-        // Handle empty lines
-        if line.is_empty() {
-            self.line_start += 1;
-            return vec![Style::new().style(line)];
-        }
-
-        // If no themed spans, return unhighlighted
-        if self.themed_spans.is_empty() {
-            self.line_start += line.len() + 1;
-            return vec![Style::new().style(line)];
-        }
-
-        // Find where this line ends in the full source
-        let line_end = self.line_start + line.len();
-
-        // Collect spans that overlap with this line
-        let mut line_spans: Vec<&ThemedSpan> = self
-            .themed_spans
-            .iter()
-            .filter(|span| {
-                let span_start = span.start as usize;
-                let span_end = span.end as usize;
-                // Span overlaps if it starts before line ends and ends after line starts
-                span_start < line_end && span_end > self.line_start
-            })
-            .collect();
+        let tokens = self
+            .lines
+            .get(self.next_line)
+            .map_or(&[][..], Vec::as_slice);
+        self.next_line += 1;
+
+        // Style each token, slicing it out of miette's line so the output
+        // borrows from it
+        let mut result = Vec::new();
+        let mut pos = 0;
+        for token in tokens {
+            let end = pos + token.text.len();
 
-        // Sort by start position
-        line_spans.sort_by_key(|span| span.start);
+            // Stop if the line doesn't match what was highlighted
+            if line.get(pos..end) != Some(token.text.as_str()) {
+                break;
+            }
 
-        // Build styled spans for this line
-        let mut result = Vec::new();
-        let mut current_pos = 0;
+            let style = token
+                .theme_index
+                .and_then(|index| self.style(index))
+                .unwrap_or_else(Style::new);
+            result.push(style.style(&line[pos..end]));
+            pos = end;
+        }
 
-        for span in line_spans {
-            let span_start = span.start as usize;
-            let span_end = span.end as usize;
+        // Add any remaining unhighlighted text (or the whole line)
+        if pos < line.len() || result.is_empty() {
+            result.push(Style::new().style(&line[pos..]));
+        }
 
-            // Calculate positions relative to this line
-            let rel_start = span_start.saturating_sub(self.line_start);
-            let rel_end = (span_end - self.line_start).min(line.len());
+        result
+    }
+}
 
-            // Skip spans we've already passed
-            if rel_end <= current_pos {
-                continue;
+impl MietteHighlighterState<'_> {
+    /// The style for the highlight category at `index`, after the style
+    /// hook has adjusted it.
+    fn style(&self, index: usize) -> Option<Style> {
+        let highlighter = self.highlighter;
+        let style = highlighter.theme.style(index);
+        match (&highlighter.style_hook, &self.snippet) {
+            (Some(hook), Some((name, language))) => {
+                let snippet = Snippet {
+                    name: name.as_deref(),
+                    language,
+                };
+                let style = hook(&snippet, index, style.unwrap_or(&ThemeStyle::new()));
+                Some(convert_theme_style_to_owo(&style, highlighter.color_depth))
             }
+            _ => style.map(|style| convert_theme_style_to_owo(style, highlighter.color_depth)),
+        }
+    }
+}
 
-            // Clip span start to current position (handle overlapping spans)
-            let rel_start = rel_start.max(current_pos);
+/// A least recently used cache of highlighted sources.
+struct SourceCache {
+    capacity: usize,
+    /// Least recently used first.
+    entries: VecDeque<(SourceKey, Arc<[Vec<StyledToken>]>)>,
+}
 
-            // Add unhighlighted text before this span
-            if current_pos < rel_start {
-                result.push(Style::new().style(&line[current_pos..rel_start]));
-                current_pos = rel_start;
-            }
+/// What a highlighted source is remembered by.
+#[derive(PartialEq, Eq)]
+struct SourceKey {
+    name: Option<String>,
+    language: String,
+    /// A hash of the source text.
+    hash: u64,
+}
 
-            // Add the highlighted span
-            if rel_start < rel_end && rel_end <= line.len() {
-                // Get style from theme
-                let style =
-                    if let Some(theme_style) = self.highlighter.theme.style(span.theme_index) {
-                        convert_theme_style_to_owo(theme_style)
-                    } else {
-                        Style::new()
-                    };
-
-                result.push(style.style(&line[rel_start..rel_end]));
-                current_pos = rel_end;
-            }
+impl SourceCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
         }
+    }
+
+    fn get(&mut self, key: &SourceKey) -> Option<Arc<[Vec<StyledToken>]>> {
+        let index = self.entries.iter().position(|(k, _)| k == key)?;
+        let entry = self.entries.remove(index)?;
+        let lines = entry.1.clone();
+        self.entries.push_back(entry);
+        Some(lines)
+    }
 
-        // Add any remaining unhighlighted text
-        if current_pos < line.len() {
-            result.push(Style::new().style(&line[current_pos..]));
+    fn insert(&mut self, key: SourceKey, lines: Arc<[Vec<StyledToken>]>) {
+        if self.capacity == 0 || self.entries.iter().any(|(k, _)| *k == key) {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
         }
+        self.entries.push_back((key, lines));
+    }
 
-        // Update line_start for next line (account for newline character)
-        self.line_start = line_end + 1;
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        let excess = self.entries.len().saturating_sub(capacity);
+        self.entries.drain(..excess);
+    }
+}
 
-        // Return unhighlighted if we didn't produce any spans
-        if result.is_empty() {
-            vec![Style::new().style(line)]
-        } else {
-            result
-        }
+/// The language of the interpreter named on a shebang line (without `#!`).
+fn shebang_language(line: &str) -> Option<&'static str> {
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    // `#!/usr/bin/env [-S] python3`
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-'))?;
     }
+    // `python3.12` and `python3` are `python`
+    let program = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+
+    Some(match program {
+        "sh" | "bash" | "dash" | "ksh" => "bash",
+        "zsh" => "zsh",
+        "fish" => "fish",
+        "python" | "pypy" => "python",
+        "node" | "nodejs" => "javascript",
+        "deno" | "ts-node" => "typescript",
+        "ruby" => "ruby",
+        "perl" => "perl",
+        "php" => "php",
+        "lua" | "luajit" => "lua",
+        "Rscript" => "r",
+        "julia" => "julia",
+        "elixir" => "elixir",
+        "awk" | "gawk" => "awk",
+        "pwsh" => "powershell",
+        _ => return None,
+    })
 }
 
-/// Convert arborium's `ThemeStyle` to `owo_colors::Style`.
-fn convert_theme_style_to_owo(theme_style: &ThemeStyle) -> Style {
+/// Whether `text` starts like a JSON object or array. A `[` must be followed
+/// by a value, which tells arrays apart from TOML and INI section headers.
+fn looks_like_json(text: &str) -> bool {
+    let mut chars = text.chars();
+    let next_significant = |chars: &mut std::str::Chars<'_>| chars.find(|c| !c.is_whitespace());
+    match chars.next() {
+        Some('{') => matches!(next_significant(&mut chars), Some('"' | '}')),
+        Some('[') => matches!(
+            next_significant(&mut chars),
+            Some('{' | '[' | '"' | ']' | '-' | '0'..='9')
+        ),
+        _ => false,
+    }
+}
+
+/// Convert arborium's `ThemeStyle` to `owo_colors::Style`, with colors in
+/// the palette of `depth`.
+fn convert_theme_style_to_owo(theme_style: &ThemeStyle, depth: ColorDepth) -> Style {
     let mut style = Style::new();
 
     // Apply foreground color if present
-    if let Some(fg) = theme_style.fg {
-        style = style.truecolor(fg.r, fg.g, fg.b);
+    if let Some(fg) = theme_style
+        .fg
+        .and_then(|fg| convert_color_to_owo(fg, depth))
+    {
+        style = style.color(fg);
     }
 
     // Apply background color if present
-    if let Some(bg) = theme_style.bg {
-        style = style.on_truecolor(bg.r, bg.g, bg.b);
+    if let Some(bg) = theme_style
+        .bg
+        .and_then(|bg| convert_color_to_owo(bg, depth))
+    {
+        style = style.on_color(bg);
     }
 
     // Apply modifiers
@@ -283,6 +710,35 @@ fn convert_theme_style_to_owo(theme_style: &ThemeStyle) -> Style {
     style
 }
 
+/// Convert a theme color to the nearest owo-colors color in the palette of
+/// `depth`, or `None` if colors are off.
+fn convert_color_to_owo(color: Color, depth: ColorDepth) -> Option<DynColors> {
+    const ANSI16: [AnsiColors; 16] = [
+        AnsiColors::Black,
+        AnsiColors::Red,
+        AnsiColors::Green,
+        AnsiColors::Yellow,
+        AnsiColors::Blue,
+        AnsiColors::Magenta,
+        AnsiColors::Cyan,
+        AnsiColors::White,
+        AnsiColors::BrightBlack,
+        AnsiColors::BrightRed,
+        AnsiColors::BrightGreen,
+        AnsiColors::BrightYellow,
+        AnsiColors::BrightBlue,
+        AnsiColors::BrightMagenta,
+        AnsiColors::BrightCyan,
+        AnsiColors::BrightWhite,
+    ];
+    match depth {
+        ColorDepth::NoColor => None,
+        ColorDepth::Ansi16 => Some(DynColors::Ansi(ANSI16[color.to_ansi16() as usize])),
+        ColorDepth::Ansi256 => Some(DynColors::Xterm(XtermColors::from(color.to_ansi256()))),
+        ColorDepth::TrueColor => Some(DynColors::Rgb(color.r, color.g, color.b)),
+    }
+}
+
 /// Install the arborium highlighter as miette's global highlighter.
 ///
 /// This should be called once at the start of your program.
@@ -326,6 +782,32 @@ pub fn install_global_with_theme(theme: Theme) -> Result<(), miette::InstallErro
     }))
 }
 
+/// Install a highlighter as miette's global highlighter, with the light or
+/// dark theme of `pair` to suit the terminal's background.
+///
+/// The terminal is queried once, here, rather than for every report.
+///
+/// This function is only available when the `terminal-background` feature
+/// is enabled.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use arborium_theme::{ThemePair, builtin};
+///
+/// fn main() {
+///     let pair = ThemePair::new(builtin::github_light(), builtin::github_dark());
+///     miette_arborium::install_global_with_theme_pair(&pair).ok();
+///     // ... rest of your program ...
+/// }
+/// ```
+#[cfg(feature = "terminal-background")]
+pub fn install_global_with_theme_pair(
+    pair: &arborium_theme::ThemePair,
+) -> Result<(), miette::InstallError> {
+    install_global_with_theme(arborium::terminal_theme(pair).clone())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -345,14 +827,186 @@ mod tests {
     }
 
     #[test]
-    fn test_theme_style_conversion() {
-        use arborium_theme::Color;
+    fn test_with_highlighter_shares_store() {
+        let core = Highlighter::new();
+        let highlighter = MietteHighlighter::with_highlighter(core.fork());
+        assert!(std::sync::Arc::ptr_eq(
+            highlighter.highlighter().store(),
+            core.store()
+        ));
+    }
+
+    #[test]
+    fn test_aliases() {
+        let mut highlighter = MietteHighlighter::new();
+        highlighter.alias("proto", "textproto");
+        highlighter.alias("rs", "my-rust");
+
+        assert_eq!(highlighter.resolve_alias("proto"), "textproto");
+        assert_eq!(highlighter.resolve_alias("rust"), "rust");
+        assert_eq!(
+            highlighter.language_for_name("src/config.proto"),
+            Some("textproto")
+        );
+        // Aliased extensions take precedence over arborium's
+        assert_eq!(highlighter.language_for_name("main.rs"), Some("my-rust"));
+        assert_eq!(highlighter.language_for_name("proto/README"), None);
+    }
+
+    #[test]
+    fn test_style_hook() {
+        use miette::highlighters::HighlighterState;
+
+        let mut highlighter = MietteHighlighter::new();
+        highlighter.set_style_hook(|snippet, _, style| match snippet.name {
+            Some("found") => ThemeStyle::new().fg(Color::new(255, 0, 0)),
+            _ => style.clone(),
+        });
+
+        let token = |text: &str, start: u32| StyledToken {
+            text: text.to_string(),
+            start,
+            theme_index: Some(0),
+        };
+        let render = |name: &str| {
+            let mut state = MietteHighlighterState {
+                highlighter: &highlighter,
+                lines: vec![vec![token("fn", 0), token(" main", 2)]].into(),
+                next_line: 0,
+                snippet: Some((Some(name.to_string()), "rust".to_string())),
+            };
+            state
+                .highlight_line("fn main")
+                .iter()
+                .map(ToString::to_string)
+                .collect::<String>()
+        };
+
+        assert_eq!(render("found").matches("38;2;255;0;0").count(), 2);
+        assert!(!render("expected").contains("38;2;255;0;0"));
+    }
+
+    #[test]
+    fn test_switch() {
+        use miette::SourceCode;
+
+        let highlighter = MietteHighlighter::new();
+        let switch = highlighter.switch();
+        switch.set_enabled(false);
+        assert!(!highlighter.is_enabled());
+
+        // Nothing is parsed, and lines come back unstyled
+        let source = "{\"key\": 1}";
+        let contents = source.read_span(&(0..1).into(), 0, 0).unwrap();
+        let mut state = highlighter.start_highlighter_state(contents.as_ref());
+        let line = state.highlight_line(source);
+        assert_eq!(line.len(), 1);
+        assert_eq!(line[0].to_string(), source);
+
+        highlighter.set_enabled(true);
+        assert!(switch.is_enabled());
+    }
+
+    #[test]
+    fn test_supported_languages() {
+        let mut highlighter = MietteHighlighter::new();
+        highlighter.alias("not-a-language", "also-not-a-language");
+        assert!(!highlighter.supports("not-a-language"));
+        assert!(!highlighter.languages().contains(&"not-a-language"));
+
+        // Every compiled-in language is listed and supported
+        for language in arborium::languages() {
+            assert!(highlighter.supports(language), "{language}");
+            assert!(highlighter.languages().contains(&language));
+        }
+        if let Some(language) = arborium::languages().next() {
+            highlighter.alias("my-language", language);
+            assert!(highlighter.supports("my-language"));
+            assert!(highlighter.languages().contains(&"my-language"));
+        }
+    }
+
+    #[test]
+    fn test_supported_languages_with_plain_text_fallback() {
+        let core = Highlighter::with_config(arborium::Config {
+            fallback: arborium::Fallback::PlainText,
+            ..Default::default()
+        });
+        let highlighter = MietteHighlighter::with_highlighter(core);
+        assert!(!highlighter.is_supported("not-a-language"));
+        assert!(!highlighter.supports("not-a-language"));
+        for language in arborium::languages() {
+            assert!(highlighter.supports(language), "{language}");
+        }
+    }
+
+    #[test]
+    fn test_source_cache_evicts_least_recently_used() {
+        let key = |name: &str| SourceKey {
+            name: Some(name.to_string()),
+            language: "rust".to_string(),
+            hash: 0,
+        };
+        let lines = |n: usize| -> Arc<[Vec<StyledToken>]> { vec![Vec::new(); n].into() };
+
+        let mut cache = SourceCache::new(2);
+        cache.insert(key("a"), lines(1));
+        cache.insert(key("b"), lines(2));
+        assert_eq!(cache.get(&key("a")).map(|l| l.len()), Some(1));
+        // `b` is now the least recently used
+        cache.insert(key("c"), lines(3));
+        assert!(cache.get(&key("b")).is_none());
+        assert!(cache.get(&key("a")).is_some());
+        assert!(cache.get(&key("c")).is_some());
+
+        cache.set_capacity(0);
+        cache.insert(key("d"), lines(4));
+        assert!(cache.get(&key("a")).is_none());
+        assert!(cache.get(&key("d")).is_none());
+    }
+
+    #[test]
+    fn test_content_language_detection() {
+        let detect = MietteHighlighter::detect_language_from_content;
+        assert_eq!(detect("#!/usr/bin/env python3\nprint(1)"), Some("python"));
+        assert_eq!(detect("#!/usr/bin/env -S deno run\n"), Some("typescript"));
+        assert_eq!(detect("#!/bin/bash -e\necho hi"), Some("bash"));
+        assert_eq!(detect("#!/usr/bin/python3.12"), Some("python"));
+        assert_eq!(detect("#!/opt/unknown"), None);
+        assert_eq!(detect("<?xml version=\"1.0\"?>\n<a/>"), Some("xml"));
+        assert_eq!(detect("<!DOCTYPE html>\n<html>"), Some("html"));
+        assert_eq!(detect("  {\n  \"key\": 1\n}"), Some("json"));
+        assert_eq!(detect("[1, 2, 3]"), Some("json"));
+        assert_eq!(detect("[package]\nname = \"x\""), None);
+        assert_eq!(detect("{ foo }"), None);
+        assert_eq!(detect("diff --git a/x b/x\n"), Some("diff"));
+        assert_eq!(detect("fn main() {}"), None);
+    }
 
+    #[test]
+    fn test_theme_style_conversion() {
         let theme_style = ThemeStyle::new().fg(Color::new(255, 0, 0)).bold().italic();
 
-        let owo_style = convert_theme_style_to_owo(&theme_style);
+        let owo_style = convert_theme_style_to_owo(&theme_style, ColorDepth::TrueColor);
 
         // We can't directly test the style, but we can verify it doesn't panic
         let _styled = owo_style.style("test");
     }
+
+    #[test]
+    fn test_color_depths() {
+        let theme_style = ThemeStyle::new().fg(Color::new(255, 0, 0)).bold();
+        let render = |depth| {
+            convert_theme_style_to_owo(&theme_style, depth)
+                .style("x")
+                .to_string()
+        };
+
+        assert!(render(ColorDepth::TrueColor).contains("38;2;255;0;0"));
+        assert!(render(ColorDepth::Ansi256).contains("38;5;196"));
+        assert!(render(ColorDepth::Ansi16).starts_with("\x1b[91"));
+        // Modifiers survive without colors
+        let plain = render(ColorDepth::NoColor);
+        assert!(plain.contains("\x1b[1m") && !plain.contains("38;"));
+    }
 }
@@ -8,7 +8,7 @@ pub fn get_theme_css() -> String {
 #[wasm_bindgen]
 pub fn highlight_code(code: &str, language: &str) -> Result<String, JsValue> {
     // Create a highlighter instance
-    let mut highlighter = arborium::Highlighter::new();
+    let highlighter = arborium::Highlighter::new();
 
     // Try to highlight the code
     let result = highlighter
@@ -103,6 +103,23 @@ struct CorpusTestsTemplate<'a> {
     grammar_id: &'a str,
 }
 
+#[derive(TemplateSimple)]
+#[template(path = "fuzz_cargo.stpl.toml")]
+struct FuzzCargoTomlTemplate<'a> {
+    generated_disclaimer: &'a str,
+    crate_name: &'a str,
+    grammar_id: &'a str,
+    shared_rel: &'a str,
+}
+
+#[derive(TemplateSimple)]
+#[template(path = "fuzz_target.stpl.rs")]
+struct FuzzTargetTemplate<'a> {
+    generated_disclaimer: &'a str,
+    crate_name_snake: &'a str,
+    grammar_id: &'a str,
+}
+
 #[derive(TemplateSimple)]
 #[template(path = "readme.stpl.md")]
 struct ReadmeTemplate<'a> {
@@ -202,6 +219,20 @@ struct LanguageEntry {
     repo_url: String,
 }
 
+/// Per-language metadata for the `LanguageInfo` table in the umbrella lib.rs
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct LanguageInfoEntry {
+    id: String,
+    module: String,
+    name: String,
+    aliases: Vec<String>,
+    /// File extensions of the grammar's sample files, lowercased and deduplicated
+    extensions: Vec<String>,
+    license: String,
+    /// Upstream repository URL, `None` for grammars maintained in this repository
+    repo_url: Option<String>,
+}
+
 // Umbrella crate templates (arborium)
 #[derive(TemplateSimple)]
 #[template(path = "umbrella_lib.stpl.rs")]
@@ -214,6 +245,8 @@ struct UmbrellaLibRsTemplate<'a> {
     permissive_grammars: &'a [LanguageEntry],
     /// List of GPL-licensed grammars
     gpl_grammars: &'a [LanguageEntry],
+    /// Metadata for every public grammar, for the `language_info` lookup
+    language_infos: &'a [LanguageInfoEntry],
 }
 
 #[derive(TemplateSimple)]
@@ -775,6 +808,35 @@ fn generate_corpus_tests(crate_name: &str, grammar_id: &str) -> String {
         .expect("CorpusTestsTemplate render failed")
 }
 
+/// Generate fuzz/Cargo.toml content for a grammar crate.
+fn generate_fuzz_cargo_toml(crate_name: &str, grammar_id: &str) -> String {
+    // fuzz/ is at langs/group-*/lang/crate/fuzz/
+    // So: fuzz -> crate -> lang -> group-* -> langs -> repo-root -> crates
+    let shared_rel = "../../../../../crates";
+    let template = FuzzCargoTomlTemplate {
+        generated_disclaimer: &generated_disclaimer("fuzz_cargo.stpl.toml"),
+        crate_name,
+        grammar_id,
+        shared_rel,
+    };
+    template
+        .render_once()
+        .expect("FuzzCargoTomlTemplate render failed")
+}
+
+/// Generate the fuzz/fuzz_targets/<id>.rs cargo-fuzz target for a grammar crate.
+fn generate_fuzz_target(crate_name: &str, grammar_id: &str) -> String {
+    let crate_name_snake = crate_name.replace('-', "_");
+    let template = FuzzTargetTemplate {
+        generated_disclaimer: &generated_disclaimer("fuzz_target.stpl.rs"),
+        crate_name_snake: &crate_name_snake,
+        grammar_id,
+    };
+    template
+        .render_once()
+        .expect("FuzzTargetTemplate render failed")
+}
+
 /// Generate README.md content for a grammar crate.
 fn generate_readme(crate_name: &str, config: &crate::types::CrateConfig) -> String {
     let grammar = config.grammars.first();
@@ -1696,6 +1758,34 @@ fn plan_crate_files_only(
         )?;
     }
 
+    // A cargo-fuzz crate that parses and highlights arbitrary input, run with
+    // `cargo fuzz run <grammar id>` from the crate directory
+    if !tests_cursed {
+        let fuzz_dir = crate_path.join("fuzz");
+        let fuzz_targets_dir = fuzz_dir.join("fuzz_targets");
+        if !fuzz_targets_dir.exists() {
+            plan.add(Operation::CreateDir {
+                path: fuzz_targets_dir.clone(),
+                description: "Create fuzz/fuzz_targets directory".to_string(),
+            });
+        }
+
+        plan_file_update(
+            &mut plan,
+            &fuzz_dir.join("Cargo.toml"),
+            generate_fuzz_cargo_toml(&crate_state.name, grammar_id),
+            "fuzz/Cargo.toml",
+            mode,
+        )?;
+        plan_file_update(
+            &mut plan,
+            &fuzz_targets_dir.join(format!("{grammar_id}.rs")),
+            generate_fuzz_target(&crate_state.name, grammar_id),
+            &format!("fuzz/fuzz_targets/{grammar_id}.rs"),
+            mode,
+        )?;
+    }
+
     // Mirror hand-written grammar sources into the crate so that the published
     // package is self-contained for crates.io verification builds.
     //
@@ -2055,6 +2145,18 @@ include = [
 [features]
 default = []
 
+# Serialize/Deserialize for Config, HtmlFormat, AnsiOptions and Theme
+serde = ["dep:serde", "arborium-highlight/serde", "arborium-theme/serde"]
+
+# Serializable spans and Highlighter::highlight_to_json
+json = ["serde", "dep:serde_json"]
+
+# anstyle segments for CLIs using anstream (AnsiHighlighter::highlight_anstyle)
+anstyle = ["dep:anstyle", "arborium-highlight/anstyle"]
+
+# Light/dark theme selection from the terminal background (terminal_theme)
+terminal-background = ["dep:libc"]
+
 # All languages
 all-languages = [
 "#
@@ -2083,6 +2185,9 @@ all-languages = [
 arborium-tree-sitter = {{ version = "{version}", path = "../arborium-tree-sitter" }}
 arborium-theme = {{ version = "{version}", path = "../arborium-theme" }}
 arborium-highlight = {{ version = "{version}", path = "../arborium-highlight", features = ["tree-sitter"] }}
+serde = {{ version = "1", features = ["derive"], optional = true }}
+serde_json = {{ version = "1", optional = true }}
+anstyle = {{ version = "1", optional = true }}
 
 # Optional grammar dependencies
 "#
@@ -2105,6 +2210,10 @@ arborium-highlight = {{ version = "{version}", path = "../arborium-highlight", f
 [dev-dependencies]
 indoc = "2"
 
+# OSC 11 terminal queries (terminal-background feature)
+[target.'cfg(unix)'.dependencies]
+libc = { version = "0.2", optional = true }
+
 # WASM allocator (automatically enabled on wasm targets)
 [target.'cfg(target_family = "wasm")'.dependencies]
 dlmalloc = "0.2"
@@ -2159,8 +2268,9 @@ dlmalloc = "0.2"
     let mut aliases: Vec<(String, String)> = Vec::new();
     let mut extensions: Vec<(String, String)> = Vec::new();
     let mut languages: Vec<(String, String, String)> = Vec::new();
+    let mut language_infos: Vec<LanguageInfoEntry> = Vec::new();
 
-    for (_state, _config, grammar) in prepared.registry.all_grammars() {
+    for (_state, config, grammar) in prepared.registry.all_grammars() {
         let grammar_id = grammar.id().to_string();
 
         // Skip internal grammars
@@ -2184,12 +2294,37 @@ dlmalloc = "0.2"
                 extensions.push((alias.clone(), grammar_id.clone()));
             }
         }
+
+        // Collect metadata for language_info()
+        let repo = config.repo.value.as_str();
+        let mut sample_extensions: Vec<String> = grammar
+            .samples
+            .iter()
+            .filter_map(|sample| Utf8Path::new(&*sample.path).extension())
+            .map(str::to_lowercase)
+            .collect();
+        sample_extensions.sort();
+        sample_extensions.dedup();
+        language_infos.push(LanguageInfoEntry {
+            id: grammar_id.clone(),
+            module: format!("lang_{}", grammar_id.replace('-', "_")),
+            name: grammar.name.value.to_string(),
+            aliases: grammar
+                .aliases
+                .as_ref()
+                .map(|a| a.values.clone())
+                .unwrap_or_default(),
+            extensions: sample_extensions,
+            license: config.license.value.to_string(),
+            repo_url: (repo != "local").then(|| repo.to_string()),
+        });
     }
 
     // Sort for deterministic output
     aliases.sort();
     extensions.sort();
     languages.sort();
+    language_infos.sort();
 
     // =========================================================================
     // Collect all grammars and separate by license type (for lib.rs and README)
@@ -2247,6 +2382,7 @@ dlmalloc = "0.2"
         extensions: &extensions,
         permissive_grammars: &permissive_grammars,
         gpl_grammars: &gpl_grammars,
+        language_infos: &language_infos,
     }
     .render_once()
     .expect("UmbrellaLibRsTemplate render failed");
@@ -2519,6 +2655,76 @@ The harness validates that:
 - The grammar compiles correctly
 - Highlight queries are syntactically valid
 - Injection queries parse without errors
+- Locals queries compile and use only the standard `local.*` captures
+- Queries only refer to node types listed in `grammar/src/node-types.json`
+
+## Corpus tests
+
+`test_corpus_with_queries` runs the cases in `corpus/*.txt`:
+
+```text
+=== function keyword
+--- input
+fn main() {}
+--- contains
+function_item
+--- highlights
+fn -> keyword.function
+--- snapshot
+```
+
+A `snapshot` section compares the parse tree with a file under
+`corpus/snapshots/`. Run the tests with `UPDATE_SNAPSHOTS=1` to write or
+refresh the snapshots after a grammar update.
+
+A `locals` section checks the locals query the same way as `highlights`, with
+lines like `x -> local.definition.parameter`.
+
+Set `ARBORIUM_CORPUS_FILTER` to a glob to run only some cases: `strings/*`
+matches every case in `corpus/strings.txt`, and a pattern without `/` matches
+case names.
+
+Corpus files in the standard tree-sitter format (`====` headers, input, `---`,
+expected tree) are accepted too, so upstream corpora can be vendored unchanged.
+
+## Reports
+
+`try_test_grammar` and `try_test_corpus` run the same checks without
+panicking, and return a `TestReport` with a result (pass or failure, span
+count, timing) per sample or case, so a runner can report every failure at
+once.
+
+## Query coverage
+
+Run `test_grammar` with `QUERY_COVERAGE=1` (and `--nocapture`) to print which
+patterns and captures in `highlights.scm` no sample or corpus case matches.
+
+## Node types
+
+Run `test_grammar` with `CHECK_NODE_TYPES=1` to also check that the queries
+only refer to node types listed in `grammar/src/node-types.json`.
+
+## Timing budgets
+
+`bench_grammar` highlights each sample several times and fails if the fastest
+run takes longer than 25ms per KB (set `BENCH_BUDGET_MS_PER_KB` to change it),
+catching pathological queries before release. Generated grammar crates run it
+as an ignored test:
+
+```bash
+cargo test --release -- --ignored bench_grammar --nocapture
+```
+
+## Fuzzing
+
+`cargo xtask gen` writes a `cargo fuzz` target to each grammar crate's `fuzz/`
+directory. It feeds arbitrary input to `fuzz_grammar` and `fuzz_highlights`,
+which panic on inconsistent trees and out-of-range spans:
+
+```bash
+cd langs/group-acorn/css/crate
+cargo +nightly fuzz run css -- -timeout=5
+```
 "#
         }
         "arborium-tree-sitter" => {
@@ -629,6 +629,9 @@ struct ParsedTheme {
     source_url: Option<String>,
     background: Option<(u8, u8, u8)>,
     foreground: Option<(u8, u8, u8)>,
+    gutter: Option<(u8, u8, u8)>,
+    line_highlight: Option<(u8, u8, u8)>,
+    selection: Option<(u8, u8, u8)>,
     styles: Vec<ParsedStyle>,
 }
 
@@ -801,6 +804,25 @@ fn parse_theme(toml_str: &str) -> Result<ParsedTheme, String> {
         foreground = resolve_color(fg_str);
     }
 
+    // Editor colors, as top-level keys or Helix's ui.* keys
+    let ui_color = |helix_keys: &[&str], attr: &str, key: &str| -> Option<(u8, u8, u8)> {
+        if let Some(s) = table.get(key).and_then(|v| v.as_str()) {
+            return resolve_color(s);
+        }
+        match helix_keys.iter().find_map(|k| table.get(*k))? {
+            toml::Value::String(s) if attr == "fg" => resolve_color(s),
+            toml::Value::Table(t) => t.get(attr).and_then(|v| v.as_str()).and_then(resolve_color),
+            _ => None,
+        }
+    };
+    let gutter = ui_color(&["ui.linenr"], "fg", "gutter");
+    let line_highlight = ui_color(
+        &["ui.cursorline.primary", "ui.cursorline"],
+        "bg",
+        "line_highlight",
+    );
+    let selection = ui_color(&["ui.selection", "ui.selection.primary"], "bg", "selection");
+
     // Parse a style value (string or table)
     let parse_style_value = |value: &toml::Value| -> ParsedStyle {
         let mut style = ParsedStyle::default();
@@ -863,6 +885,9 @@ fn parse_theme(toml_str: &str) -> Result<ParsedTheme, String> {
         source_url,
         background,
         foreground,
+        gutter,
+        line_highlight,
+        selection,
         styles,
     })
 }
@@ -1007,6 +1032,19 @@ pub fn generate_theme_code(crates_dir: &Utf8Path) -> Result<(), String> {
             gen_color_option(&theme.foreground)
         )
         .unwrap();
+        writeln!(code, "        gutter: {},", gen_color_option(&theme.gutter)).unwrap();
+        writeln!(
+            code,
+            "        line_highlight: {},",
+            gen_color_option(&theme.line_highlight)
+        )
+        .unwrap();
+        writeln!(
+            code,
+            "        selection: {},",
+            gen_color_option(&theme.selection)
+        )
+        .unwrap();
 
         writeln!(code, "        styles: [").unwrap();
         for (i, style) in theme.styles.iter().enumerate() {
@@ -1014,6 +1052,7 @@ pub fn generate_theme_code(crates_dir: &Utf8Path) -> Result<(), String> {
             writeln!(code, "            {}{}", gen_style(style), trailing).unwrap();
         }
         writeln!(code, "        ],").unwrap();
+        writeln!(code, "        languages: Default::default(),").unwrap();
         writeln!(code, "    }}").unwrap();
         writeln!(code, "}}").unwrap();
         writeln!(code).unwrap();
@@ -1028,6 +1067,26 @@ pub fn generate_theme_code(crates_dir: &Utf8Path) -> Result<(), String> {
     }
     writeln!(code, "    ]").unwrap();
     writeln!(code, "}}").unwrap();
+    writeln!(code).unwrap();
+
+    // Generate the ID table used by the theme registry
+    writeln!(
+        code,
+        "/// Built-in themes by ID: the theme file's name, e.g. `catppuccin-mocha`."
+    )
+    .unwrap();
+    writeln!(code, "#[allow(clippy::type_complexity)]").unwrap();
+    writeln!(code, "pub const BY_ID: &[(&str, fn() -> Theme)] = &[").unwrap();
+    for def in &themes {
+        writeln!(
+            code,
+            "    ({:?}, {}),",
+            def.fn_name.replace('_', "-"),
+            def.fn_name
+        )
+        .unwrap();
+    }
+    writeln!(code, "];").unwrap();
 
     // Write the file
     fs::write(&output_path, &code).map_err(|e| format!("Failed to write output: {e}"))?;
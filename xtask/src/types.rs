@@ -490,6 +490,34 @@ pub struct SampleConfig {
     /// License of the sample file (may differ from the grammar license).
     #[facet(kdl::child, default)]
     pub license: Option<SampleLicense>,
+
+    /// Languages the sample must inject, checked by the generated tests.
+    #[facet(kdl::child, default, rename = "must-inject")]
+    pub must_inject: Option<MustInject>,
+
+    /// Texts the sample must highlight, checked by the generated tests.
+    #[facet(kdl::children, default)]
+    pub must_highlight: Vec<MustHighlight>,
+}
+
+/// A text a sample must highlight, e.g. `must-highlight "fn" capture="keyword"`.
+#[derive(Debug, Clone, Facet)]
+#[facet(kdl::child, rename = "must-highlight")]
+pub struct MustHighlight {
+    /// The highlighted text.
+    #[facet(kdl::argument)]
+    pub text: String,
+
+    /// The capture it must have, or one of its subcaptures.
+    #[facet(kdl::property, default)]
+    pub capture: Option<String>,
+}
+
+/// Must-inject child node (language IDs a sample's injections must include).
+#[derive(Debug, Clone, Facet)]
+pub struct MustInject {
+    #[facet(kdl::arguments)]
+    pub values: Vec<String>,
 }
 
 impl SampleConfig {
@@ -3,6 +3,7 @@
 #![cfg(test)]
 
 use std::path::Path;
+use std::sync::Arc;
 
 use <%= crate_name_snake %> as grammar;
 use libtest_mimic::{run, Arguments, Failed, Trial};
@@ -11,9 +12,33 @@ fn main() {
     let args = Arguments::from_args();
     let cases = arborium_test_harness::collect_corpus_cases(env!("CARGO_MANIFEST_DIR"))
         .expect("failed to collect corpus cases");
+    let compiled = Arc::new(
+        arborium_test_harness::compile_queries(
+            grammar::language(),
+            "<%= grammar_id %>",
+            &grammar::HIGHLIGHTS_QUERY,
+            grammar::INJECTIONS_QUERY,
+        )
+        .expect("failed to compile queries"),
+    );
+    let locals = Arc::new(
+        arborium_test_harness::compile_locals_query(
+            grammar::language(),
+            "<%= grammar_id %>",
+            grammar::LOCALS_QUERY,
+        )
+        .expect("failed to compile locals query"),
+    );
+
+    let filter = arborium_test_harness::corpus_filter();
 
     let tests: Vec<Trial> = cases
         .into_iter()
+        .filter(|case| {
+            filter
+                .as_deref()
+                .is_none_or(|filter| case.matches_filter(filter))
+        })
         .map(|case| {
             let relative = case
                 .file
@@ -21,12 +46,24 @@ fn main() {
                 .unwrap_or(case.file.as_path());
             let name = format!("{}::{}", relative.display(), case.name);
             let display = name.clone();
+            let compiled = compiled.clone();
+            let locals = locals.clone();
             Trial::test(name, move || {
-                let sexp = arborium_test_harness::run_corpus_case_with_tree(
+                let sexp = arborium_test_harness::run_corpus_case_with_grammar(
                     grammar::language(),
                     "<%= grammar_id %>",
+                    &compiled,
                     &case,
                 )
+                .and_then(|sexp| {
+                    arborium_test_harness::check_corpus_locals(
+                        grammar::language(),
+                        "<%= grammar_id %>",
+                        &locals,
+                        &case,
+                    )?;
+                    Ok(sexp)
+                })
                 .map_err(|err| Failed::from(err.to_string()))?;
                 println!("=== corpus::{display} ===\n{sexp}\n");
                 Ok(())
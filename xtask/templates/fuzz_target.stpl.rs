@@ -0,0 +1,24 @@
+//! <%= generated_disclaimer %>
+
+#![no_main]
+
+use std::sync::LazyLock;
+
+use <%= crate_name_snake %> as grammar;
+use arborium_test_harness::arborium_highlight::CompiledGrammar;
+use libfuzzer_sys::fuzz_target;
+
+static COMPILED: LazyLock<CompiledGrammar> = LazyLock::new(|| {
+    arborium_test_harness::compile_queries(
+        grammar::language(),
+        "<%= grammar_id %>",
+        &grammar::HIGHLIGHTS_QUERY,
+        grammar::INJECTIONS_QUERY,
+    )
+    .expect("failed to compile queries")
+});
+
+fuzz_target!(|data: &[u8]| {
+    arborium_test_harness::fuzz_grammar(grammar::language(), data);
+    arborium_test_harness::fuzz_highlights(&COMPILED, data);
+});
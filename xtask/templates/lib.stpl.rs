@@ -78,7 +78,35 @@ mod tests {
 
     #[test]
     fn test_corpus() {
-        arborium_test_harness::test_corpus(language(), "<%= grammar_id %>", env!("CARGO_MANIFEST_DIR"));
+        arborium_test_harness::test_corpus_with_queries(
+            language(),
+            "<%= grammar_id %>",
+<% if !highlights_prepend.is_empty() { %>
+            &HIGHLIGHTS_QUERY,
+<% } else { %>
+            HIGHLIGHTS_QUERY,
+<% } %>
+            INJECTIONS_QUERY,
+            LOCALS_QUERY,
+            env!("CARGO_MANIFEST_DIR"),
+        );
+    }
+
+    // Timing-sensitive, so opt-in: `cargo test --release -- --ignored`
+    #[test]
+    #[ignore]
+    fn bench_grammar() {
+        arborium_test_harness::bench_grammar(
+            language(),
+            "<%= grammar_id %>",
+<% if !highlights_prepend.is_empty() { %>
+            &HIGHLIGHTS_QUERY,
+<% } else { %>
+            HIGHLIGHTS_QUERY,
+<% } %>
+            INJECTIONS_QUERY,
+            env!("CARGO_MANIFEST_DIR"),
+        );
     }
 }
 <% } %>
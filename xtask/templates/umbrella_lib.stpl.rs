@@ -8,7 +8,7 @@
 //! ```rust,ignore
 //! use arborium::Highlighter;
 //!
-//! let mut hl = Highlighter::new();
+//! let hl = Highlighter::new();
 //! let html = hl.highlight("rust", "fn main() {}")?;
 //! // Output: <a-k>fn</a-k> <a-f>main</a-f>() {}
 //! ```
@@ -21,14 +21,14 @@
 //! use arborium::{Highlighter, Config, HtmlFormat};
 //!
 //! // Default: custom elements (<a-k>, <a-f>, etc.)
-//! let mut hl = Highlighter::new();
+//! let hl = Highlighter::new();
 //!
 //! // Or use class-based output for CSS compatibility
 //! let config = Config {
 //!     html_format: HtmlFormat::ClassNames,
 //!     ..Default::default()
 //! };
-//! let mut hl = Highlighter::with_config(config);
+//! let hl = Highlighter::with_config(config);
 //! ```
 //!
 //! Use [`AnsiHighlighter`] for terminal output:
@@ -38,7 +38,7 @@
 //! use arborium::theme::builtin;
 //!
 //! let theme = builtin::catppuccin_mocha().clone();
-//! let mut hl = AnsiHighlighter::new(theme);
+//! let hl = AnsiHighlighter::new(theme);
 //! let colored = hl.highlight("rust", "fn main() {}")?;
 //! println!("{}", colored);
 //! ```
@@ -79,12 +79,29 @@
 //! | <%= grammar.name %> | `<%= grammar.feature %>` | <%= grammar.license %> |
 <% } %>
 //!
+//! # Sharing Compiled Grammars
+//!
+//! Each highlighter created with [`Highlighter::new`] compiles grammars into
+//! its own store. To create many highlighters (e.g. one per request) without
+//! recompiling highlight queries, give them all the process-wide store:
+//!
+//! ```rust,ignore
+//! use arborium::{GrammarStore, Highlighter};
+//!
+//! let hl = Highlighter::with_store(GrammarStore::global());
+//! ```
+//!
 //! # Advanced Usage
 //!
 //! For building custom grammar providers or working with raw spans, see the
 //! [`advanced`] module.
 
 // Internal modules
+#[cfg(feature = "terminal-background")]
+mod background;
+mod config;
+mod console;
+mod env;
 mod error;
 mod highlighter;
 pub(crate) mod store;
@@ -95,53 +112,38 @@ pub mod advanced;
 /// Theme system for ANSI output.
 ///
 /// Re-exports types from `arborium-theme` for configuring syntax colors.
+/// Use [`registry`](theme::registry) to look themes up by name.
 pub mod theme {
-    pub use arborium_theme::theme::{builtin, Color, Modifiers, Style, Theme};
+    pub use arborium_theme::theme::{builtin, Color, Modifiers, Style, Theme, ThemePair};
+    pub use arborium_theme::{registry, slots, CaptureMap, SlotInfo, ThemeRegistry, ThemeSlot};
 }
 
 // Primary API exports
+pub use config::{Config, Fallback, InjectionResolver, OversizePolicy};
+pub use console::enable_ansi_support;
+#[cfg(feature = "terminal-background")]
+pub use background::{terminal_background, terminal_is_dark, terminal_theme};
 pub use error::Error;
-pub use highlighter::{AnsiHighlighter, Highlighter};
+pub use highlighter::{AnsiHighlighter, HighlightReport, Highlighter};
 pub use store::GrammarStore;
 
 // Configuration types (re-exported from arborium-highlight)
-pub use arborium_highlight::HtmlFormat;
+pub use arborium_highlight::{HtmlEscape, HtmlFormat, HtmlOptions};
 
-/// Configuration for highlighting.
-///
-/// Controls injection depth and HTML output format.
-#[derive(Debug, Clone)]
-pub struct Config {
-    /// Maximum depth for processing language injections.
-    ///
-    /// - `0`: No injections (just primary language)
-    /// - `3`: Default, handles most cases (HTML with CSS/JS, Markdown with code blocks)
-    /// - Higher: For deeply nested content
-    pub max_injection_depth: u32,
+// Structured output (see `Highlighter::highlight_document`)
+pub use arborium_highlight::{HighlightedDocument, StyledToken};
 
-    /// HTML output format.
-    ///
-    /// See [`HtmlFormat`] for options.
-    pub html_format: HtmlFormat,
-}
+// Self-contained ANSI rows (see `AnsiHighlighter::highlight_lines`)
+pub use arborium_highlight::AnsiLine;
 
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            max_injection_depth: 3,
-            html_format: HtmlFormat::default(),
-        }
-    }
-}
+// Flat tokens (see `Highlighter::tokens`)
+pub use arborium_highlight::Token;
 
-impl From<Config> for arborium_highlight::HighlightConfig {
-    fn from(config: Config) -> Self {
-        arborium_highlight::HighlightConfig {
-            max_injection_depth: config.max_injection_depth,
-            html_format: config.html_format,
-        }
-    }
-}
+// Output-to-source mapping (see `Highlighter::highlight_with_source_map`)
+pub use arborium_highlight::{SourceMap, SourceMapping};
+
+// Event stream (see `Highlighter::highlight_events`)
+pub use arborium_highlight::{Highlight, HighlightEvent};
 
 // Tree-sitter re-export for advanced users
 pub use arborium_tree_sitter as tree_sitter;
@@ -220,6 +222,95 @@ pub fn get_language(name: &str) -> Option<tree_sitter::Language> {
     }
 }
 
+/// Iterate over the IDs of all supported languages, in alphabetical order.
+///
+/// Only languages enabled via `lang-*` feature flags are listed, so every ID
+/// yielded here is accepted by [`get_language`] and [`Highlighter::highlight`].
+///
+/// # Example
+///
+/// ```rust,ignore
+/// for id in arborium::languages() {
+///     println!("{id}");
+/// }
+///
+/// assert!(arborium::languages().any(|id| id == "rust"));
+/// ```
+pub fn languages() -> impl Iterator<Item = &'static str> {
+    LANGUAGES.iter().copied()
+}
+
+/// Language IDs enabled via feature flags (see [`languages`]).
+const LANGUAGES: &[&str] = &[
+<% for (_, grammar_id) in grammars { %>
+    #[cfg(feature = "lang-<%= grammar_id %>")]
+    "<%= grammar_id %>",
+<% } %>
+];
+
+/// Metadata about a supported language.
+///
+/// Returned by [`language_info`]. All fields are derived from the grammar
+/// registry at generation time, so they never require loading the grammar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LanguageInfo {
+    /// Canonical language ID (e.g. `"rust"`), as accepted by [`Highlighter::highlight`].
+    pub id: &'static str,
+    /// Human-readable name (e.g. `"Rust"`).
+    pub name: &'static str,
+    /// Alternative names that resolve to this language (e.g. `"rs"`).
+    pub aliases: &'static [&'static str],
+    /// File extensions used for this language (e.g. `"rs"`), taken from the
+    /// grammar's sample files in the registry.
+    ///
+    /// [`detect_language`] matches the ID and aliases instead, so this may
+    /// list extensions it doesn't recognize.
+    pub extensions: &'static [&'static str],
+    /// URL of the upstream tree-sitter grammar, or `None` if the grammar is
+    /// maintained in the arborium repository.
+    pub upstream_repo: Option<&'static str>,
+    /// SPDX license identifier of the grammar (e.g. `"MIT"`).
+    pub license: &'static str,
+    /// Whether the grammar ships an injections query (embedded languages).
+    pub has_injections: bool,
+    /// Whether the grammar ships a locals query (scope tracking).
+    pub has_locals: bool,
+}
+
+/// Look up metadata for a language by ID or alias.
+///
+/// Returns `None` if the language is not recognized or not enabled via
+/// feature flags.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let info = arborium::language_info("rs").unwrap();
+/// assert_eq!(info.id, "rust");
+/// assert_eq!(info.license, "MIT");
+/// ```
+pub fn language_info(language: &str) -> Option<&'static LanguageInfo> {
+    let normalized = GrammarStore::normalize_language(language);
+    LANGUAGE_INFOS.iter().find(|info| info.id == normalized)
+}
+
+/// Metadata for languages enabled via feature flags (see [`language_info`]).
+const LANGUAGE_INFOS: &[LanguageInfo] = &[
+<% for info in language_infos { %>
+    #[cfg(feature = "lang-<%= info.id %>")]
+    LanguageInfo {
+        id: "<%= info.id %>",
+        name: <%- format!("{:?}", info.name) %>,
+        aliases: &[<% for alias in &info.aliases { %>"<%= alias %>", <% } %>],
+        extensions: &[<% for extension in &info.extensions { %>"<%= extension %>", <% } %>],
+        upstream_repo: <%- format!("{:?}", info.repo_url) %>,
+        license: <%- format!("{:?}", info.license) %>,
+        has_injections: !<%= info.module %>::INJECTIONS_QUERY.is_empty(),
+        has_locals: !<%= info.module %>::LOCALS_QUERY.is_empty(),
+    },
+<% } %>
+];
+
 // =============================================================================
 // Language grammar re-exports based on enabled features.
 // Each module provides:
@@ -10,7 +10,7 @@
 
 use std::borrow::Cow;
 use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, OnceLock, RwLock};
 
 #[allow(unused_imports)]
 use arborium_highlight::tree_sitter::{CompiledGrammar, GrammarConfig};
@@ -53,6 +53,21 @@ impl GrammarStore {
         }
     }
 
+    /// Get the process-wide grammar store.
+    ///
+    /// Every call returns the same store, so highlighters created with
+    /// `Highlighter::with_store(GrammarStore::global())` compile each grammar
+    /// at most once per process, no matter how many are created (e.g. one per
+    /// request). Grammars registered on it are visible to all of them.
+    ///
+    /// ```rust,ignore
+    /// let hl = Highlighter::with_store(GrammarStore::global());
+    /// ```
+    pub fn global() -> Arc<GrammarStore> {
+        static GLOBAL: OnceLock<Arc<GrammarStore>> = OnceLock::new();
+        GLOBAL.get_or_init(|| Arc::new(Self::new())).clone()
+    }
+
     /// Get a grammar by language name, compiling and caching it if needed.
     ///
     /// Returns `None` if the language is not supported.
@@ -83,8 +98,20 @@ impl GrammarStore {
         Some(grammar)
     }
 
+    /// Register a compiled grammar under a language name.
+    ///
+    /// This is the insertion path for grammars that are not part of the built-in
+    /// `lang-*` features (e.g. a tree-sitter grammar compiled into your own binary).
+    /// Registering a name that already exists replaces the previous grammar, so this
+    /// can also be used to override a built-in language.
+    pub fn register(&self, language: &str, grammar: Arc<CompiledGrammar>) {
+        let normalized = Self::normalize_language(language);
+        let mut grammars = self.grammars.write().unwrap();
+        grammars.insert(normalized.into_owned(), grammar);
+    }
+
     /// Normalize a language name to its canonical form.
-    fn normalize_language(language: &str) -> Cow<'_, str> {
+    pub(crate) fn normalize_language(language: &str) -> Cow<'_, str> {
         match language {
             // Aliases (generated from arborium.kdl)
 <% for (alias, canonical) in aliases { %>